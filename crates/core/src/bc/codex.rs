@@ -33,6 +33,14 @@ impl Encoder<Bc> for BcCodex {
 
     fn encode(&mut self, item: Bc, dst: &mut BytesMut) -> Result<()> {
         // let context = self.context.read().unwrap();
+        if let BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::RawXml(_)),
+            ..
+        }) = &item.body
+        {
+            // Keep the reply as raw XML too
+            self.context.raw_on(item.meta.msg_num);
+        }
         const BC_ENCRYPTED: EncryptionProtocol = EncryptionProtocol::BCEncrypt;
         let buf: Vec<u8> = Default::default();
         let enc_protocol: &EncryptionProtocol = match self.context.get_encrypted() {
@@ -129,6 +137,29 @@ impl Decoder for BcCodex {
                     }
                 }
             }
+        } else if let Bc {
+            meta:
+                BcMeta {
+                    msg_id: 1,
+                    response_code,
+                    ..
+                },
+            ..
+        } = &bc
+        {
+            if response_code >> 8 == 0xdd {
+                // A legacy login reply has no nonce so it cannot choose AES
+                match response_code & 0xff {
+                    0x00 => self.context.set_encrypted(EncryptionProtocol::Unencrypted),
+                    0x01 => self.context.set_encrypted(EncryptionProtocol::BCEncrypt),
+                    _ => {}
+                }
+            }
+        }
+
+        if !self.context.in_raw_mode.is_empty() {
+            // Only the reply is raw, the msg_num may be used for parsed XML later
+            self.context.raw_off(bc.meta.msg_num);
         }
 
         if let BcBody::ModernMsg(ModernMsg {
@@ -190,6 +190,10 @@ fn bc_modern_msg<'a>(
                 }
                 _ => Some(BcPayloads::Binary(payload_buf.to_vec())),
             };
+        } else if context.in_raw_mode.contains(&(header.msg_num)) {
+            payload = Some(BcPayloads::RawXml(
+                String::from_utf8_lossy(&processed_payload_buf).to_string(),
+            ));
         } else {
             if context.debug {
                 println!(
@@ -18,16 +18,26 @@ pub const MSG_ID_LOGOUT: u32 = 2;
 pub const MSG_ID_VIDEO: u32 = 3;
 /// ID used to stop the video stream
 pub const MSG_ID_VIDEO_STOP: u32 = 4;
+/// ID used to stop a download from the SD card
+pub const MSG_ID_REPLAY_STOP: u32 = 7;
+/// ID used to download a recording from the SD card
+pub const MSG_ID_REPLAY_DOWNLOAD: u32 = 8;
 /// TalkAbility messages have this ID
 pub const MSG_ID_TALKABILITY: u32 = 10;
 /// TalkReset messages have this ID
 pub const MSG_ID_TALKRESET: u32 = 11;
+/// ID used to search the recordings on the SD card
+pub const MSG_ID_REPLAY_FILE_LIST: u32 = 14;
 /// PtzControl messages have this ID
 pub const MSG_ID_PTZ_CONTROL: u32 = 18;
 /// PTZ goto preset position
 pub const MSG_ID_PTZ_CONTROL_PRESET: u32 = 19;
 /// Reboot messages have this ID
 pub const MSG_ID_REBOOT: u32 = 23;
+/// Set the video input settings such as the mirror and flip
+pub const MSG_ID_SET_VIDEO_INPUT: u32 = 25;
+/// Get the video input settings such as the mirror and flip
+pub const MSG_ID_GET_VIDEO_INPUT: u32 = 26;
 /// Request motion detection messages
 pub const MSG_ID_MOTION_REQUEST: u32 = 31;
 /// Motion detection messages
@@ -40,10 +50,26 @@ pub const MSG_ID_GET_SERVICE_PORTS: u32 = 37;
 pub const MSG_ID_GET_EMAIL: u32 = 42;
 /// Set email settings
 pub const MSG_ID_SET_EMAIL: u32 = 43;
+/// Get the name and timestamp drawn over the video
+pub const MSG_ID_GET_OSD: u32 = 44;
+/// Set the name and timestamp drawn over the video
+pub const MSG_ID_SET_OSD: u32 = 45;
+/// Get the motion detection sensitivity and zones
+pub const MSG_ID_GET_MD_ALARM: u32 = 46;
+/// Set the motion detection sensitivity and zones
+pub const MSG_ID_SET_MD_ALARM: u32 = 47;
+/// Get the resolution, frame rate and bitrate of the streams
+pub const MSG_ID_GET_COMPRESSION: u32 = 54;
+/// Set the resolution, frame rate and bitrate of the streams
+pub const MSG_ID_SET_COMPRESSION: u32 = 55;
 /// Version messages have this ID
 pub const MSG_ID_VERSION: u32 = 80;
 /// Ping messages have this ID
 pub const MSG_ID_PING: u32 = 93;
+/// Get the SD cards or disks and their space
+pub const MSG_ID_GET_HDD_INFO: u32 = 102;
+/// Format an SD card or disk
+pub const MSG_ID_FORMAT_HDD: u32 = 103;
 /// General system info messages have this ID
 pub const MSG_ID_GET_GENERAL: u32 = 104;
 /// Setting general system info (clock mostly) messages have this ID
@@ -98,6 +124,10 @@ pub const MSG_ID_FLOODLIGHT_STATUS_LIST: u32 = 291;
 pub const MSG_ID_GET_ZOOM_FOCUS: u32 = 294;
 /// Used for camera Zoom write
 pub const MSG_ID_SET_ZOOM_FOCUS: u32 = 295;
+/// Get the AI settings such as auto tracking
+pub const MSG_ID_GET_AI_CFG: u32 = 299;
+/// Set the AI settings such as auto tracking
+pub const MSG_ID_SET_AI_CFG: u32 = 300;
 /// Get the floodlight task xml
 pub const MSG_ID_FLOODLIGHT_TASKS_READ: u32 = 438;
 
@@ -220,6 +250,7 @@ pub struct BcMeta {
 pub(crate) struct BcContext {
     pub(crate) credentials: Credentials,
     pub(crate) in_bin_mode: HashSet<u16>,
+    pub(crate) in_raw_mode: HashSet<u16>,
     pub(crate) encryption_protocol: EncryptionProtocol,
     pub(crate) debug: bool,
 }
@@ -262,6 +293,7 @@ impl BcContext {
         BcContext {
             credentials,
             in_bin_mode: HashSet::new(),
+            in_raw_mode: HashSet::new(),
             encryption_protocol: EncryptionProtocol::Unencrypted,
             debug: false,
         }
@@ -272,6 +304,7 @@ impl BcContext {
         BcContext {
             credentials: Default::default(),
             in_bin_mode: HashSet::new(),
+            in_raw_mode: HashSet::new(),
             encryption_protocol,
             debug: false,
         }
@@ -294,6 +327,14 @@ impl BcContext {
         self.in_bin_mode.remove(&msg_id);
     }
 
+    pub(crate) fn raw_on(&mut self, msg_num: u16) {
+        self.in_raw_mode.insert(msg_num);
+    }
+
+    pub(crate) fn raw_off(&mut self, msg_num: u16) {
+        self.in_raw_mode.remove(&msg_num);
+    }
+
     pub(crate) fn debug_on(&mut self) {
         self.debug = true;
     }
@@ -97,6 +97,7 @@ fn bc_payload<W: Write>(
             encryption_protocol.encrypt(enc_offset, &xml_bytes)
         }
         BcPayloads::Binary(x) => x.to_owned(),
+        BcPayloads::RawXml(x) => encryption_protocol.encrypt(enc_offset, x.as_bytes()),
     };
     slice(payload_bytes)
 }
@@ -210,3 +211,30 @@ fn test_modern_login_roundtrip() {
     let msg2 = Bc::deserialize(&context, &mut bytes::BytesMut::from(ser_buf.as_slice())).unwrap();
     assert_eq!(msg, msg2);
 }
+
+#[test]
+fn test_raw_xml_roundtrip() {
+    let mut context = BcContext::new_with_encryption(EncryptionProtocol::BCEncrypt);
+    context.raw_on(7);
+
+    // Not xml that BcXml knows
+    let xml = r#"<?xml version="1.0" encoding="UTF-8" ?><body><Unknown version="1.1"><value>3</value></Unknown></body>"#;
+    let msg = Bc::new(
+        BcMeta {
+            msg_id: 9999,
+            channel_id: 0,
+            msg_num: 7,
+            response_code: 200,
+            stream_type: 0,
+            class: 0x0000,
+        },
+        None,
+        Some(BcPayloads::RawXml(xml.to_string())),
+    );
+
+    let ser_buf = msg
+        .serialize(vec![], &EncryptionProtocol::BCEncrypt)
+        .unwrap();
+    let msg2 = Bc::deserialize(&context, &mut bytes::BytesMut::from(ser_buf.as_slice())).unwrap();
+    assert_eq!(msg, msg2);
+}
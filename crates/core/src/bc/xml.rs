@@ -15,6 +15,11 @@ pub enum BcPayloads {
     /// Binary payloads are received from the camera for streams and sent to the camera
     /// for talk-back and firmware updates
     Binary(Vec<u8>),
+    /// XML that is sent and recieved as is without parsing it into [`BcXml`]
+    ///
+    /// Used to send messages that are not otherwise supported, the reply to
+    /// such a message is also kept as raw XML
+    RawXml(String),
 }
 
 /// The top level BC Xml
@@ -138,6 +143,27 @@ pub struct BcXml {
     /// EmailTask for turning the email notifications on/off
     #[serde(rename = "EmailTask", skip_serializing_if = "Option::is_none")]
     pub email_task: Option<EmailTask>,
+    /// The recordings on the SD card, used to search and download them
+    #[serde(rename = "FileInfoList", skip_serializing_if = "Option::is_none")]
+    pub file_info_list: Option<FileInfoList>,
+    /// The camera name that is drawn over the video
+    #[serde(rename = "OsdChannelName", skip_serializing_if = "Option::is_none")]
+    pub osd_channel_name: Option<OsdChannelName>,
+    /// The timestamp that is drawn over the video
+    #[serde(rename = "OsdDatetime", skip_serializing_if = "Option::is_none")]
+    pub osd_datetime: Option<OsdDatetime>,
+    /// The SD cards or disks of the camera
+    #[serde(rename = "HddInfoList", skip_serializing_if = "Option::is_none")]
+    pub hdd_info_list: Option<HddInfoList>,
+    /// Sent to format an SD card or disk
+    #[serde(rename = "HddInitList", skip_serializing_if = "Option::is_none")]
+    pub hdd_init_list: Option<HddInitList>,
+    /// The motion detection sensitivity and zones
+    #[serde(rename = "MD", skip_serializing_if = "Option::is_none")]
+    pub md: Option<Md>,
+    /// The AI settings such as auto tracking
+    #[serde(rename = "AiCfg", skip_serializing_if = "Option::is_none")]
+    pub ai_cfg: Option<AiCfg>,
 }
 
 impl BcXml {
@@ -456,6 +482,211 @@ pub struct FloodlightManual {
     pub duration: u16,
 }
 
+/// OsdChannelName xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdChannelName {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The name shown on the video
+    pub name: String,
+    /// 1 to show the name, 0 to hide it
+    pub enable: u8,
+    /// Left of the name
+    #[serde(rename = "topLeftX", skip_serializing_if = "Option::is_none")]
+    pub top_left_x: Option<u32>,
+    /// Top of the name
+    #[serde(rename = "topLeftY", skip_serializing_if = "Option::is_none")]
+    pub top_left_y: Option<u32>,
+    /// 1 to show the Reolink logo, 0 to hide it. Not all cameras have it
+    #[serde(rename = "enWatermark", skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<u8>,
+}
+
+/// OsdDatetime xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdDatetime {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 to show the timestamp, 0 to hide it
+    pub enable: u8,
+    /// Left of the timestamp
+    #[serde(rename = "topLeftX", skip_serializing_if = "Option::is_none")]
+    pub top_left_x: Option<u32>,
+    /// Top of the timestamp
+    #[serde(rename = "topLeftY", skip_serializing_if = "Option::is_none")]
+    pub top_left_y: Option<u32>,
+    /// Width of the timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Height of the timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Language of the timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+/// HddInfoList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInfoList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// One for each SD card or disk
+    #[serde(rename = "HddInfo", default)]
+    pub hdd_info: Vec<HddInfo>,
+}
+
+/// HddInfo xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInfo {
+    /// The id of the card, used to format it
+    pub number: u8,
+    /// The size in MB
+    pub capacity: u64,
+    /// The free space in MB
+    #[serde(rename = "remainSize")]
+    pub remain_size: u64,
+    /// 1 when the card is mounted and can be recorded to
+    pub mount: u8,
+    /// 1 when the card is formatted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<u8>,
+    /// Such as `sd` or `hdd`
+    #[serde(rename = "storageType", skip_serializing_if = "Option::is_none")]
+    pub storage_type: Option<String>,
+}
+
+/// HddInitList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInitList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The cards to format
+    #[serde(rename = "HddInit")]
+    pub hdd_init: Vec<HddInit>,
+}
+
+/// HddInit xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct HddInit {
+    /// The `number` of the card in the [HddInfo]
+    #[serde(rename = "initId")]
+    pub init_id: u8,
+}
+
+/// MD xml
+///
+/// The motion detection settings
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Md {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 if motion detection is on
+    pub enable: u8,
+    /// The detection zones
+    #[serde(rename = "MdArea", skip_serializing_if = "Option::is_none")]
+    pub area: Option<MdArea>,
+    /// The sensitivity, older cameras can have one per time of day
+    #[serde(rename = "sensList", skip_serializing_if = "Option::is_none")]
+    pub sens_list: Option<MdSensList>,
+    /// The actions to take on motion
+    #[serde(rename = "alarmHandle", skip_serializing_if = "Option::is_none")]
+    pub alarm_handle: Option<AlarmHandle>,
+    /// The times that motion detection is active
+    #[serde(rename = "timeBlockList", skip_serializing_if = "Option::is_none")]
+    pub time_block_list: Option<TimeBlockList>,
+}
+
+/// AiCfg xml
+///
+/// The AI settings of Trackmix and PTZ cameras
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct AiCfg {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// 1 if the camera follows the objects it detects
+    #[serde(rename = "aiTrack")]
+    pub ai_track: u8,
+    /// The objects to follow
+    #[serde(rename = "trackType", skip_serializing_if = "Option::is_none")]
+    pub track_type: Option<AiTrackType>,
+}
+
+/// The objects that auto tracking follows, 1 to follow them
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct AiTrackType {
+    /// People
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub people: Option<u8>,
+    /// Vehicles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vehicle: Option<u8>,
+    /// Dogs and cats
+    #[serde(rename = "dog_cat", skip_serializing_if = "Option::is_none")]
+    pub dog_cat: Option<u8>,
+}
+
+/// MdArea xml
+///
+/// The zones are a grid of cells that are on or off
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct MdArea {
+    /// Number of cells across
+    pub width: u32,
+    /// Number of cells down
+    pub height: u32,
+    /// One `1` or `0` per cell starting at the top left and going across
+    /// each row in turn. `1` is detected
+    pub area: String,
+}
+
+/// sensList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct MdSensList {
+    /// The sensitivity for each time of day
+    #[serde(default, rename = "sens")]
+    pub sens: Vec<MdSens>,
+}
+
+/// sens xml
+///
+/// The sensitivity for part of the day
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct MdSens {
+    /// Hour that this starts
+    #[serde(rename = "beginHour")]
+    pub begin_hour: u8,
+    /// Minute that this starts
+    #[serde(rename = "beginMinute")]
+    pub begin_minute: u8,
+    /// Hour that this ends
+    #[serde(rename = "endHour")]
+    pub end_hour: u8,
+    /// Minute that this ends
+    #[serde(rename = "endMinute")]
+    pub end_minute: u8,
+    /// The sensitivity 1-50, higher detects smaller movements
+    pub sensitivity: u8,
+}
+
 /// rfAlarmCfg xml
 #[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
 pub struct RfAlarmCfg {
@@ -482,7 +713,7 @@ pub struct RfAlarmCfg {
 }
 
 /// TimeBlockList XML
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename = "timeBlockList")]
 pub struct TimeBlockList {
     /// List of time block entries which disable/enable the PIR at a time
@@ -491,7 +722,7 @@ pub struct TimeBlockList {
 }
 
 /// TimeBlock XML Used to set the time to enable/disable PIR dectection
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename = "timeBlock")]
 pub struct TimeBlock {
     /// Whether to enable or disable for this time block
@@ -507,7 +738,7 @@ pub struct TimeBlock {
     pub end_hour: u8,
 }
 
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 /// AlarmHandle Xml
 pub struct AlarmHandle {
     /// Items in the alarm handle
@@ -515,7 +746,7 @@ pub struct AlarmHandle {
     pub item: Vec<AlarmHandleItem>,
 }
 
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 /// An item in the alarm handle
 #[serde(rename = "item")]
 pub struct AlarmHandleItem {
@@ -633,9 +864,14 @@ pub struct AlarmEvent {
     /// The channel the event occured on. Usually zero unless from an NVR
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Motion status. Known values are `"MD"` or `"none"`
+    /// Motion status. Known values are `"MD"`, `"visitor"` or `"none"`
+    ///
+    /// Doorbells send a comma separated list such as `"MD,visitor"` while
+    /// the button is pressed
     pub status: String,
-    /// AI status. Known values are `"people"` or `"none"`
+    /// AI status. Known values are `"people"`, `"vehicle"`, `"dog_cat"` or `"none"`
+    ///
+    /// This can be a comma separated list when more than one is detected
     #[serde(rename = "AItype", skip_serializing_if = "Option::is_none")]
     pub ai_type: Option<String>,
     /// The recording status. Known values `0` or `1`
@@ -1069,7 +1305,7 @@ pub struct StartZoomFocus {
     /// Channel ID
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Command: Observed values: zoomPos. (Write Only)
+    /// Command: Observed values: zoomPos, focusPos. (Write Only)
     pub command: String,
     /// Target Position: Observed Values: 2994, 2508, 2888, 3089, 3194, 3163. (Write Only)
     #[serde(rename = "movePos")]
@@ -1387,7 +1623,7 @@ pub struct AudioPlayInfo {
     /// Channel ID
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Playmode: 0
+    /// Playmode: 0 to play `play_times` times, 1 for on/off with `on_off`
     #[serde(rename = "playMode")]
     pub play_mode: u32,
     /// Duration: 0
@@ -1399,6 +1635,11 @@ pub struct AudioPlayInfo {
     /// On or Off: 0
     #[serde(rename = "onOff")]
     pub on_off: u32,
+    /// The id of a stored audio file to play instead of the siren
+    ///
+    /// Used by the doorbells to play their quick reply messages
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<u32>,
 }
 
 /// Server port for baichaun defaults 9000
@@ -1566,6 +1807,73 @@ pub struct Schedule {
     pub time_block_list: TimeBlockList,
 }
 
+/// A list of the recordings on the SD card
+///
+/// Sent with a single [`FileInfo`] to search or download and
+/// received with the recordings that were found
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+pub struct FileInfoList {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The recordings
+    #[serde(default, rename = "FileInfo")]
+    pub file_info: Vec<FileInfo>,
+}
+
+/// A recording on the SD card
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct FileInfo {
+    /// Channel number
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The stream that was recorded, `"mainStream"` or `"subStream"`
+    #[serde(rename = "streamType", skip_serializing_if = "Option::is_none")]
+    pub stream_type: Option<String>,
+    /// Why it was recorded. Observed values include `"manual"`, `"sched"` and `"md"`
+    #[serde(rename = "recordType", skip_serializing_if = "Option::is_none")]
+    pub record_type: Option<String>,
+    /// The file name on the camera, used to download it
+    #[serde(rename = "Name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The size of the file in bytes
+    #[serde(rename = "fileSize", skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// The start of the recording or of the search
+    #[serde(rename = "startTime", skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<ReplayTime>,
+    /// The end of the recording or of the search
+    #[serde(rename = "endTime", skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<ReplayTime>,
+}
+
+/// A time in the local time of the camera
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct ReplayTime {
+    /// Year e.g. 2023
+    pub year: i32,
+    /// Month 1-12
+    pub month: u8,
+    /// Day 1-31
+    pub day: u8,
+    /// Hour 0-23
+    pub hour: u8,
+    /// Minute 0-59
+    pub minute: u8,
+    /// Second 0-59
+    pub second: u8,
+}
+
+impl std::fmt::Display for ReplayTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
 /// Convience function to return the xml version used throughout the library
 pub fn xml_ver() -> String {
     "1.1".to_string()
@@ -1915,3 +2223,213 @@ fn test_empty_floodlight_status_list() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn test_file_info_list_deser() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FileInfoList version="1.1">
+        <FileInfo>
+        <channelId>0</channelId>
+        <streamType>mainStream</streamType>
+        <recordType>md</recordType>
+        <Name>01_20231014083000</Name>
+        <fileSize>1048576</fileSize>
+        <startTime>
+        <year>2023</year>
+        <month>10</month>
+        <day>14</day>
+        <hour>8</hour>
+        <minute>30</minute>
+        <second>0</second>
+        </startTime>
+        <endTime>
+        <year>2023</year>
+        <month>10</month>
+        <day>14</day>
+        <hour>8</hour>
+        <minute>31</minute>
+        <second>5</second>
+        </endTime>
+        </FileInfo>
+        </FileInfoList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    let files = b.file_info_list.unwrap().file_info;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name.as_deref(), Some("01_20231014083000"));
+    assert_eq!(files[0].file_size, Some(1048576));
+    assert_eq!(
+        files[0].start_time.map(|t| t.to_string()).as_deref(),
+        Some("2023-10-14 08:30:00")
+    );
+}
+
+#[test]
+fn test_osd_deser() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <OsdChannelName version="1.1">
+        <channelId>0</channelId>
+        <name>Camera1</name>
+        <enable>1</enable>
+        <topLeftX>0</topLeftX>
+        <topLeftY>0</topLeftY>
+        </OsdChannelName>
+        <OsdDatetime version="1.1">
+        <channelId>0</channelId>
+        <enable>0</enable>
+        <topLeftX>1</topLeftX>
+        <topLeftY>0</topLeftY>
+        <width>0</width>
+        <height>0</height>
+        <language>English</language>
+        </OsdDatetime>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            osd_channel_name:
+                Some(OsdChannelName {
+                    name,
+                    enable: 1,
+                    watermark: None,
+                    ..
+                }),
+            osd_datetime:
+                Some(OsdDatetime {
+                    enable: 0,
+                    language: Some(language),
+                    ..
+                }),
+            ..
+        } if name == "Camera1" && language == "English" => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_hdd_info_deser() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <HddInfoList version="1.1">
+        <HddInfo>
+        <number>0</number>
+        <capacity>30417</capacity>
+        <remainSize>12034</remainSize>
+        <mount>1</mount>
+        <format>1</format>
+        <storageType>sd</storageType>
+        </HddInfo>
+        </HddInfoList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            hdd_info_list: Some(HddInfoList { hdd_info, .. }),
+            ..
+        } if hdd_info.len() == 1
+            && hdd_info[0].capacity == 30417
+            && hdd_info[0].remain_size == 12034
+            && hdd_info[0].mount == 1 => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_md_deser() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <MD version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <MdArea>
+        <width>4</width>
+        <height>2</height>
+        <area>11000011</area>
+        </MdArea>
+        <sensList>
+        <sens>
+        <beginHour>0</beginHour>
+        <beginMinute>0</beginMinute>
+        <endHour>23</endHour>
+        <endMinute>59</endMinute>
+        <sensitivity>41</sensitivity>
+        </sens>
+        </sensList>
+        </MD>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            md:
+                Some(Md {
+                    enable: 1,
+                    area:
+                        Some(MdArea {
+                            width: 4,
+                            height: 2,
+                            area,
+                        }),
+                    sens_list: Some(MdSensList { sens }),
+                    alarm_handle: None,
+                    ..
+                }),
+            ..
+        } if area == "11000011" && sens.len() == 1 && sens[0].sensitivity == 41 => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_ai_cfg_deser() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <AiCfg version="1.1">
+        <channelId>0</channelId>
+        <aiTrack>1</aiTrack>
+        <trackType>
+        <people>1</people>
+        <vehicle>0</vehicle>
+        </trackType>
+        </AiCfg>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            ai_cfg:
+                Some(AiCfg {
+                    ai_track: 1,
+                    track_type:
+                        Some(AiTrackType {
+                            people: Some(1),
+                            vehicle: Some(0),
+                            dog_cat: None,
+                        }),
+                    ..
+                }),
+            ..
+        } => {}
+        _ => panic!(),
+    }
+}
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, SocketAddr};
 use std::{
     collections::HashMap,
-    sync::atomic::{AtomicBool, AtomicU16, Ordering},
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering},
 };
 use tokio::sync::RwLock;
 use tokio_util::sync::CancellationToken;
@@ -13,10 +13,12 @@ use tokio_util::sync::CancellationToken;
 use Md5Trunc::*;
 
 mod abilityinfo;
+mod aitrack;
 mod battery;
 mod connection;
 mod credentials;
 mod email;
+mod encoding;
 mod errors;
 mod floodlight;
 mod keepalive;
@@ -25,15 +27,20 @@ mod link;
 mod login;
 mod logout;
 mod motion;
+mod motion_config;
+mod osd;
 mod ping;
 mod pirstate;
 mod ptz;
 mod pushinfo;
+mod raw;
 mod reboot;
+mod replay;
 mod resolution;
 mod services;
 mod siren;
 mod snap;
+mod storage;
 mod stream;
 mod stream_info;
 mod support;
@@ -41,13 +48,16 @@ mod talk;
 mod time;
 mod uid;
 mod version;
+mod videoinput;
 
 pub(crate) use connection::*;
+pub use connection::{scan_lan, LanCamera, Proxy, ProxyKind, UdpSettings};
 pub use credentials::*;
+pub use encoding::StreamEncoding;
 pub use errors::Error;
 pub use ledstate::LightState;
-pub use login::MaxEncryption;
-pub use motion::{MotionData, MotionStatus};
+pub use login::{EncryptionLevel, LoginMethod, MaxEncryption};
+pub use motion::{AiDetection, MotionData, MotionStatus};
 pub use pirstate::PirState;
 pub use ptz::Direction;
 pub use pushinfo::PhoneType;
@@ -72,6 +82,8 @@ pub struct BcCamera {
     connection: Arc<BcConnection>,
     logged_in: AtomicBool,
     message_num: AtomicU16,
+    // The byte of the EncryptionLevel negotiated at login or u8::MAX before then
+    encryption_level: AtomicU8,
     // Certain commands such as logout require the username/pass in plain text.... why....???
     credentials: Credentials,
     abilities: RwLock<HashMap<String, ReadKind>>,
@@ -103,6 +115,41 @@ pub struct BcCameraOpt {
     pub credentials: Credentials,
     /// Toggle debug print of underlying data
     pub debug: bool,
+    /// Tuning of the UDP connection, this is unused over TCP
+    pub udp: UdpSettings,
+    /// Proxy to reach the camera through, only TCP can use one
+    pub proxy: Option<Proxy>,
+}
+
+/// Options for the default reolink login on channel 0 with all discovery methods
+///
+/// Use it to fill in the rest of the options so that new options do not
+/// break the build
+///
+/// ```
+/// # use neolink_core::bc_protocol::BcCameraOpt;
+/// let options = BcCameraOpt {
+///     addrs: vec!["192.168.1.10".parse().unwrap()],
+///     ..Default::default()
+/// };
+/// ```
+impl Default for BcCameraOpt {
+    fn default() -> Self {
+        Self {
+            name: "camera".to_string(),
+            channel_id: 0,
+            addrs: vec![],
+            uid: None,
+            port: None,
+            protocol: ConnectionProtocol::TcpUdp,
+            discovery: DiscoveryMethods::Relay,
+            max_discovery_retries: 10,
+            credentials: Default::default(),
+            debug: false,
+            udp: Default::default(),
+            proxy: None,
+        }
+    }
 }
 
 /// Used to choose the print format of various status messages like battery levels
@@ -140,7 +187,7 @@ impl BcCamera {
     /// Try to connect to the camera via appropaite methods and return
     /// the location that should be used
     async fn find_camera(options: &BcCameraOpt) -> Result<CameraLocation> {
-        let discovery = Discovery::new().await?;
+        let discovery = Discovery::new(options.udp.mtu).await?;
         if let ConnectionProtocol::Tcp | ConnectionProtocol::TcpUdp = options.protocol {
             let mut sockets = vec![];
             match options.port {
@@ -160,17 +207,26 @@ impl BcCamera {
                 info!("{}: Trying TCP discovery", options.name);
                 for socket in sockets.drain(..) {
                     let channel_id: u8 = options.channel_id;
-                    if let Ok(addr) = discovery.check_tcp(socket, channel_id).await.map(|_| {
-                        info!("{}: TCP Discovery success at {:?}", options.name, &socket);
-                        socket
-                    }) {
+                    if let Ok(addr) = discovery
+                        .check_tcp(socket, channel_id, options.proxy.as_ref())
+                        .await
+                        .map(|_| {
+                            info!("{}: TCP Discovery success at {:?}", options.name, &socket);
+                            socket
+                        })
+                    {
                         return Ok(CameraLocation::Tcp(addr));
                     }
                 }
             }
         }
 
-        if let (Some(uid), ConnectionProtocol::Udp | ConnectionProtocol::TcpUdp) =
+        if let Some(proxy) = options.proxy.as_ref() {
+            info!(
+                "{}: Not trying UDP discovery through the proxy {}",
+                options.name, proxy
+            );
+        } else if let (Some(uid), ConnectionProtocol::Udp | ConnectionProtocol::TcpUdp) =
             (options.uid.as_ref(), options.protocol)
         {
             let mut sockets = vec![];
@@ -218,7 +274,7 @@ impl BcCamera {
                     }
                 }, if allow_local => Ok(v),
                 Ok(v) = async {
-                    let mut discovery = Discovery::new().await?;
+                    let mut discovery = Discovery::new(options.udp.mtu).await?;
                     let reg_result;
                     // Registration is looped as it seems that reolink
                     // only updates the registration lazily when someone attempts
@@ -242,7 +298,7 @@ impl BcCamera {
                         retry += 1;
                         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                         // New discovery to get new client IDs
-                        discovery = Discovery::new().await?;
+                        discovery = Discovery::new(options.udp.mtu).await?;
                     };
                     tokio::select! {
                         Ok(v) = async {
@@ -332,9 +388,15 @@ impl BcCamera {
         let (sink, source): (BcConnSink, BcConnSource) = {
             match BcCamera::find_camera(options).await? {
                 CameraLocation::Tcp(addr) => {
-                    let (x, r) = TcpSource::new(addr, &username, passwd.as_ref(), options.debug)
-                        .await?
-                        .split();
+                    let (x, r) = TcpSource::new(
+                        addr,
+                        &username,
+                        passwd.as_ref(),
+                        options.debug,
+                        options.proxy.as_ref(),
+                    )
+                    .await?
+                    .split();
                     (Box::new(x), Box::new(r))
                 }
                 CameraLocation::Udp(discovery) => {
@@ -343,6 +405,7 @@ impl BcCamera {
                         &username,
                         passwd.as_ref(),
                         options.debug,
+                        options.udp,
                     )
                     .await?
                     .split();
@@ -359,6 +422,7 @@ impl BcCamera {
             message_num: AtomicU16::new(0),
             channel_id: options.channel_id,
             logged_in: AtomicBool::new(false),
+            encryption_level: AtomicU8::new(u8::MAX),
             credentials: Credentials::new(username, passwd),
             abilities: Default::default(),
             cancel: CancellationToken::new(),
@@ -367,6 +431,15 @@ impl BcCamera {
         Ok(me)
     }
 
+    /// Create a new camera interface and log into it
+    ///
+    /// This is [`BcCamera::new`] followed by [`BcCamera::login`]
+    pub async fn connect(options: &BcCameraOpt) -> Result<Self> {
+        let camera = Self::new(options).await?;
+        camera.login().await?;
+        Ok(camera)
+    }
+
     /// This method will get a new message number and increment the message count atomically
     pub fn new_message_num(&self) -> u16 {
         self.message_num.fetch_add(1, Ordering::Relaxed)
@@ -0,0 +1,113 @@
+//! Turns the AI auto tracking of Trackmix and PTZ cameras on and off
+//!
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [AiCfg] xml of the camera
+    pub async fn get_ai_cfg(&self) -> Result<AiCfg> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_AI_CFG, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_AI_CFG,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    ai_cfg: Some(ai_cfg),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(ai_cfg)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected AiCfg xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the [AiCfg] xml of the camera
+    ///
+    /// Get it with `get_ai_cfg` first and change it so that the
+    /// tracked objects are kept
+    pub async fn set_ai_cfg(&self, ai_cfg: AiCfg) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_AI_CFG, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_AI_CFG,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    ai_cfg: Some(ai_cfg),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the camera follows the objects that its AI detects
+    pub async fn get_auto_tracking(&self) -> Result<bool> {
+        let ai_cfg = self.get_ai_cfg().await?;
+        Ok(ai_cfg.ai_track == 1)
+    }
+
+    /// This is a convience function to turn the auto tracking on or off
+    /// True is on and false is off
+    pub async fn set_auto_tracking(&self, state: bool) -> Result<()> {
+        let mut ai_cfg = self.get_ai_cfg().await?;
+        ai_cfg.ai_track = match state {
+            true => 1,
+            false => 0,
+        };
+        self.set_ai_cfg(ai_cfg).await
+    }
+}
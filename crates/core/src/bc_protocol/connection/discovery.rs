@@ -2,7 +2,7 @@
 //!
 //! Given a UID find the associated IP
 //!
-use super::DiscoveryResult;
+use super::{DiscoveryResult, Proxy};
 use crate::bc::model::*;
 use crate::bc_protocol::{md5_string, Md5Trunc, TcpSource};
 use crate::bcudp::codex::BcUdpCodex;
@@ -58,7 +58,6 @@ struct UidLookupResults {
     relay: SocketAddr,
 }
 
-const MTU: u32 = 1350;
 lazy_static! {
     static ref P2P_RELAY_HOSTNAMES: [&'static str; 12] = [
         "p2p.reolink.com",
@@ -102,6 +101,8 @@ pub(crate) struct Discoverer {
     handlers: Handlers,
     local_addr: SocketAddr,
     cancel: CancellationToken,
+    /// The MTU that the camera is asked to use
+    mtu: u32,
 }
 
 fn valid_ip(ip: &str) -> bool {
@@ -117,7 +118,7 @@ fn valid_addr(ip_port: &IpPort) -> bool {
 }
 
 impl Discoverer {
-    async fn new() -> Result<Discoverer> {
+    async fn new(mtu: u32) -> Result<Discoverer> {
         let socket = Arc::new(connect().await?);
         let local_addr = socket.local_addr()?;
         let inner: ArcFramedSocket = UdpFramed::new(socket.clone(), BcUdpCodex::new());
@@ -201,6 +202,7 @@ impl Discoverer {
             handlers,
             local_addr,
             cancel,
+            mtu,
         })
     }
 
@@ -356,7 +358,7 @@ impl Discoverer {
                 uid: uid.to_string(),
                 cli: ClientList { port: port as u32 },
                 cid: client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 debug: false,
                 os: "MAC".to_string(),
             }),
@@ -638,7 +640,7 @@ impl Discoverer {
                 conn: "local".to_string(),
                 cid: register_result.client_id,
                 did: local_did,
-                mtu: MTU,
+                mtu: self.mtu,
             }),
         };
 
@@ -749,7 +751,7 @@ impl Discoverer {
                 conn: "map".to_string(),
                 cid: register_result.client_id,
                 did: local_did,
-                mtu: MTU,
+                mtu: self.mtu,
             }),
         };
 
@@ -825,7 +827,7 @@ impl Discoverer {
             payload: UdpXml::C2dT(C2dT {
                 sid: register_result.sid,
                 cid: register_result.client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 conn: "local".to_string(),
             }),
         };
@@ -888,7 +890,7 @@ impl Discoverer {
             payload: UdpXml::C2dT(C2dT {
                 sid: register_result.sid,
                 cid: register_result.client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 conn: "relay".to_string(),
             }),
         };
@@ -1031,9 +1033,9 @@ pub(crate) struct Discovery {
 }
 
 impl Discovery {
-    pub(crate) async fn new() -> Result<Self> {
+    pub(crate) async fn new(mtu: u32) -> Result<Self> {
         Ok(Self {
-            discoverer: Discoverer::new().await?,
+            discoverer: Discoverer::new(mtu).await?,
             client_id: generate_cid(),
         })
     }
@@ -1076,11 +1078,19 @@ impl Discovery {
     // Check if TCP is possible
     //
     // To do this we send a dummy login  and see if it replies with any BC packet
-    pub(crate) async fn check_tcp(&self, addr: SocketAddr, channel_id: u8) -> Result<()> {
+    pub(crate) async fn check_tcp(
+        &self,
+        addr: SocketAddr,
+        channel_id: u8,
+        proxy: Option<&Proxy>,
+    ) -> Result<()> {
         let username = "admin";
         let password = Some("123456");
-        let mut tcp_source =
-            timeout(*TCP_WAIT, TcpSource::new(addr, username, password, false)).await??;
+        let mut tcp_source = timeout(
+            *TCP_WAIT,
+            TcpSource::new(addr, username, password, false, proxy),
+        )
+        .await??;
 
         let md5_username = md5_string(username, Md5Trunc::ZeroLast);
         let md5_password = password
@@ -11,6 +11,8 @@ use tokio::net::UdpSocket;
 mod bcconn;
 mod bcsub;
 mod discovery;
+mod proxy;
+mod scan;
 mod tcpsource;
 mod udpsource;
 
@@ -18,6 +20,11 @@ pub(crate) use self::{
     bcconn::BcConnection, bcconn::*, bcsub::BcSubscription, discovery::Discovery,
     tcpsource::TcpSource, udpsource::UdpSource,
 };
+pub use self::{
+    proxy::{Proxy, ProxyKind},
+    scan::{scan_lan, LanCamera},
+    udpsource::UdpSettings,
+};
 
 pub(crate) struct DiscoveryResult {
     socket: Arc<UdpSocket>,
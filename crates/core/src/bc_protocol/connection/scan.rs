@@ -0,0 +1,140 @@
+//! Finds the cameras on the lan without knowing their UID
+//!
+//! Like the reolink client the `C2D_S` discovery is broadcast to port 2015
+//! along with `aaaa0000` to port 2000. The cameras reply to port 3000 with
+//! binary data that is not fully understood yet, the UID is picked out of it
+//! when it can be found. Every address that replies is listed.
+use crate::bcudp::codex::BcUdpCodex;
+use crate::bcudp::model::*;
+use crate::bcudp::xml::*;
+use crate::Result;
+use bytes::BytesMut;
+use log::*;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time::{interval, sleep, Duration};
+use tokio_util::codec::Encoder;
+
+/// Port the cameras send their binary reply to
+const REPLY_PORT: u16 = 3000;
+
+/// Length of a reolink UID such as `95270000YGAKNWKJ`
+const UID_LEN: usize = 16;
+
+/// How often the broadcasts are sent again while waiting
+const RESEND_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A camera that answered the lan broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanCamera {
+    /// The address that the reply came from
+    pub addr: IpAddr,
+    /// The UID if it was found in the reply
+    pub uid: Option<String>,
+}
+
+/// Broadcast on the lan and list the cameras that reply within `wait`
+pub async fn scan_lan(wait: Duration) -> Result<Vec<LanCamera>> {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0))).await?;
+    socket.set_broadcast(true)?;
+    // Another program may already have port 3000, the replies that are sent
+    // to our own port can still be heard
+    let reply_socket = match UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], REPLY_PORT))).await {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            warn!(
+                "Could not listen on port {}, some cameras may be missed: {}",
+                REPLY_PORT, e
+            );
+            None
+        }
+    };
+
+    let mut discovery = BytesMut::new();
+    BcUdpCodex::new().encode(
+        BcUdp::Discovery(UdpDiscovery {
+            tid: 0,
+            payload: UdpXml::C2dS(C2dS {
+                to: PortList {
+                    port: socket.local_addr()?.port() as u32,
+                },
+            }),
+        }),
+        &mut discovery,
+    )?;
+    let mut broadcasts = vec![Ipv4Addr::BROADCAST];
+    for iface in get_if_addrs::get_if_addrs()?.iter() {
+        if let get_if_addrs::IfAddr::V4(ifacev4) = &iface.addr {
+            if let Some(broadcast) = ifacev4.broadcast {
+                broadcasts.push(broadcast);
+            }
+        }
+    }
+
+    let mut found: BTreeMap<IpAddr, Option<String>> = Default::default();
+    let mut resend = interval(RESEND_INTERVAL);
+    let mut buf = [0u8; 2048];
+    let mut reply_buf = [0u8; 2048];
+    let deadline = sleep(wait);
+    tokio::pin!(deadline);
+    loop {
+        let (len, addr, on_reply_port) = tokio::select! {
+            _ = &mut deadline => break,
+            _ = resend.tick() => {
+                for broadcast in broadcasts.iter() {
+                    let _ = socket.send_to(&discovery, (*broadcast, 2015)).await;
+                    let _ = socket.send_to(&[0xaa, 0xaa, 0x00, 0x00], (*broadcast, 2000)).await;
+                }
+                continue;
+            }
+            v = socket.recv_from(&mut buf) => {
+                let (len, addr) = v?;
+                (len, addr, false)
+            }
+            v = async { reply_socket.as_ref().unwrap().recv_from(&mut reply_buf).await }, if reply_socket.is_some() => {
+                let (len, addr) = v?;
+                (len, addr, true)
+            }
+        };
+        let data = if on_reply_port {
+            &reply_buf[..len]
+        } else {
+            &buf[..len]
+        };
+        trace!("Lan scan reply from {}: {:02x?}", addr, data);
+        let known = found.entry(addr.ip()).or_insert_with(|| {
+            debug!("Lan scan found {}", addr.ip());
+            None
+        });
+        if known.is_none() {
+            *known = find_uid(data);
+        }
+    }
+
+    Ok(found
+        .into_iter()
+        .map(|(addr, uid)| LanCamera { addr, uid })
+        .collect())
+}
+
+/// Picks out a run of exactly 16 upper case letters and digits
+fn find_uid(data: &[u8]) -> Option<String> {
+    data.split(|b| !(b.is_ascii_uppercase() || b.is_ascii_digit()))
+        .find(|run| run.len() == UID_LEN && run.iter().any(|b| b.is_ascii_uppercase()))
+        .map(|run| String::from_utf8_lossy(run).into_owned())
+}
+
+#[test]
+fn test_find_uid() {
+    let mut reply = vec![0x01, 0x00, 0x00, 0x00];
+    reply.extend_from_slice(b"Front Door\0");
+    reply.extend_from_slice(b"192.168.1.10\0");
+    reply.extend_from_slice(b"95270000YGAKNWKJ\0");
+    assert_eq!(find_uid(&reply).as_deref(), Some("95270000YGAKNWKJ"));
+
+    // Not a UID when it runs on or is only digits
+    assert_eq!(find_uid(b"95270000YGAKNWKJX"), None);
+    assert_eq!(find_uid(b"1234567890123456"), None);
+    assert_eq!(find_uid(&[0xaa, 0xaa, 0x00, 0x00]), None);
+}
@@ -1,3 +1,4 @@
+use super::Proxy;
 use crate::bc::model::*;
 use crate::Result;
 use crate::{bc::codex::BcCodex, Credentials};
@@ -19,8 +20,12 @@ impl TcpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        proxy: Option<&Proxy>,
     ) -> Result<TcpSource> {
-        let stream = connect_to(addr).await?;
+        let stream = match proxy {
+            Some(proxy) => proxy.connect(addr).await?,
+            None => connect_to(addr).await?,
+        };
 
         let codex = if debug {
             BcCodex::new_with_debug(Credentials::new(username, password))
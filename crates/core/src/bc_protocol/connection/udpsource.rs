@@ -35,9 +35,46 @@ use tokio_util::{
     udp::UdpFramed,
 };
 
-const MTU: usize = 1350;
 const UDPDATA_HEADER_SIZE: usize = 20;
 
+/// Packets are resent less often each time they go unacknowledged, up
+/// to `resend_interval` times this
+const MAX_RESEND_BACKOFF: u32 = 8;
+
+/// Tuning of the UDP connection for cameras on poor networks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpSettings {
+    /// Largest UDP packet, the camera is also told to use this
+    pub mtu: u32,
+    /// How often the received packets are acknowledged to the camera
+    pub ack_interval: Duration,
+    /// How long a sent packet waits for its acknowledgement before it is
+    /// sent again
+    pub resend_interval: Duration,
+    /// The most packets that are resent at once, 0 for no limit
+    pub resend_window: usize,
+}
+
+impl Default for UdpSettings {
+    fn default() -> Self {
+        // The same as the official client
+        Self {
+            mtu: 1350,
+            ack_interval: Duration::from_millis(10),
+            resend_interval: Duration::from_millis(500),
+            resend_window: 64,
+        }
+    }
+}
+
+/// A packet that the camera has not acknowledged yet
+struct SentPacket {
+    data: UdpData,
+    sent_at: Instant,
+    /// Times that it has been resent
+    resends: u32,
+}
+
 pub(crate) type InnerFramed = Framed<Compat<IntoAsyncRead<UdpPayloadSource>>, BcCodex>;
 pub(crate) struct UdpSource {
     inner: Pin<Box<InnerFramed>>,
@@ -52,11 +89,12 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        settings: UdpSettings,
     ) -> Result<Self> {
         let stream = Arc::new(connect().await?);
 
         Self::new_from_socket(
-            stream, addr, client_id, camera_id, username, password, debug,
+            stream, addr, client_id, camera_id, username, password, debug, settings,
         )
         .await
     }
@@ -65,6 +103,7 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        settings: UdpSettings,
     ) -> Result<Self> {
         // Ensure that the discovery keep alive are all stopped here
         // We now handle all coms in UdpSource
@@ -77,10 +116,12 @@ impl UdpSource {
             username,
             password,
             debug,
+            settings,
         )
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new_from_socket<T: Into<String>, U: Into<String>>(
         stream: Arc<UdpSocket>,
         addr: SocketAddr,
@@ -89,9 +130,12 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        settings: UdpSettings,
     ) -> Result<Self> {
         let bcudp_source = BcUdpSource::new_from_socket(stream, addr).await?;
-        let payload_source = bcudp_source.into_payload_source(client_id, camera_id).await;
+        let payload_source = bcudp_source
+            .into_payload_source(client_id, camera_id, settings)
+            .await;
         let async_read = payload_source.into_async_read().compat();
         let codex = if debug {
             BcCodex::new_with_debug(Credentials::new(username, password))
@@ -175,8 +219,9 @@ impl BcUdpSource {
         self,
         client_id: i32,
         camera_id: i32,
+        settings: UdpSettings,
     ) -> UdpPayloadSource {
-        UdpPayloadSource::new(self, client_id, camera_id).await
+        UdpPayloadSource::new(self, client_id, camera_id, settings).await
     }
 }
 
@@ -300,14 +345,15 @@ struct UdpPayloadInner {
     camera_id: i32,
     packets_sent: u32,
     packets_want: u32,
-    sent: BTreeMap<u32, UdpData>,
+    sent: BTreeMap<u32, SentPacket>,
     recieved: BTreeMap<u32, Vec<u8>>,
     /// Offical Client does ack every 10ms if we don't also do this the camera
     /// seems to think we have a poor connection and will abort
     /// This `ack_interval` controls how ofen we do this
     /// Offical Client does resend every 500ms
-    /// This `resend_interval` controls how ofen we do this
+    /// This `resend_interval` controls how ofen we check for packets to resend
     resend_interval: Interval,
+    settings: UdpSettings,
     ack_latency: AckLatency,
     cancel: CancellationToken,
     set: JoinSet<Result<()>>,
@@ -319,6 +365,7 @@ impl UdpPayloadInner {
         thread_sink: ReceiverStream<Vec<u8>>,
         client_id: i32,
         camera_id: i32,
+        settings: UdpSettings,
     ) -> Self {
         let mut set = JoinSet::new();
         let camera_addr = inner.addr;
@@ -407,7 +454,7 @@ impl UdpPayloadInner {
 
         // Queue up ack packets
         let ack_cancel = cancel.clone();
-        let mut ack_interval = interval(Duration::from_millis(10)); // Offical Client does ack every 10ms
+        let mut ack_interval = interval(settings.ack_interval.max(Duration::from_millis(1))); // Offical Client does ack every 10ms
         ack_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         let (ack_tx, ack_rx) = watch(UdpAck::empty(camera_id));
         let ack_socket_in_tx = socket_in_tx.clone();
@@ -471,7 +518,12 @@ impl UdpPayloadInner {
             packets_want: 0,
             sent: Default::default(),
             recieved: Default::default(),
-            resend_interval: interval(Duration::from_millis(500)), // Offical Client does resend every 500ms
+            // Checked more often than the resend so that each packet waits about
+            // `resend_interval` from when it was sent
+            resend_interval: interval(
+                (settings.resend_interval / 4).max(Duration::from_millis(10)),
+            ),
+            settings,
             ack_latency: Default::default(),
             cancel,
             set,
@@ -482,8 +534,28 @@ impl UdpPayloadInner {
         tokio::select! {
             _ = self.resend_interval.tick() => {
                 log::trace!("Resend Tick");
-                for (_, resend) in self.sent.iter() {
-                    self.socket_in.feed(BcUdp::Data(resend.clone())).await?;
+                let now = Instant::now();
+                let resend_interval = self.settings.resend_interval;
+                let window = match self.settings.resend_window {
+                    0 => usize::MAX,
+                    n => n,
+                };
+                let mut resent = 0;
+                // Oldest first, the camera cannot use the later ones without them
+                for packet in self.sent.values_mut() {
+                    if resent >= window {
+                        break;
+                    }
+                    let wait = resend_interval * 2u32.saturating_pow(packet.resends).min(MAX_RESEND_BACKOFF);
+                    if now - packet.sent_at >= wait {
+                        packet.sent_at = now;
+                        packet.resends += 1;
+                        resent += 1;
+                        self.socket_in.feed(BcUdp::Data(packet.data.clone())).await?;
+                    }
+                }
+                if resent > 0 {
+                    log::trace!("Resent {resent} of {}", self.sent.len());
                 }
                 self.ack_tx.send_replace(self.build_send_ack()); // Ensure we update the ack packet sometimes too
                 Result::Ok(())
@@ -494,14 +566,19 @@ impl UdpPayloadInner {
                 // Outgoing on socket
                 let item = v.ok_or(Error::BcUdpDropSender)?;
 
-                for chunk in item.chunks(MTU - UDPDATA_HEADER_SIZE) {
+                let chunk_size = (self.settings.mtu as usize).saturating_sub(UDPDATA_HEADER_SIZE).max(1);
+                for chunk in item.chunks(chunk_size) {
                     let udp_data = UdpData {
                         connection_id: self.camera_id,
                         packet_id: self.packets_sent,
                         payload: chunk.to_vec(),
                     };
                     self.packets_sent += 1;
-                    self.sent.insert(udp_data.packet_id, udp_data.clone());
+                    self.sent.insert(udp_data.packet_id, SentPacket {
+                        data: udp_data.clone(),
+                        sent_at: Instant::now(),
+                        resends: 0,
+                    });
                     self.socket_in.feed(BcUdp::Data(udp_data)).await?;
                 }
                 Ok(())
@@ -530,7 +607,9 @@ impl UdpPayloadInner {
                         BcUdp::Discovery(_disc) => {},
                         BcUdp::Ack(ack) => {
                             if ack.connection_id == self.client_id {
-                                self.handle_ack(ack);
+                                for resend in self.handle_ack(ack) {
+                                    self.socket_in.feed(BcUdp::Data(resend)).await?;
+                                }
                             }
                         },
                         BcUdp::Data(data)  => {
@@ -599,21 +678,38 @@ impl UdpPayloadInner {
         }
     }
 
-    fn handle_ack(&mut self, ack: UdpAck) {
+    /// Forget the packets that the camera has and give back the ones to
+    /// send again straight away
+    ///
+    /// The ack lists the packets after the last one in order up to the last
+    /// that the camera has. The gaps in that were lost so they are resent
+    /// without waiting for the `resend_interval`, but not more often than a
+    /// few acks apart so that a packet that is still on its way is not sent
+    /// over and over.
+    fn handle_ack(&mut self, ack: UdpAck) -> Vec<UdpData> {
+        let mut resend = vec![];
         let start = ack.packet_id;
         if start != 0xffffffff {
             // -1 means havent got anything yet
             self.sent.retain(|&k, _| k > start);
 
+            let now = Instant::now();
+            let fast_after = self.settings.ack_interval * 4;
             for (idx, &value) in ack.payload.iter().enumerate() {
                 let packet_id = (start + 1) + idx as u32;
                 if value > 0 {
                     self.sent.remove(&packet_id);
+                } else if let Some(packet) = self.sent.get_mut(&packet_id) {
+                    if now - packet.sent_at >= fast_after {
+                        packet.sent_at = now;
+                        resend.push(packet.data.clone());
+                    }
                 }
             }
         }
         self.ack_latency.feed_ack();
         log::trace!("sent: {}", self.sent.len());
+        resend
     }
 }
 
@@ -630,7 +726,12 @@ impl Drop for UdpPayloadInner {
     }
 }
 impl UdpPayloadSource {
-    async fn new(inner: BcUdpSource, client_id: i32, camera_id: i32) -> Self {
+    async fn new(
+        inner: BcUdpSource,
+        client_id: i32,
+        camera_id: i32,
+        settings: UdpSettings,
+    ) -> Self {
         let (inner_sink, thread_sink) = channel(100);
         let (thread_stream, inner_stream) = channel(100);
 
@@ -640,6 +741,7 @@ impl UdpPayloadSource {
             ReceiverStream::new(thread_sink),
             client_id,
             camera_id,
+            settings,
         );
         let cancel_token = tokio_util::sync::CancellationToken::new();
 
@@ -0,0 +1,132 @@
+//! The resolution, frame rate and bitrate of the streams
+//!
+//! These are in the `<Compression>` settings as a `<mainStream>` and a
+//! `<subStream>` along with the encoder profile and gop that are not
+//! modelled here. Like the video input, the xml that the camera gives is
+//! edited and sent back so that those are kept.
+use super::videoinput::{element_range, element_text, with_element_text};
+use super::{BcCamera, Error, Result, StreamKind};
+use crate::bc::model::*;
+
+/// The encoder settings of one stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamEncoding {
+    /// Width of the picture
+    pub width: u32,
+    /// Height of the picture
+    pub height: u32,
+    /// Frames per second
+    pub fps: u32,
+    /// Bitrate in kbps
+    pub bitrate: u32,
+}
+
+impl BcCamera {
+    /// The resolution, frame rate and bitrate that the camera encodes a stream with
+    pub async fn get_encoding(&self, stream: StreamKind) -> Result<StreamEncoding> {
+        let tag = stream_tag(stream)?;
+        let xml = self.send_raw_xml(MSG_ID_GET_COMPRESSION, "").await?;
+        stream_encoding(&xml, tag).ok_or(Error::Other(
+            "The camera has no encoder settings for the stream",
+        ))
+    }
+
+    /// Change the resolution, frame rate and bitrate of a stream
+    ///
+    /// The camera only takes the values that are in its
+    /// [`crate::bc::xml::EncodeTable`] for the stream
+    pub async fn set_encoding(&self, stream: StreamKind, encoding: StreamEncoding) -> Result<()> {
+        let tag = stream_tag(stream)?;
+        let xml = self.send_raw_xml(MSG_ID_GET_COMPRESSION, "").await?;
+        let xml = with_stream_encoding(&xml, tag, &encoding).ok_or(Error::Other(
+            "The camera has no encoder settings for the stream",
+        ))?;
+        self.send_raw_xml(MSG_ID_SET_COMPRESSION, &xml).await?;
+        Ok(())
+    }
+}
+
+fn stream_tag(stream: StreamKind) -> Result<&'static str> {
+    match stream {
+        StreamKind::Main => Ok("mainStream"),
+        StreamKind::Sub => Ok("subStream"),
+        StreamKind::Extern => Err(Error::Other(
+            "The encoder settings of the extern stream are not known",
+        )),
+    }
+}
+
+fn stream_encoding(xml: &str, tag: &str) -> Option<StreamEncoding> {
+    let block = &xml[element_range(xml, tag)?];
+    let number = |name: &str| element_text(block, name)?.trim().parse::<u32>().ok();
+    Some(StreamEncoding {
+        width: number("width")?,
+        height: number("height")?,
+        fps: number("frame")?,
+        bitrate: number("bitRate")?,
+    })
+}
+
+fn with_stream_encoding(xml: &str, tag: &str, encoding: &StreamEncoding) -> Option<String> {
+    let range = element_range(xml, tag)?;
+    let block = &xml[range.clone()];
+    let block = with_element_text(
+        block,
+        "resolutionName",
+        &format!("{}*{}", encoding.width, encoding.height),
+    )
+    .unwrap_or_else(|| block.to_string());
+    let block = with_element_text(&block, "width", &encoding.width.to_string())?;
+    let block = with_element_text(&block, "height", &encoding.height.to_string())?;
+    let block = with_element_text(&block, "frame", &encoding.fps.to_string())?;
+    let block = with_element_text(&block, "bitRate", &encoding.bitrate.to_string())?;
+    let mut edited = xml.to_string();
+    edited.replace_range(range, &block);
+    Some(edited)
+}
+
+#[test]
+fn test_encoding_xml() {
+    let xml = concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<body>\n",
+        "<Compression version=\"1.1\">\n<channelId>0</channelId>\n",
+        "<mainStream>\n<audio>1</audio>\n<resolutionName>3840*2160</resolutionName>\n",
+        "<width>3840</width>\n<height>2160</height>\n<frame>25</frame>\n",
+        "<bitRate>6144</bitRate>\n<gop>2</gop>\n</mainStream>\n",
+        "<subStream>\n<audio>1</audio>\n<resolutionName>640*360</resolutionName>\n",
+        "<width>640</width>\n<height>360</height>\n<frame>15</frame>\n",
+        "<bitRate>256</bitRate>\n<gop>2</gop>\n</subStream>\n",
+        "</Compression>\n</body>\n"
+    );
+    let main = StreamEncoding {
+        width: 3840,
+        height: 2160,
+        fps: 25,
+        bitrate: 6144,
+    };
+    assert_eq!(stream_encoding(xml, "mainStream"), Some(main));
+    assert_eq!(
+        stream_encoding(xml, "subStream"),
+        Some(StreamEncoding {
+            width: 640,
+            height: 360,
+            fps: 15,
+            bitrate: 256,
+        })
+    );
+    assert_eq!(stream_encoding(xml, "thirdStream"), None);
+
+    // Only the sub stream is changed
+    let sub = StreamEncoding {
+        width: 896,
+        height: 512,
+        fps: 10,
+        bitrate: 512,
+    };
+    let edited = with_stream_encoding(xml, "subStream", &sub).unwrap();
+    assert_eq!(stream_encoding(&edited, "subStream"), Some(sub));
+    assert_eq!(stream_encoding(&edited, "mainStream"), Some(main));
+    assert!(edited.contains("<resolutionName>896*512</resolutionName>"));
+    assert!(edited.contains("<resolutionName>3840*2160</resolutionName>"));
+    assert!(edited.contains("<gop>2</gop>\n</subStream>"));
+}
@@ -1,4 +1,5 @@
 use super::bc::model::{Bc, BcXml};
+use super::EncryptionLevel;
 use crate::NomErrorType;
 use thiserror::Error;
 
@@ -128,6 +129,17 @@ pub enum Error {
     #[error("Credential error")]
     AuthFailed,
 
+    /// Raised when the camera chose weaker encryption than the login allows
+    ///
+    /// The credentials are not sent to the camera
+    #[error("Camera chose {} encryption which is weaker than the minimum of {min}", level.map(|level| level.to_string()).unwrap_or_else(|| "unknown".to_string()))]
+    InsecureLogin {
+        /// The encryption that the camera chose, `None` when it did not say
+        level: Option<EncryptionLevel>,
+        /// The weakest encryption that was allowed
+        min: EncryptionLevel,
+    },
+
     /// Raised when the given camera url could not be resolved
     #[error("Failed to translate camera address")]
     AddrResolutionError,
@@ -227,6 +239,10 @@ pub enum Error {
     #[error("Thread panicked")]
     JoinError(#[from] std::sync::Arc<tokio::task::JoinError>),
 
+    /// Raised when the proxy url is bad or the proxy refuses the connection
+    #[error("Proxy error: {0}")]
+    ProxyError(String),
+
     /// A generic catch all error
     #[error("Other error: {0}")]
     Other(&'static str),
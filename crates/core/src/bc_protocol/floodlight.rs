@@ -207,6 +207,21 @@ impl BcCamera {
         Ok(())
     }
 
+    /// Convience function: Set the brightness of the Flood Light in %
+    ///
+    /// This is clamped to the range that the camera reports
+    pub async fn floodlight_brightness_set(&self, brightness: u32) -> Result<()> {
+        let mut curr_state = self.get_flightlight_tasks().await?;
+        let brightness = brightness
+            .max(curr_state.brightness_min.unwrap_or(1))
+            .min(curr_state.brightness_max.unwrap_or(100));
+        if curr_state.brightness_cur != brightness {
+            curr_state.brightness_cur = brightness;
+            self.set_flightlight_tasks(curr_state).await?;
+        }
+        Ok(())
+    }
+
     /// Convience function: Check if Flood Light tasks are enbabled
     pub async fn is_flightlight_tasks_enabled(&self) -> Result<bool> {
         let curr_state = self.get_flightlight_tasks().await?;
@@ -123,6 +123,25 @@ impl BcCamera {
         Ok(())
     }
 
+    /// This is a convience function to read the state of the IR lights
+    ///
+    /// It is the reverse of `irled_light_set`
+    pub async fn irled_light_get(&self) -> Result<LightState> {
+        let led_state = self.get_ledstate().await?;
+        match led_state.state.as_str() {
+            "open" => Ok(LightState::On),
+            "close" => Ok(LightState::Off),
+            "auto" => Ok(LightState::Auto),
+            _ => Err(Error::UnintelligibleXml {
+                reply: std::sync::Arc::new(Box::new(BcXml {
+                    led_state: Some(led_state),
+                    ..Default::default()
+                })),
+                why: "Unknown IR state",
+            }),
+        }
+    }
+
     /// This is a convience function to control the LED light
     /// True is on and false is off
     ///
@@ -139,6 +158,7 @@ impl BcCamera {
 }
 
 /// This is pased to `irled_light_set` to turn it on, off or set it to light based auto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LightState {
     /// Turn the light on
     On,
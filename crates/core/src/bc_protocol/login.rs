@@ -1,4 +1,4 @@
-use super::{md5_string, BcCamera, Error, Result, Truncate};
+use super::{md5_string, BcCamera, BcSubscription, Error, Result, Truncate, ZeroLast};
 use crate::bc::{model::*, xml::*};
 use std::sync::atomic::Ordering;
 
@@ -20,7 +20,68 @@ pub enum MaxEncryption {
     Aes,
 }
 
+/// How to log in to the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoginMethod {
+    /// Try the modern login and fall back to the legacy one when the
+    /// camera does not offer it
+    #[default]
+    Auto,
+    /// The modern login with the nonce exchange
+    Modern,
+    /// The legacy login with the MD5 of the username and password, this is
+    /// for old firmwares. It still moves on to the modern login if the
+    /// camera asks for it
+    Legacy,
+}
+
+/// The encryption level that the camera chose during login
+///
+/// Ordered from weakest to strongest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EncryptionLevel {
+    /// No encryption
+    None,
+    /// BCEncrypt is a simple XOR algortirhm with a fixed key
+    BcEncrypt,
+    /// AES on the control messages
+    Aes,
+    /// AES on the control messages and the camera feed
+    FullAes,
+}
+
+impl EncryptionLevel {
+    /// Get the level from the low byte of the login reply's response code
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(Self::None),
+            0x01 => Some(Self::BcEncrypt),
+            0x02 => Some(Self::Aes),
+            0x12 => Some(Self::FullAes),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EncryptionLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::BcEncrypt => write!(f, "BCEncrypt"),
+            Self::Aes => write!(f, "AES"),
+            Self::FullAes => write!(f, "Full AES"),
+        }
+    }
+}
+
 impl BcCamera {
+    /// The encryption level that the camera chose during the last login
+    ///
+    /// This is `None` until a login reply has been recieved
+    pub fn encryption_level(&self) -> Option<EncryptionLevel> {
+        EncryptionLevel::from_byte(self.encryption_level.load(Ordering::Relaxed))
+    }
+
     /// Login to the camera.
     ///
     /// This should be called before most other commands
@@ -31,85 +92,254 @@ impl BcCamera {
     ///
     /// This should be called before most other commands
     pub async fn login_with_maxenc(&self, max_encryption: MaxEncryption) -> Result<DeviceInfo> {
+        self.login_with_method(max_encryption, LoginMethod::Auto)
+            .await
+    }
+
+    /// Login to the camera with a chosen login method
+    ///
+    /// This should be called before most other commands
+    pub async fn login_with_method(
+        &self,
+        max_encryption: MaxEncryption,
+        method: LoginMethod,
+    ) -> Result<DeviceInfo> {
+        self.login_with_min_security(max_encryption, method, EncryptionLevel::None)
+            .await
+    }
+
+    /// Login to the camera but refuse to send the credentials if it chooses
+    /// weaker encryption than `min_security`
+    ///
+    /// The camera only says what it chose in the reply to the first message.
+    /// The legacy login sends the credentials in that first message, so it is
+    /// refused outright when `min_security` is above [`EncryptionLevel::None`]
+    pub async fn login_with_min_security(
+        &self,
+        max_encryption: MaxEncryption,
+        method: LoginMethod,
+        min_security: EncryptionLevel,
+    ) -> Result<DeviceInfo> {
+        if method == LoginMethod::Legacy && min_security > EncryptionLevel::None {
+            return Err(Error::InsecureLogin {
+                level: None,
+                min: min_security,
+            });
+        }
         let device_info;
         // This { is here due to the connection and set_credentials both requiring a mutable borrow
         {
-            let credentials = self.get_credentials();
             let connection = self.get_connection();
             let msg_num = self.new_message_num();
             let mut sub_login = connection.subscribe(MSG_ID_LOGIN, msg_num).await?;
 
-            // Login flow is: Send legacy login message, expect back a modern message with Encryption
-            // details.  Then, re-send the login as a modern login message.  Expect back a device info
-            // congratulating us on logging in.
-
-            // In the legacy scheme, username/password are MD5'd if they are encrypted (which they need
-            // to be to "upgrade" to the modern login flow), then the hex of the MD5 is sent.
-            // Note: I suspect there may be a buffer overflow opportunity in the firmware since in the
-            // Baichuan library, these strings are capped at 32 bytes with a null terminator.  This
-            // could also be a mistake in the library, the effect being it only compares 31 chars, not 32.
-            // let md5_username = md5_string(&credentials.username, ZeroLast);
-            // let md5_password = credentials
-            //     .password
-            //     .as_ref()
-            //     .map(|p| md5_string(p, ZeroLast))
-            //     .unwrap_or_else(|| EMPTY_LEGACY_PASSWORD.to_owned());
+            // Modern login flow is: Send legacy login message, expect back a modern message with
+            // Encryption details.  Then, re-send the login as a modern login message.  Expect back a
+            // device info congratulating us on logging in.
+            //
+            // Old firmwares do not have the nonce exchange and log in with just the legacy message
 
             let enc_byte = match max_encryption {
                 MaxEncryption::None => 0xdc00,
                 MaxEncryption::BcEncrypt => 0xdc01,
                 MaxEncryption::Aes => 0xdc12,
             };
-            let legacy_login = Bc {
-                meta: BcMeta {
-                    msg_id: MSG_ID_LOGIN,
-                    channel_id: self.channel_id,
-                    msg_num,
-                    stream_type: 0,
-                    response_code: enc_byte,
-                    class: 0x6514,
-                },
-                body: BcBody::LegacyMsg(LegacyMsg::LoginUpgrade),
-            };
 
-            sub_login.send(legacy_login).await?;
+            let first_reply = match method {
+                LoginMethod::Auto | LoginMethod::Modern => {
+                    let upgrade_login = Bc {
+                        meta: BcMeta {
+                            msg_id: MSG_ID_LOGIN,
+                            channel_id: self.channel_id,
+                            msg_num,
+                            stream_type: 0,
+                            response_code: enc_byte,
+                            class: 0x6514,
+                        },
+                        body: BcBody::LegacyMsg(LegacyMsg::LoginUpgrade),
+                    };
+                    sub_login.send(upgrade_login).await?;
+                    sub_login.recv().await?
+                }
+                LoginMethod::Legacy => {
+                    self.send_legacy_login(&sub_login, msg_num, enc_byte)
+                        .await?;
+                    sub_login.recv().await?
+                }
+            };
 
-            let legacy_reply = sub_login.recv().await?;
+            // The camera replies with 0xddXX where XX is the encryption it chose
+            let mut level = None;
+            if first_reply.meta.response_code >> 8 == 0xdd {
+                let byte = (first_reply.meta.response_code & 0xff) as u8;
+                level = EncryptionLevel::from_byte(byte);
+                if level.is_some() {
+                    self.encryption_level.store(byte, Ordering::Relaxed);
+                }
+            }
+            // Check before any credentials are sent
+            if level.is_none_or(|level| level < min_security)
+                && min_security > EncryptionLevel::None
+            {
+                return Err(Error::InsecureLogin {
+                    level,
+                    min: min_security,
+                });
+            }
 
-            let nonce;
-            match &legacy_reply.body {
-                BcBody::ModernMsg(ModernMsg {
-                    payload:
-                        Some(BcPayloads::BcXml(BcXml {
-                            encryption: Some(encryption),
-                            ..
-                        })),
-                    ..
-                }) => {
-                    nonce = &encryption.nonce;
+            // Whether the camera logged us in without the nonce exchange
+            let mut legacy = false;
+            let modern_reply = match (method, login_nonce(&first_reply)) {
+                (_, Some(nonce)) => {
+                    // Even for the legacy method, a camera that sends a nonce wants the modern login
+                    self.send_modern_login(&sub_login, msg_num, nonce).await?;
+                    sub_login.recv().await?
                 }
-                _ => {
+                (LoginMethod::Modern, None) => {
                     return Err(Error::UnintelligibleReply {
-                        reply: std::sync::Arc::new(Box::new(legacy_reply)),
+                        reply: std::sync::Arc::new(Box::new(first_reply)),
                         why: "Expected an Encryption message back",
                     })
                 }
+                (LoginMethod::Auto, None) => {
+                    log::debug!("No nonce in the login reply, trying the legacy login");
+                    self.send_legacy_login(&sub_login, msg_num, enc_byte)
+                        .await?;
+                    let legacy_reply = sub_login.recv().await?;
+                    match login_nonce(&legacy_reply) {
+                        Some(nonce) => {
+                            self.send_modern_login(&sub_login, msg_num, nonce).await?;
+                            sub_login.recv().await?
+                        }
+                        None => {
+                            legacy = true;
+                            legacy_reply
+                        }
+                    }
+                }
+                (LoginMethod::Legacy, None) => {
+                    legacy = true;
+                    first_reply
+                }
+            };
+
+            if legacy {
+                // Old firmwares reply with 200 or the encryption they chose and may not
+                // send a device info
+                let code = modern_reply.meta.response_code;
+                if code != 200 && code >> 8 != 0xdd {
+                    return Err(Error::CameraLoginFail);
+                }
+                device_info = match modern_reply.body {
+                    BcBody::ModernMsg(ModernMsg {
+                        payload:
+                            Some(BcPayloads::BcXml(BcXml {
+                                device_info: Some(info),
+                                ..
+                            })),
+                        ..
+                    }) => info,
+                    _ => DeviceInfo::default(),
+                };
+                log::debug!("Logged in with the legacy login");
+                self.logged_in.store(true, Ordering::Relaxed);
+            } else {
+                if modern_reply.meta.response_code != 200 {
+                    return Err(Error::CameraLoginFail);
+                }
+
+                match modern_reply.body {
+                    BcBody::ModernMsg(ModernMsg {
+                        payload:
+                            Some(BcPayloads::BcXml(BcXml {
+                                device_info: Some(info),
+                                ..
+                            })),
+                        ..
+                    }) => {
+                        // Login succeeded!
+                        self.logged_in.store(true, Ordering::Relaxed);
+                        device_info = info;
+                    }
+                    BcBody::ModernMsg(ModernMsg {
+                        extension: None,
+                        payload: None,
+                    }) => return Err(Error::AuthFailed),
+                    _ => {
+                        return Err(Error::UnintelligibleReply {
+                            reply: std::sync::Arc::new(Box::new(modern_reply)),
+                            why: "Expected a DeviceInfo message back from login",
+                        })
+                    }
+                }
             }
+        }
+
+        // Populate the list of abilities this user has with the camera
+        log::debug!("Populating abilities");
+        self.polulate_abilities().await?;
+        Ok(device_info)
+    }
 
-            // In the modern login flow, the username/password are concat'd with the server's nonce
-            // string, then MD5'd, then the hex of this MD5 is sent as the password.  This nonce
-            // prevents replay attacks if the server were to require modern flow, but not rainbow table
-            // attacks (since  the plain user/password MD5s have already been sent).  The upshot is that
-            // you should use a very strong random password that is not found in a rainbow table and
-            // not feasibly crackable with John the Ripper.
+    /// The legacy login with the MD5 of the username and password
+    async fn send_legacy_login(
+        &self,
+        sub_login: &BcSubscription<'_>,
+        msg_num: u16,
+        enc_byte: u16,
+    ) -> Result<()> {
+        // In the legacy scheme, username/password are MD5'd if they are encrypted, then the hex of
+        // the MD5 is sent.
+        // Note: I suspect there may be a buffer overflow opportunity in the firmware since in the
+        // Baichuan library, these strings are capped at 32 bytes with a null terminator.  This
+        // could also be a mistake in the library, the effect being it only compares 31 chars, not 32.
+        let credentials = self.get_credentials();
+        let md5_username = md5_string(&credentials.username, ZeroLast);
+        let md5_password = credentials
+            .password
+            .as_ref()
+            .map(|p| md5_string(p, ZeroLast))
+            .unwrap_or_else(|| EMPTY_LEGACY_PASSWORD.to_owned());
+        sub_login
+            .send(Bc {
+                meta: BcMeta {
+                    msg_id: MSG_ID_LOGIN,
+                    channel_id: self.channel_id,
+                    msg_num,
+                    stream_type: 0,
+                    response_code: enc_byte,
+                    class: 0x6514,
+                },
+                body: BcBody::LegacyMsg(LegacyMsg::LoginMsg {
+                    username: md5_username,
+                    password: md5_password,
+                }),
+            })
+            .await
+    }
 
-            let modern_password = credentials.password.clone().unwrap_or_default();
-            let concat_username = format!("{}{}", credentials.username, nonce);
-            let concat_password = format!("{}{}", modern_password, nonce);
-            let md5_username = md5_string(&concat_username, Truncate);
-            let md5_password = md5_string(&concat_password, Truncate);
+    /// The modern login with the MD5 of the username and password with the nonce
+    async fn send_modern_login(
+        &self,
+        sub_login: &BcSubscription<'_>,
+        msg_num: u16,
+        nonce: &str,
+    ) -> Result<()> {
+        // In the modern login flow, the username/password are concat'd with the server's nonce
+        // string, then MD5'd, then the hex of this MD5 is sent as the password.  This nonce
+        // prevents replay attacks if the server were to require modern flow, but not rainbow table
+        // attacks (since  the plain user/password MD5s have already been sent).  The upshot is that
+        // you should use a very strong random password that is not found in a rainbow table and
+        // not feasibly crackable with John the Ripper.
+        let credentials = self.get_credentials();
+        let modern_password = credentials.password.clone().unwrap_or_default();
+        let concat_username = format!("{}{}", credentials.username, nonce);
+        let concat_password = format!("{}{}", modern_password, nonce);
+        let md5_username = md5_string(&concat_username, Truncate);
+        let md5_password = md5_string(&concat_password, Truncate);
 
-            let modern_login = Bc::new_from_xml(
+        sub_login
+            .send(Bc::new_from_xml(
                 BcMeta {
                     msg_id: MSG_ID_LOGIN,
                     channel_id: self.channel_id,
@@ -128,43 +358,22 @@ impl BcCamera {
                     login_net: Some(LoginNet::default()),
                     ..Default::default()
                 },
-            );
-
-            sub_login.send(modern_login).await?;
-            let modern_reply = sub_login.recv().await?;
-            if modern_reply.meta.response_code != 200 {
-                return Err(Error::CameraLoginFail);
-            }
+            ))
+            .await
+    }
+}
 
-            match modern_reply.body {
-                BcBody::ModernMsg(ModernMsg {
-                    payload:
-                        Some(BcPayloads::BcXml(BcXml {
-                            device_info: Some(info),
-                            ..
-                        })),
+/// The nonce of a login reply that starts the modern login
+fn login_nonce(reply: &Bc) -> Option<&str> {
+    match &reply.body {
+        BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    encryption: Some(encryption),
                     ..
-                }) => {
-                    // Login succeeded!
-                    self.logged_in.store(true, Ordering::Relaxed);
-                    device_info = info;
-                }
-                BcBody::ModernMsg(ModernMsg {
-                    extension: None,
-                    payload: None,
-                }) => return Err(Error::AuthFailed),
-                _ => {
-                    return Err(Error::UnintelligibleReply {
-                        reply: std::sync::Arc::new(Box::new(legacy_reply)),
-                        why: "Expected a DeviceInfo message back from login",
-                    })
-                }
-            }
-        }
-
-        // Populate the list of abilities this user has with the camera
-        log::debug!("Populating abilities");
-        self.polulate_abilities().await?;
-        Ok(device_info)
+                })),
+            ..
+        }) => Some(&encryption.nonce),
+        _ => None,
     }
 }
@@ -1,7 +1,10 @@
 use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc::{channel, error::TryRecvError, Receiver};
+use tokio::sync::{
+    mpsc::{channel, error::TryRecvError, Receiver},
+    watch::{channel as watch, Receiver as WatchReceiver},
+};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
@@ -16,6 +19,42 @@ pub enum MotionStatus {
     NoChange(Instant),
 }
 
+/// The objects that the AI of the camera classified in the motion
+///
+/// Newer firmwares send this in the `AItype` of the alarm events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AiDetection {
+    /// A person was detected
+    pub person: bool,
+    /// A car or other vehicle was detected
+    pub vehicle: bool,
+    /// A dog or cat was detected
+    pub pet: bool,
+}
+
+impl AiDetection {
+    /// Read the comma separated `AItype` of an alarm event
+    ///
+    /// Known values are `"people"`, `"vehicle"`, `"dog_cat"` and `"none"`
+    pub fn from_ai_type(ai_type: &str) -> Self {
+        let mut detection = Self::default();
+        for ai_type in ai_type.split(',').map(|ai_type| ai_type.trim()) {
+            match ai_type {
+                "people" | "person" => detection.person = true,
+                "vehicle" => detection.vehicle = true,
+                "dog_cat" | "pet" | "animal" => detection.pet = true,
+                _ => {}
+            }
+        }
+        detection
+    }
+
+    /// Whether anything was detected
+    pub fn any(&self) -> bool {
+        self.person || self.vehicle || self.pet
+    }
+}
+
 /// A handle on current motion related events comming from the camera
 ///
 /// When this object is dropped the motion events are stopped
@@ -24,6 +63,9 @@ pub struct MotionData {
     cancel: CancellationToken,
     rx: Receiver<Result<MotionStatus>>,
     last_update: MotionStatus,
+    recording: WatchReceiver<Option<bool>>,
+    visitor: WatchReceiver<Option<bool>>,
+    ai: WatchReceiver<Option<AiDetection>>,
 }
 
 impl MotionData {
@@ -53,6 +95,45 @@ impl MotionData {
         })
     }
 
+    /// Get if the camera is currently writing a clip to its storage.
+    /// Returns None if the camera has not yet reported it
+    ///
+    /// This is sent by the camera along with the motion events
+    pub fn recording(&self) -> Option<bool> {
+        *self.recording.borrow()
+    }
+
+    /// Get a watcher on the recording status, see [`MotionData::recording`]
+    pub fn recording_watcher(&self) -> WatchReceiver<Option<bool>> {
+        self.recording.clone()
+    }
+
+    /// Get if the doorbell button is currently pressed.
+    /// Returns None if the camera has not yet reported it
+    ///
+    /// Only doorbells report this, it is sent along with the motion events
+    pub fn visitor(&self) -> Option<bool> {
+        *self.visitor.borrow()
+    }
+
+    /// Get a watcher on the visitor status, see [`MotionData::visitor`]
+    pub fn visitor_watcher(&self) -> WatchReceiver<Option<bool>> {
+        self.visitor.clone()
+    }
+
+    /// Get what the AI of the camera detected in the latest alarm event.
+    /// Returns None if the camera has not reported it
+    ///
+    /// Only cameras with AI detection report this
+    pub fn ai(&self) -> Option<AiDetection> {
+        *self.ai.borrow()
+    }
+
+    /// Get a watcher on the AI detections, see [`MotionData::ai`]
+    pub fn ai_watcher(&self) -> WatchReceiver<Option<AiDetection>> {
+        self.ai.clone()
+    }
+
     /// Consume the motion events diretly
     ///
     /// An error is raised if the motion connection to the camera is dropped
@@ -211,6 +292,9 @@ impl BcCamera {
         // After start_motion_query (MSG_ID 31) the camera sends motion messages
         // when whenever motion is detected.
         let (tx, rx) = channel(20);
+        let (recording_tx, recording_rx) = watch(None);
+        let (visitor_tx, visitor_rx) = watch(None);
+        let (ai_tx, ai_rx) = watch(None);
 
         let mut set = JoinSet::new();
         let channel_id = self.channel_id;
@@ -239,13 +323,27 @@ impl BcCamera {
                                     let mut result = MotionStatus::NoChange(Instant::now());
                                     for alarm_event in &alarm_event_list.alarm_events {
                                         if alarm_event.channel_id == channel_id {
-                                            if alarm_event.status != "none"
-                                                || alarm_event
-                                                    .ai_type
-                                                    .as_ref()
-                                                    .map(|ai_type| ai_type != "none")
-                                                    .unwrap_or(false)
-                                            {
+                                            recording_tx.send_if_modified(|recording| {
+                                                let now = Some(alarm_event.recording != 0);
+                                                let changed = *recording != now;
+                                                *recording = now;
+                                                changed
+                                            });
+                                            let visitor = is_visitor(alarm_event);
+                                            visitor_tx.send_if_modified(|old| {
+                                                let changed = *old != Some(visitor);
+                                                *old = Some(visitor);
+                                                changed
+                                            });
+                                            if let Some(ai_type) = alarm_event.ai_type.as_ref() {
+                                                let ai = Some(AiDetection::from_ai_type(ai_type));
+                                                ai_tx.send_if_modified(|old| {
+                                                    let changed = *old != ai;
+                                                    *old = ai;
+                                                    changed
+                                                });
+                                            }
+                                            if is_motion(alarm_event) {
                                                 result = MotionStatus::Start(Instant::now());
                                                 break;
                                             } else {
@@ -278,10 +376,36 @@ impl BcCamera {
             cancel,
             rx,
             last_update: MotionStatus::NoChange(Instant::now()),
+            recording: recording_rx,
+            visitor: visitor_rx,
+            ai: ai_rx,
         })
     }
 }
 
+/// Doorbells add `visitor` to the status while the button is pressed
+fn is_visitor(alarm_event: &AlarmEvent) -> bool {
+    alarm_event
+        .status
+        .split(',')
+        .any(|status| status.trim() == "visitor")
+}
+
+/// Whether the event is motion, the `visitor` of a doorbell on its own is not
+fn is_motion(alarm_event: &AlarmEvent) -> bool {
+    let motion = alarm_event
+        .status
+        .split(',')
+        .map(|status| status.trim())
+        .any(|status| status != "none" && status != "visitor");
+    motion
+        || alarm_event
+            .ai_type
+            .as_ref()
+            .map(|ai_type| ai_type != "none")
+            .unwrap_or(false)
+}
+
 impl Drop for MotionData {
     fn drop(&mut self) {
         log::trace!("Drop MotionData");
@@ -294,3 +418,63 @@ impl Drop for MotionData {
         });
     }
 }
+
+#[test]
+fn test_ai_type() {
+    assert_eq!(AiDetection::from_ai_type("none"), AiDetection::default());
+    assert_eq!(
+        AiDetection::from_ai_type("people,dog_cat"),
+        AiDetection {
+            person: true,
+            vehicle: false,
+            pet: true,
+        }
+    );
+    assert!(AiDetection::from_ai_type("vehicle").any());
+}
+
+#[test]
+fn test_alarm_event_kind() {
+    let event = |status: &str, ai_type: &str| {
+        let xml = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<body>\n",
+                "<AlarmEventList version=\"1.1\">\n<AlarmEvent version=\"1.1\">\n",
+                "<channelId>0</channelId>\n<status>{}</status>\n<AItype>{}</AItype>\n",
+                "<recording>0</recording>\n<timeStamp>0</timeStamp>\n",
+                "</AlarmEvent>\n</AlarmEventList>\n</body>\n"
+            ),
+            status, ai_type
+        );
+        match BcXml::try_parse(xml.as_bytes()).unwrap() {
+            BcXml {
+                alarm_event_list:
+                    Some(AlarmEventList {
+                        mut alarm_events, ..
+                    }),
+                ..
+            } if alarm_events.len() == 1 => alarm_events.remove(0),
+            _ => panic!(),
+        }
+    };
+    let pressed = event("MD,visitor", "none");
+    assert!(is_visitor(&pressed));
+    assert!(is_motion(&pressed));
+
+    // Pressing the button is not motion on its own
+    let pressed = event("none, visitor", "none");
+    assert!(is_visitor(&pressed));
+    assert!(!is_motion(&pressed));
+
+    let motion = event("MD", "none");
+    assert!(!is_visitor(&motion));
+    assert!(is_motion(&motion));
+
+    let person = event("none", "people");
+    assert!(!is_visitor(&person));
+    assert!(is_motion(&person));
+
+    let idle = event("none", "none");
+    assert!(!is_visitor(&idle));
+    assert!(!is_motion(&idle));
+}
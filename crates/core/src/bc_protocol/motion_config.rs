@@ -0,0 +1,92 @@
+//! Reads and writes the motion detection sensitivity and zones
+//!
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [Md] xml of the camera
+    pub async fn get_md_alarm(&self) -> Result<Md> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_MD_ALARM, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_MD_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload: Some(BcPayloads::BcXml(BcXml { md: Some(md), .. })),
+            ..
+        }) = msg.body
+        {
+            Ok(md)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected MD xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the [Md] xml of the camera
+    ///
+    /// Get it with `get_md_alarm` first and change it so that the
+    /// schedule and alarm actions are kept
+    pub async fn set_md_alarm(&self, md: Md) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_MD_ALARM, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_MD_ALARM,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    md: Some(md),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+//! Controls the text drawn over the video
+//!
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [OsdChannelName] and [OsdDatetime] xml of the camera
+    pub async fn get_osd(&self) -> Result<(OsdChannelName, OsdDatetime)> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_OSD, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(channel_name),
+                    osd_datetime: Some(datetime),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok((channel_name, datetime))
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected OsdChannelName and OsdDatetime xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the [OsdChannelName] and [OsdDatetime] xml of the camera
+    ///
+    /// Get them with `get_osd` first and change them so that the
+    /// positions are kept
+    pub async fn set_osd(&self, channel_name: OsdChannelName, datetime: OsdDatetime) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_OSD, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(channel_name),
+                    osd_datetime: Some(datetime),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Send messages that are not otherwise supported
+
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Send a message with any `msg_id` and an XML body as is and return the XML of the reply
+    ///
+    /// The XML is not checked and is sent without an XML declaration if not
+    /// given one. This is for camera features that are not otherwise supported,
+    /// prefer the other methods when one exists.
+    pub async fn send_raw_xml(&self, msg_id: u32, xml: &str) -> Result<String> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(msg_id, msg_num).await?;
+
+        let msg = Bc {
+            meta: BcMeta {
+                msg_id,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                // An empty body is sent the same as no payload
+                payload: Some(BcPayloads::RawXml(xml.to_string())),
+            }),
+        };
+
+        sub.send(msg).await?;
+        let msg = sub.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload: Some(BcPayloads::RawXml(xml)),
+                ..
+            }) => Ok(xml),
+            BcBody::ModernMsg(ModernMsg { payload: None, .. }) => Ok(String::new()),
+            _ => Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected a raw xml reply",
+            }),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Search and download of the recordings on the SD card
+
+use super::{BcCamera, Error, Result, StreamData, StreamKind};
+use crate::bc::{model::*, xml::*};
+use futures::stream::StreamExt;
+use tokio::sync::mpsc::channel;
+use tokio::task;
+use tokio_util::sync::CancellationToken;
+
+impl BcCamera {
+    /// Search the SD card for the recordings between start and end
+    pub async fn replay_file_list(
+        &self,
+        stream: StreamKind,
+        start: ReplayTime,
+        end: ReplayTime,
+    ) -> Result<Vec<FileInfo>> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection
+            .subscribe(MSG_ID_REPLAY_FILE_LIST, msg_num)
+            .await?;
+        let search = Bc::new_from_xml(
+            BcMeta {
+                msg_id: MSG_ID_REPLAY_FILE_LIST,
+                channel_id: self.channel_id,
+                msg_num,
+                stream_type: 0,
+                response_code: 0,
+                class: 0x6414,
+            },
+            BcXml {
+                file_info_list: Some(FileInfoList {
+                    version: xml_ver(),
+                    file_info: vec![FileInfo {
+                        channel_id: self.channel_id,
+                        stream_type: Some(stream.to_string()),
+                        start_time: Some(start),
+                        end_time: Some(end),
+                        ..Default::default()
+                    }],
+                }),
+                ..Default::default()
+            },
+        );
+
+        sub.send(search).await?;
+        let msg = sub.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        file_info_list: Some(file_info_list),
+                        ..
+                    })),
+                ..
+            }) => Ok(file_info_list.file_info),
+            // Nothing was recorded in that time
+            BcBody::ModernMsg(ModernMsg { payload: None, .. }) => Ok(vec![]),
+            _ => Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected FileInfoList xml but it was not recieved",
+            }),
+        }
+    }
+
+    /// Download a recording that was found with [`BcCamera::replay_file_list`]
+    ///
+    /// The recording is sent as BcMedia packets like a live stream. When the
+    /// returned object is dropped the download is stopped
+    pub async fn replay_download(
+        &self,
+        file: FileInfo,
+        mut buffer_size: usize,
+    ) -> Result<StreamData> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let channel_id = self.channel_id;
+
+        let abort_handle = CancellationToken::new();
+        let abort_handle_thread = abort_handle.clone();
+
+        if buffer_size == 0 {
+            buffer_size = 100;
+        }
+        let (tx, rx) = channel(buffer_size);
+
+        let handle = task::spawn(async move {
+            let mut sub_download = connection
+                .subscribe(MSG_ID_REPLAY_DOWNLOAD, msg_num)
+                .await?;
+            let request = |msg_id| {
+                Bc::new_from_xml(
+                    BcMeta {
+                        msg_id,
+                        channel_id,
+                        msg_num,
+                        stream_type: 0,
+                        response_code: 0,
+                        class: 0x6414,
+                    },
+                    BcXml {
+                        file_info_list: Some(FileInfoList {
+                            version: xml_ver(),
+                            file_info: vec![file.clone()],
+                        }),
+                        ..Default::default()
+                    },
+                )
+            };
+
+            sub_download.send(request(MSG_ID_REPLAY_DOWNLOAD)).await?;
+            let msg = sub_download.recv().await?;
+            if msg.meta.response_code != 200 {
+                return Err(Error::UnintelligibleReply {
+                    reply: std::sync::Arc::new(Box::new(msg)),
+                    why: "The camera did not accept the download command.",
+                });
+            }
+
+            {
+                let mut media_sub = sub_download.bcmedia_stream(false);
+
+                tokio::select! {
+                    _ = abort_handle_thread.cancelled() => {},
+                    _ = async {
+                        while let Some(bc_media) = media_sub.next().await {
+                            if tx.send(bc_media).await.is_err() {
+                                break; // Connection dropped
+                            }
+                        }
+                    } => {}
+                }
+            }
+
+            let mut sub_stop = connection.subscribe(MSG_ID_REPLAY_STOP, msg_num).await?;
+            sub_stop.send(request(MSG_ID_REPLAY_STOP)).await?;
+            // Some cameras do not reply to the stop
+            let _ =
+                tokio::time::timeout(tokio::time::Duration::from_secs(2), sub_stop.recv()).await;
+
+            Ok(())
+        });
+
+        Ok(StreamData {
+            handle: Some(handle),
+            rx,
+            abort_handle,
+        })
+    }
+}
@@ -1,4 +1,4 @@
-//! Trigger for the siren
+//! Trigger for the siren and the doorbell quick replies
 
 use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
@@ -6,6 +6,47 @@ use crate::bc::{model::*, xml::*};
 impl BcCamera {
     /// Trigger the siren
     pub async fn siren(&self) -> Result<()> {
+        self.play_audio(AudioPlayInfo {
+            channel_id: self.channel_id,
+            play_mode: 0,
+            play_duration: 0,
+            play_times: 1,
+            on_off: 0,
+            file_id: None,
+        })
+        .await
+    }
+
+    /// Turn the siren on until it is turned off again
+    pub async fn siren_manual(&self, on: bool) -> Result<()> {
+        self.play_audio(AudioPlayInfo {
+            channel_id: self.channel_id,
+            play_mode: 1,
+            play_duration: 0,
+            play_times: 0,
+            on_off: match on {
+                true => 1,
+                false => 0,
+            },
+            file_id: None,
+        })
+        .await
+    }
+
+    /// Play one of the quick reply messages stored on a doorbell
+    pub async fn quick_reply(&self, file_id: u32) -> Result<()> {
+        self.play_audio(AudioPlayInfo {
+            channel_id: self.channel_id,
+            play_mode: 0,
+            play_duration: 0,
+            play_times: 1,
+            on_off: 0,
+            file_id: Some(file_id),
+        })
+        .await
+    }
+
+    async fn play_audio(&self, audio_play_info: AudioPlayInfo) -> Result<()> {
         let connection = self.get_connection();
         let msg_num = self.new_message_num();
         let mut sub_get = connection.subscribe(MSG_ID_PLAY_AUDIO, msg_num).await?;
@@ -24,13 +65,7 @@ impl BcCamera {
                     ..Default::default()
                 }),
                 payload: Some(BcPayloads::BcXml(BcXml {
-                    audio_play_info: Some(AudioPlayInfo {
-                        channel_id: self.channel_id,
-                        play_mode: 0,
-                        play_duration: 0,
-                        play_times: 1,
-                        on_off: 0,
-                    }),
+                    audio_play_info: Some(audio_play_info),
                     ..Default::default()
                 })),
             }),
@@ -0,0 +1,92 @@
+//! SD card and disk methods
+//!
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [HddInfoList] xml with the SD cards or disks of the camera
+    pub async fn get_hdd_info(&self) -> Result<HddInfoList> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_HDD_INFO, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_HDD_INFO,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    hdd_info_list: Some(hdd_info_list),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(hdd_info_list)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected HddInfoList xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Format the SD card or disk with this `number` from the [HddInfo]
+    ///
+    /// **This erases all of the recordings on it**
+    pub async fn format_hdd(&self, number: u8) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_FORMAT_HDD, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_FORMAT_HDD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: None,
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    hdd_init_list: Some(HddInitList {
+                        version: xml_ver(),
+                        hdd_init: vec![HddInit { init_id: number }],
+                    }),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,9 @@ use crate::{
     bc::{model::*, xml::*},
     bcmedia::model::*,
 };
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use tokio::sync::mpsc::{channel, Receiver};
 use tokio::task::{self, JoinHandle};
 use tokio_util::sync::CancellationToken;
@@ -38,9 +40,9 @@ impl std::fmt::Display for StreamKind {
 ///
 /// When this object is dropped the streaming is stopped
 pub struct StreamData {
-    handle: Option<JoinHandle<Result<()>>>,
-    rx: Receiver<Result<BcMedia>>,
-    abort_handle: CancellationToken,
+    pub(super) handle: Option<JoinHandle<Result<()>>>,
+    pub(super) rx: Receiver<Result<BcMedia>>,
+    pub(super) abort_handle: CancellationToken,
 }
 
 impl StreamData {
@@ -77,6 +79,23 @@ impl StreamData {
     }
 }
 
+/// The media of the stream until it finishes
+///
+/// Unlike `get_data` an error of the background task only ends the stream
+impl Stream for StreamData {
+    type Item = Result<BcMedia>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(None) => {
+                self.abort_handle.cancel();
+                Poll::Ready(None)
+            }
+            v => v,
+        }
+    }
+}
+
 impl Drop for StreamData {
     fn drop(&mut self) {
         log::trace!("Drop StreamData");
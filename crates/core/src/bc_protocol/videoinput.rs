@@ -0,0 +1,78 @@
+//! The mirror and flip of the picture
+//!
+//! These are in the `<InputAdvanceCfg>` of the video input settings along
+//! with the exposure and day night settings that are not modelled here. So
+//! that those are kept, the xml that the camera gives is edited and sent
+//! back as it is.
+use super::{BcCamera, Error, Result};
+use crate::bc::model::*;
+
+impl BcCamera {
+    /// Whether the picture is mirrored (left to right) and flipped (upside down)
+    pub async fn get_mirror_flip(&self) -> Result<(bool, bool)> {
+        let xml = self.send_raw_xml(MSG_ID_GET_VIDEO_INPUT, "").await?;
+        let mirror = element_text(&xml, "mirror");
+        let flip = element_text(&xml, "flip");
+        match (mirror, flip) {
+            (Some(mirror), Some(flip)) => Ok((mirror.trim() == "1", flip.trim() == "1")),
+            _ => Err(Error::Other("The camera has no mirror or flip setting")),
+        }
+    }
+
+    /// Mirror (left to right) and flip (upside down) the picture
+    ///
+    /// The other video input settings are left as they are
+    pub async fn set_mirror_flip(&self, mirror: bool, flip: bool) -> Result<()> {
+        let xml = self.send_raw_xml(MSG_ID_GET_VIDEO_INPUT, "").await?;
+        let xml = with_element_text(&xml, "mirror", if mirror { "1" } else { "0" })
+            .and_then(|xml| with_element_text(&xml, "flip", if flip { "1" } else { "0" }))
+            .ok_or(Error::Other("The camera has no mirror or flip setting"))?;
+        self.send_raw_xml(MSG_ID_SET_VIDEO_INPUT, &xml).await?;
+        Ok(())
+    }
+}
+
+/// The range of the text of the first `<name>` element
+pub(super) fn element_range(xml: &str, name: &str) -> Option<std::ops::Range<usize>> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(start..end)
+}
+
+pub(super) fn element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    element_range(xml, name).map(|range| &xml[range])
+}
+
+pub(super) fn with_element_text(xml: &str, name: &str, text: &str) -> Option<String> {
+    let range = element_range(xml, name)?;
+    let mut edited = xml.to_string();
+    edited.replace_range(range, text);
+    Some(edited)
+}
+
+#[test]
+fn test_mirror_flip_xml() {
+    let xml = concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<body>\n",
+        "<VideoInput version=\"1.1\">\n<channelId>0</channelId>\n<bright>128</bright>\n</VideoInput>\n",
+        "<InputAdvanceCfg version=\"1.1\">\n<channelId>0</channelId>\n",
+        "<DayNight>\n<mode>auto</mode>\n</DayNight>\n",
+        "<mirror>0</mirror>\n<flip>1</flip>\n",
+        "</InputAdvanceCfg>\n</body>\n"
+    );
+    assert_eq!(element_text(xml, "mirror"), Some("0"));
+    assert_eq!(element_text(xml, "flip"), Some("1"));
+
+    let edited = with_element_text(xml, "mirror", "1").unwrap();
+    assert_eq!(element_text(&edited, "mirror"), Some("1"));
+    // Everything else is kept
+    assert_eq!(
+        edited.replace("<mirror>1</mirror>", "<mirror>0</mirror>"),
+        xml
+    );
+
+    assert_eq!(element_text(xml, "rotation"), None);
+    assert_eq!(with_element_text(xml, "rotation", "1"), None);
+}
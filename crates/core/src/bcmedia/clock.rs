@@ -0,0 +1,156 @@
+//! Presentation times for a stream of [`BcMedia`]
+//!
+//! The video frames carry the camera's own microsecond clock. Using that
+//! rather than the time that a frame arrived keeps the spacing of the frames
+//! through network jitter. The audio has no time of its own so it follows on
+//! from its durations and is pulled back to the video if they drift apart.
+//!
+//! The camera clock is a `u32` so it wraps about every 71 minutes, it can also
+//! jump when the camera restarts its encoder. Jumps that are backwards or too
+//! large are read as one frame so that the times never go backwards.
+
+use super::model::*;
+
+/// Longest gap between two frames that is taken as real
+const MAX_FRAME_GAP: u64 = 2_000_000;
+
+/// How far the audio may get from the video before it is moved back to it
+const MAX_AV_DRIFT: u64 = 1_000_000;
+
+/// Turns the camera timestamps into times from the start of the stream
+#[derive(Debug, Clone)]
+pub struct MediaClock {
+    /// Microseconds of one frame at the stream fps
+    frame_duration: u64,
+    /// The camera timestamp of the last video frame
+    last_camera: Option<u32>,
+    /// The time of the last video frame
+    video: u64,
+    /// The time of the next audio
+    audio: u64,
+}
+
+impl MediaClock {
+    /// A clock for a stream at this fps
+    pub fn new(fps: u32) -> Self {
+        Self {
+            frame_duration: Self::frame_duration(fps),
+            last_camera: None,
+            video: 0,
+            audio: 0,
+        }
+    }
+
+    fn frame_duration(fps: u32) -> u64 {
+        1_000_000 / fps.max(1) as u64
+    }
+
+    /// Used for the gaps that cannot be trusted
+    pub fn set_fps(&mut self, fps: u32) {
+        self.frame_duration = Self::frame_duration(fps);
+    }
+
+    /// Start again from this time in microseconds such as after a seek
+    pub fn seek(&mut self, micros: u64) {
+        self.last_camera = None;
+        self.video = micros;
+        self.audio = micros;
+    }
+
+    /// The time in microseconds of a video frame
+    pub fn video(&mut self, camera_micros: u32) -> u64 {
+        if let Some(last) = self.last_camera {
+            let gap = camera_micros.wrapping_sub(last) as u64;
+            let gap = if gap == 0 || gap > MAX_FRAME_GAP {
+                log::debug!(
+                    "Camera clock jumped from {last} to {camera_micros}, using one frame instead"
+                );
+                self.frame_duration
+            } else {
+                gap
+            };
+            self.video += gap;
+        }
+        self.last_camera = Some(camera_micros);
+        self.video
+    }
+
+    /// The time in microseconds of some audio that lasts `duration`
+    pub fn audio(&mut self, duration: u32) -> u64 {
+        if self.audio.abs_diff(self.video) > MAX_AV_DRIFT {
+            log::debug!(
+                "Audio drifted {}ms from the video, moving it back",
+                (self.audio as i64 - self.video as i64) / 1000
+            );
+            self.audio = self.video;
+        }
+        let at = self.audio;
+        self.audio += duration as u64;
+        at
+    }
+
+    /// The time of a video frame or audio
+    ///
+    /// None for media that has no time such as the info packets or audio whose
+    /// duration cannot be read
+    pub fn media(&mut self, media: &BcMedia) -> Option<u64> {
+        match media {
+            BcMedia::Iframe(BcMediaIframe { microseconds, .. })
+            | BcMedia::Pframe(BcMediaPframe { microseconds, .. }) => {
+                Some(self.video(*microseconds))
+            }
+            BcMedia::Aac(aac) => aac.duration().map(|duration| self.audio(duration)),
+            BcMedia::Adpcm(adpcm) => adpcm.duration().map(|duration| self.audio(duration)),
+            BcMedia::InfoV1(_) | BcMedia::InfoV2(_) => None,
+        }
+    }
+}
+
+#[test]
+fn test_media_clock_follows_camera() {
+    let mut clock = MediaClock::new(25);
+    assert_eq!(clock.video(1_000_000), 0);
+    // Late frames keep the spacing of the camera
+    assert_eq!(clock.video(1_040_000), 40_000);
+    assert_eq!(clock.video(1_090_000), 90_000);
+}
+
+#[test]
+fn test_media_clock_wraps() {
+    let mut clock = MediaClock::new(25);
+    assert_eq!(clock.video(u32::MAX - 9_999), 0);
+    assert_eq!(clock.video(30_000), 40_000);
+}
+
+#[test]
+fn test_media_clock_discontinuity() {
+    let mut clock = MediaClock::new(20);
+    assert_eq!(clock.video(5_000_000), 0);
+    // Backwards is one frame on
+    assert_eq!(clock.video(1_000), 50_000);
+    // As is a large jump forwards
+    assert_eq!(clock.video(100_000_000), 100_000);
+    assert_eq!(clock.video(100_050_000), 150_000);
+}
+
+#[test]
+fn test_media_clock_audio_drift() {
+    let mut clock = MediaClock::new(25);
+    clock.video(0);
+    assert_eq!(clock.audio(64_000), 0);
+    assert_eq!(clock.audio(64_000), 64_000);
+    // The video moves on without any audio
+    for n in 1..=50 {
+        clock.video(n * 40_000);
+    }
+    assert_eq!(clock.audio(64_000), 2_000_000);
+}
+
+#[test]
+fn test_media_clock_seek() {
+    let mut clock = MediaClock::new(25);
+    clock.video(1_000);
+    clock.seek(10_000_000);
+    assert_eq!(clock.video(500), 10_000_000);
+    assert_eq!(clock.audio(1_000), 10_000_000);
+}
@@ -1,3 +1,5 @@
+/// Presentation times from the camera timestamps
+pub mod clock;
 pub(crate) mod codex;
 /// Deserlizer for BCMedia
 pub mod de;
@@ -6,7 +6,27 @@
 //!
 //! Most high level camera controls are in the [`bc_protocol`] module
 //!
-//! A camera can be initialised with
+//! A camera can be connected to and logged into with
+//!
+//! ```no_run
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! use neolink_core::bc_protocol::{BcCamera, BcCameraOpt, Credentials};
+//! let options = BcCameraOpt {
+//!     name: "CamName".to_string(),
+//!     addrs: vec!["192.168.1.1".parse().unwrap()],
+//!     uid: Some("CAMUID".to_string()),
+//!     credentials: Credentials {
+//!         username: "username".to_string(),
+//!         password: Some("password".to_string()),
+//!     },
+//!     ..Default::default()
+//! };
+//! let camera = BcCamera::connect(&options).await.unwrap();
+//! # })
+//! ```
+//!
+//! All the options can also be given, such as to only use some of the
+//! discovery methods
 //!
 //! ```no_run
 //! # tokio::runtime::Runtime::new().unwrap().block_on(async {
@@ -25,37 +45,46 @@
 //!     },
 //!     debug: false,
 //!     max_discovery_retries: 10,
+//!     udp: Default::default(),
+//!     proxy: None,
 //! };
 //! let mut camera = BcCamera::new(&options).await.unwrap();
+//! camera.login().await.unwrap();
 //! # })
 //! ```
 //!
-//! After that login can be conducted with
+//! The video and audio of a stream are a [`futures::Stream`] of
+//! [`bcmedia::model::BcMedia`] and the commands are methods of the camera
 //!
 //! ```no_run
 //! # tokio::runtime::Runtime::new().unwrap().block_on(async {
-//! # use neolink_core::bc_protocol::{BcCamera, BcCameraOpt, DiscoveryMethods, ConnectionProtocol, Credentials};
-//! # let options = BcCameraOpt {
-//! #    name: "CamName".to_string(),
-//! #    channel_id: 0,
-//! #    addrs: ["192.168.1.1".parse().unwrap()].to_vec(),
-//! #    port: Some(9000),
-//! #    uid: Some("CAMUID".to_string()),
-//! #    protocol: ConnectionProtocol::TcpUdp,
-//! #    discovery: DiscoveryMethods::Relay,
-//! #    credentials: Credentials {
-//! #        username: "username".to_string(),
-//! #        password: Some("password".to_string()),
-//! #    },
-//! #    debug: false,
-//! #    max_discovery_retries: 10,
-//! # };
-//! # let mut camera = BcCamera::new(&options).await.unwrap();
-//! camera.login().await;
+//! use futures::StreamExt;
+//! use neolink_core::bc_protocol::{BcCamera, BcCameraOpt, StreamKind};
+//! use neolink_core::bcmedia::model::BcMedia;
+//! # let options = BcCameraOpt::default();
+//! let camera = BcCamera::connect(&options).await.unwrap();
+//! println!("{:?}", camera.version().await.unwrap());
+//!
+//! let mut stream = camera.start_video(StreamKind::Main, 0, false).await.unwrap();
+//! while let Some(media) = stream.next().await {
+//!     if let BcMedia::Iframe(frame) = media.unwrap() {
+//!         println!("Keyframe of {} bytes", frame.data.len());
+//!     }
+//! }
 //! # })
 //! ```
 //! For further commands see the [`bc_protocol::BcCamera`] struct.
 //!
+//! ## Stability
+//!
+//! The [`bc_protocol`] module is the public api of this crate and follows
+//! semver, construct [`bc_protocol::BcCameraOpt`] with `..Default::default()`
+//! so that new options are not a breaking change. The [`bc`], [`bcmedia`] and
+//! [`bcudp`] modules are the wire formats of the camera, their xml
+//! structures grow as new cameras are supported and may change between
+//! minor versions until 1.0. The [`bcmedia::model::BcMedia`] that a stream
+//! yields is kept stable.
+//!
 
 /// Contains low level BC structures and formats
 pub mod bc;
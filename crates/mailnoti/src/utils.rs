@@ -94,6 +94,8 @@ impl AddressOrUid {
                 password: camera_config.password.clone(),
             },
             debug: false,
+            udp: Default::default(),
+            proxy: None,
         };
 
         trace!("Camera Info: {:?}", options);
@@ -1,8 +1,12 @@
+use crate::cmdline::CameraSelection;
 use clap::Parser;
 
 /// The battery command will dump the battery status to XML
 #[derive(Parser, Debug)]
 pub struct Opt {
     /// The name of the camera. Must be a name in the config
-    pub camera: String,
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
 }
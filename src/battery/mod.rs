@@ -14,7 +14,10 @@ use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    utils::for_each_camera,
+};
 
 pub(crate) use cmdline::Opt;
 
@@ -22,8 +25,10 @@ pub(crate) use cmdline::Opt;
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
+    for_each_camera(&reactor, opt.camera.as_deref(), &opt.select, camera_main).await
+}
 
+async fn camera_main(camera: NeoInstance) -> Result<String> {
     let state = camera
         .run_task(|cam| {
             Box::pin(async move {
@@ -40,7 +45,6 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         buf.to_vec()
     })
     .expect("Should be UTF8");
-    println!("{}", ser);
 
-    Ok(())
+    Ok(ser)
 }
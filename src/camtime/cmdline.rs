@@ -0,0 +1,16 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The time command will print the camera clock and how far it is from ours
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Set the camera clock to now
+    #[arg(long)]
+    pub sync: bool,
+}
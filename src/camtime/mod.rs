@@ -0,0 +1,62 @@
+///
+/// # Neolink Time
+///
+/// This module prints and sets the camera clock
+///
+/// # Usage
+///
+/// ```bash
+/// # Print the camera time and how far it is from this machine's
+/// neolink time --config=config.toml CameraName
+/// # Set the camera time to now
+/// neolink time --config=config.toml CameraName --sync
+/// ```
+///
+use anyhow::{Context, Result};
+use std::time::SystemTime;
+
+mod cmdline;
+
+use crate::{
+    common::{camera_time_drift, NeoReactor},
+    utils::for_each_camera,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the time subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let sync = opt.sync;
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        if sync {
+                            cam.set_time(SystemTime::now().into())
+                                .await
+                                .context("Unable to set the camera time")?;
+                        }
+                        let time = cam
+                            .get_time()
+                            .await
+                            .context("Unable to get the camera time")?;
+                        Ok(match time {
+                            Some(time) => format!(
+                                "{} ({:.1}s off)",
+                                time,
+                                camera_time_drift(time.into()).as_secs_f64()
+                            ),
+                            None => "The camera time is not set".to_string(),
+                        })
+                    })
+                })
+                .await
+        },
+    )
+    .await
+}
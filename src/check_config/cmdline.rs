@@ -0,0 +1,10 @@
+use clap::Parser;
+
+/// The check-config command parses and validates the config, it exits
+/// with an error when there are problems
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Also connect and log into each enabled camera
+    #[arg(long)]
+    pub connect: bool,
+}
@@ -0,0 +1,190 @@
+///
+/// # Neolink Check Config
+///
+/// This module handles the check-config subcommand
+///
+/// The subcommand reads the config the same way as the other commands and
+/// then looks for the problems that would otherwise only show later:
+///
+/// - Keys that are not used, such as a misspelt setting that is silently
+///   ignored
+/// - Cameras or users with the same name, the cameras would share the
+///   same rtsp paths and output directories
+/// - Optionally that each enabled camera can be connected to and logged into
+///
+/// All the problems are printed and it exits with an error if there are any.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink check-config --config=config.toml
+/// neolink check-config --config=config.toml --connect
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tokio::time::{timeout, Duration};
+use validator::Validate;
+
+mod cmdline;
+
+use crate::{
+    config::{expand_toml, Config},
+    utils::{connect_and_login, file_safe_name},
+};
+pub(crate) use cmdline::Opt;
+
+/// How long each camera has to connect and log in
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One step into the toml document
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Entry point for the check-config subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: &Opt, conf_path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(conf_path)
+        .with_context(|| format!("Failed to read {:?}", conf_path))?;
+    let config = Config::from_toml(&text)
+        .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
+
+    let mut problems = vec![];
+    if let Err(e) = config.validate() {
+        problems.push(format!("Invalid settings: {e}"));
+    }
+    for key in unknown_keys(&text, &config)? {
+        problems.push(format!("Unknown key `{key}`, it is ignored"));
+    }
+    problems.extend(duplicate_names(&config));
+
+    if opt.connect {
+        for camera_config in config.cameras.iter().filter(|cam| cam.enabled) {
+            match timeout(CONNECT_TIMEOUT, connect_and_login(camera_config)).await {
+                Ok(Ok(camera)) => {
+                    println!("{}: Connected and logged in", camera_config.name);
+                    let _ = camera.logout().await;
+                }
+                Ok(Err(e)) => problems.push(format!("{}: {:?}", camera_config.name, e)),
+                Err(_) => problems.push(format!(
+                    "{}: Could not connect within {}s",
+                    camera_config.name,
+                    CONNECT_TIMEOUT.as_secs()
+                )),
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{:?} is OK", conf_path);
+        Ok(())
+    } else {
+        for problem in problems.iter() {
+            println!("{problem}");
+        }
+        Err(anyhow!(
+            "Found {} problems in {:?}",
+            problems.len(),
+            conf_path
+        ))
+    }
+}
+
+/// The keys in the file that the config does not use
+///
+/// Serde skips over keys that it does not know. To find them each key in
+/// turn is given a value that no setting accepts, if the config is still
+/// read and is unchanged then nothing used that key.
+fn unknown_keys(text: &str, config: &Config) -> Result<Vec<String>> {
+    let mut doc: toml::Value = toml::from_str(text)?;
+    expand_toml(&mut doc)?;
+    let mut unknown = vec![];
+    find_unknown(&doc, &doc, &mut vec![], config, &mut unknown);
+    Ok(unknown)
+}
+
+fn find_unknown(
+    doc: &toml::Value,
+    value: &toml::Value,
+    path: &mut Vec<Step>,
+    config: &Config,
+    unknown: &mut Vec<String>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                path.push(Step::Key(key.clone()));
+                if is_ignored(doc, path, config) {
+                    unknown.push(path_name(path));
+                } else {
+                    find_unknown(doc, value, path, config, unknown);
+                }
+                path.pop();
+            }
+        }
+        toml::Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                path.push(Step::Index(i));
+                find_unknown(doc, value, path, config, unknown);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_ignored(doc: &toml::Value, path: &[Step], config: &Config) -> bool {
+    let mut doc = doc.clone();
+    let mut value = &mut doc;
+    for step in path.iter() {
+        let next = match step {
+            Step::Key(key) => value.get_mut(key.as_str()),
+            Step::Index(i) => value.get_mut(*i),
+        };
+        match next {
+            Some(next) => value = next,
+            None => return false,
+        }
+    }
+    *value = toml::Value::Datetime("1970-01-01T00:00:00Z".parse().unwrap());
+    matches!(doc.try_into::<Config>(), Ok(changed) if changed == *config)
+}
+
+fn path_name(path: &[Step]) -> String {
+    let mut name = String::new();
+    for step in path.iter() {
+        match step {
+            Step::Key(key) if name.is_empty() => name.push_str(key),
+            Step::Key(key) => name.push_str(&format!(".{key}")),
+            Step::Index(i) => name.push_str(&format!("[{i}]")),
+        }
+    }
+    name
+}
+
+/// Cameras and users that have the same name
+fn duplicate_names(config: &Config) -> Vec<String> {
+    let mut problems = vec![];
+    let mut cameras: HashMap<String, &str> = HashMap::new();
+    for camera in config.cameras.iter() {
+        // The directories of the recordings, hls and timelapse use the file
+        // safe name so those must differ as well
+        if let Some(other) = cameras.insert(file_safe_name(&camera.name), &camera.name) {
+            problems.push(format!(
+                "Cameras `{}` and `{}` have the same name, their rtsp paths and directories would clash",
+                other, camera.name
+            ));
+        }
+    }
+    let mut users = HashSet::new();
+    for user in config.users.iter() {
+        if !users.insert(&user.name) {
+            problems.push(format!("User `{}` is given more than once", user.name));
+        }
+    }
+    problems
+}
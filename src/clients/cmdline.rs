@@ -0,0 +1,9 @@
+use clap::Parser;
+
+/// The clients command will list the rtsp clients connected to a running
+/// `neolink rtsp` through its control socket
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Only list the clients of this camera. Must be a name in the config
+    pub camera: Option<String>,
+}
@@ -0,0 +1,69 @@
+///
+/// # Neolink Clients
+///
+/// This module handles the clients subcommand
+///
+/// The subcommand asks a running `neolink rtsp` which rtsp clients are
+/// connected to it. It talks to the control socket set by `control_addr`
+/// in the config and never connects to the camera itself.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink clients --config=config.toml [CameraName]
+/// ```
+///
+use anyhow::{anyhow, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod cmdline;
+
+use crate::common::{ControlReply, ControlRequest, ControlSocket, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the clients subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    let control = ControlSocket::from_config(&config)
+        .ok_or_else(|| anyhow!("`control_addr` must be set in the config to list clients"))?;
+
+    let ControlReply::Clients { mut clients } = control.query(&ControlRequest::Clients).await?
+    else {
+        return Err(anyhow!("Unexpected reply from the control socket"));
+    };
+    if let Some(camera) = opt.camera.as_ref() {
+        clients.retain(|client| {
+            client
+                .path
+                .as_deref()
+                .and_then(|path| path.trim_start_matches('/').split('/').next())
+                .is_some_and(|name| name == camera)
+        });
+    }
+
+    println!("{} clients connected", clients.len());
+    if !clients.is_empty() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        println!(
+            "{:<24} {:<12} {:<14} {:<10} PATH",
+            "ADDRESS", "USER", "TRANSPORT", "CONNECTED"
+        );
+        for client in clients.iter() {
+            println!(
+                "{:<24} {:<12} {:<14} {:<10} {}",
+                client.address,
+                client.user.as_deref().unwrap_or("-"),
+                client.transport.as_deref().unwrap_or("-"),
+                format!("{}s", now.saturating_sub(client.connected)),
+                client.path.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -20,9 +20,22 @@ pub enum Command {
     #[cfg(feature = "gstreamer")]
     Rtsp(super::rtsp::Opt),
     StatusLight(super::statusled::Opt),
+    Ir(super::ir::Opt),
+    MotionConfig(super::motion_config::Opt),
+    Osd(super::osd::Opt),
+    Time(super::camtime::Opt),
+    Sdcard(super::sdcard::Opt),
     Reboot(super::reboot::Opt),
     Pir(super::pir::Opt),
+    Floodlight(super::floodlight::Opt),
+    Siren(super::siren::Opt),
+    Quickreply(super::quickreply::Opt),
+    Download(super::download::Opt),
+    Events(super::events::Opt),
     Ptz(super::ptz::Opt),
+    Zoom(super::zoom::Opt),
+    Focus(super::focus::Opt),
+    Tracking(super::tracking::Opt),
     #[cfg(feature = "gstreamer")]
     Talk(super::talk::Opt),
     Mqtt(super::mqtt::Opt),
@@ -30,6 +43,59 @@ pub enum Command {
     MqttRtsp(super::mqtt::Opt),
     #[cfg(feature = "gstreamer")]
     Image(super::image::Opt),
+    #[cfg(feature = "gstreamer")]
+    Record(super::record::Opt),
+    #[command(alias = "status")]
+    Info(super::info::Opt),
     Battery(super::battery::Opt),
     Services(super::services::Opt),
+    ConfigGet(super::config_get::Opt),
+    ConfigSet(super::config_set::Opt),
+    Sessions(super::sessions::Opt),
+    #[cfg(feature = "gstreamer")]
+    Clients(super::clients::Opt),
+    #[cfg(feature = "gstreamer")]
+    Kick(super::kick::Opt),
+    Discover(super::discover::Opt),
+    CheckConfig(super::check_config::Opt),
+}
+
+impl Command {
+    /// Whether this is one of the long running subcommands
+    pub fn is_service(&self) -> bool {
+        #[cfg(feature = "gstreamer")]
+        if matches!(self, Command::Rtsp(_) | Command::MqttRtsp(_)) {
+            return true;
+        }
+        matches!(self, Command::Mqtt(_))
+    }
+}
+
+/// Chooses the cameras that a one shot command runs against
+///
+/// This is flattened into the subcommand options next to their `camera`
+/// argument
+#[derive(clap::Args, Debug)]
+pub struct CameraSelection {
+    /// Run against all the enabled cameras in the config
+    #[arg(long, conflicts_with_all = ["camera", "pattern"])]
+    pub all: bool,
+    /// Run against the enabled cameras whose name matches this glob e.g. `driveway*`.
+    /// Wrap in slashes to use a regex instead e.g. `/^(front|back)$/`
+    #[arg(long = "match", value_name = "GLOB", conflicts_with = "camera")]
+    pub pattern: Option<String>,
+    /// The number of cameras to run against at the same time
+    #[arg(long, default_value_t = 4, value_parser = parse_parallel)]
+    pub parallel: usize,
+    /// Print the result of each camera as json
+    #[arg(long)]
+    pub json: bool,
+}
+
+fn parse_parallel(src: &str) -> Result<usize, String> {
+    match src.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
 }
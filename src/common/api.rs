@@ -0,0 +1,319 @@
+//! Rest api to control the cameras without an mqtt broker
+//!
+//! When `[api]` is in the config the long running subcommands answer
+//!
+//! - `GET /api/cameras` with the cameras, their state and rtsp streams
+//! - `GET /api/cameras/{name}` with one camera
+//! - `GET /api/clients` with the connected rtsp clients, this needs
+//!   `control_addr` and a running `neolink rtsp`
+//! - `GET /api/streams` with the rtsp streams and the bytes sent on each,
+//!   this also needs `control_addr`
+//! - `POST /api/cameras/{name}/control/{control}` with the message as the
+//!   body to run a control
+//!
+//! The controls are the same as the `control/...` mqtt topics e.g.
+//! `POST /api/cameras/Garage/control/ir` with `auto`. There is also
+//! `pause` and `resume` to disconnect the camera and connect it again and
+//! `disable` and `enable` which do the same but the camera stays disconnected
+//! even when something wants to use it, such as for a privacy schedule.
+//! The reply is the text that mqtt would publish, it is a 200 on `OK` and a
+//! 500 on `FAIL`
+//!
+//! The `[cameras.hls]` playlists are also served at
+//! `GET /hls/{name}/index.m3u8`
+//!
+//! When there are `[[users]]` in the config the requests must log in as one
+//! of them with basic auth and only the `admin` users may run controls.
+//! Without users there is no login so the api must be bound to a loopback
+//! address, which is the default. Requests with an `Origin` header which
+//! browsers send on cross site requests may not run controls
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use std::{net::IpAddr, sync::Arc};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    time::{timeout, Duration},
+};
+
+use super::{
+    basic_auth, login_user, percent_decode, read_request, run_control, write_response,
+    write_response_with_headers, ControlReply, ControlRequest, ControlSocket, HttpRequest,
+    NeoCamThreadState, NeoReactor,
+};
+use crate::{
+    config::{ApiConfig, CameraConfig},
+    AnyResult,
+};
+use neolink_core::bc_protocol::StreamKind;
+
+#[derive(Debug, Serialize)]
+struct CameraInfo {
+    name: String,
+    /// Whether the camera is connected right now
+    connected: bool,
+    /// Whether the camera was paused with the api or by `idle_disconnect`
+    paused: bool,
+    /// Whether the camera was disabled with the api or mqtt
+    disabled: bool,
+    /// The rtsp paths of the streams
+    streams: Vec<String>,
+}
+
+struct ApiServer {
+    reactor: NeoReactor,
+}
+
+/// Serve the rest api until an error
+pub(crate) async fn serve_api(config: ApiConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let loopback = config
+        .bind_addr
+        .parse::<IpAddr>()
+        .is_ok_and(|ip| ip.is_loopback())
+        || config.bind_addr == "localhost";
+    if !loopback && reactor.config().await?.borrow().users.is_empty() {
+        return Err(anyhow!(
+            "The api has no login without [[users]] so it will only bind to 127.0.0.1 not {}",
+            config.bind_addr
+        ));
+    }
+    let listener = TcpListener::bind((config.bind_addr.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Could not bind the api server to {}:{}",
+                config.bind_addr, config.port
+            )
+        })?;
+    log::info!(
+        "Rest api at http://{}:{}/api",
+        config.bind_addr,
+        config.port
+    );
+
+    let server = Arc::new(ApiServer { reactor });
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = server.answer(stream).await {
+                log::debug!("Api request from {peer} failed: {e:?}");
+            }
+        });
+    }
+}
+
+impl ApiServer {
+    async fn answer(&self, stream: TcpStream) -> AnyResult<()> {
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let request = timeout(Duration::from_secs(10), read_request(&mut read))
+            .await
+            .map_err(|_| anyhow!("Timed out reading the request"))??;
+
+        if !self.authorised(&request).await? {
+            return write_response(&mut write, 401, "text/plain", "Unauthorized").await;
+        }
+        if let Some((status, content_type, body)) = self.hls_file(&request).await? {
+            // Web players are often on another origin
+            let headers = [("Access-Control-Allow-Origin", "*")];
+            return write_response_with_headers(&mut write, status, content_type, &headers, &body)
+                .await;
+        }
+        let (status, content_type, body) = match self.route(&request).await {
+            Ok(reply) => reply,
+            Err(e) => (500, "text/plain", format!("FAIL: {e:?}")),
+        };
+        write_response(&mut write, status, content_type, &body).await
+    }
+
+    /// Serve the playlists and segments of `[cameras.hls]`
+    ///
+    /// This is None if the request is not for hls
+    async fn hls_file(
+        &self,
+        request: &HttpRequest,
+    ) -> AnyResult<Option<(u16, &'static str, Vec<u8>)>> {
+        let path = percent_decode(&request.path);
+        let parts = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        let (name, file) = match (request.method.as_str(), parts.as_slice()) {
+            ("GET", ["hls", name, file]) => (*name, *file),
+            _ => return Ok(None),
+        };
+        let content_type = if file == "index.m3u8" {
+            "application/vnd.apple.mpegurl"
+        } else if file.ends_with(".ts") && !file.starts_with('.') && !file.contains('\\') {
+            "video/mp2t"
+        } else {
+            return Ok(Some((404, "text/plain", b"Not Found".to_vec())));
+        };
+        let Some(hls) = self.camera(name).await?.and_then(|camera| camera.hls) else {
+            return Ok(Some((404, "text/plain", b"Not Found".to_vec())));
+        };
+        match tokio::fs::read(hls.dir(name).join(file)).await {
+            Ok(data) => Ok(Some((200, content_type, data))),
+            Err(_) => Ok(Some((404, "text/plain", b"Not Found".to_vec()))),
+        }
+    }
+
+    /// Check the basic auth against the users in the config
+    async fn authorised(&self, request: &HttpRequest) -> AnyResult<bool> {
+        let config = self.reactor.config().await?;
+        let users = config.borrow().users.clone();
+        Ok(basic_auth(request, &users))
+    }
+
+    /// Whether the request may run controls on the camera
+    ///
+    /// Without users the api is only on loopback and anyone there may
+    async fn may_control(&self, request: &HttpRequest, camera: &str) -> AnyResult<bool> {
+        if request.headers.contains_key("origin") {
+            return Ok(false);
+        }
+        let config = self.reactor.config().await?;
+        let users = config.borrow().users.clone();
+        Ok(users.is_empty()
+            || login_user(request, &users).is_some_and(|user| user.may_control(camera)))
+    }
+
+    async fn route(&self, request: &HttpRequest) -> AnyResult<(u16, &'static str, String)> {
+        let path = percent_decode(&request.path);
+        let parts = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        let reply = match (request.method.as_str(), parts.as_slice()) {
+            ("GET", ["api", "cameras"]) => {
+                let cameras = self.cameras().await?;
+                let mut infos = vec![];
+                for camera in cameras.iter() {
+                    infos.push(self.camera_info(camera).await);
+                }
+                (200, "application/json", serde_json::to_string(&infos)?)
+            }
+            ("GET", ["api", "cameras", name]) => match self.camera(name).await? {
+                Some(camera) => (
+                    200,
+                    "application/json",
+                    serde_json::to_string(&self.camera_info(&camera).await)?,
+                ),
+                None => not_found(),
+            },
+            ("GET", ["api", "clients"]) => self.query_rtsp(ControlRequest::Clients).await?,
+            ("GET", ["api", "streams"]) => self.query_rtsp(ControlRequest::Streams).await?,
+            ("POST", ["api", "cameras", name, "control", control @ ..]) if !control.is_empty() => {
+                if self.camera(name).await?.is_none() {
+                    return Ok(not_found());
+                }
+                if !self.may_control(request, name).await? {
+                    return Ok((403, "text/plain", "Forbidden".to_string()));
+                }
+                let camera = self.reactor.get(name).await?;
+                let control = control.join("/");
+                let message = request.body.trim();
+                log::info!("{name}: Api control {control} {message}");
+                match run_control(&camera, &control, message).await {
+                    Some(reply) if reply.starts_with("OK") => (200, "text/plain", reply),
+                    Some(reply) => (500, "text/plain", reply),
+                    None => (
+                        404,
+                        "text/plain",
+                        format!("FAIL: Unknown control {control} {message}"),
+                    ),
+                }
+            }
+            ("GET", _) | ("POST", _) => not_found(),
+            _ => (405, "text/plain", "Method Not Allowed".to_string()),
+        };
+        Ok(reply)
+    }
+
+    /// The cameras that the api can control
+    async fn cameras(&self) -> AnyResult<Vec<CameraConfig>> {
+        let config = self.reactor.config().await?;
+        let cameras = config
+            .borrow()
+            .cameras
+            .iter()
+            .filter(|camera| camera.enabled)
+            .cloned()
+            .collect();
+        Ok(cameras)
+    }
+
+    async fn camera(&self, name: &str) -> AnyResult<Option<CameraConfig>> {
+        Ok(self
+            .cameras()
+            .await?
+            .into_iter()
+            .find(|camera| camera.name == name))
+    }
+
+    async fn camera_info(&self, camera: &CameraConfig) -> CameraInfo {
+        let (connected, paused, disabled) = match self.reactor.get(&camera.name).await {
+            Ok(instance) => {
+                let connected = instance.camera().borrow().upgrade().is_some();
+                let state = instance.get_state().await.ok();
+                (
+                    connected,
+                    state == Some(NeoCamThreadState::Disconnected),
+                    state == Some(NeoCamThreadState::Disabled),
+                )
+            }
+            Err(_) => (false, false, false),
+        };
+        let streams = camera
+            .stream
+            .as_stream_kinds()
+            .iter()
+            .map(|stream| {
+                let kind = match stream {
+                    StreamKind::Main => "mainStream",
+                    StreamKind::Sub => "subStream",
+                    StreamKind::Extern => "externStream",
+                };
+                camera
+                    .custom_path(*stream)
+                    .unwrap_or_else(|| format!("/{}/{}", camera.name, kind))
+            })
+            .collect();
+        CameraInfo {
+            name: camera.name.clone(),
+            connected,
+            paused,
+            disabled,
+            streams,
+        }
+    }
+
+    /// Ask the rtsp server for its clients or streams over the control socket
+    async fn query_rtsp(&self, request: ControlRequest) -> AnyResult<(u16, &'static str, String)> {
+        let config = self.reactor.config().await?;
+        let Some(control) = ControlSocket::from_config(&config.borrow()) else {
+            return Ok((
+                404,
+                "text/plain",
+                "FAIL: Set control_addr to ask the rtsp server".to_string(),
+            ));
+        };
+        let body = match control.query(&request).await? {
+            ControlReply::Clients { clients } => serde_json::to_string(&clients)?,
+            ControlReply::Streams { streams } => serde_json::to_string(&streams)?,
+            reply => {
+                return Err(anyhow!(
+                    "Unexpected reply from the control socket: {reply:?}"
+                ))
+            }
+        };
+        Ok((200, "application/json", body))
+    }
+}
+
+fn not_found() -> (u16, &'static str, String) {
+    (404, "text/plain", "Not Found".to_string())
+}
@@ -1,17 +1,76 @@
 use std::sync::{Arc, Weak};
 use tokio::{
-    sync::watch::{Receiver as WatchReceiver, Sender as WatchSender},
+    sync::{
+        watch::{Receiver as WatchReceiver, Sender as WatchSender},
+        Notify,
+    },
     time::{interval, sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{config::CameraConfig, utils::connect_and_login, AnyResult};
-use neolink_core::bc_protocol::BcCamera;
+use super::{SessionLimitReached, SessionRegistry, SESSION_STALE_AFTER};
+use crate::{
+    config::{CameraConfig, EncoderConfig, FailurePolicy, OsdConfig},
+    utils::connect_and_login,
+    AnyResult,
+};
+use anyhow::anyhow;
+use neolink_core::bc_protocol::{BcCamera, StreamKind};
+use rand::Rng;
+use serde::Serialize;
+
+/// Why a camera failed for good
+///
+/// Each has its own exit code from sysexits so that supervisors can tell them
+/// apart from a crash and from each other, e.g. with systemd's
+/// `RestartPreventExitStatus=77` to not retry a wrong password
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CameraFailure {
+    /// The login was refused
+    Auth,
+    /// The camera could not be reached within `max_attempts`
+    Network,
+    /// The camera kept answering with something neolink does not understand
+    Protocol,
+}
+
+impl CameraFailure {
+    fn of(e: &anyhow::Error) -> Self {
+        use neolink_core::Error::*;
+        match e.downcast_ref::<neolink_core::Error>() {
+            Some(CameraLoginFail | AuthFailed | InsecureLogin { .. }) => CameraFailure::Auth,
+            Some(
+                UnintelligibleReply { .. }
+                | UnintelligibleXml { .. }
+                | CameraServiceUnavailable { .. }
+                | UnknownEncryption(_)
+                | NomIncomplete(_)
+                | NomError(_)
+                | MissingAbility { .. },
+            ) => CameraFailure::Protocol,
+            _ => CameraFailure::Network,
+        }
+    }
+
+    pub(crate) fn exit_code(self) -> i32 {
+        match self {
+            // EX_NOPERM
+            CameraFailure::Auth => 77,
+            // EX_TEMPFAIL
+            CameraFailure::Network => 75,
+            // EX_PROTOCOL
+            CameraFailure::Protocol => 76,
+        }
+    }
+}
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub(crate) enum NeoCamThreadState {
     Connected,
     Disconnected,
+    /// Disconnected until it is enabled again, `connect` does not change it
+    Disabled,
 }
 
 pub(crate) struct NeoCamThread {
@@ -19,6 +78,11 @@ pub(crate) struct NeoCamThread {
     config: WatchReceiver<CameraConfig>,
     cancel: CancellationToken,
     camera_watch: WatchSender<Weak<BcCamera>>,
+    /// Drops the current connection so that a new one is made
+    reconnect: Arc<Notify>,
+    sessions: SessionRegistry,
+    /// Set while the camera has failed for good with `failure_policy = "unhealthy"`
+    failure: WatchSender<Option<CameraFailure>>,
 }
 
 impl NeoCamThread {
@@ -26,6 +90,9 @@ impl NeoCamThread {
         watch_state_rx: WatchReceiver<NeoCamThreadState>,
         watch_config_rx: WatchReceiver<CameraConfig>,
         camera_watch_tx: WatchSender<Weak<BcCamera>>,
+        reconnect: Arc<Notify>,
+        sessions: SessionRegistry,
+        failure: WatchSender<Option<CameraFailure>>,
         cancel: CancellationToken,
     ) -> Self {
         Self {
@@ -33,18 +100,46 @@ impl NeoCamThread {
             config: watch_config_rx,
             cancel,
             camera_watch: camera_watch_tx,
+            reconnect,
+            sessions,
+            failure,
         }
     }
     async fn run_camera(&mut self, config: &CameraConfig) -> AnyResult<()> {
         let name = config.name.clone();
         log::trace!("Attempting connection with config: {config:?}");
-        let camera = Arc::new(connect_and_login(config).await?);
+        // Held until we logout so that other neolink processes can see our login
+        let _session = self.sessions.acquire(config.max_own_sessions).await?;
+        let cancel = self.cancel.clone();
+        let camera = tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            v = connect_and_login(config) => Arc::new(v?),
+        };
         log::trace!("  - Connected");
 
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
-        if let Err(e) = update_camera_time(&camera, &name, config.update_time).await {
+        if let Err(e) =
+            update_camera_time(&camera, &name, config.update_time, config.max_time_drift).await
+        {
             log::warn!("Could not set camera time, (perhaps missing on this camera of your login in not an admin): {e:?}");
         }
+        if let Some(osd) = config.osd.as_ref() {
+            if let Err(e) = update_camera_osd(&camera, osd).await {
+                log::warn!("{name}: Could not set the osd: {e:?}");
+            }
+        }
+        for (stream, encoder) in [
+            (StreamKind::Main, config.main_stream.as_ref()),
+            (StreamKind::Sub, config.sub_stream.as_ref()),
+        ]
+        .iter()
+        {
+            if let Some(encoder) = encoder {
+                if let Err(e) = update_camera_encoding(&camera, *stream, encoder).await {
+                    log::warn!("{name}::{stream}: Could not set the encoder: {e:?}");
+                }
+            }
+        }
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
 
         self.camera_watch.send_replace(Arc::downgrade(&camera));
@@ -59,6 +154,7 @@ impl NeoCamThread {
                 v?;
                 Ok(())
             },
+            v = sync_time_every(&camera, config), if config.time_sync_interval.is_some() => v,
             v = async {
                 let mut interval = interval(Duration::from_secs(5));
                 let mut missed_pings = 0;
@@ -105,10 +201,8 @@ impl NeoCamThread {
     // A watch sender is used to send the new camera
     // whenever it changes
     pub(crate) async fn run(&mut self) -> AnyResult<()> {
-        const MAX_BACKOFF: Duration = Duration::from_secs(5);
-        const MIN_BACKOFF: Duration = Duration::from_millis(50);
-
-        let mut backoff = MIN_BACKOFF;
+        let mut backoff = None;
+        let mut attempts = 0u32;
 
         loop {
             self.state
@@ -122,15 +216,20 @@ impl NeoCamThread {
             let name = config.name.clone();
 
             let mut state = self.state.clone();
+            let reconnect = self.reconnect.clone();
 
             let res = tokio::select! {
                 Ok(_) = config_rec.changed() => {
                     None
                 }
-                Ok(_) = state.wait_for(|state| matches!(state, NeoCamThreadState::Disconnected)) => {
+                Ok(_) = state.wait_for(|state| !matches!(state, NeoCamThreadState::Connected)) => {
                     log::trace!("State changed to disconnect");
                     None
                 }
+                _ = reconnect.notified() => {
+                    log::info!("{name}: Reconnecting");
+                    None
+                }
                 v = self.run_camera(&config) => {
                     Some(v)
                 }
@@ -141,19 +240,19 @@ impl NeoCamThread {
                 // If None go back and reload NOW
                 //
                 // This occurs if there was a config change
-                log::trace!("Config change or Manual disconnect");
+                log::trace!("Config change, manual disconnect or reconnect");
                 continue;
             }
 
             // Else we see what the result actually was
             let result = res.unwrap();
 
+            let min_backoff = Duration::from_millis(config.retry_min);
+            let max_backoff = Duration::from_millis(config.retry_max);
             if now.elapsed() > Duration::from_secs(60) {
                 // Command ran long enough to be considered a success
-                backoff = MIN_BACKOFF;
-            }
-            if backoff > MAX_BACKOFF {
-                backoff = MAX_BACKOFF;
+                backoff = None;
+                attempts = 0;
             }
 
             match result {
@@ -167,19 +266,61 @@ impl NeoCamThread {
                     // An error
                     // Check if it is non-retry
                     let e_inner = e.downcast_ref::<neolink_core::Error>();
+                    if let Some(limit) = e.downcast_ref::<SessionLimitReached>() {
+                        // Not a connection problem, wait for one of the other processes
+                        // to release its session or for its entry to go stale
+                        log::warn!("{limit}");
+                        log::info!("{name}: Waiting for a free session");
+                        tokio::select! {
+                            _ = self.cancel.cancelled() => return Ok(()),
+                            _ = sleep(SESSION_STALE_AFTER) => {},
+                        }
+                        continue;
+                    }
                     match e_inner {
                         Some(neolink_core::Error::CameraLoginFail) => {
                             // Fatal
                             log::error!("{name}: Login credentials were not accepted");
-                            self.cancel.cancel();
-                            return Err(e);
+                            let policy = config.failure_policy.unwrap_or(FailurePolicy::Continue);
+                            self.give_up(&name, CameraFailure::Auth, policy, e).await?;
+                            backoff = None;
+                            attempts = 0;
+                        }
+                        Some(neolink_core::Error::InsecureLogin { .. }) => {
+                            // Fatal, retrying would get the same encryption
+                            log::error!("{name}: Refusing to log in: {e:?}");
+                            let policy = config.failure_policy.unwrap_or(FailurePolicy::Continue);
+                            self.give_up(&name, CameraFailure::Auth, policy, e).await?;
+                            backoff = None;
+                            attempts = 0;
                         }
                         _ => {
                             // Non fatal
                             log::warn!("{name}: Connection Lost: {:?}", e);
-                            log::info!("{name}: Attempt reconnect in {:?}", backoff);
-                            sleep(backoff).await;
-                            backoff *= 2;
+                            attempts += 1;
+                            if config.max_attempts.is_some_and(|max| attempts > max) {
+                                log::error!(
+                                    "{name}: Giving up after {} failed reconnects",
+                                    attempts - 1
+                                );
+                                let policy = config.failure_policy.unwrap_or(FailurePolicy::Exit);
+                                self.give_up(&name, CameraFailure::of(&e), policy, e)
+                                    .await?;
+                                backoff = None;
+                                attempts = 0;
+                                continue;
+                            }
+                            let wait = backoff
+                                .map(|backoff: Duration| backoff * 2)
+                                .unwrap_or(min_backoff)
+                                .clamp(min_backoff, max_backoff);
+                            backoff = Some(wait);
+                            let wait = with_jitter(wait, config.jitter);
+                            log::info!("{name}: Attempt reconnect in {:?}", wait);
+                            tokio::select! {
+                                _ = self.cancel.cancelled() => return Ok(()),
+                                _ = sleep(wait) => {},
+                            }
                         }
                     }
                 }
@@ -188,19 +329,119 @@ impl NeoCamThread {
     }
 }
 
+impl NeoCamThread {
+    /// Act on the `failure_policy` once the camera has failed for good
+    ///
+    /// This only returns Ok with `unhealthy` once the config has changed and
+    /// the camera should be tried again
+    async fn give_up(
+        &mut self,
+        name: &str,
+        failure: CameraFailure,
+        policy: FailurePolicy,
+        e: anyhow::Error,
+    ) -> AnyResult<()> {
+        match policy {
+            FailurePolicy::Continue => {
+                self.cancel.cancel();
+                Err(e)
+            }
+            FailurePolicy::Exit => {
+                log::error!(
+                    "{name}: Exiting with code {} after: {e:?}",
+                    failure.exit_code()
+                );
+                std::process::exit(failure.exit_code());
+            }
+            FailurePolicy::Unhealthy => {
+                log::error!("{name}: Marked unhealthy until its config changes");
+                self.failure.send_replace(Some(failure));
+                let mut config = self.config.clone();
+                config.borrow_and_update();
+                let cancel = self.cancel.clone();
+                let res = tokio::select! {
+                    _ = cancel.cancelled() => Err(e),
+                    v = config.changed() => v.map_err(|e| e.into()),
+                };
+                self.failure.send_replace(None);
+                res
+            }
+        }
+    }
+}
+
+/// Change the wait by up to `jitter` of itself so that cameras do not all
+/// reconnect at once
+fn with_jitter(wait: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return wait;
+    }
+    let factor = 1.0 + rand::thread_rng().gen_range(-jitter..=jitter);
+    wait.mul_f64(factor.max(0.0))
+}
+
 impl Drop for NeoCamThread {
     fn drop(&mut self) {
         self.cancel.cancel();
     }
 }
 
-async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) -> AnyResult<()> {
+/// Check the camera time every `time_sync_interval` while it is connected
+async fn sync_time_every(camera: &BcCamera, config: &CameraConfig) -> AnyResult<()> {
+    let every = Duration::from_secs(config.time_sync_interval.unwrap_or(3600));
+    let mut interval = interval(every);
+    // The first tick is now and the time was just checked on connect
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = update_camera_time(
+            camera,
+            &config.name,
+            config.update_time,
+            config.max_time_drift,
+        )
+        .await
+        {
+            log::warn!("{}: Could not sync the camera time: {e:?}", config.name);
+        }
+    }
+}
+
+/// How far the camera clock is from ours
+pub(crate) fn camera_time_drift(time: std::time::SystemTime) -> Duration {
+    let now = std::time::SystemTime::now();
+    now.duration_since(time)
+        .or_else(|_| time.duration_since(now))
+        .unwrap_or_default()
+}
+
+/// Set the camera time to now
+///
+/// This is done when the camera has no time, when it is more than
+/// `max_drift` seconds off or always with `force`
+async fn update_camera_time(
+    camera: &BcCamera,
+    name: &str,
+    force: bool,
+    max_drift: Option<u64>,
+) -> AnyResult<()> {
     let cam_time = camera.get_time().await?;
     let mut update = false;
     if let Some(time) = cam_time {
-        log::info!("{}: Camera time is already set: {}", name, time);
-        if update_time {
+        let drift = camera_time_drift(time.into());
+        if force {
+            log::info!("{}: Camera time is already set: {}", name, time);
             update = true;
+        } else if max_drift.is_some_and(|max| drift > Duration::from_secs(max)) {
+            log::warn!(
+                "{}: Camera time {} is {:?} off, Updating",
+                name,
+                time,
+                drift
+            );
+            update = true;
+        } else {
+            log::info!("{}: Camera time is already set: {}", name, time);
         }
     } else {
         update = true;
@@ -229,3 +470,79 @@ async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) ->
     }
     Ok(())
 }
+
+/// Change the resolution, frame rate and bitrate of a stream to match `encoder`
+///
+/// The settings that are not given are left as they are and the camera is
+/// only told when something changed. A size that the camera does not offer
+/// is refused with the sizes that it does
+pub(crate) async fn update_camera_encoding(
+    camera: &BcCamera,
+    stream: StreamKind,
+    encoder: &EncoderConfig,
+) -> AnyResult<()> {
+    let old_encoding = camera.get_encoding(stream).await?;
+    let mut encoding = old_encoding;
+    if let Some((width, height)) = encoder.size() {
+        // The camera refuses sizes that are not in its table, say which it has
+        let tag = match stream {
+            StreamKind::Main => "mainStream",
+            StreamKind::Sub => "subStream",
+            StreamKind::Extern => "externStream",
+        };
+        if let Ok(info) = camera.get_stream_info().await {
+            let sizes: Vec<String> = info
+                .stream_infos
+                .iter()
+                .flat_map(|info| info.encode_tables.iter())
+                .filter(|table| table.name == tag)
+                .map(|table| format!("{}x{}", table.resolution.width, table.resolution.height))
+                .collect();
+            if !sizes.is_empty() && !sizes.contains(&format!("{width}x{height}")) {
+                return Err(anyhow!(
+                    "The camera does not have a {width}x{height} {stream} stream, it has {}",
+                    sizes.join(", ")
+                ));
+            }
+        }
+        encoding.width = width;
+        encoding.height = height;
+    }
+    if let Some(fps) = encoder.fps {
+        encoding.fps = fps;
+    }
+    if let Some(bitrate) = encoder.bitrate {
+        encoding.bitrate = bitrate;
+    }
+    if encoding != old_encoding {
+        camera.set_encoding(stream, encoding).await?;
+    }
+    Ok(())
+}
+
+/// Change the text drawn over the video to match `osd`
+///
+/// The settings that are not given are left as they are
+pub(crate) async fn update_camera_osd(camera: &BcCamera, osd: &OsdConfig) -> AnyResult<()> {
+    let (mut channel_name, mut datetime) = camera.get_osd().await?;
+    let (old_channel_name, old_datetime) = (channel_name.clone(), datetime.clone());
+    if let Some(name) = osd.name.as_ref() {
+        channel_name.name = name.clone();
+    }
+    if let Some(show) = osd.show_name {
+        channel_name.enable = show as u8;
+    }
+    if let Some(show) = osd.timestamp {
+        datetime.enable = show as u8;
+    }
+    if let Some(show) = osd.watermark {
+        if channel_name.watermark.is_none() {
+            return Err(anyhow!("The camera does not have a watermark setting"));
+        }
+        channel_name.watermark = Some(show as u8);
+    }
+    if channel_name != old_channel_name || datetime != old_datetime {
+        camera.set_osd(channel_name, datetime).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,321 @@
+//! Local control socket of a running neolink process
+//!
+//! When `control_addr` is set in the config the rtsp server listens on it
+//! for simple queries from other neolink processes on this machine such as
+//! `neolink clients` and `neolink kick`. One shot commands such as
+//! `neolink reboot` also send their control through it when it is running so
+//! that they do not need a camera session of their own.
+//!
+//! A `control_addr` that is a path is a unix socket that only this user may
+//! open. Anyone on the machine can connect to a tcp address so each request
+//! also carries the token that the server writes to `control.token` in the
+//! `session_dir`, which only this user can read.
+//!
+//! Each connection carries one request and one reply, both are a single
+//! line of json.
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, path::PathBuf};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::{timeout, Duration},
+};
+
+use crate::{config::Config, AnyResult};
+
+/// A request sent to the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub(crate) enum ControlRequest {
+    /// List the rtsp clients that are connected
+    Clients,
+    /// Disconnect the rtsp clients of a camera that connect from `client`
+    Kick { camera: String, client: String },
+    /// Run one of the `control/...` mqtt messages on a camera
+    Control {
+        camera: String,
+        control: String,
+        message: String,
+    },
+    /// List the rtsp streams with the bytes sent to their clients
+    Streams,
+}
+
+/// The request with the token of the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ControlMessage {
+    token: String,
+    #[serde(flatten)]
+    request: ControlRequest,
+}
+
+/// The reply from the control socket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub(crate) enum ControlReply {
+    Clients {
+        clients: Vec<RtspClientInfo>,
+    },
+    Kicked {
+        count: usize,
+    },
+    /// The `OK` or `FAIL` that mqtt would publish
+    Controlled {
+        reply: String,
+    },
+    Streams {
+        streams: Vec<RtspStreamInfo>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Details of a connected rtsp client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RtspClientInfo {
+    /// The remote address as `ip:port`
+    pub(crate) address: String,
+    /// The user the client logged in as
+    pub(crate) user: Option<String>,
+    /// The path the client requested e.g. `/Garage/subStream`
+    pub(crate) path: Option<String>,
+    /// How the client asked for the media to be sent e.g. `TCP` or `UDP`
+    pub(crate) transport: Option<String>,
+    /// When the client connected in seconds since the unix epoch
+    pub(crate) connected: u64,
+}
+
+/// The bytes sent to the rtsp clients of a stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RtspStreamInfo {
+    pub(crate) camera: String,
+    /// `main`, `sub` or `extern`
+    pub(crate) stream: String,
+    /// The rtsp paths of the stream
+    pub(crate) paths: Vec<String>,
+    /// Bytes of video and audio sent to the clients since neolink started
+    pub(crate) bytes_sent: u64,
+}
+
+trait ControlStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ControlStream for T {}
+
+enum ControlListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl ControlListener {
+    async fn accept(&self) -> std::io::Result<Box<dyn ControlStream>> {
+        match self {
+            ControlListener::Tcp(listener) => Ok(Box::new(listener.accept().await?.0)),
+            #[cfg(unix)]
+            ControlListener::Unix(listener) => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+/// The control socket of the config
+#[derive(Debug, Clone)]
+pub(crate) struct ControlSocket {
+    addr: String,
+    token_file: PathBuf,
+}
+
+impl ControlSocket {
+    /// The socket at `control_addr`, None when it is not set
+    pub(crate) fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            addr: config.control_addr.clone()?,
+            token_file: config.session_dir.join("control.token"),
+        })
+    }
+
+    fn is_unix(&self) -> bool {
+        cfg!(unix) && self.addr.contains('/')
+    }
+
+    /// Listen on the control address and answer requests with the handler
+    ///
+    /// This only returns if the socket cannot be bound, only the rtsp server
+    /// serves it
+    #[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
+    pub(crate) async fn serve<F, Fut>(&self, handler: F) -> AnyResult<()>
+    where
+        F: Fn(ControlRequest) -> Fut + Send + Sync + Clone + 'static,
+        Fut: Future<Output = ControlReply> + Send,
+    {
+        let addr = &self.addr;
+        let token = self.write_token().await?;
+        let listener = self
+            .bind()
+            .await
+            .with_context(|| format!("Could not bind the control socket to {addr}"))?;
+        log::info!("Control socket listening on {addr}");
+        loop {
+            let stream = listener.accept().await?;
+            let handler = handler.clone();
+            let token = token.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = answer(stream, &token, handler).await {
+                    log::debug!("Control request failed: {e:?}");
+                }
+            });
+        }
+    }
+
+    async fn bind(&self) -> AnyResult<ControlListener> {
+        #[cfg(unix)]
+        if self.is_unix() {
+            use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+            // Left behind by a neolink that did not stop cleanly
+            if let Ok(meta) = tokio::fs::symlink_metadata(&self.addr).await {
+                if meta.file_type().is_socket() {
+                    tokio::fs::remove_file(&self.addr).await?;
+                }
+            }
+            let listener = tokio::net::UnixListener::bind(&self.addr)?;
+            tokio::fs::set_permissions(&self.addr, std::fs::Permissions::from_mode(0o600)).await?;
+            return Ok(ControlListener::Unix(listener));
+        }
+        Ok(ControlListener::Tcp(TcpListener::bind(&self.addr).await?))
+    }
+
+    /// Write a new token that only this user may read
+    async fn write_token(&self) -> AnyResult<String> {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        if let Some(dir) = self.token_file.parent() {
+            let mut builder = tokio::fs::DirBuilder::new();
+            builder.recursive(true);
+            #[cfg(unix)]
+            builder.mode(0o700);
+            builder.create(dir).await?;
+        }
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options
+            .open(&self.token_file)
+            .await
+            .with_context(|| format!("Could not write the control token {:?}", self.token_file))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))
+                .await?;
+        }
+        file.write_all(token.as_bytes()).await?;
+        Ok(token)
+    }
+
+    async fn connect(&self) -> std::io::Result<Box<dyn ControlStream>> {
+        #[cfg(unix)]
+        if self.is_unix() {
+            return Ok(Box::new(tokio::net::UnixStream::connect(&self.addr).await?));
+        }
+        Ok(Box::new(TcpStream::connect(&self.addr).await?))
+    }
+
+    /// Whether a neolink is answering on the control socket
+    pub(crate) async fn running(&self) -> bool {
+        matches!(
+            timeout(Duration::from_secs(1), self.connect()).await,
+            Ok(Ok(_))
+        )
+    }
+
+    /// Run a control on a camera of the neolink on the control socket
+    pub(crate) async fn control(
+        &self,
+        camera: &str,
+        control: &str,
+        message: &str,
+    ) -> AnyResult<()> {
+        let request = ControlRequest::Control {
+            camera: camera.to_string(),
+            control: control.to_string(),
+            message: message.to_string(),
+        };
+        match self.query(&request).await? {
+            ControlReply::Controlled { reply } if reply.starts_with("OK") => Ok(()),
+            ControlReply::Controlled { reply } => Err(anyhow!("{reply}")),
+            reply => Err(anyhow!(
+                "Unexpected reply from the control socket: {reply:?}"
+            )),
+        }
+    }
+
+    /// Send a request to the control socket of a running neolink
+    pub(crate) async fn query(&self, request: &ControlRequest) -> AnyResult<ControlReply> {
+        let addr = &self.addr;
+        let token = tokio::fs::read_to_string(&self.token_file)
+            .await
+            .with_context(|| {
+                format!(
+                    "Could not read the control token {:?}. Is `neolink rtsp` running as this user?",
+                    self.token_file
+                )
+            })?;
+        let mut stream = timeout(Duration::from_secs(5), self.connect())
+            .await
+            .map_err(|_| anyhow!("Timed out connecting to the control socket at {addr}"))?
+            .with_context(|| {
+                format!(
+                    "Could not connect to the control socket at {addr}. Is `neolink rtsp` running?"
+                )
+            })?;
+        let mut data = serde_json::to_string(&ControlMessage {
+            token: token.trim().to_string(),
+            request: request.clone(),
+        })?;
+        data.push('\n');
+        stream.write_all(data.as_bytes()).await?;
+
+        // A safe reboot waits for the camera to stop recording first
+        let wait = match request {
+            ControlRequest::Control { .. } => Duration::from_secs(300),
+            _ => Duration::from_secs(10),
+        };
+        let mut line = String::new();
+        timeout(wait, BufReader::new(stream).read_line(&mut line))
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the control socket reply"))??;
+        match serde_json::from_str(&line)
+            .with_context(|| format!("Invalid reply from the control socket: {line:?}"))?
+        {
+            ControlReply::Error { message } => Err(anyhow!("{message}")),
+            reply => Ok(reply),
+        }
+    }
+}
+
+async fn answer<F, Fut>(stream: Box<dyn ControlStream>, token: &str, handler: F) -> AnyResult<()>
+where
+    F: Fn(ControlRequest) -> Fut,
+    Fut: Future<Output = ControlReply>,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut line = String::new();
+    timeout(
+        Duration::from_secs(5),
+        BufReader::new(read).read_line(&mut line),
+    )
+    .await??;
+    let reply = match serde_json::from_str::<ControlMessage>(&line) {
+        Ok(message) if message.token == token => handler(message.request).await,
+        Ok(_) => ControlReply::Error {
+            message: "Wrong control token".to_string(),
+        },
+        Err(e) => ControlReply::Error {
+            message: format!("Invalid request: {e}"),
+        },
+    };
+    let mut data = serde_json::to_string(&reply)?;
+    data.push('\n');
+    write.write_all(data.as_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! The model, firmware and stream details of a camera
+//!
+//! This is what `neolink info` prints and what mqtt publishes to the
+//! retained `status/info` when the camera connects
+use anyhow::Context;
+use serde::Serialize;
+
+use super::NeoInstance;
+use crate::AnyResult;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DeviceInfo {
+    pub(crate) model: Option<String>,
+    pub(crate) firmware: String,
+    pub(crate) hardware: String,
+    pub(crate) serial: String,
+    pub(crate) build_day: String,
+    pub(crate) encryption: Option<String>,
+    pub(crate) streams: Vec<StreamDetails>,
+}
+
+/// The encoding of a stream as the camera reports it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StreamDetails {
+    /// The name the camera gives it such as `mainStream`
+    pub(crate) name: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) fps: u32,
+    /// In kbps
+    pub(crate) bitrate: u32,
+}
+
+impl std::fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Model: {}",
+            self.model.as_deref().unwrap_or("Undeclared")
+        )?;
+        writeln!(f, "Firmware: {}", self.firmware)?;
+        writeln!(f, "Hardware: {}", self.hardware)?;
+        writeln!(f, "Serial: {}", self.serial)?;
+        writeln!(f, "Build: {}", self.build_day)?;
+        write!(
+            f,
+            "Encryption: {}",
+            self.encryption.as_deref().unwrap_or("Unknown")
+        )?;
+        for stream in self.streams.iter() {
+            write!(
+                f,
+                "\n{}: {}x{} {}fps {}kbps",
+                stream.name, stream.width, stream.height, stream.fps, stream.bitrate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Ask the camera for its version and stream info
+pub(crate) async fn device_info(camera: &NeoInstance) -> AnyResult<DeviceInfo> {
+    camera
+        .run_task(|camera| {
+            Box::pin(async move {
+                let version = camera
+                    .version()
+                    .await
+                    .context("Could not get the version of the camera")?;
+                let stream_info = camera
+                    .get_stream_info()
+                    .await
+                    .context("Could not get the stream info of the camera")?;
+                let streams = stream_info
+                    .stream_infos
+                    .iter()
+                    .flat_map(|info| info.encode_tables.iter())
+                    .map(|encode| StreamDetails {
+                        name: encode.name.clone(),
+                        width: encode.resolution.width,
+                        height: encode.resolution.height,
+                        fps: table_value(&encode.framerate_table, encode.default_framerate),
+                        bitrate: table_value(&encode.bitrate_table, encode.default_bitrate),
+                    })
+                    .collect();
+                Ok(DeviceInfo {
+                    model: version.model,
+                    firmware: version.firmwareVersion,
+                    hardware: version.hardwareVersion,
+                    serial: version.serialNumber,
+                    build_day: version.buildDay,
+                    encryption: camera.encryption_level().map(|level| level.to_string()),
+                    streams,
+                })
+            })
+        })
+        .await
+}
+
+/// The defaults in the encode table are sometimes an index into the
+/// comma separated table and sometimes the value itself
+fn table_value(table: &str, value: u32) -> u32 {
+    table
+        .split(',')
+        .filter_map(|c| c.trim().parse::<u32>().ok())
+        .nth(value as usize)
+        .unwrap_or(value)
+}
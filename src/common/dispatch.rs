@@ -0,0 +1,618 @@
+//! The controls of a camera that are shared by mqtt and the rest api
+//!
+//! Each control takes the same message as the matching `control/...` mqtt
+//! topic and gives back the reply that mqtt would publish, either `OK` or
+//! `FAIL` with an optional reason after a `:`
+use log::*;
+use tokio::time::{sleep, Duration};
+
+use neolink_core::bc_protocol::{Direction as BcDirection, LightState};
+
+use super::NeoInstance;
+use crate::{utils::wait_until_idle, AnyResult};
+
+/// How long `reboot` waits for a recording to finish
+const REBOOT_WAIT: Duration = Duration::from_secs(120);
+
+/// Run the control `name` on the camera with the message
+///
+/// The name is the mqtt topic without the `control/` e.g. `ir` or
+/// `ptz/preset`. This is `None` when there is no such control or it does
+/// not take the message
+pub(crate) async fn run_control(camera: &NeoInstance, name: &str, message: &str) -> Option<String> {
+    let reply = match (name, message) {
+        ("floodlight", "on") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_floodlight_manual(true, 180).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn on the floodlight light: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("floodlight", "off") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_floodlight_manual(false, 180).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn off the floodlight light: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("led", "on") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.led_light_set(true).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn on the led: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("led", "off") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.led_light_set(false).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn off the led: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("ir", "on") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.irled_light_set(LightState::On).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn on the ir: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("ir", "off") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.irled_light_set(LightState::Off).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn off the ir: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("ir", "auto") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.irled_light_set(LightState::Auto).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn set to auto on the led: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("reboot", message) => {
+            // Waiting for the camera to finish writing a clip is opt in, a
+            // camera that never reports its recording status could not be
+            // rebooted otherwise
+            let waited = match message {
+                "safe" => wait_until_idle(camera, REBOOT_WAIT, false).await,
+                _ => Ok(()),
+            };
+            let res = match waited {
+                Ok(()) => {
+                    camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                cam.reboot().await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if res.is_err() {
+                error!("Failed to reboot the camera: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("zoom", message) => {
+            if let Ok(amount) = message.parse::<f32>() {
+                if let Err(e) = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.zoom_to((amount * 1000.0) as u32).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                {
+                    error!("Failed to send PTZ: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            } else {
+                "FAIL: Could not convert message to number".to_string()
+            }
+        }
+        ("focus", message) => {
+            if let Ok(position) = message.parse::<u32>() {
+                if let Err(e) = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.focus_to(position).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                {
+                    error!("Failed to send focus: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            } else {
+                "FAIL: Could not convert message to number".to_string()
+            }
+        }
+        ("ptz", message) | ("pt", message) => {
+            let lowercase_message = message.to_lowercase();
+            let mut words = lowercase_message.split_whitespace();
+            if words.clone().next() == Some("stop") {
+                if let Err(e) = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.send_ptz(BcDirection::Stop, 32f32).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                {
+                    error!("Failed to send PTZ: {:?}", e);
+                    "FAIL"
+                } else {
+                    "OK"
+                }
+            } else if let Some(direction_txt) = words.next() {
+                // Target amount to move
+                let amount = words.next().unwrap_or("32.0");
+                // Not all cameras support the speed
+                let speed = words
+                    .next()
+                    .and_then(|speed| speed.parse::<f32>().ok())
+                    .filter(|speed| *speed > 0.0)
+                    .unwrap_or(32f32);
+
+                if let Ok(amount) = amount.parse::<f32>() {
+                    let seconds = amount / speed;
+                    // range checking on seconds so that you can't sleep for 3.4E+38 seconds
+                    let seconds = match seconds {
+                        x if (0.0..10.0).contains(&x) => Some(seconds),
+                        _ => {
+                            error!("seconds was not a valid number (out of range)");
+                            None
+                        }
+                    };
+
+                    let bc_direction = match direction_txt {
+                        "up" => Some(BcDirection::Up),
+                        "down" => Some(BcDirection::Down),
+                        "left" => Some(BcDirection::Left),
+                        "right" => Some(BcDirection::Right),
+                        n => {
+                            error!("Unrecognized PTZ direction \"{}\"", n);
+                            None
+                        }
+                    };
+
+                    if let (Some(seconds), Some(bc_direction)) = (seconds, bc_direction) {
+                        // On drop send the stop command again just to make sure it stops
+                        let _drop_command = camera.clone().drop_command(
+                            move |cam| {
+                                Box::pin(async move {
+                                    cam.send_ptz(BcDirection::Stop, speed).await?;
+                                    AnyResult::Ok(())
+                                })
+                            },
+                            Duration::from_millis(100),
+                        );
+                        if let Err(e) = camera
+                            .run_task(|cam| {
+                                Box::pin(async move {
+                                    cam.send_ptz(bc_direction, speed).await?;
+                                    sleep(Duration::from_secs_f32(seconds)).await;
+                                    cam.send_ptz(BcDirection::Stop, speed).await?;
+                                    AnyResult::Ok(())
+                                })
+                            })
+                            .await
+                        {
+                            error!("Failed to send PTZ: {:?}", e);
+                            "FAIL"
+                        } else {
+                            "OK"
+                        }
+                    } else {
+                        "FAIL"
+                    }
+                } else {
+                    error!("No PTZ speed as a valid number");
+                    "FAIL"
+                }
+            } else {
+                error!("No PTZ Direction given. Please add up/down/left/right/stop");
+                "FAIL"
+            }
+            .to_string()
+        }
+        ("ptz/preset", message) => {
+            let mut words = message.split_whitespace();
+            match words.next() {
+                Some("save") => ptz_preset_assign(camera, words).await,
+                Some("goto") => ptz_preset_goto(camera, words.next()).await,
+                id => ptz_preset_goto(camera, id).await,
+            }
+            .to_string()
+        }
+        ("ptz/assign", message) => ptz_preset_assign(camera, message.split_whitespace())
+            .await
+            .to_string(),
+        ("pir", "on") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.pir_set(true).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn on the pir: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("pir", "off") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.pir_set(false).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to turn off the pir: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("tracking", "on") | ("tracking", "off") => {
+            let on = message == "on";
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.set_auto_tracking(on).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if res.is_err() {
+                error!("Failed to set the auto tracking: {:?}", res.err());
+                "FAIL"
+            } else {
+                "OK"
+            }
+            .to_string()
+        }
+        ("wakeup", message) => {
+            match message.parse::<u64>() {
+                Ok(secs) => {
+                    if let Ok(permit) = camera.permit().await {
+                        // This task waits for the `run_task` to send the OK then starts the countdown
+                        // to drop the permit
+                        let camera = camera.clone();
+                        tokio::task::spawn(async move {
+                            // Wait for connection then start the countdown
+                            // By using a run_task we can delay the countdown until AFTER we are connected
+                            let _ = camera
+                                .run_task(|_cam| Box::pin(async move { AnyResult::Ok(()) }))
+                                .await;
+
+                            sleep(Duration::from_secs(secs * 60)).await;
+
+                            drop(permit);
+                        });
+                        "OK"
+                    } else {
+                        "FAIL: Camera shutting down"
+                    }
+                    .to_string()
+                }
+                Err(e) => {
+                    error!("Failed to parse minutes: {:?}", e);
+                    format!("FAIL: '{message}' => {e:?}")
+                }
+            }
+        }
+        ("floodlight_tasks", message) => {
+            let state = match message.to_lowercase().as_ref() {
+                "on" => Ok(true),
+                "off" => Ok(false),
+                n => match n.parse::<bool>() {
+                    Ok(state) => Ok(state),
+                    Err(e) => AnyResult::Err(e.into()),
+                },
+            };
+
+            match state {
+                Ok(state) => {
+                    if let Err(e) = camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                cam.flightlight_tasks_enable(state).await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await
+                    {
+                        format!("FAIL: {e:?}")
+                    } else {
+                        "OK".to_string()
+                    }
+                }
+                Err(e) => format!("FAIL: Could not parse message to {e:?}"),
+            }
+        }
+        ("floodlight/brightness", message) => match message.trim().parse::<u32>() {
+            Ok(brightness @ 1..=100) => {
+                if let Err(e) = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.floodlight_brightness_set(brightness).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await
+                {
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            }
+            _ => "FAIL: Brightness should be a percent from 1 to 100".to_string(),
+        },
+        ("siren", "off") => {
+            if let Ok(config) = camera.config().await {
+                crate::siren::cancel_timed_siren(&config.borrow().name);
+            }
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.siren_manual(false).await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if let Err(e) = res {
+                error!("Failed to stop siren: {:?}", e);
+                format!("FAIL: {e:?}")
+            } else {
+                "OK".to_string()
+            }
+        }
+        ("siren", "on") => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.siren().await?;
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            if let Err(e) = res {
+                error!("Failed to trigger siren: {:?}", e);
+                format!("FAIL: {e:?}")
+            } else {
+                "OK".to_string()
+            }
+        }
+        ("siren", message) if message.parse::<u64>().is_ok_and(|secs| secs > 0) => {
+            let duration = Duration::from_secs(message.parse::<u64>().ok()?);
+            if let Err(e) = crate::siren::siren_for(camera, duration).await {
+                error!("Failed to sound siren: {:?}", e);
+                format!("FAIL: {e:?}")
+            } else {
+                "OK".to_string()
+            }
+        }
+        ("quickreply", message) => match message.trim().parse::<u32>() {
+            Ok(id) => {
+                let res = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.quick_reply(id).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await;
+                if let Err(e) = res {
+                    error!("Failed to play quick reply: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            }
+            Err(_) => "FAIL: Expected the id of the quick reply".to_string(),
+        },
+        #[cfg(feature = "gstreamer")]
+        ("say", message) if !message.trim().is_empty() => {
+            match crate::talk::say(camera, message.trim()).await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to say {message}: {:?}", e);
+                    format!("FAIL: {e:?}")
+                }
+            }
+        }
+        #[cfg(feature = "gstreamer")]
+        ("play", message) if !message.trim().is_empty() => {
+            match crate::talk::play(camera, message.trim()).await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to play {message}: {:?}", e);
+                    format!("FAIL: {e:?}")
+                }
+            }
+        }
+        #[cfg(not(feature = "gstreamer"))]
+        ("say", _) | ("play", _) => "FAIL: Neolink was built without gstreamer".to_string(),
+        ("pause", _) => {
+            // The camera stays disconnected until it is resumed
+            match camera.disconnect().await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to pause the camera: {:?}", e);
+                    format!("FAIL: {e:?}")
+                }
+            }
+        }
+        ("resume", _) => match camera.connect().await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => {
+                error!("Failed to resume the camera: {:?}", e);
+                format!("FAIL: {e:?}")
+            }
+        },
+        ("disable", _) => {
+            // Unlike pause this is not undone when the camera is used
+            match camera.disable().await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to disable the camera: {:?}", e);
+                    format!("FAIL: {e:?}")
+                }
+            }
+        }
+        ("enable", _) => match camera.enable().await {
+            Ok(()) => "OK".to_string(),
+            Err(e) => {
+                error!("Failed to enable the camera: {:?}", e);
+                format!("FAIL: {e:?}")
+            }
+        },
+        _ => return None,
+    };
+    Some(reply)
+}
+
+/// Move to the preset `id`
+async fn ptz_preset_goto(camera: &NeoInstance, id: Option<&str>) -> &'static str {
+    let Some(Ok(id)) = id.map(|id| id.parse::<u8>()) else {
+        error!("PTZ preset was not a valid number");
+        return "FAIL";
+    };
+    let res = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.moveto_ptz_preset(id).await?;
+                AnyResult::Ok(())
+            })
+        })
+        .await;
+    if res.is_err() {
+        error!("Failed to move to ptz preset: {:?}", res.err());
+        "FAIL"
+    } else {
+        "OK"
+    }
+}
+
+/// Save the current position as `<id> <name>`, the name is the rest of the words
+async fn ptz_preset_assign<'a>(
+    camera: &NeoInstance,
+    mut words: impl Iterator<Item = &'a str>,
+) -> &'static str {
+    let Some(Ok(id)) = words.next().map(|id| id.parse::<u8>()) else {
+        error!("PTZ preset was not a valid number");
+        return "FAIL";
+    };
+    let name = words.collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        error!("PTZ preset was not given a name");
+        return "FAIL";
+    }
+    let res = camera
+        .run_task(|cam| {
+            let name = name.clone();
+            Box::pin(async move {
+                cam.set_ptz_preset(id, name).await?;
+                AnyResult::Ok(())
+            })
+        })
+        .await;
+    if res.is_err() {
+        error!("Failed to assign ptz preset: {:?}", res.err());
+        "FAIL"
+    } else {
+        "OK"
+    }
+}
@@ -0,0 +1,94 @@
+//! The gstreamer elements shared by the rtsp server and the camera threads
+use anyhow::{anyhow, Context};
+use gstreamer::{prelude::*, Caps, Element, ElementFactory};
+use gstreamer_app::AppSrc;
+use neolink_core::bcmedia::model::VideoType;
+
+use crate::AnyResult;
+
+/// Make an element or say which plugin is missing
+pub(crate) fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
+    ElementFactory::make_with_name(kind, Some(name)).with_context(|| {
+        let plugin = match kind {
+            "appsrc" => "app (gst-plugins-base)",
+            "audioconvert" => "audioconvert (gst-plugins-base)",
+            "adpcmdec" => "Required for audio",
+            "h264parse" | "h265parse" => "videoparsersbad (gst-plugins-bad)",
+            "rtph264pay" => "rtp (gst-plugins-good)",
+            "rtph265pay" => "rtp (gst-plugins-good)",
+            "rtpjitterbuffer" => "rtp (gst-plugins-good)",
+            "rtph264depay" => "rtp (gst-plugins-good)",
+            "rtph265depay" => "rtp (gst-plugins-good)",
+            "rtspsrc" => "rtsp (gst-plugins-good)",
+            "aacparse" => "audioparsers (gst-plugins-good)",
+            "rtpL16pay" => "rtp (gst-plugins-good)",
+            "x264enc" => "x264 (gst-plugins-ugly)",
+            "x265enc" => "x265 (gst-plugins-bad)",
+            "avdec_h264" => "libav (gst-libav)",
+            "avdec_h265" => "libav (gst-libav)",
+            "vaapih265dec" | "vaapih264enc" => "vaapi (gstreamer-vaapi)",
+            "nvh265dec" | "nvh264enc" => "nvcodec (gst-plugins-bad)",
+            "v4l2h265dec" | "v4l2h264enc" => "video4linux2 (gst-plugins-good)",
+            "videoconvert" => "videoconvertscale (gst-plugins-base)",
+            "videotestsrc" => "videotestsrc (gst-plugins-base)",
+            "imagefreeze" => "imagefreeze (gst-plugins-good)",
+            "audiotestsrc" => "audiotestsrc (gst-plugins-base)",
+            "audioresample" => "audioresample (gst-plugins-base)",
+            "avenc_aac" | "voaacenc" => "libav (gst-libav) or voaacenc (gst-plugins-bad)",
+            "opusenc" => "opus (gst-plugins-base)",
+            "rtpmp4gpay" | "rtpopuspay" => "rtp (gst-plugins-good)",
+            "decodebin" => "playback (gst-plugins-good)",
+            "compositor" => "compositor (gst-plugins-base)",
+            "jpegdec" => "jpeg (gst-plugins-good)",
+            "level" => "level (gst-plugins-good)",
+            "mp4mux" => "isomp4 (gst-plugins-good)",
+            "matroskamux" => "matroska (gst-plugins-good)",
+            "flvmux" => "flv (gst-plugins-good)",
+            "hlssink2" => "hls (gst-plugins-bad)",
+            "rtmp2sink" => "rtmp2 (gst-plugins-bad)",
+            "rtmpsink" => "rtmp (gst-plugins-bad)",
+            "rtspclientsink" => "rtspclientsink (gst-rtsp-server)",
+            "webrtcbin" => "webrtc (gst-plugins-bad) and libnice",
+            "capsfilter" | "filesink" | "fakesink" => "coreelements (gstreamer)",
+            _ => "Unknown",
+        };
+        format!(
+            "Missing required gstreamer plugin `{}` for `{}` element",
+            plugin, kind
+        )
+    })
+}
+
+/// An appsrc for the byte stream of the camera and the parser to link after it
+///
+/// They are named `<prefix>src` and `<prefix>parse`. A live source stamps
+/// the buffers as they are pushed.
+pub(crate) fn make_video_source(
+    prefix: &str,
+    video_type: VideoType,
+    live: bool,
+) -> AnyResult<(Element, Element)> {
+    let (caps, parser) = match video_type {
+        VideoType::H264 => ("video/x-h264", "h264parse"),
+        VideoType::H265 => ("video/x-h265", "h265parse"),
+    };
+    let source = make_element("appsrc", &format!("{prefix}src"))?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    source.set_caps(Some(
+        &Caps::builder(caps)
+            .field("stream-format", "byte-stream")
+            .build(),
+    ));
+    source.set_format(gstreamer::Format::Time);
+    source.set_is_live(live);
+    source.set_block(false);
+    source.set_do_timestamp(live);
+    source.set_max_bytes(32 * 1024 * 1024);
+    let source = source
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot cast back"))?;
+
+    let parser = make_element(parser, &format!("{prefix}parse"))?;
+    Ok((source, parser))
+}
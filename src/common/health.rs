@@ -0,0 +1,273 @@
+//! Health checks over http for docker and kubernetes
+//!
+//! When `[health]` is in the config the long running subcommands answer
+//!
+//! - `GET /health` with 200 while no more than `max_unhealthy` cameras are
+//!   unhealthy, otherwise 503
+//! - `GET /status` with the state of each camera as json
+//!
+//! A camera is unhealthy when it should be connected but has not been for
+//! `grace` seconds. Cameras that were disconnected on purpose, such as with
+//! `idle_disconnect`, are healthy. A camera that has failed for good with
+//! `failure_policy = "unhealthy"` is unhealthy straight away.
+use anyhow::{anyhow, Context};
+use futures::future::join_all;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    time::{interval, timeout, Duration, Instant, MissedTickBehavior},
+};
+
+use super::{read_request, write_response, CameraFailure, NeoCamThreadState, NeoReactor};
+use crate::{config::HealthConfig, AnyResult};
+
+/// How often the cameras are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+struct CameraStatus {
+    name: String,
+    /// Whether the camera is connected right now
+    connected: bool,
+    /// Whether neolink wants the camera to be connected
+    wanted: bool,
+    /// Seconds since the camera was lost
+    disconnected_for: Option<u64>,
+    /// The streams and tasks that are using the camera
+    users: u32,
+    /// Whether the camera is writing a clip to its storage, unknown until
+    /// it reports it with its motion events
+    busy: Option<bool>,
+    /// Why the camera failed for good with `failure_policy = "unhealthy"`
+    failed: Option<CameraFailure>,
+    healthy: bool,
+    #[serde(skip)]
+    lost_at: Option<Instant>,
+}
+
+/// The state of a camera when it was checked
+struct Answer {
+    connected: bool,
+    wanted: bool,
+    users: u32,
+    busy: Option<bool>,
+    failed: Option<CameraFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    healthy: bool,
+    /// Seconds since neolink started
+    uptime: u64,
+    cameras: Vec<CameraStatus>,
+}
+
+struct HealthServer {
+    config: HealthConfig,
+    started: Instant,
+    cameras: Mutex<HashMap<String, CameraStatus>>,
+}
+
+/// Serve the health checks until an error
+pub(crate) async fn serve_health(config: HealthConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let listener = TcpListener::bind((config.bind_addr.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Could not bind the health server to {}:{}",
+                config.bind_addr, config.port
+            )
+        })?;
+    log::info!(
+        "Health checks at http://{}:{}/health",
+        config.bind_addr,
+        config.port
+    );
+
+    let server = Arc::new(HealthServer {
+        config,
+        started: Instant::now(),
+        cameras: Default::default(),
+    });
+
+    let thread_server = server.clone();
+    tokio::select! {
+        v = thread_server.check(&reactor) => v,
+        v = async {
+            loop {
+                let (stream, peer) = listener.accept().await?;
+                let server = server.clone();
+                tokio::task::spawn(async move {
+                    if let Err(e) = server.answer(stream).await {
+                        log::debug!("Health request from {peer} failed: {e:?}");
+                    }
+                });
+            }
+        } => v,
+    }
+}
+
+impl HealthServer {
+    /// Keep the state of the cameras up to date
+    async fn check(&self, reactor: &NeoReactor) -> AnyResult<()> {
+        let grace = Duration::from_secs(self.config.grace);
+        let mut config = reactor.config().await?;
+        let mut check = interval(CHECK_INTERVAL);
+        check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            check.tick().await;
+            let names = config
+                .borrow_and_update()
+                .cameras
+                .iter()
+                .filter(|camera| camera.enabled)
+                .map(|camera| camera.name.clone())
+                .collect::<Vec<_>>();
+
+            // Each camera is asked at the same time and given until the next
+            // check so that one that is stuck does not hold up the others
+            let answers = join_all(names.into_iter().map(|name| async move {
+                let answer = timeout(CHECK_INTERVAL, Self::ask(reactor, &name))
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("The camera did not answer")));
+                (name, answer)
+            }))
+            .await;
+
+            let mut found = HashMap::new();
+            for (name, answer) in answers {
+                let previous = self.cameras.lock().unwrap().get(&name).cloned();
+                let Answer {
+                    connected,
+                    wanted,
+                    users,
+                    busy,
+                    failed,
+                } = match answer {
+                    Ok(answer) => answer,
+                    Err(e) => {
+                        log::debug!("{name}: Health check failed: {e:?}");
+                        Answer {
+                            connected: false,
+                            wanted: true,
+                            users: 0,
+                            busy: None,
+                            failed: None,
+                        }
+                    }
+                };
+                let now = Instant::now();
+                let lost_at = if connected || !wanted {
+                    None
+                } else {
+                    previous.and_then(|status| status.lost_at).or(Some(now))
+                };
+                let disconnected_for = lost_at.map(|at| now.saturating_duration_since(at));
+                found.insert(
+                    name.clone(),
+                    CameraStatus {
+                        name,
+                        connected,
+                        wanted,
+                        disconnected_for: disconnected_for.map(|d| d.as_secs()),
+                        users,
+                        busy,
+                        failed,
+                        healthy: failed.is_none() && disconnected_for.is_none_or(|d| d <= grace),
+                        lost_at,
+                    },
+                );
+            }
+            *self.cameras.lock().unwrap() = found;
+        }
+    }
+
+    /// What a camera says when it is checked
+    async fn ask(reactor: &NeoReactor, name: &str) -> AnyResult<Answer> {
+        let instance = reactor.get(name).await?;
+        let connected = instance.camera().borrow().upgrade().is_some();
+        let wanted = instance
+            .get_state()
+            .await
+            .map(|state| state == NeoCamThreadState::Connected)
+            .unwrap_or(true);
+        let users = match instance.users().await {
+            Ok(users) => *users.borrow(),
+            Err(_) => 0,
+        };
+        let busy = match instance.recording().await {
+            Ok(recording) => *recording.borrow(),
+            Err(_) => None,
+        };
+        let failed = match instance.failure().await {
+            Ok(failure) => *failure.borrow(),
+            Err(_) => None,
+        };
+        Ok(Answer {
+            connected,
+            wanted,
+            users,
+            busy,
+            failed,
+        })
+    }
+
+    fn status(&self) -> Status {
+        let mut cameras = self
+            .cameras
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        cameras.sort_by(|a, b| a.name.cmp(&b.name));
+        let unhealthy = cameras.iter().filter(|camera| !camera.healthy).count();
+        Status {
+            healthy: unhealthy <= self.config.max_unhealthy,
+            uptime: self.started.elapsed().as_secs(),
+            cameras,
+        }
+    }
+
+    async fn answer(&self, stream: TcpStream) -> AnyResult<()> {
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let request = timeout(Duration::from_secs(10), read_request(&mut read))
+            .await
+            .map_err(|_| anyhow!("Timed out reading the request"))??;
+
+        let (status, content_type, body) = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => {
+                let status = self.status();
+                if status.healthy {
+                    (200, "text/plain", "OK".to_string())
+                } else {
+                    let unhealthy = status
+                        .cameras
+                        .iter()
+                        .filter(|camera| !camera.healthy)
+                        .map(|camera| camera.name.as_str())
+                        .collect::<Vec<_>>();
+                    (
+                        503,
+                        "text/plain",
+                        format!("Unhealthy: {}", unhealthy.join(", ")),
+                    )
+                }
+            }
+            ("GET", "/status") => (
+                200,
+                "application/json",
+                serde_json::to_string(&self.status())?,
+            ),
+            ("GET", _) => (404, "text/plain", "Not Found".to_string()),
+            _ => (405, "text/plain", "Method Not Allowed".to_string()),
+        };
+        write_response(&mut write, status, content_type, &body).await
+    }
+}
@@ -0,0 +1,228 @@
+//! This thread writes the camera as HLS for players that cannot use rtsp
+//! such as iOS and smart TVs
+//!
+//! This is only active on cameras with `[cameras.hls]`
+//!
+//! The playlist and segments are written to
+//! `<output>/<camera>/index.m3u8` and `<output>/<camera>/segmentNNNNN.ts`
+//! with `hlssink2`. Older segments are removed as new ones are written. With
+//! `[api]` they are also served at `/hls/<camera>/index.m3u8`.
+//!
+//! Only the video is written and it is not re-encoded
+
+use anyhow::{anyhow, Context};
+use gstreamer::{prelude::*, BufferFlags, Element, MessageView, Pipeline};
+use gstreamer_app::AppSrc;
+use neolink_core::{
+    bc_protocol::StreamKind,
+    bcmedia::model::{BcMedia, BcMediaIframe, BcMediaPframe, VideoType},
+};
+use std::path::Path;
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{make_element, make_video_source, NeoInstance};
+use crate::{config::HlsConfig, AnyResult, Result};
+
+/// The name of the playlist in the camera's directory
+const HLS_PLAYLIST: &str = "index.m3u8";
+
+pub(crate) struct NeoCamHlsThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamHlsThread {
+    pub(crate) async fn new(instance: NeoInstance) -> Result<Self> {
+        Ok(Self {
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                let mut config_rx = instance.config().await?;
+                loop {
+                    // Wait for the green light
+                    let hls_config = config_rx
+                        .wait_for(|config| config.hls.is_some())
+                        .await?
+                        .hls
+                        .clone()
+                        .expect("Checked by the wait_for");
+
+                    let r = tokio::select! {
+                        v = config_rx.wait_for(|config| config.hls.as_ref() != Some(&hls_config)) => {
+                            // Config changed go back and restart with the new one
+                            v?;
+                            AnyResult::Ok(())
+                        },
+                        v = hls(&instance, &hls_config) => v,
+                    };
+                    if let Err(e) = r {
+                        log::warn!("Error in hls task Restarting: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            } => v
+        }
+    }
+}
+
+impl Drop for NeoCamHlsThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamHlsThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamHlsThread");
+    }
+}
+
+async fn hls(instance: &NeoInstance, config: &HlsConfig) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let dir = config.dir(&name);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Could not create hls directory {:?}", dir))?;
+    remove_old(&dir).await?;
+    let stream: StreamKind = config
+        .stream
+        .as_stream_kinds()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No stream for hls"))?;
+    log::info!("{name}: Writing {stream} as hls to {:?}", dir);
+
+    let mut media_rx = instance.stream(stream).await?;
+    let mut writer: Option<HlsWriter> = None;
+    let mut check = interval(Duration::from_secs(1));
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            media = media_rx.recv() => {
+                let Some(media) = media else {
+                    return Err(anyhow!("The camera stream ended"));
+                };
+                let (data, video_type, keyframe) = match media {
+                    BcMedia::Iframe(BcMediaIframe {
+                        data, video_type, ..
+                    }) => (data, video_type, true),
+                    BcMedia::Pframe(BcMediaPframe {
+                        data, video_type, ..
+                    }) => (data, video_type, false),
+                    _ => continue,
+                };
+                if writer.is_none() {
+                    if !keyframe {
+                        // Wait for an iframe to start on
+                        continue;
+                    }
+                    writer = Some(HlsWriter::new(&dir, config, video_type)?);
+                }
+                writer
+                    .as_ref()
+                    .expect("Set above")
+                    .push(data, keyframe)?;
+            }
+            _ = check.tick() => {
+                if let Some(writer) = writer.as_ref() {
+                    writer.check()?;
+                }
+            }
+        }
+    }
+}
+
+/// Remove what was left from the last run so that players do not start on it
+async fn remove_old(dir: &Path) -> AnyResult<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_hls = path.extension().is_some_and(|ext| ext == "ts")
+            || path.file_name().is_some_and(|file| file == HLS_PLAYLIST);
+        if is_hls {
+            tokio::fs::remove_file(&path)
+                .await
+                .with_context(|| format!("Could not remove {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+/// The pipeline that writes the segments
+///
+/// It is made on the first iframe since that tells us the codec
+struct HlsWriter {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+}
+
+impl HlsWriter {
+    fn new(dir: &Path, config: &HlsConfig, video_type: VideoType) -> AnyResult<Self> {
+        gstreamer::init().context("Gstreamer failed to initialise")?;
+        let pipeline = Pipeline::new();
+
+        let (source, parser) = make_video_source("hls", video_type, true)?;
+        // Each segment should start with the parameter sets
+        parser.set_property("config-interval", -1i32);
+        let sink = make_element("hlssink2", "hlssink")?;
+        sink.set_property(
+            "location",
+            dir.join("segment%05d.ts").to_string_lossy().to_string(),
+        );
+        sink.set_property(
+            "playlist-location",
+            dir.join(HLS_PLAYLIST).to_string_lossy().to_string(),
+        );
+        sink.set_property("target-duration", config.segment);
+        sink.set_property("playlist-length", config.playlist_length);
+        // Keep a few segments past the playlist for players that are behind
+        sink.set_property("max-files", config.playlist_length * 2);
+
+        pipeline.add_many([&source, &parser, &sink])?;
+        Element::link_many([&source, &parser, &sink])?;
+        pipeline.set_state(gstreamer::State::Playing)?;
+
+        let appsrc = source
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        Ok(Self { pipeline, appsrc })
+    }
+
+    fn push(&self, data: Vec<u8>, keyframe: bool) -> AnyResult<()> {
+        let mut buffer = gstreamer::Buffer::from_slice(data);
+        if !keyframe {
+            buffer
+                .get_mut()
+                .ok_or(anyhow!("Hls buffer is not writable"))?
+                .set_flags(BufferFlags::DELTA_UNIT);
+        }
+        self.appsrc.push_buffer(buffer)?;
+        Ok(())
+    }
+
+    fn check(&self) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .ok_or(anyhow!("Hls pipeline has no bus"))?;
+        while let Some(msg) = bus.pop() {
+            if let MessageView::Error(e) = msg.view() {
+                return Err(anyhow!("Error in hls pipeline: {}", e.error()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for HlsWriter {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+    }
+}
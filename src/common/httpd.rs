@@ -0,0 +1,182 @@
+//! The small http server used by the health checks, the rest api and onvif
+//!
+//! Only what those need is supported: one request per connection and a
+//! body with a `Content-Length`
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::collections::HashMap;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{config::UserConfig, AnyResult};
+
+/// The largest body that is accepted
+const MAX_BODY: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    /// The path without the query
+    pub(crate) path: String,
+    /// The headers with lowercase names
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: String,
+}
+
+/// Read one request from the connection
+pub(crate) async fn read_request<R: AsyncBufRead + Unpin>(read: &mut R) -> AnyResult<HttpRequest> {
+    let mut line = String::new();
+    if read.read_line(&mut line).await? == 0 {
+        return Err(anyhow!("Connection closed before the request"));
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let mut headers = HashMap::new();
+    let mut header = String::new();
+    for _ in 0..100 {
+        header.clear();
+        if read.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            let length = headers
+                .get("content-length")
+                .map(|length: &String| length.parse::<usize>())
+                .transpose()
+                .map_err(|_| anyhow!("Invalid Content-Length"))?
+                .unwrap_or(0);
+            if length > MAX_BODY {
+                return Err(anyhow!("Body of {length} bytes is too large"));
+            }
+            let mut body = vec![0; length];
+            read.read_exact(&mut body).await?;
+            return Ok(HttpRequest {
+                method,
+                path,
+                headers,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    Err(anyhow!("Too many headers"))
+}
+
+/// Whether the basic auth logs in as one of `users`
+///
+/// Anyone may log in when there are no users
+pub(crate) fn basic_auth(request: &HttpRequest, users: &[UserConfig]) -> bool {
+    users.is_empty() || login_user(request, users).is_some()
+}
+
+/// The one of `users` that the basic auth logs in as
+pub(crate) fn login_user<'a>(
+    request: &HttpRequest,
+    users: &'a [UserConfig],
+) -> Option<&'a UserConfig> {
+    let login = request
+        .headers
+        .get("authorization")
+        .and_then(|auth| auth.strip_prefix("Basic "))
+        .and_then(|login| BASE64.decode(login.trim()).ok())
+        .and_then(|login| String::from_utf8(login).ok())?;
+    let (name, pass) = login.split_once(':')?;
+    users
+        .iter()
+        .find(|user| user.name == name && user.password_matches(pass))
+}
+
+/// Write the response and close the connection
+pub(crate) async fn write_response<W: AsyncWrite + Unpin>(
+    write: &mut W,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> AnyResult<()> {
+    write_response_with_headers(write, status, content_type, &[], body.as_bytes()).await
+}
+
+/// Write the response with extra `headers` and close the connection
+pub(crate) async fn write_response_with_headers<W: AsyncWrite + Unpin>(
+    write: &mut W,
+    status: u16,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> AnyResult<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        415 => "Unsupported Media Type",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    );
+    if status == 401 {
+        head.push_str("WWW-Authenticate: Basic realm=\"neolink\"\r\n");
+    }
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    write.write_all(head.as_bytes()).await?;
+    write.write_all(body).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// Decode the `%xx` in a path such as the spaces in the camera names
+pub(crate) fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent encode a path segment such as a camera name
+#[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
+pub(crate) fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
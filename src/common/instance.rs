@@ -9,15 +9,19 @@ use futures::TryFutureExt;
 use std::sync::{Arc, Weak};
 use tokio::{
     sync::{
-        mpsc::Sender as MpscSender, oneshot::channel as oneshot, watch::Receiver as WatchReceiver,
+        mpsc::Sender as MpscSender,
+        oneshot::channel as oneshot,
+        watch::{Receiver as WatchReceiver, Sender as WatchSender},
     },
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{MdState, NeoCamCommand, NeoCamThreadState, Permit};
+#[cfg(feature = "gstreamer")]
+use super::SoundState;
+use super::{CameraFailure, MdState, NeoCamCommand, NeoCamThreadState, Permit, StreamStatsMap};
 use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::BcCamera;
+use neolink_core::bc_protocol::{AiDetection, BcCamera};
 
 #[cfg(feature = "gstreamer")]
 mod gst;
@@ -238,6 +242,74 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// Whether the camera is writing a clip to its storage
+    ///
+    /// This is None until the camera reports it or while disconnected
+    pub(crate) async fn recording(&self) -> Result<WatchReceiver<Option<bool>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Recording(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Whether the doorbell button is being pressed
+    ///
+    /// This is None until the camera reports it or while disconnected.
+    /// Only doorbells will report it as pressed
+    pub(crate) async fn visitor(&self) -> Result<WatchReceiver<Option<bool>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Visitor(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Why the camera failed for good with `failure_policy = "unhealthy"`
+    ///
+    /// This is None while the camera is being used or retried
+    pub(crate) async fn failure(&self) -> Result<WatchReceiver<Option<CameraFailure>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Failure(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// What the AI of the camera classified the latest motion as
+    ///
+    /// This is None until the camera reports it or while disconnected.
+    /// Only cameras with AI detection will report it
+    pub(crate) async fn ai(&self) -> Result<WatchReceiver<Option<AiDetection>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Ai(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Sound events from the audio detection
+    ///
+    /// Only changes from Unknown if `audio_detect` is configured
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn sound(&self) -> Result<WatchReceiver<SoundState>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Sound(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The current audio level in dB as measured by the audio detection
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn sound_level(&self) -> Result<WatchReceiver<Option<f64>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SoundLevel(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn config(&self) -> Result<WatchReceiver<CameraConfig>> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -266,7 +338,37 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
-    #[allow(dead_code)]
+    /// Drop the connection and keep it dropped until [`Self::enable`]
+    ///
+    /// Unlike [`Self::disconnect`] the camera is not connected again when it
+    /// is used such as by `idle_disconnect`
+    pub(crate) async fn disable(&self) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Disable(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Connect a camera that was disabled or paused
+    pub(crate) async fn enable(&self) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Enable(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Drop the connection to the camera and make a new one
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn reconnect(&self) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Reconnect(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn get_state(&self) -> Result<NeoCamThreadState> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -275,6 +377,65 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// The number of permits that are using the camera
+    pub(crate) async fn users(&self) -> Result<WatchReceiver<u32>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Users(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The number of rtsp clients watching the camera
+    ///
+    /// This stays at zero unless the rtsp server runs in this process
+    pub(crate) async fn rtsp_clients(&self) -> Result<WatchReceiver<u32>> {
+        Ok(self.rtsp_clients_sender().await?.subscribe())
+    }
+
+    /// Used by the rtsp server to update [`NeoInstance::rtsp_clients`]
+    pub(crate) async fn rtsp_clients_sender(&self) -> Result<Arc<WatchSender<u32>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::RtspClients(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The latest stats of each stream the rtsp server is sending
+    ///
+    /// This stays empty unless the rtsp server runs in this process with a
+    /// `stats_interval`
+    pub(crate) async fn stream_stats(&self) -> Result<WatchReceiver<StreamStatsMap>> {
+        Ok(self.stream_stats_sender().await?.subscribe())
+    }
+
+    /// Used by the rtsp server to update [`NeoInstance::stream_stats`]
+    pub(crate) async fn stream_stats_sender(&self) -> Result<Arc<WatchSender<StreamStatsMap>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::StreamStats(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// When the main stream started being served from the sub stream, None
+    /// while the main stream is working
+    ///
+    /// This is only set with `fallback_to_sub`
+    pub(crate) async fn fallback(&self) -> Result<WatchReceiver<Option<Instant>>> {
+        Ok(self.fallback_sender().await?.subscribe())
+    }
+
+    /// Used by the streams to update [`NeoInstance::fallback`]
+    pub(crate) async fn fallback_sender(&self) -> Result<Arc<WatchSender<Option<Instant>>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Fallback(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn permit(&self) -> Result<Permit> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -3,7 +3,7 @@ use super::*;
 use crate::common::UseCounter;
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use neolink_core::{bc_protocol::StreamKind, bcmedia::model::BcMedia};
-use tokio::sync::mpsc::Receiver as MpscReceiver;
+use tokio::{sync::mpsc::Receiver as MpscReceiver, time::Duration};
 
 #[cfg(feature = "pushnoti")]
 use crate::common::PushNoti;
@@ -17,7 +17,7 @@ impl NeoInstance {
         let config = self.config().await?.borrow().clone();
         let name = config.name.clone();
 
-        let media_rx = if config.pause.on_motion {
+        let media_rx = if config.pause.for_stream(stream).on_motion {
             let (media_tx, media_rx) = tokio::sync::mpsc::channel(100);
             let counter = UseCounter::new().await;
 
@@ -175,26 +175,89 @@ impl NeoInstance {
     }
 
     /// Streams a camera source
+    ///
+    /// When no frames arrive for `stall_timeout` the camera is reconnected
+    /// and the stream started again
+    ///
+    /// With `fallback_to_sub` the sub stream is sent in place of a main
+    /// stream that keeps failing
     pub(crate) async fn stream(&self, stream: StreamKind) -> AnyResult<MpscReceiver<BcMedia>> {
         let (media_tx, media_rx) = tokio::sync::mpsc::channel(100);
         let config = self.config().await?.borrow().clone();
+        let name = config.name.clone();
         let strict = config.strict;
+        let stall_timeout = Duration::from_secs(config.stall_timeout);
+        let can_fall_back = config.fallback_to_sub && stream == StreamKind::Main;
+        let fallback_tx = self.fallback_sender().await?;
         let thread_camera = self.clone();
         tokio::task::spawn(
             tokio::task::spawn(async move {
-                thread_camera
-                    .run_task(move |cam| {
-                        let media_tx = media_tx.clone();
-                        Box::pin(async move {
-                            let mut media_stream = cam.start_video(stream, 0, strict).await?;
-                            log::trace!("Camera started");
-                            while let Ok(media) = media_stream.get_data().await? {
-                                media_tx.send(media).await?;
-                            }
-                            AnyResult::Ok(())
+                let mut failures = 0;
+                loop {
+                    // Try the main stream again once in a while
+                    let falling_back = can_fall_back
+                        && fallback_tx
+                            .borrow()
+                            .is_some_and(|since| since.elapsed() < FALLBACK_RETRY);
+                    let source = if falling_back {
+                        StreamKind::Sub
+                    } else {
+                        stream
+                    };
+                    let res = thread_camera
+                        .run_task(|cam| {
+                            let media_tx = media_tx.clone();
+                            let fallback_tx = fallback_tx.clone();
+                            Box::pin(async move {
+                                let mut media_stream = cam.start_video(source, 0, strict).await?;
+                                if can_fall_back && !falling_back {
+                                    // The main stream is back
+                                    fallback_tx.send_if_modified(|since| since.take().is_some());
+                                }
+                                log::trace!("Camera started");
+                                loop {
+                                    let media = if stall_timeout.is_zero() {
+                                        media_stream.get_data().await?
+                                    } else {
+                                        tokio::time::timeout(stall_timeout, media_stream.get_data())
+                                            .await
+                                            .map_err(|_| StreamStalled)??
+                                    };
+                                    match media {
+                                        Ok(media) => media_tx.send(media).await?,
+                                        Err(_) => break,
+                                    }
+                                }
+                                AnyResult::Ok(())
+                            })
                         })
-                    })
-                    .await
+                        .await;
+                    match res {
+                        Err(e) if e.is::<StreamStalled>() => {
+                            log::warn!(
+                                "{name}::{source}: No frames for {}s, restarting the stream",
+                                stall_timeout.as_secs()
+                            );
+                            if can_fall_back && !falling_back {
+                                failures += 1;
+                            }
+                            thread_camera.reconnect().await?;
+                        }
+                        Err(e) if can_fall_back && !falling_back => {
+                            failures += 1;
+                            log::debug!("{name}::{stream}: Failed {failures} times: {e:?}");
+                            sleep(Duration::from_secs(1)).await;
+                        }
+                        res => break res,
+                    }
+                    if failures >= FALLBACK_FAILURES {
+                        log::warn!(
+                            "{name}::{stream}: Failed {failures} times, sending the sub stream in its place"
+                        );
+                        failures = 0;
+                        fallback_tx.send_replace(Some(tokio::time::Instant::now()));
+                    }
+                }
             })
             .and_then(|res| async move {
                 log::debug!("Camera finished streaming: {res:?}");
@@ -205,3 +268,21 @@ impl NeoInstance {
         Ok(media_rx)
     }
 }
+
+/// Times the main stream may fail before the sub stream is sent instead
+const FALLBACK_FAILURES: usize = 3;
+
+/// How long new streams use the sub stream before trying the main again
+const FALLBACK_RETRY: Duration = Duration::from_secs(300);
+
+/// The camera stopped sending frames but the connection is still up
+#[derive(Debug)]
+struct StreamStalled;
+
+impl std::fmt::Display for StreamStalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "The stream stopped sending frames")
+    }
+}
+
+impl std::error::Error for StreamStalled {}
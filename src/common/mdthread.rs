@@ -15,7 +15,7 @@ use tokio_util::sync::CancellationToken;
 
 use super::NeoInstance;
 use crate::{AnyResult, Result};
-use neolink_core::bc_protocol::MotionStatus;
+use neolink_core::bc_protocol::{AiDetection, MotionStatus};
 
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
@@ -27,6 +27,9 @@ pub(crate) enum MdState {
 
 pub(crate) struct NeoCamMdThread {
     md_watcher: Arc<WatchSender<MdState>>,
+    recording_watcher: Arc<WatchSender<Option<bool>>>,
+    visitor_watcher: Arc<WatchSender<Option<bool>>>,
+    ai_watcher: Arc<WatchSender<Option<AiDetection>>>,
     md_request_rx: MpscReceiver<MdRequest>,
     cancel: CancellationToken,
     instance: NeoInstance,
@@ -39,8 +42,14 @@ impl NeoCamMdThread {
     ) -> Result<Self> {
         let (md_watcher, _) = watch(MdState::Unknown);
         let md_watcher = Arc::new(md_watcher);
+        let (recording_watcher, _) = watch(None);
+        let (visitor_watcher, _) = watch(None);
+        let (ai_watcher, _) = watch(None);
         Ok(Self {
             md_watcher,
+            recording_watcher: Arc::new(recording_watcher),
+            visitor_watcher: Arc::new(visitor_watcher),
+            ai_watcher: Arc::new(ai_watcher),
             md_request_rx,
             cancel: CancellationToken::new(),
             instance,
@@ -50,6 +59,9 @@ impl NeoCamMdThread {
     pub(crate) async fn run(&mut self) -> Result<()> {
         let thread_cancel = self.cancel.clone();
         let watcher = self.md_watcher.clone();
+        let recording_watcher = self.recording_watcher.clone();
+        let visitor_watcher = self.visitor_watcher.clone();
+        let ai_watcher = self.ai_watcher.clone();
         let md_instance = self.instance.clone();
         tokio::select! {
             _ = thread_cancel.cancelled() => {
@@ -63,6 +75,21 @@ impl NeoCamMdThread {
                         } => {
                           let _ = sender.send(self.md_watcher.subscribe());
                         },
+                        MdRequest::Recording {
+                            sender
+                        } => {
+                          let _ = sender.send(self.recording_watcher.subscribe());
+                        },
+                        MdRequest::Visitor {
+                            sender
+                        } => {
+                          let _ = sender.send(self.visitor_watcher.subscribe());
+                        },
+                        MdRequest::Ai {
+                            sender
+                        } => {
+                          let _ = sender.send(self.ai_watcher.subscribe());
+                        },
                     }
                 }
                 Ok(())
@@ -71,11 +98,35 @@ impl NeoCamMdThread {
                 loop {
                     let r: AnyResult<()> = md_instance.run_passive_task(|cam| {
                         let watcher = watcher.clone();
+                        let recording_watcher = recording_watcher.clone();
+                        let visitor_watcher = visitor_watcher.clone();
+                        let ai_watcher = ai_watcher.clone();
                         Box::pin(
                         async move {
                             let mut md = cam.listen_on_motion().await.with_context(|| "Error in getting MD listen_on_motion")?;
                             loop {
                                 let event = md.next_motion().await.with_context(|| "Error in getting MD next_motion")?;
+                                // The recording status comes with every alarm event
+                                let recording = md.recording();
+                                recording_watcher.send_if_modified(|old| {
+                                    let changed = *old != recording;
+                                    *old = recording;
+                                    changed
+                                });
+                                // So is the doorbell button
+                                let visitor = md.visitor();
+                                visitor_watcher.send_if_modified(|old| {
+                                    let changed = *old != visitor;
+                                    *old = visitor;
+                                    changed
+                                });
+                                // And what the AI classified the motion as
+                                let ai = md.ai();
+                                ai_watcher.send_if_modified(|old| {
+                                    let changed = *old != ai;
+                                    *old = ai;
+                                    changed
+                                });
                                 match event {
                                     MotionStatus::Start(at) => {
                                         watcher.send_replace(
@@ -92,6 +143,10 @@ impl NeoCamMdThread {
                             }
                         }
                     )}).await;
+                    // We cannot know once the connection is lost
+                    recording_watcher.send_replace(None);
+                    visitor_watcher.send_replace(None);
+                    ai_watcher.send_replace(None);
                     log::debug!("Error in MD task Restarting: {:?}", r);
                     sleep(Duration::from_secs(1)).await;
                 }
@@ -113,4 +168,13 @@ pub(crate) enum MdRequest {
     Get {
         sender: OneshotSender<WatchReceiver<MdState>>,
     },
+    Recording {
+        sender: OneshotSender<WatchReceiver<Option<bool>>>,
+    },
+    Visitor {
+        sender: OneshotSender<WatchReceiver<Option<bool>>>,
+    },
+    Ai {
+        sender: OneshotSender<WatchReceiver<Option<AiDetection>>>,
+    },
 }
@@ -1,17 +1,72 @@
+mod api;
 mod camthread;
+mod control;
+mod devinfo;
+mod dispatch;
+#[cfg(feature = "gstreamer")]
+mod gstelements;
+mod health;
+#[cfg(feature = "gstreamer")]
+mod hlsthread;
+mod httpd;
 mod instance;
 mod mdthread;
 mod neocam;
 #[cfg(feature = "pushnoti")]
 mod pushnoti;
+#[cfg(feature = "gstreamer")]
+mod pushthread;
 mod reactor;
+#[cfg(feature = "gstreamer")]
+mod recordthread;
+mod reload;
+mod schedulethread;
+#[cfg(unix)]
+mod sdnotify;
+mod sessions;
+mod settings;
+#[cfg(feature = "gstreamer")]
+mod sndthread;
+mod streamstats;
+#[cfg(feature = "gstreamer")]
+mod timelapsethread;
 mod usecounter;
+mod webhookthread;
+#[cfg(feature = "gstreamer")]
+mod whep;
 
+pub(crate) use api::*;
 pub(crate) use camthread::*;
+pub(crate) use control::*;
+pub(crate) use devinfo::*;
+pub(crate) use dispatch::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use gstelements::*;
+pub(crate) use health::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use hlsthread::*;
+pub(crate) use httpd::*;
 pub(crate) use instance::*;
 pub(crate) use mdthread::*;
 pub(crate) use neocam::*;
 #[cfg(feature = "pushnoti")]
 pub(crate) use pushnoti::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use pushthread::*;
 pub(crate) use reactor::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use recordthread::*;
+pub(crate) use schedulethread::*;
+#[cfg(unix)]
+pub(crate) use sdnotify::*;
+pub(crate) use sessions::*;
+pub(crate) use settings::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use sndthread::*;
+pub(crate) use streamstats::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use timelapsethread::*;
 pub(crate) use usecounter::*;
+pub(crate) use webhookthread::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use whep::*;
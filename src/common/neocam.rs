@@ -6,41 +6,70 @@
 //!    Clonable interface to share amongst threadsanyhow::anyhow;
 use anyhow::Context;
 use futures::{stream::StreamExt, TryFutureExt};
-use std::sync::Weak;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Weak},
+};
 use tokio::{
     sync::{
         mpsc::{channel as mpsc, Sender as MpscSender},
         oneshot::{channel as oneshot, Sender as OneshotSender},
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
+        Notify,
     },
     task::JoinSet,
-    time::{sleep, Duration},
+    time::{sleep, timeout_at, Duration, Instant},
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
 use super::{
-    MdRequest, MdState, NeoCamMdThread, NeoCamThread, NeoCamThreadState, NeoInstance, Permit,
+    CameraFailure, MdRequest, MdState, NeoCamMdThread, NeoCamScheduleThread, NeoCamThread,
+    NeoCamThreadState, NeoCamWebhookThread, NeoInstance, Permit, SessionRegistry, StreamStatsMap,
     UseCounter,
 };
+#[cfg(feature = "gstreamer")]
+use super::{
+    NeoCamHlsThread, NeoCamPushThread, NeoCamRecordThread, NeoCamSoundThread,
+    NeoCamTimelapseThread, SoundRequest, SoundState,
+};
 #[cfg(feature = "pushnoti")]
 use super::{PnRequest, PushNoti};
 use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::BcCamera;
+use neolink_core::bc_protocol::{AiDetection, BcCamera, EncryptionLevel};
+
+/// Reconnects asked for this soon after the last one are dropped, such as
+/// when the main and sub streams stall together
+const RECONNECT_DEBOUNCE: Duration = Duration::from_secs(5);
 
 #[allow(dead_code)]
 pub(crate) enum NeoCamCommand {
     HangUp,
     Instance(OneshotSender<Result<NeoInstance>>),
     Motion(OneshotSender<WatchReceiver<MdState>>),
+    Recording(OneshotSender<WatchReceiver<Option<bool>>>),
+    Visitor(OneshotSender<WatchReceiver<Option<bool>>>),
+    Ai(OneshotSender<WatchReceiver<Option<AiDetection>>>),
     Config(OneshotSender<WatchReceiver<CameraConfig>>),
     Disconnect(OneshotSender<()>),
     Connect(OneshotSender<()>),
+    Disable(OneshotSender<()>),
+    Enable(OneshotSender<()>),
+    Reconnect(OneshotSender<()>),
     State(OneshotSender<NeoCamThreadState>),
     GetPermit(OneshotSender<Permit>),
+    Users(OneshotSender<WatchReceiver<u32>>),
+    RtspClients(OneshotSender<Arc<WatchSender<u32>>>),
+    StreamStats(OneshotSender<Arc<WatchSender<StreamStatsMap>>>),
+    Fallback(OneshotSender<Arc<WatchSender<Option<Instant>>>>),
+    Failure(OneshotSender<WatchReceiver<Option<CameraFailure>>>),
     #[cfg(feature = "pushnoti")]
     PushNoti(OneshotSender<WatchReceiver<Option<PushNoti>>>),
     GetUid(OneshotSender<String>),
+    #[cfg(feature = "gstreamer")]
+    Sound(OneshotSender<WatchReceiver<SoundState>>),
+    #[cfg(feature = "gstreamer")]
+    SoundLevel(OneshotSender<WatchReceiver<Option<f64>>>),
 }
 /// The underlying camera binding
 pub(crate) struct NeoCam {
@@ -54,14 +83,27 @@ pub(crate) struct NeoCam {
 impl NeoCam {
     pub(crate) async fn new(
         config: CameraConfig,
+        session_dir: PathBuf,
         #[cfg(feature = "pushnoti")] pn_request_tx: MpscSender<PnRequest>,
     ) -> Result<NeoCam> {
         let (commander_tx, commander_rx) = mpsc(100);
         let (watch_config_tx, watch_config_rx) = watch(config.clone());
         let (camera_watch_tx, camera_watch_rx) = watch(Weak::new());
         let (md_request_tx, md_request_rx) = mpsc(100);
+        #[cfg(feature = "gstreamer")]
+        let (sound_request_tx, sound_request_rx) = mpsc(100);
         let (state_tx, state_rx) = watch(NeoCamThreadState::Connected);
         let (uid_tx, uid_rx) = watch(config.camera_uid.clone());
+        let reconnect = Arc::new(Notify::new());
+        // Updated by the rtsp server when it is in the same process
+        let (rtsp_clients_tx, _) = watch(0u32);
+        let rtsp_clients_tx = Arc::new(rtsp_clients_tx);
+        let (stream_stats_tx, _) = watch(StreamStatsMap::new());
+        let stream_stats_tx = Arc::new(stream_stats_tx);
+        // When the main stream started falling back to the sub stream
+        let (fallback_tx, _) = watch(None);
+        let fallback_tx = Arc::new(fallback_tx);
+        let (failure_tx, failure_rx) = watch(None);
 
         let set = JoinSet::new();
         let users = UseCounter::new().await;
@@ -83,6 +125,7 @@ impl NeoCam {
         let mut commander_rx = ReceiverStream::new(commander_rx);
         let thread_commander_tx = commander_tx.clone();
         let thread_watch_config_rx = watch_config_rx.clone();
+        let thread_reconnect = reconnect.clone();
         #[cfg(feature = "pushnoti")]
         let thread_pn_request_tx = pn_request_tx.clone();
 
@@ -93,6 +136,7 @@ impl NeoCam {
                     Result::Ok(())
                 },
                 v = async {
+                    let mut last_reconnect: Option<Instant> = None;
                     while let Some(command) = commander_rx.next().await {
                         match command {
                             NeoCamCommand::HangUp => {
@@ -114,27 +158,85 @@ impl NeoCam {
                                     }
                                 ).await?;
                             },
+                            NeoCamCommand::Recording(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Recording {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Visitor(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Visitor {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Ai(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Ai {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Failure(sender) => {
+                                let _ = sender.send(failure_rx.clone());
+                            },
                             NeoCamCommand::Config(sender) => {
                                 let _ = sender.send(thread_watch_config_rx.clone());
                             },
                             NeoCamCommand::Connect(sender) => {
-                                if !matches!(*state_tx.borrow(), NeoCamThreadState::Connected) {
+                                if matches!(*state_tx.borrow(), NeoCamThreadState::Disconnected) {
                                     state_tx.send_replace(NeoCamThreadState::Connected);
                                 }
                                 let _ = sender.send(());
                             }
                             NeoCamCommand::Disconnect(sender) => {
-                                if !matches!(*state_tx.borrow(), NeoCamThreadState::Disconnected) {
+                                if matches!(*state_tx.borrow(), NeoCamThreadState::Connected) {
                                     state_tx.send_replace(NeoCamThreadState::Disconnected);
                                 }
                                 let _ = sender.send(());
                             }
+                            NeoCamCommand::Disable(sender) => {
+                                if !matches!(*state_tx.borrow(), NeoCamThreadState::Disabled) {
+                                    state_tx.send_replace(NeoCamThreadState::Disabled);
+                                }
+                                let _ = sender.send(());
+                            }
+                            NeoCamCommand::Enable(sender) => {
+                                if !matches!(*state_tx.borrow(), NeoCamThreadState::Connected) {
+                                    state_tx.send_replace(NeoCamThreadState::Connected);
+                                }
+                                let _ = sender.send(());
+                            }
+                            NeoCamCommand::Reconnect(sender) => {
+                                if last_reconnect.is_none_or(|last| last.elapsed() >= RECONNECT_DEBOUNCE) {
+                                    last_reconnect = Some(Instant::now());
+                                    // Kept until the camera thread next waits for it
+                                    thread_reconnect.notify_one();
+                                } else {
+                                    log::debug!("Already reconnecting");
+                                }
+                                let _ = sender.send(());
+                            }
                             NeoCamCommand::State(sender) => {
                                 let _ = sender.send(*state_tx.borrow());
                             }
                             NeoCamCommand::GetPermit(sender) => {
                                 let _ = sender.send(users.create_activated().await?);
                             }
+                            NeoCamCommand::Users(sender) => {
+                                let _ = sender.send(users.users());
+                            }
+                            NeoCamCommand::RtspClients(sender) => {
+                                let _ = sender.send(rtsp_clients_tx.clone());
+                            }
+                            NeoCamCommand::StreamStats(sender) => {
+                                let _ = sender.send(stream_stats_tx.clone());
+                            }
+                            NeoCamCommand::Fallback(sender) => {
+                                let _ = sender.send(fallback_tx.clone());
+                            }
                             #[cfg(feature = "pushnoti")]
                             NeoCamCommand::PushNoti(sender) => {
                                 thread_pn_request_tx.send(
@@ -143,6 +245,22 @@ impl NeoCam {
                                     }
                                 ).await?;
                             },
+                            #[cfg(feature = "gstreamer")]
+                            NeoCamCommand::Sound(sender) => {
+                                sound_request_tx.send(
+                                    SoundRequest::Get {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            #[cfg(feature = "gstreamer")]
+                            NeoCamCommand::SoundLevel(sender) => {
+                                sound_request_tx.send(
+                                    SoundRequest::Level {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
                             NeoCamCommand::GetUid(sender) => {
                                 let mut uid_rx = uid_rx.clone();
                                 tokio::task::spawn(async move {
@@ -176,6 +294,9 @@ impl NeoCam {
             state_rx,
             thread_watch_config_rx,
             camera_watch_tx,
+            reconnect,
+            SessionRegistry::new(&session_dir, &config.name),
+            failure_tx,
             me.cancel.clone(),
         )
         .await;
@@ -194,6 +315,121 @@ impl NeoCam {
             }
         });
 
+        // This thread listens to the audio for sounds
+        //
+        // It idles unless audio_detect is configured
+        #[cfg(feature = "gstreamer")]
+        {
+            let sound_instance = instance.subscribe().await?;
+            let sound_cancel = me.cancel.clone();
+            let mut sound_thread = NeoCamSoundThread::new(sound_request_rx, sound_instance).await?;
+            me.set.spawn(async move {
+                tokio::select! {
+                    _ = sound_cancel.cancelled() => AnyResult::Ok(()),
+                    v = sound_thread.run() => {
+                        v
+                    },
+                }
+            });
+        }
+
+        // This thread captures the timelapse stills
+        //
+        // It idles unless timelapse is configured
+        #[cfg(feature = "gstreamer")]
+        {
+            let timelapse_instance = instance.subscribe().await?;
+            let timelapse_cancel = me.cancel.clone();
+            let mut timelapse_thread = NeoCamTimelapseThread::new(timelapse_instance).await?;
+            me.set.spawn(async move {
+                tokio::select! {
+                    _ = timelapse_cancel.cancelled() => AnyResult::Ok(()),
+                    v = timelapse_thread.run() => {
+                        v
+                    },
+                }
+            });
+        }
+
+        // This thread records on motion or continuously
+        //
+        // It idles unless record is configured
+        #[cfg(feature = "gstreamer")]
+        {
+            let record_instance = instance.subscribe().await?;
+            // It stops itself on the cancel so that it can finish the file
+            let mut record_thread =
+                NeoCamRecordThread::new(record_instance, me.cancel.child_token()).await?;
+            me.set.spawn(async move { record_thread.run().await });
+        }
+
+        // This thread writes the hls playlist
+        //
+        // It idles unless hls is configured
+        #[cfg(feature = "gstreamer")]
+        {
+            let hls_instance = instance.subscribe().await?;
+            let hls_cancel = me.cancel.clone();
+            let mut hls_thread = NeoCamHlsThread::new(hls_instance).await?;
+            me.set.spawn(async move {
+                tokio::select! {
+                    _ = hls_cancel.cancelled() => AnyResult::Ok(()),
+                    v = hls_thread.run() => {
+                        v
+                    },
+                }
+            });
+        }
+
+        // This thread publishes the streams to other servers
+        //
+        // It idles unless push is configured
+        #[cfg(feature = "gstreamer")]
+        {
+            let push_instance = instance.subscribe().await?;
+            let push_cancel = me.cancel.clone();
+            let mut push_thread = NeoCamPushThread::new(push_instance).await?;
+            me.set.spawn(async move {
+                tokio::select! {
+                    _ = push_cancel.cancelled() => AnyResult::Ok(()),
+                    v = push_thread.run() => {
+                        v
+                    },
+                }
+            });
+        }
+
+        // This thread posts the events to a webhook
+        //
+        // It idles unless webhooks is configured
+        let webhook_instance = instance.subscribe().await?;
+        let webhook_cancel = me.cancel.clone();
+        let mut webhook_thread = NeoCamWebhookThread::new(webhook_instance).await?;
+        me.set.spawn(async move {
+            tokio::select! {
+                _ = webhook_cancel.cancelled() => AnyResult::Ok(()),
+                v = webhook_thread.run() => {
+                    v
+                },
+            }
+        });
+
+        // This thread disconnects the camera at the times in its schedule
+        // and reboots it on its reboot_cron
+        //
+        // It idles unless one of them is configured
+        let schedule_instance = instance.subscribe().await?;
+        let schedule_cancel = me.cancel.clone();
+        let mut schedule_thread = NeoCamScheduleThread::new(schedule_instance).await?;
+        me.set.spawn(async move {
+            tokio::select! {
+                _ = schedule_cancel.cancelled() => AnyResult::Ok(()),
+                v = schedule_thread.run() => {
+                    v
+                },
+            }
+        });
+
         // This thread just does a one time report on camera info
         let report_instance = instance.subscribe().await?;
         let report_cancel = me.cancel.clone();
@@ -212,6 +448,20 @@ impl NeoCam {
                     log::info!("{}: Model {}", report_name, version.model.unwrap_or("Undeclared".to_string()));
                     log::info!("{}: Firmware Version {}", report_name, version.firmwareVersion);
 
+                    let level = report_instance.run_task(|cam| Box::pin(
+                        async move {
+                            Ok(cam.encryption_level())
+                        }
+                    )).await?;
+                    match level {
+                        Some(level @ (EncryptionLevel::None | EncryptionLevel::BcEncrypt)) => log::warn!(
+                            "{}: Camera negotiated {} encryption, the login and control messages can be read by anyone on the network path. Set min_protocol_security = \"aes\" to refuse this",
+                            report_name, level
+                        ),
+                        Some(level) => log::info!("{}: Encryption {}", report_name, level),
+                        None => log::info!("{}: Encryption Unknown", report_name),
+                    }
+
                     let stream_info = report_instance.run_task(|cam| Box::pin(
                         async move {
                             Ok(cam.get_stream_info().await?)
@@ -272,6 +522,7 @@ impl NeoCam {
                             rx.await??;
 
                             let pn_permit_instance = pn_root_instance.subscribe().await?;
+                            let duration_rx = config_rx.clone();
                             let r = tokio::select! {
                                 // Push notification permits
                                 v = async {
@@ -280,7 +531,12 @@ impl NeoCam {
                                     loop{
                                         prev_noti = pn.wait_for(|noti| noti != &prev_noti && noti.is_some()).await.map(|noti| noti.clone())?;
                                         let _permit = pn_permit_instance.permit().await?;
-                                        sleep(Duration::from_secs(30)).await; // Push notification will wake us up for 30s
+                                        // Push notification will wake us up for 30s or for the motion_gated_duration
+                                        let awake = {
+                                            let config = duration_rx.borrow();
+                                            if config.motion_gated { config.motion_gated_duration } else { 30 }
+                                        };
+                                        sleep(Duration::from_secs(awake)).await;
                                     }
                                 } => v,
                                 // Continue loop on Red light
@@ -345,11 +601,18 @@ impl NeoCam {
                     let mut config_rx = connect_instance.config().await?;
                     loop {
                         // Wait for the green light
-                        config_rx.wait_for(|config| config.idle_disconnect).await?;
+                        config_rx.wait_for(|config| config.idle_disconnect || config.motion_gated || config.pause.disconnect).await?;
+                        let timeout_rx = config_rx.clone();
+                        if config_rx.borrow().motion_gated && !cfg!(feature = "pushnoti") {
+                            log::warn!("{}: motion_gated needs push notifications to wake the camera but neolink was built without pushnoti", config_rx.borrow().name);
+                        }
+                        if config_rx.borrow().pause.disconnect && config_rx.borrow().pause.on_motion && !cfg!(feature = "pushnoti") {
+                            log::warn!("{}: pause.disconnect stops the motion detection so pause.on_motion needs push notifications to resume but neolink was built without pushnoti", config_rx.borrow().name);
+                        }
 
                         let r = tokio::select!{
                             // Wait for red light
-                            v = config_rx.wait_for(|config| !config.idle_disconnect).map_ok(|_| ()) => {
+                            v = config_rx.wait_for(|config| !config.idle_disconnect && !config.motion_gated && !config.pause.disconnect).map_ok(|_| ()) => {
                                 v?;
                                 connect_instance.connect().await?; // Ensure we are online now that we are not idle_disconnect
                                 AnyResult::Ok(())
@@ -362,9 +625,19 @@ impl NeoCam {
                                     permit.aquired_users().await?;
                                     connect_instance.connect().await?;
                                     permit.dropped_users().await?;
-                                    // Wait 30s or if we hit another use then go back and wait again
+                                    // Wait for the idle_timeout or if we hit another use then go back and wait again
+                                    //
+                                    // With only pause.disconnect the streams have all paused so it goes straight away
+                                    let idle_timeout = {
+                                        let config = timeout_rx.borrow();
+                                        if config.idle_disconnect || config.motion_gated {
+                                            Duration::from_secs(config.idle_timeout)
+                                        } else {
+                                            Duration::ZERO
+                                        }
+                                    };
                                     tokio::select! {
-                                        _ = sleep(Duration::from_secs(30)) => {},
+                                        _ = sleep(idle_timeout) => {},
                                         _ = permit.aquired_users() => continue,
                                     };
                                     connect_instance.disconnect().await?;
@@ -394,9 +667,45 @@ impl NeoCam {
     }
 
     pub(crate) async fn update_config(&self, config: CameraConfig) -> Result<()> {
-        self.config_watch.send_replace(config);
+        // Only a real change should reconnect the camera
+        self.config_watch.send_if_modified(|current| {
+            if *current != config {
+                *current = config;
+                true
+            } else {
+                false
+            }
+        });
         Ok(())
     }
+
+    /// Stop the threads of the camera and wait for them until `deadline`
+    ///
+    /// This gives the recordings the chance to finish their files. The
+    /// threads that are still running at the deadline are aborted.
+    pub(crate) async fn shutdown(mut self, deadline: Instant) {
+        let name = self.config_watch.borrow().name.clone();
+        let connected = self.camera_watch.borrow().upgrade().is_some();
+        let started = Instant::now();
+        self.cancel.cancel();
+        let mut set = std::mem::take(&mut self.set);
+        let _ = timeout_at(deadline, async { while set.join_next().await.is_some() {} }).await;
+        let waited = started.elapsed().as_secs_f32();
+        let state = if connected {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        if set.is_empty() {
+            log::info!("{name}: Stopped in {waited:.1}s, the camera was {state}");
+        } else {
+            log::warn!(
+                "{name}: {} tasks did not stop within {waited:.1}s and were aborted, the camera was {state}",
+                set.len()
+            );
+            set.abort_all();
+        }
+    }
 }
 
 impl Drop for NeoCam {
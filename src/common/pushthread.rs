@@ -0,0 +1,227 @@
+//! This thread publishes the camera to other servers such as MediaMTX or
+//! YouTube
+//!
+//! This is only active on cameras with `[[cameras.push]]`
+//!
+//! `rtsp://` urls are published with RECORD through `rtspclientsink` and
+//! `rtmp://` urls through `flvmux` and `rtmp2sink`. This runs alongside the
+//! builtin rtsp server, set `stream = "none"` on the camera to only push.
+//!
+//! Only the video is pushed
+
+use anyhow::{anyhow, Context};
+use gstreamer::{prelude::*, BufferFlags, Element, MessageView, Pipeline};
+use gstreamer_app::AppSrc;
+use neolink_core::{
+    bc_protocol::StreamKind,
+    bcmedia::model::{BcMedia, BcMediaIframe, BcMediaPframe, VideoType},
+};
+use tokio::{
+    task::JoinSet,
+    time::{interval, sleep, Duration, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{make_element, make_video_source, NeoInstance};
+use crate::{config::PushConfig, AnyResult, Result};
+
+pub(crate) struct NeoCamPushThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamPushThread {
+    pub(crate) async fn new(instance: NeoInstance) -> Result<Self> {
+        Ok(Self {
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                let mut config_rx = instance.config().await?;
+                loop {
+                    // Wait for the green light
+                    let push_configs = config_rx
+                        .wait_for(|config| !config.push.is_empty())
+                        .await?
+                        .push
+                        .clone();
+
+                    let mut set = JoinSet::new();
+                    for push_config in push_configs.iter().cloned() {
+                        let instance = instance.clone();
+                        set.spawn(async move {
+                            loop {
+                                if let Err(e) = push(&instance, &push_config).await {
+                                    log::warn!(
+                                        "Error pushing to {} Restarting: {:?}",
+                                        push_config.url,
+                                        e
+                                    );
+                                }
+                                sleep(Duration::from_secs(10)).await;
+                            }
+                        });
+                    }
+
+                    // Config changed go back and restart with the new one
+                    let r = config_rx
+                        .wait_for(|config| config.push != push_configs)
+                        .await
+                        .map(|_| ());
+                    set.shutdown().await;
+                    r?;
+                }
+            } => v
+        }
+    }
+}
+
+impl Drop for NeoCamPushThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamPushThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamPushThread");
+    }
+}
+
+async fn push(instance: &NeoInstance, config: &PushConfig) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let stream: StreamKind = config
+        .stream
+        .as_stream_kinds()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No stream to push"))?;
+    log::info!("{name}: Pushing {stream} to {}", config.url);
+
+    let mut media_rx = instance.stream(stream).await?;
+    let mut publisher: Option<Publisher> = None;
+    let mut check = interval(Duration::from_secs(1));
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            media = media_rx.recv() => {
+                let Some(media) = media else {
+                    return Err(anyhow!("The camera stream ended"));
+                };
+                let (data, video_type, keyframe) = match media {
+                    BcMedia::Iframe(BcMediaIframe {
+                        data, video_type, ..
+                    }) => (data, video_type, true),
+                    BcMedia::Pframe(BcMediaPframe {
+                        data, video_type, ..
+                    }) => (data, video_type, false),
+                    _ => continue,
+                };
+                if publisher.is_none() {
+                    if !keyframe {
+                        // Wait for an iframe to start on
+                        continue;
+                    }
+                    publisher = Some(Publisher::new(&config.url, video_type)?);
+                }
+                publisher
+                    .as_ref()
+                    .expect("Set above")
+                    .push(data, keyframe)?;
+            }
+            _ = check.tick() => {
+                if let Some(publisher) = publisher.as_ref() {
+                    publisher.check()?;
+                }
+            }
+        }
+    }
+}
+
+/// The pipeline that publishes to the server
+///
+/// It is made on the first iframe since that tells us the codec
+struct Publisher {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+}
+
+impl Publisher {
+    fn new(url: &str, video_type: VideoType) -> AnyResult<Self> {
+        gstreamer::init().context("Gstreamer failed to initialise")?;
+        let pipeline = Pipeline::new();
+        let rtmp = url.to_lowercase().starts_with("rtmp");
+
+        if rtmp && matches!(video_type, VideoType::H265) {
+            return Err(anyhow!(
+                "RTMP can only carry H264 but the camera sends H265, push with rtsp:// instead"
+            ));
+        }
+        let (source, parser) = make_video_source("push", video_type, true)?;
+        // Repeat the parameter sets so that the server can start on any iframe
+        parser.set_property("config-interval", -1i32);
+        if rtmp {
+            let muxer = make_element("flvmux", "pushmux")?;
+            muxer.set_property("streamable", true);
+            let sink = match make_element("rtmp2sink", "pushsink") {
+                Ok(ele) => Ok(ele),
+                Err(_) => make_element("rtmpsink", "pushsink"),
+            }?;
+            sink.set_property("location", url);
+            pipeline.add_many([&source, &parser, &muxer, &sink])?;
+            Element::link_many([&source, &parser, &muxer, &sink])?;
+        } else {
+            let sink = make_element("rtspclientsink", "pushsink")?;
+            sink.set_property("location", url);
+            pipeline.add_many([&source, &parser, &sink])?;
+            Element::link_many([&source, &parser, &sink])?;
+        }
+        pipeline.set_state(gstreamer::State::Playing)?;
+
+        let appsrc = source
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        Ok(Self { pipeline, appsrc })
+    }
+
+    fn push(&self, data: Vec<u8>, keyframe: bool) -> AnyResult<()> {
+        let mut buffer = gstreamer::Buffer::from_slice(data);
+        if !keyframe {
+            buffer
+                .get_mut()
+                .ok_or(anyhow!("Push buffer is not writable"))?
+                .set_flags(BufferFlags::DELTA_UNIT);
+        }
+        self.appsrc.push_buffer(buffer)?;
+        Ok(())
+    }
+
+    /// Fail once the server drops the stream
+    fn check(&self) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .ok_or(anyhow!("Push pipeline has no bus"))?;
+        while let Some(msg) = bus.pop() {
+            match msg.view() {
+                MessageView::Error(e) => {
+                    return Err(anyhow!("Error in push pipeline: {}", e.error()));
+                }
+                MessageView::Eos(_) => return Err(anyhow!("The server ended the push")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+    }
+}
@@ -1,8 +1,10 @@
 //! This is the highest level to a camera
 //! it represents a collection of managed cameras
 use anyhow::anyhow;
+use futures::future::join_all;
 use std::{
     collections::{hash_map::Entry, HashMap},
+    path::PathBuf,
     sync::Arc,
 };
 #[cfg(feature = "pushnoti")]
@@ -14,10 +16,14 @@ use tokio::{
         watch::{channel as watch, Receiver as WatchReceiver},
     },
     task::JoinSet,
+    time::Instant,
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{NeoCam, NeoInstance};
+use super::{
+    reload::{warn_needs_restart, ConfigFile},
+    NeoCam, NeoInstance,
+};
 #[cfg(feature = "pushnoti")]
 use crate::common::PushNotiThread;
 use crate::{config::Config, AnyResult, Result};
@@ -28,18 +34,21 @@ enum NeoReactorCommand {
     Config(OneshotSender<WatchReceiver<Config>>),
     UpdateConfig(Config, OneshotSender<Result<()>>),
     Get(String, OneshotSender<Result<Option<NeoInstance>>>),
+    Shutdown(Instant, OneshotSender<()>),
 }
 
 /// Reactor handles the collection of cameras
 #[derive(Clone)]
 pub(crate) struct NeoReactor {
     cancel: CancellationToken,
+    stopping: CancellationToken,
     commander: MpscSender<NeoReactorCommand>,
     set: Option<Arc<JoinSet<AnyResult<()>>>>,
 }
 
 impl NeoReactor {
-    pub(crate) async fn new(config: Config) -> Self {
+    /// Changes to the file at `config_path` are applied as they are made
+    pub(crate) async fn new(config: Config, config_path: PathBuf) -> Self {
         let (commad_tx, mut command_rx) = mpsc(100);
         #[cfg(feature = "pushnoti")]
         let (push_noti, mut pn_rx) = mpsc(10);
@@ -69,26 +78,41 @@ impl NeoReactor {
                                 cancel2.cancel();
                                 return Result::<(), anyhow::Error>::Ok(());
                             }
+                            NeoReactorCommand::Shutdown(deadline, reply) =>  {
+                                // All at once so that each camera has until the deadline
+                                join_all(instances.drain().map(|(_, instance)| instance.shutdown(deadline))).await;
+                                cancel2.cancel();
+                                let _ = reply.send(());
+                                return Result::<(), anyhow::Error>::Ok(());
+                            }
                             NeoReactorCommand::Config(reply) =>  {
                                 let _ = reply.send(thread_config_tx.subscribe());
                             }
                             NeoReactorCommand::Get(name, sender) => {
+                                // The errors go back to the caller, one camera that
+                                // fails must not stop the reactor for the others
                                 let new = match instances.entry(name.clone()) {
-                                    Entry::Occupied(occ) => Result::Ok(Some(occ.get().subscribe().await?)),
+                                    Entry::Occupied(occ) => match occ.get().subscribe().await {
+                                        Ok(instance) => Result::Ok(Some(instance)),
+                                        Err(e) => {
+                                            // Its threads have stopped, it is made again on the next get
+                                            log::warn!("{name}: Camera stopped: {e:?}");
+                                            occ.remove();
+                                            Err(e)
+                                        }
+                                    },
                                     Entry::Vacant(vac) => {
                                         let current_config: Config = (*thread_config_tx.borrow()).clone();
                                         if let Some(config) = current_config.cameras.iter().find(|cam| cam.name == name).cloned() {
+                                            let session_dir = current_config.session_dir.clone();
                                             #[cfg(feature = "pushnoti")]
-                                            let cam = NeoCam::new(config, push_noti.clone()).await?;
+                                            let cam = NeoCam::new(config, session_dir, push_noti.clone()).await;
                                             #[cfg(not(feature = "pushnoti"))]
-                                            let cam = NeoCam::new(config).await?;
-                                            Result::Ok(Some(
-                                                vac.insert(
-                                                    cam,
-                                                )
-                                                .subscribe()
-                                                .await?
-                                            ))
+                                            let cam = NeoCam::new(config, session_dir).await;
+                                            match cam {
+                                                Ok(cam) => vac.insert(cam).subscribe().await.map(Some),
+                                                Err(e) => Err(e),
+                                            }
                                         } else {
                                             Result::Ok(None)
                                         }
@@ -120,6 +144,33 @@ impl NeoReactor {
             r
         });
 
+        // Apply changes to the config file
+        let cancel1 = cancel.clone();
+        let thread_commander = commad_tx.clone();
+        let thread_config_rx = config_tx.subscribe();
+        set.spawn(async move {
+            let mut config_file = ConfigFile::new(config_path);
+            tokio::select! {
+                _ = cancel1.cancelled() => AnyResult::Ok(()),
+                v = async {
+                    loop {
+                        let new_config = config_file.changed().await;
+                        let current_config = thread_config_rx.borrow().clone();
+                        if new_config == current_config {
+                            continue;
+                        }
+                        warn_needs_restart(&current_config, &new_config);
+                        log::info!("Config file changed, applying it");
+                        let (reply_tx, reply_rx) = oneshot();
+                        thread_commander
+                            .send(NeoReactorCommand::UpdateConfig(new_config, reply_tx))
+                            .await?;
+                        reply_rx.await??;
+                    }
+                } => v,
+            }
+        });
+
         // Push notification client
         #[cfg(feature = "pushnoti")]
         {
@@ -152,6 +203,7 @@ impl NeoReactor {
 
         Self {
             cancel,
+            stopping: CancellationToken::new(),
             commander: commad_tx,
             set: Some(Arc::new(set)),
         }
@@ -186,6 +238,23 @@ impl NeoReactor {
 
         sender_rx.await?
     }
+
+    /// Cancelled once neolink has been asked to stop
+    ///
+    /// The long running subcommands close their clients and return on it
+    pub(crate) fn stopping(&self) -> CancellationToken {
+        self.stopping.clone()
+    }
+
+    /// Stop all of the cameras, they are waited on until `deadline`
+    pub(crate) async fn shutdown(&self, deadline: Instant) -> Result<()> {
+        self.stopping.cancel();
+        let (sender_tx, sender_rx) = oneshot();
+        self.commander
+            .send(NeoReactorCommand::Shutdown(deadline, sender_tx))
+            .await?;
+        Ok(sender_rx.await?)
+    }
 }
 
 impl Drop for NeoReactor {
@@ -0,0 +1,604 @@
+//! This thread records the camera to disk while there is motion
+//! or all of the time in `segment` long files with `mode = "continuous"`
+//!
+//! This is only active on cameras with `[cameras.record]`
+//!
+//! The camera is streamed the whole time that recording is configured so
+//! that the last `pre_roll` seconds are at hand when the motion starts.
+//! The recordings are stored as
+//! `<output>/<camera>/<camera>-<YYYY-MM-DD>-<HHMMSS>.<mp4|mkv>` and the oldest
+//! are removed once they pass `max_age` or `max_size`.
+//!
+//! With `motion_gated` on the camera a recording is made each time that the
+//! camera is woken instead, it has no pre roll since the camera was asleep
+//!
+//! Only the video is recorded
+
+use anyhow::{anyhow, Context};
+use gstreamer::{prelude::*, BufferFlags, ClockTime, Element, MessageView, Pipeline};
+use gstreamer_app::AppSrc;
+use neolink_core::{
+    bc_protocol::StreamKind,
+    bcmedia::{
+        clock::MediaClock,
+        model::{BcMedia, BcMediaIframe, BcMediaPframe, VideoType},
+    },
+};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::time::{interval, sleep, sleep_until, Duration, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{date_name, make_element, make_video_source, MdState, NeoInstance};
+use crate::{
+    config::{RecordConfig, RecordFormat, RecordMode},
+    utils::{file_safe_name, now_local},
+    AnyResult, Result,
+};
+
+pub(crate) struct NeoCamRecordThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamRecordThread {
+    /// The thread stops once `cancel` is cancelled and the current file is finished
+    pub(crate) async fn new(instance: NeoInstance, cancel: CancellationToken) -> Result<Self> {
+        Ok(Self { cancel, instance })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        let mut config_rx = instance.config().await?;
+        loop {
+            // Wait for the green light
+            let record_config = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                v = config_rx.wait_for(|config| config.record.is_some()) => {
+                    v?.record.clone().expect("Checked by the wait_for")
+                }
+            };
+
+            let motion_gated = config_rx.borrow().motion_gated;
+
+            // The recording sees the cancel itself rather than being dropped
+            // so that the file it is writing is kept
+            let r = tokio::select! {
+                v = config_rx.wait_for(|config| config.record.as_ref() != Some(&record_config) || config.motion_gated != motion_gated) => {
+                    // Config changed go back and restart with the new one
+                    v?;
+                    AnyResult::Ok(())
+                },
+                v = record(&instance, &record_config, motion_gated, &cancel) => v,
+            };
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            if let Err(e) = r {
+                log::warn!("Error in record task Restarting: {:?}", e);
+                tokio::select! {
+                    _ = cancel.cancelled() => return Ok(()),
+                    _ = sleep(Duration::from_secs(10)) => {}
+                }
+            }
+        }
+    }
+}
+
+impl Drop for NeoCamRecordThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamRecordThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamRecordThread");
+    }
+}
+
+async fn record(
+    instance: &NeoInstance,
+    config: &RecordConfig,
+    motion_gated: bool,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let dir = config.output.join(file_safe_name(&name));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Could not create record directory {:?}", dir))?;
+    let stream: StreamKind = config
+        .stream
+        .as_stream_kinds()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No stream to record"))?;
+    if motion_gated {
+        return record_gated(instance, config, &name, &dir, stream, cancel).await;
+    }
+    if config.mode == RecordMode::Continuous {
+        return record_continuous(instance, config, &name, &dir, stream, cancel).await;
+    }
+    log::info!("{name}: Recording {stream} on motion to {:?}", dir);
+
+    let mut motion = instance.motion().await?;
+    let mut media_rx = instance.stream(stream).await?;
+    let mut pre_roll = PreRoll::new(Duration::from_secs(config.pre_roll));
+    let post_roll = Duration::from_secs(config.post_roll);
+    let mut recording: Option<Recording> = None;
+    let mut stop_at: Option<Instant> = None;
+    let mut prune = interval(Duration::from_secs(60 * 60));
+    prune.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let r = loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break Ok(()),
+            media = media_rx.recv() => {
+                let Some(media) = media else {
+                    break Err(anyhow!("The camera stream ended"));
+                };
+                let now = Instant::now();
+                match recording.as_mut() {
+                    Some(recording) => {
+                        if let Err(e) = recording.push(now, media) {
+                            log::warn!("{name}: Could not write the recording: {e:?}");
+                        }
+                    }
+                    None => pre_roll.push(now, media),
+                }
+            }
+            v = motion.changed() => {
+                if let Err(e) = v {
+                    break Err(e.into());
+                }
+                let state = motion.borrow_and_update().clone();
+                match state {
+                    MdState::Start(_) => {
+                        // Motion before the post roll ended continues the same recording
+                        stop_at = None;
+                        if recording.is_none() {
+                            let path = dir.join(recording_name(&name, config.format));
+                            log::info!("{name}: Motion recording to {:?}", path);
+                            let mut new_recording = Recording::new(path, config.format);
+                            for (at, media) in pre_roll.take() {
+                                if let Err(e) = new_recording.push(at, media) {
+                                    log::warn!("{name}: Could not write the recording: {e:?}");
+                                }
+                            }
+                            recording = Some(new_recording);
+                        }
+                    }
+                    MdState::Stop(_) => {
+                        if recording.is_some() {
+                            stop_at = Some(Instant::now() + post_roll);
+                        }
+                    }
+                    MdState::Unknown => {}
+                }
+            }
+            _ = sleep_until(stop_at.unwrap_or_else(Instant::now)), if stop_at.is_some() => {
+                stop_at = None;
+                if let Some(recording) = recording.take() {
+                    finish(&name, recording).await;
+                }
+                prune_recordings(&name, &dir, config).await;
+            }
+            _ = prune.tick() => {
+                prune_recordings(&name, &dir, config).await;
+            }
+        }
+    };
+
+    // Keep what was recorded before the stream was lost
+    if let Some(recording) = recording.take() {
+        finish(&name, recording).await;
+    }
+    r
+}
+
+/// Record all of the time, starting a new file every `segment` seconds
+async fn record_continuous(
+    instance: &NeoInstance,
+    config: &RecordConfig,
+    name: &str,
+    dir: &Path,
+    stream: StreamKind,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    log::info!("{name}: Recording {stream} continuously to {:?}", dir);
+
+    let mut media_rx = instance.stream(stream).await?;
+    let segment = Duration::from_secs(config.segment);
+    let mut recording =
+        Recording::new(dir.join(recording_name(name, config.format)), config.format);
+    let mut prune = interval(Duration::from_secs(60 * 60));
+    prune.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let r = loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break Ok(()),
+            media = media_rx.recv() => {
+                let Some(media) = media else {
+                    break Err(anyhow!("The camera stream ended"));
+                };
+                let now = Instant::now();
+                // The next file starts on an iframe so it can be decoded from its start
+                if matches!(media, BcMedia::Iframe(_))
+                    && recording
+                        .started()
+                        .is_some_and(|start| now.saturating_duration_since(start) >= segment)
+                {
+                    let next = Recording::new(dir.join(recording_name(name, config.format)), config.format);
+                    let done = std::mem::replace(&mut recording, next);
+                    // Finish in the background so that no frames are missed
+                    let done_name = name.to_string();
+                    tokio::task::spawn(async move { finish(&done_name, done).await });
+                    prune_recordings(name, dir, config).await;
+                }
+                if let Err(e) = recording.push(now, media) {
+                    log::warn!("{name}: Could not write the recording: {e:?}");
+                }
+            }
+            _ = prune.tick() => {
+                prune_recordings(name, dir, config).await;
+            }
+        }
+    };
+
+    // Keep what was recorded before the stream was lost
+    finish(name, recording).await;
+    r
+}
+
+/// Record each time that a `motion_gated` camera wakes up
+///
+/// The stream is only held while something else, such as the push
+/// notification that woke the camera, is using it. Otherwise the stream
+/// would keep the camera awake
+async fn record_gated(
+    instance: &NeoInstance,
+    config: &RecordConfig,
+    name: &str,
+    dir: &Path,
+    stream: StreamKind,
+    cancel: &CancellationToken,
+) -> AnyResult<()> {
+    log::info!(
+        "{name}: Recording {stream} when the camera wakes to {:?}",
+        dir
+    );
+
+    let mut camera_watch = instance.camera();
+    let mut users = instance.users().await?;
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            v = camera_watch.wait_for(|cam| cam.upgrade().is_some()) => v.map(|_| ())?,
+        }
+        let woken = tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            v = async { users.wait_for(|users| *users > 0).await.map(|_| ()) } => {
+                v?;
+                true
+            }
+            v = async { camera_watch.wait_for(|cam| cam.upgrade().is_none()).await.map(|_| ()) } => {
+                v?;
+                false
+            }
+        };
+        if !woken {
+            continue;
+        }
+
+        let path = dir.join(recording_name(name, config.format));
+        log::info!("{name}: Camera woke up, recording to {:?}", path);
+        let mut media_rx = instance.stream(stream).await?;
+        let mut recording = Recording::new(path, config.format);
+        // Our own stream is one of the users once the frames arrive
+        let mut streaming = false;
+        let r = loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break Ok(()),
+                media = media_rx.recv() => {
+                    let Some(media) = media else {
+                        break Err(anyhow!("The camera stream ended"));
+                    };
+                    streaming = true;
+                    if let Err(e) = recording.push(Instant::now(), media) {
+                        log::warn!("{name}: Could not write the recording: {e:?}");
+                    }
+                }
+                v = async { users.wait_for(|users| *users <= 1).await.map(|_| ()) }, if streaming => {
+                    break v.map_err(|e| e.into());
+                }
+            }
+        };
+        drop(media_rx);
+        finish(name, recording).await;
+        prune_recordings(name, dir, config).await;
+        r?;
+
+        // Let it sleep before waiting for the next wake
+        tokio::select! {
+            _ = cancel.cancelled() => return Ok(()),
+            v = camera_watch.wait_for(|cam| cam.upgrade().is_none()) => v.map(|_| ())?,
+        }
+    }
+}
+
+async fn finish(name: &str, recording: Recording) {
+    match recording.finish().await {
+        Ok(Some(path)) => log::info!("{name}: Recording saved to {:?}", path),
+        Ok(None) => log::debug!("{name}: Recording had no video"),
+        Err(e) => log::warn!("{name}: Could not finish the recording: {e:?}"),
+    }
+}
+
+fn recording_name(name: &str, format: RecordFormat) -> String {
+    let now = now_local();
+    format!(
+        "{}-{}-{:02}{:02}{:02}.{}",
+        file_safe_name(name),
+        date_name(now.date()),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        format.extension()
+    )
+}
+
+/// The recent video kept for the start of the next recording
+struct PreRoll {
+    duration: Duration,
+    frames: VecDeque<(Instant, BcMedia)>,
+}
+
+impl PreRoll {
+    fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            frames: Default::default(),
+        }
+    }
+
+    fn push(&mut self, at: Instant, media: BcMedia) {
+        if !matches!(media, BcMedia::Iframe(_) | BcMedia::Pframe(_)) {
+            return;
+        }
+        self.frames.push_back((at, media));
+
+        let Some(cutoff) = at.checked_sub(self.duration) else {
+            return;
+        };
+        // Start on the newest iframe that is at least `duration` old so that
+        // the recording can be decoded from its first frame
+        if let Some(start) = self
+            .frames
+            .iter()
+            .rposition(|(at, media)| *at <= cutoff && matches!(media, BcMedia::Iframe(_)))
+        {
+            self.frames.drain(..start);
+        }
+        // Frames before any iframe cannot be decoded
+        while self
+            .frames
+            .front()
+            .is_some_and(|(at, media)| *at <= cutoff && !matches!(media, BcMedia::Iframe(_)))
+        {
+            self.frames.pop_front();
+        }
+    }
+
+    fn take(&mut self) -> VecDeque<(Instant, BcMedia)> {
+        std::mem::take(&mut self.frames)
+    }
+}
+
+/// A recording in progress
+///
+/// The pipeline is made on the first iframe since that tells us the codec
+pub(crate) struct Recording {
+    path: PathBuf,
+    format: RecordFormat,
+    writer: Option<Writer>,
+}
+
+struct Writer {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+    start: Instant,
+    /// The times of the frames from the camera's clock
+    clock: MediaClock,
+    partial: PathBuf,
+}
+
+impl Recording {
+    pub(crate) fn new(path: PathBuf, format: RecordFormat) -> Self {
+        Self {
+            path,
+            format,
+            writer: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, at: Instant, media: BcMedia) -> AnyResult<()> {
+        let (data, video_type, microseconds, keyframe) = match media {
+            BcMedia::Iframe(BcMediaIframe {
+                data,
+                video_type,
+                microseconds,
+                ..
+            }) => (data, video_type, microseconds, true),
+            BcMedia::Pframe(BcMediaPframe {
+                data,
+                video_type,
+                microseconds,
+                ..
+            }) => (data, video_type, microseconds, false),
+            _ => return Ok(()),
+        };
+        if self.writer.is_none() {
+            if !keyframe {
+                // Wait for an iframe to start on
+                return Ok(());
+            }
+            self.writer = Some(Writer::new(&self.path, self.format, video_type, at)?);
+        }
+        let writer = self.writer.as_mut().expect("Set above");
+
+        let mut buffer = gstreamer::Buffer::from_slice(data);
+        {
+            let buffer = buffer
+                .get_mut()
+                .ok_or(anyhow!("Record buffer is not writable"))?;
+            let time = ClockTime::from_useconds(writer.clock.video(microseconds));
+            buffer.set_pts(time);
+            buffer.set_dts(time);
+            if !keyframe {
+                buffer.set_flags(BufferFlags::DELTA_UNIT);
+            }
+        }
+        writer.appsrc.push_buffer(buffer)?;
+        Ok(())
+    }
+
+    /// When the first frame was written
+    pub(crate) fn started(&self) -> Option<Instant> {
+        self.writer.as_ref().map(|writer| writer.start)
+    }
+
+    /// Finish writing the file
+    ///
+    /// Returns the path of the recording if there was any video
+    pub(crate) async fn finish(self) -> AnyResult<Option<PathBuf>> {
+        let Some(writer) = self.writer else {
+            return Ok(None);
+        };
+        let path = self.path;
+        tokio::task::spawn_blocking(move || writer.finish(&path).map(|_| Some(path))).await?
+    }
+}
+
+impl Writer {
+    fn new(
+        path: &Path,
+        format: RecordFormat,
+        video_type: VideoType,
+        start: Instant,
+    ) -> AnyResult<Self> {
+        gstreamer::init().context("Gstreamer failed to initialise")?;
+        // Write to a temporary name so that an unfinished file is never pruned
+        // or mistaken for a finished one
+        let partial = path.with_extension(format!("{}.partial", format.extension()));
+        let pipeline = Pipeline::new();
+
+        let (source, parser) = make_video_source("rec", video_type, false)?;
+        let muxer = match format {
+            RecordFormat::Mp4 => make_element("mp4mux", "recmux")?,
+            RecordFormat::Mkv => make_element("matroskamux", "recmux")?,
+        };
+        let sink = make_element("filesink", "recsink")?;
+        sink.set_property("location", partial.to_string_lossy().to_string());
+
+        pipeline.add_many([&source, &parser, &muxer, &sink])?;
+        Element::link_many([&source, &parser, &muxer, &sink])?;
+        pipeline.set_state(gstreamer::State::Playing)?;
+
+        let appsrc = source
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        Ok(Self {
+            pipeline,
+            appsrc,
+            start,
+            // The fps is only used for the gaps in the camera clock
+            clock: MediaClock::new(25),
+            partial,
+        })
+    }
+
+    /// Send the EOS and wait for the muxer to write the file
+    ///
+    /// This blocks until the file is written
+    fn finish(self, path: &Path) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .ok_or(anyhow!("Record pipeline has no bus"))?;
+        let r = (|| {
+            self.appsrc.end_of_stream()?;
+            for msg in bus.iter_timed(ClockTime::from_seconds(30)) {
+                match msg.view() {
+                    MessageView::Eos(_) => return AnyResult::Ok(()),
+                    MessageView::Error(e) => {
+                        return Err(anyhow!("Error in record pipeline: {}", e.error()));
+                    }
+                    _ => {}
+                }
+            }
+            Err(anyhow!("Timed out finishing the recording"))
+        })();
+
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+        match r {
+            Ok(()) => {
+                std::fs::rename(&self.partial, path)
+                    .with_context(|| format!("Could not rename {:?}", self.partial))?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&self.partial);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Remove the recordings past `max_age` then the oldest until under `max_size`
+async fn prune_recordings(name: &str, dir: &Path, config: &RecordConfig) {
+    if config.max_age.is_none() && config.max_size.is_none() {
+        return;
+    }
+    if let Err(e) = prune_dir(name, dir, config).await {
+        log::warn!("{name}: Could not remove old recordings: {e:?}");
+    }
+}
+
+async fn prune_dir(name: &str, dir: &Path, config: &RecordConfig) -> AnyResult<()> {
+    let mut recordings = vec![];
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "mp4" || ext == "mkv")
+        {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            recordings.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    // Oldest first
+    recordings.sort();
+
+    let now = SystemTime::now();
+    let mut total: u64 = recordings.iter().map(|(_, size, _)| size).sum();
+    for (modified, size, path) in recordings {
+        let too_old = config.max_age.is_some_and(|hours| {
+            now.duration_since(modified).unwrap_or_default() > Duration::from_secs(hours * 60 * 60)
+        });
+        let too_big = config.max_size.is_some_and(|mb| total > mb * 1024 * 1024);
+        if !too_old && !too_big {
+            break;
+        }
+        log::debug!("{name}: Removing the recording {:?}", path);
+        tokio::fs::remove_file(&path)
+            .await
+            .with_context(|| format!("Could not remove {:?}", path))?;
+        total = total.saturating_sub(size);
+    }
+    Ok(())
+}
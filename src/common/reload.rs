@@ -0,0 +1,66 @@
+//! Watch the config file for changes while running
+//!
+//! The file is polled for a new modification time. The reactor applies the
+//! new config by starting or stopping the cameras that were added or removed.
+//! Cameras whose config changed reconnect, the others are left alone.
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::time::{interval, Duration, Interval, MissedTickBehavior};
+
+use crate::config::Config;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub(super) struct ConfigFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    interval: Interval,
+}
+
+impl ConfigFile {
+    pub(super) fn new(path: PathBuf) -> Self {
+        let mut interval = interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            modified: modified_time(&path),
+            path,
+            interval,
+        }
+    }
+
+    /// Wait for the file to change and return the new valid config
+    pub(super) async fn changed(&mut self) -> Config {
+        loop {
+            self.interval.tick().await;
+            let modified = modified_time(&self.path);
+            if modified == self.modified {
+                continue;
+            }
+            self.modified = modified;
+            match Config::from_file(&self.path) {
+                Ok(config) => return config,
+                // Often the editor is half way through writing, the next write will retry
+                Err(e) => log::warn!("Not reloading the config: {e:?}"),
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The servers are only bound on start
+pub(super) fn warn_needs_restart(current: &Config, new: &Config) {
+    if current.bind_addrs != new.bind_addrs || current.bind_port != new.bind_port {
+        log::warn!("The new rtsp bind address will be used after a restart");
+    }
+    if current.control_addr != new.control_addr {
+        log::warn!("The new control_addr will be used after a restart");
+    }
+    if current.onvif != new.onvif {
+        log::warn!("The new onvif settings will be used after a restart");
+    }
+}
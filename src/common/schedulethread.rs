@@ -0,0 +1,192 @@
+//! This thread disconnects the camera during the times in its
+//! `[cameras.schedule]`, such as an indoor camera while someone is home
+//!
+//! The camera is only changed when a rule starts or ends so that a manual
+//! `control/enable` during a rule is left alone until the next change.
+//! The times are in the local time of neolink.
+//!
+//! PTZ cameras can be turned away with `park_preset` before they are
+//! disconnected and back with `return_preset` once they connect again.
+//!
+//! It also reboots the camera at the times of its `reboot_cron`.
+
+use tokio::time::{interval, sleep, timeout, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{run_control, NeoInstance};
+use crate::{
+    config::{cron_due, ScheduleAction, ScheduleConfig},
+    utils::now_local,
+    AnyResult, Result,
+};
+
+/// How often the rules are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// How long a preset may take, it needs the camera to be connected
+const PRESET_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a reboot may take, it first waits for a recording to finish
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(180);
+
+pub(crate) struct NeoCamScheduleThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamScheduleThread {
+    pub(crate) async fn new(instance: NeoInstance) -> Result<Self> {
+        Ok(Self {
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                let mut config_rx = instance.config().await?;
+                // What was done to the camera by the schedule, kept over config
+                // changes so that it can be undone
+                let mut applied = None;
+                loop {
+                    let schedule = config_rx.borrow_and_update().schedule.clone();
+                    let r = tokio::select! {
+                        v = config_rx.wait_for(|config| config.schedule != schedule) => {
+                            // Config changed go back and restart with the new one
+                            v?;
+                            AnyResult::Ok(())
+                        },
+                        v = follow_schedule(&instance, schedule.as_ref(), &mut applied) => v,
+                    };
+                    if let Err(e) = r {
+                        log::warn!("Error in schedule task Restarting: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            } => v,
+            v = reboot_on_cron(&instance) => v,
+        }
+    }
+}
+
+impl Drop for NeoCamScheduleThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamScheduleThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamScheduleThread");
+    }
+}
+
+async fn follow_schedule(
+    instance: &NeoInstance,
+    schedule: Option<&ScheduleConfig>,
+    applied: &mut Option<ScheduleAction>,
+) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let mut check = interval(CHECK_INTERVAL);
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        check.tick().await;
+        let wanted = schedule.and_then(|schedule| {
+            let now = now_local();
+            let weekday = now.weekday().number_days_from_monday() as usize;
+            let minute = now.hour() as u16 * 60 + now.minute() as u16;
+            schedule
+                .rules
+                .iter()
+                .any(|rule| rule.contains(weekday, minute))
+                .then_some(schedule.action)
+        });
+        if wanted == *applied {
+            if schedule.is_none() {
+                // Nothing left to undo
+                futures::future::pending::<()>().await;
+            }
+            continue;
+        }
+
+        if let Some(action) = applied.take() {
+            log::info!("{name}: Schedule ended, connecting the camera");
+            let control = match action {
+                ScheduleAction::Disable => "enable",
+                ScheduleAction::Pause => "resume",
+            };
+            control_camera(instance, &name, control, "").await;
+            if let Some(preset) = schedule.and_then(|schedule| schedule.return_preset) {
+                control_camera(instance, &name, "ptz/preset", &preset.to_string()).await;
+            }
+        }
+        if let (Some(action), Some(schedule)) = (wanted, schedule) {
+            log::info!("{name}: Schedule started, disconnecting the camera");
+            if let Some(preset) = schedule.park_preset {
+                control_camera(instance, &name, "ptz/preset", &preset.to_string()).await;
+            }
+            let control = match action {
+                ScheduleAction::Disable => "disable",
+                ScheduleAction::Pause => "pause",
+            };
+            control_camera(instance, &name, control, "").await;
+            *applied = Some(action);
+        }
+    }
+}
+
+/// Reboot the camera each time that its `reboot_cron` is due
+///
+/// This goes through `control/reboot` with `safe` so a clip that is being
+/// recorded is given time to finish first. If the camera is still busy
+/// after that it is rebooted anyway, the schedule is what was asked for.
+async fn reboot_on_cron(instance: &NeoInstance) -> AnyResult<()> {
+    let mut config_rx = instance.config().await?;
+    let mut check = interval(CHECK_INTERVAL);
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // The minute of the last reboot, it is checked more than once a minute
+    let mut last = None;
+    loop {
+        let (name, cron) = {
+            let config = config_rx.borrow_and_update();
+            (config.name.clone(), config.reboot_cron.clone())
+        };
+        let Some(cron) = cron else {
+            config_rx.changed().await?;
+            continue;
+        };
+        check.tick().await;
+        let now = now_local();
+        let minute = now.replace_second(0).ok();
+        if minute == last || cron_due(&cron, &now) != Some(true) {
+            continue;
+        }
+        last = minute;
+        log::info!("{name}: Rebooting for the reboot_cron `{cron}`");
+        match timeout(REBOOT_TIMEOUT, run_control(instance, "reboot", "safe")).await {
+            Ok(Some(reply)) if reply.starts_with("OK") => continue,
+            Ok(reply) => log::warn!(
+                "{name}: Could not reboot for the reboot_cron once idle: {reply:?}, forcing it"
+            ),
+            Err(_) => {
+                log::warn!("{name}: Timed out waiting to reboot for the reboot_cron, forcing it")
+            }
+        }
+        match timeout(REBOOT_TIMEOUT, run_control(instance, "reboot", "force")).await {
+            Ok(Some(reply)) if reply.starts_with("OK") => {}
+            Ok(reply) => log::warn!("{name}: Could not reboot for the reboot_cron: {reply:?}"),
+            Err(_) => log::warn!("{name}: Timed out rebooting for the reboot_cron"),
+        }
+    }
+}
+
+/// Run a control and log when it fails, the schedule carries on regardless
+async fn control_camera(instance: &NeoInstance, name: &str, control: &str, message: &str) {
+    match timeout(PRESET_TIMEOUT, run_control(instance, control, message)).await {
+        Ok(Some(reply)) if reply.starts_with("OK") => {}
+        Ok(reply) => log::warn!("{name}: Schedule could not {control} {message}: {reply:?}"),
+        Err(_) => log::warn!("{name}: Schedule timed out during {control} {message}"),
+    }
+}
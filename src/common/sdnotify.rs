@@ -0,0 +1,169 @@
+//! Tell systemd when neolink is ready and that it is still alive
+//!
+//! This only does anything when systemd starts neolink with `Type=notify`,
+//! which sets `NOTIFY_SOCKET`
+//!
+//! - `READY=1` is sent once every camera is connected, paused or has failed
+//!   for good
+//! - `STATUS=` is sent with the number of connected cameras when it changes
+//! - With `WatchdogSec` a `WATCHDOG=1` is sent at half that interval for as
+//!   long as every camera thread still answers, so that systemd restarts a
+//!   neolink that has hung
+use anyhow::Context;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use tokio::time::{interval, timeout, Duration, MissedTickBehavior};
+
+use super::{NeoCamThreadState, NeoReactor};
+use crate::AnyResult;
+
+/// The longest time between the checks of the cameras
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the cameras have to answer before neolink is thought hung
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct NotifySocket {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl NotifySocket {
+    /// The socket in `NOTIFY_SOCKET`, None when not started by systemd
+    fn from_env() -> AnyResult<Option<Self>> {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let path = path.to_string_lossy().into_owned();
+        let addr = match path.strip_prefix('@') {
+            // Abstract sockets are only on linux
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                SocketAddr::from_abstract_name(name.as_bytes())?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(anyhow::anyhow!(
+                    "Abstract NOTIFY_SOCKET {path} is not supported"
+                ))
+            }
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        let socket = UnixDatagram::unbound().context("Could not create the notify socket")?;
+        Ok(Some(Self { socket, addr }))
+    }
+
+    fn notify(&self, state: &str) -> AnyResult<()> {
+        self.socket
+            .send_to_addr(state.as_bytes(), &self.addr)
+            .with_context(|| format!("Could not send {state} to systemd"))?;
+        Ok(())
+    }
+}
+
+/// The interval of `WatchdogSec` when it is for this process
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CameraCounts {
+    total: usize,
+    connected: usize,
+    /// Connected, paused or failed for good
+    settled: usize,
+}
+
+/// Send the notifications to systemd until an error
+///
+/// This returns straight away when neolink was not started by systemd
+pub(crate) async fn serve_systemd(reactor: NeoReactor) -> AnyResult<()> {
+    let Some(socket) = NotifySocket::from_env()? else {
+        return Ok(());
+    };
+    let watchdog = watchdog_interval();
+    let every = watchdog
+        .map(|watchdog| (watchdog / 2).min(CHECK_INTERVAL))
+        .unwrap_or(CHECK_INTERVAL);
+    log::debug!("Notifying systemd, watchdog: {watchdog:?}");
+
+    let mut ready = false;
+    let mut last_counts = None;
+    let mut check = interval(every);
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        check.tick().await;
+        let counts = match timeout(ANSWER_TIMEOUT, camera_counts(&reactor)).await {
+            Ok(counts) => counts?,
+            Err(_) => {
+                log::warn!("The cameras did not answer in time, not pinging the systemd watchdog");
+                continue;
+            }
+        };
+        if !ready && counts.settled == counts.total {
+            socket.notify("READY=1")?;
+            ready = true;
+        }
+        if last_counts.as_ref() != Some(&counts) {
+            socket.notify(&format!(
+                "STATUS={} of {} cameras connected",
+                counts.connected, counts.total
+            ))?;
+            last_counts = Some(counts);
+        }
+        if watchdog.is_some() {
+            socket.notify("WATCHDOG=1")?;
+        }
+    }
+}
+
+async fn camera_counts(reactor: &NeoReactor) -> AnyResult<CameraCounts> {
+    let config = reactor.config().await?;
+    let names = config
+        .borrow()
+        .cameras
+        .iter()
+        .filter(|camera| camera.enabled)
+        .map(|camera| camera.name.clone())
+        .collect::<Vec<_>>();
+
+    let mut counts = CameraCounts {
+        total: names.len(),
+        connected: 0,
+        settled: 0,
+    };
+    for name in names {
+        let instance = match reactor.get(&name).await {
+            Ok(instance) => instance,
+            Err(e) => {
+                // Such as when it was just removed from the config
+                log::debug!("{name}: Could not check the camera: {e:?}");
+                counts.settled += 1;
+                continue;
+            }
+        };
+        let connected = instance.camera().borrow().upgrade().is_some();
+        // An error is a camera that has been stopped
+        let paused = instance
+            .get_state()
+            .await
+            .map_or(true, |state| state != NeoCamThreadState::Connected);
+        let failed = instance
+            .failure()
+            .await
+            .map_or(true, |failure| failure.borrow().is_some());
+        if connected {
+            counts.connected += 1;
+        }
+        if connected || paused || failed {
+            counts.settled += 1;
+        }
+    }
+    Ok(counts)
+}
@@ -0,0 +1,223 @@
+//! Registry of the camera logins held by neolink processes on this machine
+//!
+//! Every process that logs into a camera writes a small entry file into
+//! `<session_dir>/<camera>/`. The entry is rewritten periodically while the
+//! login is held so that entries left behind by processes that died
+//! go stale and are ignored.
+//!
+//! This lets several neolink processes (e.g. the rtsp server and one shot
+//! commands run from cron) share the `max_own_sessions` budget of a camera
+//! and lets `neolink sessions` report who is holding them.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task::JoinHandle, time::sleep};
+
+use crate::{utils::file_safe_name, AnyResult};
+
+/// How often a held session refreshes its entry
+const HEARTBEAT: Duration = Duration::from_secs(10);
+/// Entries that have not been refreshed within this time are considered dead
+pub(crate) const SESSION_STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// Information stored in each registry entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SessionInfo {
+    /// Process ID of the neolink process holding the session
+    pub(crate) pid: u32,
+    /// Command line of the process holding the session
+    pub(crate) command: String,
+    /// When the session was acquired in seconds since the unix epoch
+    pub(crate) started: u64,
+}
+
+/// Raised when a camera has no more of its `max_own_sessions` budget available
+#[derive(Debug)]
+pub(crate) struct SessionLimitReached {
+    pub(crate) camera: String,
+    pub(crate) max: usize,
+    pub(crate) holders: Vec<SessionInfo>,
+}
+
+impl std::fmt::Display for SessionLimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: All {} of the sessions allowed by max_own_sessions are in use by:",
+            self.camera, self.max
+        )?;
+        for holder in self.holders.iter() {
+            write!(f, " [pid {}: {}]", holder.pid, holder.command)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SessionLimitReached {}
+
+/// The registry for a single camera
+pub(crate) struct SessionRegistry {
+    camera: String,
+    dir: PathBuf,
+}
+
+impl SessionRegistry {
+    pub(crate) fn new(session_dir: &Path, camera: &str) -> Self {
+        Self {
+            camera: camera.to_string(),
+            dir: session_dir.join(file_safe_name(camera)),
+        }
+    }
+
+    /// List the sessions that are currently held
+    ///
+    /// Stale entries are removed as they are found
+    pub(crate) async fn list(&self) -> AnyResult<Vec<SessionInfo>> {
+        Ok(self
+            .live_entries()
+            .await?
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect())
+    }
+
+    async fn live_entries(&self) -> AnyResult<Vec<(PathBuf, SessionInfo)>> {
+        let mut entries = vec![];
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Could not read {:?}", self.dir));
+            }
+        };
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "session") {
+                continue;
+            }
+            let is_stale = entry
+                .metadata()
+                .await
+                .and_then(|meta| meta.modified())
+                .map(|modified| {
+                    modified
+                        .elapsed()
+                        .is_ok_and(|elapsed| elapsed > SESSION_STALE_AFTER)
+                })
+                .unwrap_or(false);
+            if is_stale {
+                log::debug!("{}: Removing stale session entry {:?}", self.camera, path);
+                let _ = tokio::fs::remove_file(&path).await;
+                continue;
+            }
+            // An entry that cannot be read may still be in the middle of being written
+            if let Ok(info) = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_str::<SessionInfo>(&data)?))
+            {
+                entries.push((path, info));
+            }
+        }
+        entries.sort_by(|(path_a, info_a), (path_b, info_b)| {
+            info_a
+                .started
+                .cmp(&info_b.started)
+                .then_with(|| path_a.cmp(path_b))
+        });
+        Ok(entries)
+    }
+
+    /// Reserve a session for this process
+    ///
+    /// The session is held until the returned [`SessionSlot`] is dropped.
+    /// If `max` sessions are already held by other processes then
+    /// [`SessionLimitReached`] is returned
+    pub(crate) async fn acquire(&self, max: Option<usize>) -> AnyResult<SessionSlot> {
+        let mut builder = tokio::fs::DirBuilder::new();
+        builder.recursive(true);
+        // Only this user should see the commands that hold the sessions
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder
+            .create(&self.dir)
+            .await
+            .with_context(|| format!("Could not create session directory {:?}", self.dir))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = tokio::fs::metadata(&self.dir).await?.permissions().mode();
+            if mode & 0o022 != 0 {
+                return Err(anyhow::anyhow!(
+                    "The session directory {:?} can be written by other users",
+                    self.dir
+                ));
+            }
+        }
+
+        let info = SessionInfo {
+            pid: std::process::id(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        let data = serde_json::to_string(&info)?;
+        let path = self
+            .dir
+            .join(format!("{}-{}.session", info.pid, uuid::Uuid::new_v4()));
+        tokio::fs::write(&path, &data)
+            .await
+            .with_context(|| format!("Could not write session entry {:?}", path))?;
+
+        // We write our entry first then count it so that two processes starting
+        // together cannot both take the last session. The oldest entries win.
+        if let Some(max) = max {
+            let entries = self.live_entries().await?;
+            if !entries.iter().take(max).any(|(p, _)| p == &path) {
+                let _ = tokio::fs::remove_file(&path).await;
+                return Err(SessionLimitReached {
+                    camera: self.camera.clone(),
+                    max,
+                    holders: entries
+                        .into_iter()
+                        .filter(|(p, _)| p != &path)
+                        .map(|(_, info)| info)
+                        .collect(),
+                }
+                .into());
+            }
+        }
+
+        let heartbeat_path = path.clone();
+        let heartbeat = tokio::task::spawn(async move {
+            loop {
+                sleep(HEARTBEAT).await;
+                if let Err(e) = tokio::fs::write(&heartbeat_path, &data).await {
+                    log::debug!("Could not refresh session entry {heartbeat_path:?}: {e:?}");
+                }
+            }
+        });
+
+        Ok(SessionSlot { path, heartbeat })
+    }
+}
+
+/// A session held in the registry
+///
+/// The entry is removed when this is dropped
+pub(crate) struct SessionSlot {
+    path: PathBuf,
+    heartbeat: JoinHandle<()>,
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,301 @@
+//! Camera settings as JSON for `neolink config-get` and `neolink config-set`
+//!
+//! The xml of a BC get or set message is turned into JSON as is, so that
+//! settings that neolink does not wrap can still be scripted. Elements are
+//! objects, attributes are keys starting with `@`, repeated elements are
+//! arrays and all the values are strings
+use anyhow::{anyhow, Context};
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use serde_json::{Map, Value};
+
+use super::NeoInstance;
+use crate::AnyResult;
+use neolink_core::bc::model::*;
+
+/// A group of settings and the BC messages that get and set them
+pub(crate) struct Section {
+    pub(crate) name: &'static str,
+    pub(crate) get: u32,
+    pub(crate) set: Option<u32>,
+}
+
+pub(crate) const SECTIONS: &[Section] = &[
+    Section {
+        name: "email",
+        get: MSG_ID_GET_EMAIL,
+        set: Some(MSG_ID_SET_EMAIL),
+    },
+    Section {
+        name: "email_task",
+        get: MSG_ID_GET_EMAIL_TASK,
+        set: Some(MSG_ID_SET_EMAIL_TASK),
+    },
+    Section {
+        name: "general",
+        get: MSG_ID_GET_GENERAL,
+        set: Some(MSG_ID_SET_GENERAL),
+    },
+    Section {
+        name: "led",
+        get: MSG_ID_GET_LED_STATUS,
+        set: Some(MSG_ID_SET_LED_STATUS),
+    },
+    Section {
+        name: "motion",
+        get: MSG_ID_GET_MD_ALARM,
+        set: Some(MSG_ID_SET_MD_ALARM),
+    },
+    Section {
+        name: "osd",
+        get: MSG_ID_GET_OSD,
+        set: Some(MSG_ID_SET_OSD),
+    },
+    Section {
+        name: "pir",
+        get: MSG_ID_GET_PIR_ALARM,
+        set: Some(MSG_ID_START_PIR_ALARM),
+    },
+    Section {
+        name: "video_input",
+        get: MSG_ID_GET_VIDEO_INPUT,
+        set: Some(MSG_ID_SET_VIDEO_INPUT),
+    },
+    Section {
+        name: "service_ports",
+        get: MSG_ID_GET_SERVICE_PORTS,
+        set: Some(MSG_ID_SET_SERVICE_PORTS),
+    },
+    Section {
+        name: "hdd",
+        get: MSG_ID_GET_HDD_INFO,
+        set: None,
+    },
+    Section {
+        name: "support",
+        get: MSG_ID_GET_SUPPORT,
+        set: None,
+    },
+    Section {
+        name: "version",
+        get: MSG_ID_VERSION,
+        set: None,
+    },
+];
+
+/// The ids of the get and set messages of a section name or a msg id
+///
+/// A msg id is used for both so that sections that are not in the list can
+/// still be reached
+pub(crate) fn section_ids(section: &str) -> AnyResult<(u32, Option<u32>)> {
+    if let Ok(msg_id) = section.parse::<u32>() {
+        return Ok((msg_id, Some(msg_id)));
+    }
+    SECTIONS
+        .iter()
+        .find(|known| known.name == section)
+        .map(|known| (known.get, known.set))
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown section {section}, expected a msg id or one of {}",
+                SECTIONS
+                    .iter()
+                    .map(|known| known.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// Get the settings as JSON
+pub(crate) async fn get_settings(camera: &NeoInstance, msg_id: u32) -> AnyResult<Value> {
+    let xml = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.send_raw_xml(msg_id, "").await?) }))
+        .await?;
+    xml_to_json(&xml)
+}
+
+/// Send the JSON as the settings and give back the JSON of the reply
+pub(crate) async fn set_settings(
+    camera: &NeoInstance,
+    msg_id: u32,
+    settings: &Value,
+) -> AnyResult<Value> {
+    let xml = json_to_xml(settings)?;
+    let reply = camera
+        .run_task(|cam| {
+            let xml = xml.clone();
+            Box::pin(async move { Ok(cam.send_raw_xml(msg_id, &xml).await?) })
+        })
+        .await?;
+    xml_to_json(&reply)
+}
+
+/// The JSON of the xml without its `<body>`
+pub(crate) fn xml_to_json(xml: &str) -> AnyResult<Value> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    // The element being read and its parents
+    let mut stack: Vec<(String, Map<String, Value>, String)> =
+        vec![(String::new(), Map::new(), String::new())];
+    loop {
+        match reader.read_event().context("Could not read the xml")? {
+            Event::Start(start) => stack.push(open_element(&start)?),
+            Event::Empty(start) => {
+                let (name, attributes, _) = open_element(&start)?;
+                let parent = stack.last_mut().expect("The root is never removed");
+                add_child(
+                    &mut parent.1,
+                    name,
+                    close_element(attributes, String::new()),
+                );
+            }
+            Event::Text(text) => {
+                let text = text.unescape().context("Could not read the xml text")?;
+                stack
+                    .last_mut()
+                    .expect("The root is never removed")
+                    .2
+                    .push_str(&text);
+            }
+            Event::CData(text) => {
+                let text = String::from_utf8_lossy(&text).into_owned();
+                stack
+                    .last_mut()
+                    .expect("The root is never removed")
+                    .2
+                    .push_str(&text);
+            }
+            Event::End(_) => {
+                if stack.len() < 2 {
+                    return Err(anyhow!("The xml closes more elements than it opens"));
+                }
+                let (name, children, text) = stack.pop().expect("Checked the length");
+                let parent = stack.last_mut().expect("Checked the length");
+                add_child(&mut parent.1, name, close_element(children, text));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if stack.len() != 1 {
+        return Err(anyhow!("The xml ended before all its elements were closed"));
+    }
+    let (_, mut root, _) = stack.pop().expect("Checked the length");
+    Ok(match root.remove("body") {
+        Some(body) => body,
+        None => Value::Object(root),
+    })
+}
+
+fn open_element(start: &BytesStart) -> AnyResult<(String, Map<String, Value>, String)> {
+    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    let mut attributes = Map::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.context("Could not read an xml attribute")?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+        let value = attribute
+            .unescape_value()
+            .context("Could not read an xml attribute")?;
+        attributes.insert(format!("@{key}"), Value::String(value.into_owned()));
+    }
+    Ok((name, attributes, String::new()))
+}
+
+/// An element with only text is just the string
+fn close_element(mut children: Map<String, Value>, text: String) -> Value {
+    if children.is_empty() {
+        Value::String(text)
+    } else {
+        if !text.is_empty() {
+            children.insert("#text".to_string(), Value::String(text));
+        }
+        Value::Object(children)
+    }
+}
+
+/// Repeated elements become an array
+fn add_child(parent: &mut Map<String, Value>, name: String, child: Value) {
+    match parent.get_mut(&name) {
+        Some(Value::Array(items)) => items.push(child),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, child]);
+        }
+        None => {
+            parent.insert(name, child);
+        }
+    }
+}
+
+/// The xml of the JSON inside a `<body>`
+pub(crate) fn json_to_xml(settings: &Value) -> AnyResult<String> {
+    let Value::Object(settings) = settings else {
+        return Err(anyhow!("The settings should be a JSON object"));
+    };
+    // The output of `config-get` has no body but allow it anyway
+    let settings = match settings.get("body") {
+        Some(Value::Object(body)) if settings.len() == 1 => body,
+        _ => settings,
+    };
+
+    let mut writer = Writer::new(Vec::new());
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer.write_event(Event::Start(BytesStart::new("body")))?;
+    for (name, value) in settings.iter() {
+        write_element(&mut writer, name, value)?;
+    }
+    writer.write_event(Event::End(BytesEnd::new("body")))?;
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn write_element(writer: &mut Writer<Vec<u8>>, name: &str, value: &Value) -> AnyResult<()> {
+    match value {
+        Value::Array(items) => {
+            for item in items.iter() {
+                write_element(writer, name, item)?;
+            }
+        }
+        Value::Object(children) => {
+            let mut start = BytesStart::new(name);
+            for (key, value) in children.iter() {
+                if let Some(key) = key.strip_prefix('@') {
+                    start.push_attribute((key, text_of(value)?.as_str()));
+                }
+            }
+            writer.write_event(Event::Start(start))?;
+            if let Some(text) = children.get("#text") {
+                writer.write_event(Event::Text(BytesText::new(&text_of(text)?)))?;
+            }
+            for (key, value) in children.iter() {
+                if !key.starts_with('@') && key != "#text" {
+                    write_element(writer, key, value)?;
+                }
+            }
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+        Value::Null => {
+            writer.write_event(Event::Empty(BytesStart::new(name)))?;
+        }
+        value => {
+            writer.write_event(Event::Start(BytesStart::new(name)))?;
+            writer.write_event(Event::Text(BytesText::new(&text_of(value)?)))?;
+            writer.write_event(Event::End(BytesEnd::new(name)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Numbers and bools are allowed so that edited JSON does not need quotes
+fn text_of(value: &Value) -> AnyResult<String> {
+    match value {
+        Value::String(text) => Ok(text.clone()),
+        Value::Number(number) => Ok(number.to_string()),
+        Value::Bool(value) => Ok(if *value { "1" } else { "0" }.to_string()),
+        Value::Null => Ok(String::new()),
+        _ => Err(anyhow!("Expected a value but got {value}")),
+    }
+}
@@ -0,0 +1,330 @@
+//! This thread will listen to the audio from the camera
+//! and detect loud sounds
+//!
+//! This is only active on cameras with `[cameras.audio_detect]`
+//! the audio is decoded with gstreamer and measured using the
+//! `level` element
+
+use anyhow::{anyhow, Context};
+use futures::StreamExt;
+use gstreamer::{element_error, glib, prelude::*, Caps, CoreError, Element, MessageView, Pipeline};
+use gstreamer_app::AppSrc;
+use std::sync::Arc;
+use tokio::{
+    sync::{
+        mpsc::Receiver as MpscReceiver,
+        oneshot::Sender as OneshotSender,
+        watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
+    },
+    time::{sleep, Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{make_element, NeoInstance};
+use crate::{config::AudioDetectConfig, AnyResult, Result};
+use neolink_core::{bc_protocol::StreamKind, bcmedia::model::BcMedia};
+
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub(crate) enum SoundState {
+    Start(Instant),
+    Stop(Instant),
+    Unknown,
+}
+
+pub(crate) struct NeoCamSoundThread {
+    sound_watcher: Arc<WatchSender<SoundState>>,
+    level_watcher: Arc<WatchSender<Option<f64>>>,
+    sound_request_rx: MpscReceiver<SoundRequest>,
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamSoundThread {
+    pub(crate) async fn new(
+        sound_request_rx: MpscReceiver<SoundRequest>,
+        instance: NeoInstance,
+    ) -> Result<Self> {
+        let (sound_watcher, _) = watch(SoundState::Unknown);
+        let (level_watcher, _) = watch(None);
+        Ok(Self {
+            sound_watcher: Arc::new(sound_watcher),
+            level_watcher: Arc::new(level_watcher),
+            sound_request_rx,
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let sound_watcher = self.sound_watcher.clone();
+        let level_watcher = self.level_watcher.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                while let Some(request) = self.sound_request_rx.recv().await {
+                    match request {
+                        SoundRequest::Get {
+                            sender
+                        } => {
+                          let _ = sender.send(self.sound_watcher.subscribe());
+                        },
+                        SoundRequest::Level {
+                            sender
+                        } => {
+                          let _ = sender.send(self.level_watcher.subscribe());
+                        },
+                    }
+                }
+                Ok(())
+            } => v,
+            v = async {
+                let mut config_rx = instance.config().await?;
+                loop {
+                    // Wait for the green light
+                    let detect_config = config_rx
+                        .wait_for(|config| config.audio_detect.is_some())
+                        .await?
+                        .audio_detect
+                        .clone()
+                        .expect("Checked by the wait_for");
+
+                    let r = tokio::select! {
+                        v = config_rx.wait_for(|config| config.audio_detect.as_ref() != Some(&detect_config)) => {
+                            // Config changed go back and restart with the new one
+                            v?;
+                            AnyResult::Ok(())
+                        },
+                        v = detect_sound(&instance, &detect_config, &sound_watcher, &level_watcher) => v,
+                    };
+                    level_watcher.send_replace(None);
+                    if matches!(*sound_watcher.borrow(), SoundState::Start(_)) {
+                        sound_watcher.send_replace(SoundState::Stop(Instant::now()));
+                    }
+                    if let Err(e) = r {
+                        log::debug!("Error in audio detection task Restarting: {:?}", e);
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            } => v
+        }
+    }
+}
+
+impl Drop for NeoCamSoundThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamSoundThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamSoundThread");
+    }
+}
+
+/// Used to pass messages to the SoundThread
+pub(crate) enum SoundRequest {
+    Get {
+        sender: OneshotSender<WatchReceiver<SoundState>>,
+    },
+    Level {
+        sender: OneshotSender<WatchReceiver<Option<f64>>>,
+    },
+}
+
+async fn detect_sound(
+    instance: &NeoInstance,
+    config: &AudioDetectConfig,
+    sound_watcher: &WatchSender<SoundState>,
+    level_watcher: &WatchSender<Option<f64>>,
+) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    // Audio is the same on all streams so use the cheapest one
+    let mut media_rx = instance.stream(StreamKind::Sub).await?;
+
+    // Wait for the first audio packet to learn the audio type
+    let (first, pipeline) = loop {
+        match media_rx.recv().await {
+            Some(BcMedia::Aac(aac)) => break (aac.data, build_level_pipeline(None)?),
+            Some(BcMedia::Adpcm(adpcm)) => {
+                let block_size = adpcm.block_size();
+                break (adpcm.data, build_level_pipeline(Some(block_size))?);
+            }
+            Some(_) => continue,
+            None => return Err(anyhow!("Camera stream ended before any audio was recieved")),
+        }
+    };
+    log::info!("{name}: Audio detection started");
+    let appsrc = pipeline
+        .by_name("audsrc")
+        .ok_or(anyhow!("Level pipeline is missing its source"))?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    let mut bus = pipeline
+        .bus()
+        .ok_or(anyhow!("Level pipeline has no bus"))?
+        .stream();
+    pipeline.set_state(gstreamer::State::Playing)?;
+
+    let r = async {
+        appsrc.push_buffer(gstreamer::Buffer::from_slice(first))?;
+
+        let threshold = config.threshold;
+        let min_duration = Duration::from_millis(config.min_duration);
+        let cooldown = Duration::from_millis(config.cooldown);
+        let mut loud_since: Option<Instant> = None;
+        let mut quiet_since: Option<Instant> = None;
+        let mut last_stop: Option<Instant> = None;
+        let mut active = false;
+        loop {
+            tokio::select! {
+                media = media_rx.recv() => {
+                    match media {
+                        Some(BcMedia::Aac(aac)) => {
+                            appsrc.push_buffer(gstreamer::Buffer::from_slice(aac.data))?;
+                        }
+                        Some(BcMedia::Adpcm(adpcm)) => {
+                            appsrc.push_buffer(gstreamer::Buffer::from_slice(adpcm.data))?;
+                        }
+                        Some(_) => {}
+                        None => break Err(anyhow!("Camera stream ended")),
+                    }
+                }
+                msg = bus.next() => {
+                    let msg = msg.ok_or(anyhow!("Level pipeline bus closed"))?;
+                    match msg.view() {
+                        MessageView::Element(element) => {
+                            let Some(level) = element.structure().filter(|s| s.has_name("level")).and_then(peak_rms) else {
+                                continue;
+                            };
+                            log::trace!("{name}: Audio level {level:.1}dB");
+                            level_watcher.send_replace(Some(level));
+
+                            let now = Instant::now();
+                            if level >= threshold {
+                                quiet_since = None;
+                                let since = *loud_since.get_or_insert(now);
+                                let cooled = last_stop.is_none_or(|stop| now - stop >= cooldown);
+                                if !active && cooled && now - since >= min_duration {
+                                    log::info!("{name}: Sound detected at {level:.1}dB");
+                                    active = true;
+                                    sound_watcher.send_replace(SoundState::Start(now));
+                                }
+                            } else {
+                                loud_since = None;
+                                let since = *quiet_since.get_or_insert(now);
+                                if active && now - since >= min_duration {
+                                    log::info!("{name}: Sound stopped");
+                                    active = false;
+                                    last_stop = Some(now);
+                                    sound_watcher.send_replace(SoundState::Stop(now));
+                                }
+                            }
+                        }
+                        MessageView::Error(e) => {
+                            break Err(anyhow!("Error in audio detection pipeline: {}", e.error()));
+                        }
+                        MessageView::Eos(_) => {
+                            break Err(anyhow!("Audio detection pipeline ended"));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    let _ = pipeline.set_state(gstreamer::State::Null);
+    r
+}
+
+/// The loudest channel's RMS in dB from a level message
+fn peak_rms(structure: &gstreamer::StructureRef) -> Option<f64> {
+    structure
+        .get::<glib::ValueArray>("rms")
+        .ok()?
+        .iter()
+        .filter_map(|value| value.get::<f64>().ok())
+        .reduce(f64::max)
+}
+
+/// Build `appsrc ! decode ! audioconvert ! level ! fakesink`
+///
+/// When block size is given it is ADPCM otherwise it is AAC
+fn build_level_pipeline(adpcm_block_size: Option<u32>) -> AnyResult<Pipeline> {
+    gstreamer::init().context("Gstreamer failed to initialise")?;
+    let pipeline = Pipeline::new();
+
+    let source = make_element("appsrc", "audsrc")?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    source.set_is_live(true);
+    source.set_block(false);
+    source.set_do_timestamp(true);
+    source.set_property("emit-signals", false);
+    source.set_max_bytes(512 * 1416);
+    let source = source
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot cast back"))?;
+
+    let queue = make_element("queue", "audqueue")?;
+    let convert = make_element("audioconvert", "audconvert")?;
+    let level = make_element("level", "audlevel")?;
+    level.set_property("post-messages", true);
+    level.set_property("interval", 100_000_000u64);
+    let sink = make_element("fakesink", "audsink")?;
+    sink.set_property("sync", false);
+
+    pipeline.add_many([&source, &queue, &convert, &level, &sink])?;
+    Element::link_many([&convert, &level, &sink])?;
+
+    match adpcm_block_size {
+        Some(block_size) => {
+            let appsrc = source
+                .clone()
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+            appsrc.set_caps(Some(
+                &Caps::builder("audio/x-adpcm")
+                    .field("layout", "div")
+                    .field("block_align", block_size as i32)
+                    .field("channels", 1i32)
+                    .field("rate", 8000i32)
+                    .build(),
+            ));
+            let decoder = make_element("decodebin", "auddecoder")?;
+            pipeline.add(&decoder)?;
+            Element::link_many([&source, &queue, &decoder])?;
+            let convert = convert.clone();
+            // A failed link is posted to the bus which stops the detection
+            decoder.connect_pad_added(move |element, pad| {
+                let Some(sink_pad) = convert.static_pad("sink") else {
+                    log::error!("Audioconvert is missing its pad");
+                    element_error!(element, CoreError::Pad, ("Audioconvert is missing its pad"));
+                    return;
+                };
+                if let Err(e) = pad.link(&sink_pad) {
+                    log::error!("Failed to link ADPCM decoder to audioconvert: {e:?}");
+                    element_error!(
+                        element,
+                        CoreError::Negotiation,
+                        ("Failed to link ADPCM decoder to audioconvert: {e:?}")
+                    );
+                }
+            });
+        }
+        None => {
+            let parser = make_element("aacparse", "audparser")?;
+            let decoder = match make_element("faad", "auddecoder_faad") {
+                Ok(ele) => Ok(ele),
+                Err(_) => make_element("avdec_aac", "auddecoder_avdec_aac"),
+            }?;
+            pipeline.add_many([&parser, &decoder])?;
+            Element::link_many([&source, &queue, &parser, &decoder, &convert])?;
+        }
+    }
+
+    Ok(pipeline)
+}
@@ -0,0 +1,102 @@
+//! Throughput of the streams that the rtsp server is sending
+//!
+//! The rtsp streaming loop counts the frames it gets from the camera and the
+//! buffers it had to drop because the client was not keeping up. Every
+//! `stats_interval` seconds these are logged and put in
+//! [`super::NeoInstance::stream_stats`] for mqtt
+use serde::Serialize;
+use std::collections::HashMap;
+#[cfg(feature = "gstreamer")]
+use tokio::time::{Duration, Instant};
+
+use neolink_core::bc_protocol::StreamKind;
+#[cfg(feature = "gstreamer")]
+use neolink_core::bcmedia::model::BcMedia;
+
+/// The latest stats of each stream of a camera
+pub(crate) type StreamStatsMap = HashMap<StreamKind, StreamStats>;
+
+/// What a stream did over the last interval
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct StreamStats {
+    /// Video frames each second
+    pub(crate) fps: f32,
+    /// Video and audio from the camera in kbit/s
+    pub(crate) kbps: u32,
+    /// Average seconds between keyframes, None when there were none
+    pub(crate) keyframe_interval: Option<f32>,
+    /// Buffers dropped because the client was not reading them
+    pub(crate) dropped: u64,
+}
+
+impl std::fmt::Display for StreamStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:.1}fps {}kbps keyframe every ", self.fps, self.kbps)?;
+        match self.keyframe_interval {
+            Some(interval) => write!(f, "{interval:.1}s")?,
+            None => write!(f, "-")?,
+        }
+        write!(f, " {} dropped", self.dropped)
+    }
+}
+
+/// Counts the media of one stream until the stats are taken
+#[cfg(feature = "gstreamer")]
+pub(crate) struct StreamCounter {
+    since: Instant,
+    frames: u64,
+    keyframes: u64,
+    bytes: u64,
+    dropped: u64,
+}
+
+#[cfg(feature = "gstreamer")]
+impl StreamCounter {
+    pub(crate) fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            frames: 0,
+            keyframes: 0,
+            bytes: 0,
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn media(&mut self, media: &BcMedia) {
+        match media {
+            BcMedia::Iframe(frame) => {
+                self.frames += 1;
+                self.keyframes += 1;
+                self.bytes += frame.data.len() as u64;
+            }
+            BcMedia::Pframe(frame) => {
+                self.frames += 1;
+                self.bytes += frame.data.len() as u64;
+            }
+            BcMedia::Aac(aac) => self.bytes += aac.data.len() as u64,
+            BcMedia::Adpcm(adpcm) => self.bytes += adpcm.data.len() as u64,
+            _ => {}
+        }
+    }
+
+    pub(crate) fn dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// The stats since the last time and start counting again
+    pub(crate) fn take(&mut self) -> StreamStats {
+        let secs = self.elapsed().as_secs_f32().max(f32::EPSILON);
+        let stats = StreamStats {
+            fps: self.frames as f32 / secs,
+            kbps: (self.bytes as f32 * 8.0 / 1000.0 / secs) as u32,
+            keyframe_interval: (self.keyframes > 0).then(|| secs / self.keyframes as f32),
+            dropped: self.dropped,
+        };
+        *self = Self::new();
+        stats
+    }
+}
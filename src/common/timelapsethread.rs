@@ -0,0 +1,406 @@
+//! This thread will capture a still from the camera at a regular
+//! interval and assemble each day's stills into a timelapse video
+//!
+//! This is only active on cameras with `[cameras.timelapse]`
+//!
+//! The stills are stored as `<output>/<camera>/<YYYY-MM-DD>/<HHMMSS>.jpeg`
+//! or as `filename` gives with its strftime fields,
+//! and the videos as `<output>/<camera>/<camera>-<YYYY-MM-DD>.mp4`.
+//! Any finished day without a video is assembled at `video_at`
+//! so days missed while neolink was not running are caught up.
+//!
+//! The stills come from the snapshot command unless `stream` is set, then
+//! the next iframe of that stream is used. With `keep_days` the older stills
+//! are removed.
+
+use anyhow::{anyhow, Context};
+use gstreamer::{prelude::*, Caps, ClockTime, Element, MessageView, Pipeline};
+use gstreamer_app::AppSrc;
+use std::{
+    convert::TryFrom,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use time::{Date, OffsetDateTime, Time};
+use tokio::time::{interval, sleep, timeout, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{make_element, NeoInstance};
+use crate::{
+    config::TimelapseConfig,
+    image::jpeg_from_stream,
+    utils::{file_safe_name, now_local},
+    AnyResult, Result,
+};
+
+pub(crate) struct NeoCamTimelapseThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamTimelapseThread {
+    pub(crate) async fn new(instance: NeoInstance) -> Result<Self> {
+        Ok(Self {
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                let mut config_rx = instance.config().await?;
+                loop {
+                    // Wait for the green light
+                    let timelapse_config = config_rx
+                        .wait_for(|config| config.timelapse.is_some())
+                        .await?
+                        .timelapse
+                        .clone()
+                        .expect("Checked by the wait_for");
+
+                    let r = tokio::select! {
+                        v = config_rx.wait_for(|config| config.timelapse.as_ref() != Some(&timelapse_config)) => {
+                            // Config changed go back and restart with the new one
+                            v?;
+                            AnyResult::Ok(())
+                        },
+                        v = timelapse(&instance, &timelapse_config) => v,
+                    };
+                    if let Err(e) = r {
+                        log::warn!("Error in timelapse task Restarting: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            } => v
+        }
+    }
+}
+
+impl Drop for NeoCamTimelapseThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamTimelapseThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamTimelapseThread");
+    }
+}
+
+async fn timelapse(instance: &NeoInstance, config: &TimelapseConfig) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let dir = config.output.join(file_safe_name(&name));
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Could not create timelapse directory {:?}", dir))?;
+    log::info!("{name}: Timelapse saving to {:?}", dir);
+
+    let mut capture = interval(Duration::from_secs(config.interval));
+    capture.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut prune = interval(Duration::from_secs(60 * 60));
+    prune.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = capture.tick() => {
+                // A missed frame (e.g. the camera is asleep) just leaves a gap
+                if let Err(e) = capture_frame(instance, &dir, config).await {
+                    log::debug!("{name}: Timelapse frame skipped: {e:?}");
+                }
+            }
+            _ = prune.tick(), if config.keep_days.is_some() => {
+                let keep = Duration::from_secs(config.keep_days.unwrap_or_default() * 24 * 60 * 60);
+                if let Err(e) = prune_frames(&name, &dir, keep).await {
+                    log::warn!("{name}: Could not remove old timelapse frames: {e:?}");
+                }
+            }
+            _ = sleep(until_time_of_day(&config.video_at)), if config.daily_video => {
+                if let Err(e) = assemble_finished_days(&name, &dir, config).await {
+                    log::warn!("{name}: Could not create timelapse video: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+async fn capture_frame(
+    instance: &NeoInstance,
+    dir: &Path,
+    config: &TimelapseConfig,
+) -> AnyResult<()> {
+    let stream = config
+        .stream
+        .and_then(|stream| stream.as_stream_kinds().first().copied());
+    let jpeg = timeout(Duration::from_secs(30), async {
+        match stream {
+            // The stream has to be played so this connects to the camera
+            Some(stream) => jpeg_from_stream(instance, stream).await,
+            // Passive so that a sleeping battery camera is not woken for a still
+            None => {
+                instance
+                    .run_passive_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+                    .await
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("Timed out waiting for the snapshot"))??;
+
+    let path = dir.join(format_time(&config.filename, now_local()));
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, jpeg)
+        .await
+        .with_context(|| format!("Could not write {:?}", path))?;
+    log::trace!("Timelapse frame saved to {:?}", path);
+    Ok(())
+}
+
+/// Make the videos of every day before today that does not have one yet
+async fn assemble_finished_days(name: &str, dir: &Path, config: &TimelapseConfig) -> AnyResult<()> {
+    let today = date_name(now_local().date());
+    let mut days = vec![];
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let day = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().await?.is_dir() && is_date_name(&day) && day < today {
+            days.push(day);
+        }
+    }
+    days.sort();
+
+    for day in days {
+        let day_dir = dir.join(&day);
+        let video = dir.join(format!("{}-{}.mp4", file_safe_name(name), day));
+        if !tokio::fs::try_exists(&video).await? {
+            let mut frames = vec![];
+            let mut entries = tokio::fs::read_dir(&day_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "jpeg") {
+                    frames.push(path);
+                }
+            }
+            frames.sort();
+            if frames.is_empty() {
+                log::debug!("{name}: No timelapse frames for {day}");
+            } else {
+                log::info!(
+                    "{name}: Creating timelapse video {:?} from {} frames",
+                    video,
+                    frames.len()
+                );
+                let fps = config.fps;
+                let thread_video = video.clone();
+                tokio::task::spawn_blocking(move || encode_video(&frames, &thread_video, fps))
+                    .await??;
+            }
+        }
+        if config.prune {
+            log::debug!("{name}: Removing timelapse frames for {day}");
+            tokio::fs::remove_dir_all(&day_dir)
+                .await
+                .with_context(|| format!("Could not remove {:?}", day_dir))?;
+        }
+    }
+    Ok(())
+}
+
+/// Encode the jpegs into an mp4
+///
+/// This blocks until the video is written
+fn encode_video(frames: &[PathBuf], video: &Path, fps: u32) -> AnyResult<()> {
+    gstreamer::init().context("Gstreamer failed to initialise")?;
+    // Write to a temporary name so a failed encode is retried next time
+    let partial = video.with_extension("mp4.partial");
+    let pipeline = Pipeline::new();
+
+    let source = make_element("appsrc", "tlsrc")?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    source.set_caps(Some(
+        &Caps::builder("image/jpeg")
+            .field("framerate", gstreamer::Fraction::new(fps as i32, 1))
+            .build(),
+    ));
+    source.set_format(gstreamer::Format::Time);
+    source.set_block(true);
+    source.set_max_bytes(16 * 1024 * 1024);
+    let source = source
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot cast back"))?;
+
+    let decoder = make_element("jpegdec", "tldec")?;
+    let convert = make_element("videoconvert", "tlconvert")?;
+    let encoder = make_element("x264enc", "tlenc")?;
+    let muxer = make_element("mp4mux", "tlmux")?;
+    let sink = make_element("filesink", "tlsink")?;
+    sink.set_property("location", partial.to_string_lossy().to_string());
+
+    pipeline.add_many([&source, &decoder, &convert, &encoder, &muxer, &sink])?;
+    Element::link_many([&source, &decoder, &convert, &encoder, &muxer, &sink])?;
+
+    let appsrc = source
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    let bus = pipeline
+        .bus()
+        .ok_or(anyhow!("Timelapse pipeline has no bus"))?;
+    pipeline.set_state(gstreamer::State::Playing)?;
+
+    let r = (|| {
+        let frame_duration = ClockTime::SECOND / fps as u64;
+        let mut index = 0u64;
+        for frame in frames.iter() {
+            // Skip unreadable frames rather than lose the whole day
+            let data = match std::fs::read(frame) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::debug!("Skipping timelapse frame {:?}: {e:?}", frame);
+                    continue;
+                }
+            };
+            let mut buffer = gstreamer::Buffer::from_slice(data);
+            {
+                let buffer = buffer
+                    .get_mut()
+                    .ok_or(anyhow!("Timelapse buffer is not writable"))?;
+                buffer.set_pts(frame_duration * index);
+                buffer.set_duration(frame_duration);
+            }
+            appsrc.push_buffer(buffer)?;
+            index += 1;
+        }
+        appsrc.end_of_stream()?;
+
+        for msg in bus.iter_timed(ClockTime::NONE) {
+            match msg.view() {
+                MessageView::Eos(_) => return AnyResult::Ok(()),
+                MessageView::Error(e) => {
+                    return Err(anyhow!("Error in timelapse pipeline: {}", e.error()));
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow!("Timelapse pipeline bus closed"))
+    })();
+
+    let _ = pipeline.set_state(gstreamer::State::Null);
+    match r {
+        Ok(()) => {
+            std::fs::rename(&partial, video)
+                .with_context(|| format!("Could not rename {:?}", partial))?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Remove the frames older than `keep` and then any directories left empty
+async fn prune_frames(name: &str, dir: &Path, keep: Duration) -> AnyResult<()> {
+    let now = SystemTime::now();
+    let mut dirs = vec![dir.to_path_buf()];
+    let mut visited = vec![];
+    while let Some(current) = dirs.pop() {
+        let mut entries = tokio::fs::read_dir(&current).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                dirs.push(path);
+            } else if metadata.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "jpeg" || ext == "jpg")
+                && now.duration_since(metadata.modified()?).unwrap_or_default() > keep
+            {
+                log::trace!("{name}: Removing the timelapse frame {:?}", path);
+                tokio::fs::remove_file(&path)
+                    .await
+                    .with_context(|| format!("Could not remove {:?}", path))?;
+            }
+        }
+        if current != dir {
+            visited.push(current);
+        }
+    }
+    // Deepest first so that parents are empty by the time they are reached
+    for current in visited.iter().rev() {
+        // Fails when it still has files which is fine
+        let _ = tokio::fs::remove_dir(current).await;
+    }
+    Ok(())
+}
+
+/// Fill in the strftime fields of the frame's filename
+///
+/// Only the numeric fields `%Y %y %m %d %H %M %S %j %s` and `%%` are known,
+/// anything else is kept as it is
+fn format_time(template: &str, at: OffsetDateTime) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", at.year())),
+            Some('y') => out.push_str(&format!("{:02}", at.year().rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{:02}", at.month() as u8)),
+            Some('d') => out.push_str(&format!("{:02}", at.day())),
+            Some('H') => out.push_str(&format!("{:02}", at.hour())),
+            Some('M') => out.push_str(&format!("{:02}", at.minute())),
+            Some('S') => out.push_str(&format!("{:02}", at.second())),
+            Some('j') => out.push_str(&format!("{:03}", at.ordinal())),
+            Some('s') => out.push_str(&at.unix_timestamp().to_string()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+pub(super) fn date_name(date: Date) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        date.year(),
+        date.month() as u8,
+        date.day()
+    )
+}
+
+fn is_date_name(name: &str) -> bool {
+    name.len() == 10
+        && name.chars().enumerate().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// How long until the next `HH:MM`
+fn until_time_of_day(time_of_day: &str) -> Duration {
+    let (hour, minute) = time_of_day
+        .split_once(':')
+        .and_then(|(h, m)| Some((h.parse::<u8>().ok()?, m.parse::<u8>().ok()?)))
+        .unwrap_or((0, 0));
+    let now = now_local();
+    let Ok(at) = Time::from_hms(hour, minute, 0) else {
+        return Duration::from_secs(24 * 60 * 60);
+    };
+    let mut next = now.replace_time(at);
+    if next <= now {
+        next += time::Duration::DAY;
+    }
+    Duration::try_from(next - now).unwrap_or(Duration::from_secs(24 * 60 * 60))
+}
@@ -63,6 +63,11 @@ impl UseCounter {
         Ok(res)
     }
 
+    /// The number of active users
+    pub(crate) fn users(&self) -> WatchReceiver<u32> {
+        self.value.clone()
+    }
+
     #[cfg(feature = "gstreamer")]
     #[allow(dead_code)]
     pub(crate) async fn create_deactivated(&self) -> Result<Permit> {
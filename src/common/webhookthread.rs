@@ -0,0 +1,253 @@
+//! This thread will POST the events of the camera to a webhook
+//!
+//! This is only active on cameras with `[cameras.webhooks]`
+//!
+//! The events are motion start/stop, the camera connecting or disconnecting,
+//! the battery going below `battery_low`, a doorbell being pressed and rtsp
+//! clients starting or stopping to watch. Failed requests are logged and
+//! not retried.
+
+use anyhow::Context;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, sleep, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{MdState, NeoInstance};
+use crate::{
+    config::{WebhookConfig, WebhookEvent},
+    AnyResult, Result,
+};
+
+const BATTERY_INTERVAL: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) struct NeoCamWebhookThread {
+    cancel: CancellationToken,
+    instance: NeoInstance,
+}
+
+impl NeoCamWebhookThread {
+    pub(crate) async fn new(instance: NeoInstance) -> Result<Self> {
+        Ok(Self {
+            cancel: CancellationToken::new(),
+            instance,
+        })
+    }
+
+    pub(crate) async fn run(&mut self) -> Result<()> {
+        let thread_cancel = self.cancel.clone();
+        let instance = self.instance.clone();
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {
+                Ok(())
+            },
+            v = async {
+                let mut config_rx = instance.config().await?;
+                loop {
+                    // Wait for the green light
+                    let webhook_config = config_rx
+                        .wait_for(|config| config.webhooks.is_some())
+                        .await?
+                        .webhooks
+                        .clone()
+                        .expect("Checked by the wait_for");
+
+                    let r = tokio::select! {
+                        v = config_rx.wait_for(|config| config.webhooks.as_ref() != Some(&webhook_config)) => {
+                            // Config changed go back and restart with the new one
+                            v?;
+                            AnyResult::Ok(())
+                        },
+                        v = webhooks(&instance, &webhook_config) => v,
+                    };
+                    if let Err(e) = r {
+                        log::warn!("Error in webhook task Restarting: {:?}", e);
+                        sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            } => v
+        }
+    }
+}
+
+impl Drop for NeoCamWebhookThread {
+    fn drop(&mut self) {
+        log::trace!("Drop NeoCamWebhookThread");
+        self.cancel.cancel();
+        log::trace!("Dropped NeoCamWebhookThread");
+    }
+}
+
+async fn webhooks(instance: &NeoInstance, config: &WebhookConfig) -> AnyResult<()> {
+    let name = instance.config().await?.borrow().name.clone();
+    let hook = Webhook {
+        client: reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Could not create the webhook client")?,
+        camera: name.clone(),
+        config: config.clone(),
+    };
+    log::info!("{name}: Sending events to {}", config.url);
+
+    let wants = |event| config.events.contains(&event);
+    tokio::select! {
+        v = async {
+            let mut md = instance.motion().await?;
+            loop {
+                md.wait_for(|state| matches!(state, MdState::Start(_))).await?;
+                hook.send(WebhookEvent::MotionStart, Default::default());
+                md.wait_for(|state| matches!(state, MdState::Stop(_))).await?;
+                hook.send(WebhookEvent::MotionStop, Default::default());
+            }
+        }, if wants(WebhookEvent::MotionStart) || wants(WebhookEvent::MotionStop) => v,
+        v = async {
+            let mut camera = instance.camera();
+            loop {
+                camera.wait_for(|cam| cam.upgrade().is_some()).await?;
+                hook.send(WebhookEvent::Connected, Default::default());
+                camera.wait_for(|cam| cam.upgrade().is_none()).await?;
+                hook.send(WebhookEvent::Disconnected, Default::default());
+            }
+        }, if wants(WebhookEvent::Connected) || wants(WebhookEvent::Disconnected) => v,
+        v = battery(instance, &hook), if wants(WebhookEvent::BatteryLow) => v,
+        v = async {
+            let mut visitor = instance.visitor().await?;
+            loop {
+                visitor.wait_for(|state| *state == Some(true)).await?;
+                hook.send(WebhookEvent::Visitor, Default::default());
+                visitor.wait_for(|state| *state != Some(true)).await?;
+            }
+        }, if wants(WebhookEvent::Visitor) => v,
+        v = async {
+            let mut clients = instance.rtsp_clients().await?;
+            let mut prev = *clients.borrow_and_update();
+            loop {
+                clients.changed().await?;
+                let count = *clients.borrow_and_update();
+                let details = Details {
+                    clients: Some(count),
+                    ..Default::default()
+                };
+                if count > prev {
+                    hook.send(WebhookEvent::ClientConnected, details);
+                } else if count < prev {
+                    hook.send(WebhookEvent::ClientDisconnected, details);
+                }
+                prev = count;
+            }
+        }, if wants(WebhookEvent::ClientConnected) || wants(WebhookEvent::ClientDisconnected) => v,
+        // No events were chosen
+        else => futures::future::pending().await,
+    }
+}
+
+/// Send `battery_low` each time the battery goes below the threshold
+async fn battery(instance: &NeoInstance, hook: &Webhook) -> AnyResult<()> {
+    let mut check = interval(BATTERY_INTERVAL);
+    check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut was_low = false;
+    loop {
+        check.tick().await;
+        // Passive so that a sleeping battery camera is not woken to check it
+        let info = instance
+            .run_passive_task(|cam| Box::pin(async move { Ok(cam.battery_info().await?) }))
+            .await;
+        let percent = match info {
+            Ok(info) => info.battery_percent,
+            Err(e) => match e.downcast::<neolink_core::Error>() {
+                Ok(neolink_core::Error::CameraServiceUnavailable { .. }) => {
+                    log::debug!("{}: Battery not supported", hook.camera);
+                    futures::future::pending().await
+                }
+                _ => continue,
+            },
+        };
+        let is_low = percent < hook.config.battery_low;
+        if is_low && !was_low {
+            hook.send(
+                WebhookEvent::BatteryLow,
+                Details {
+                    battery: Some(percent),
+                    ..Default::default()
+                },
+            );
+        }
+        was_low = is_low;
+    }
+}
+
+/// The extra values of an event
+#[derive(Default, Clone, Copy)]
+struct Details {
+    /// The percent for `battery_low`
+    battery: Option<u32>,
+    /// The rtsp clients left watching for `client_connected` and `client_disconnected`
+    clients: Option<u32>,
+}
+
+struct Webhook {
+    client: reqwest::Client,
+    camera: String,
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    /// POST the event in the background so the events are never held up
+    fn send(&self, event: WebhookEvent, details: Details) {
+        if !self.config.events.contains(&event) {
+            return;
+        }
+        let body = self.body(event, details);
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
+        for (key, value) in self.config.headers.iter() {
+            request = request.header(key, value);
+        }
+        let camera = self.camera.clone();
+        tokio::task::spawn(async move {
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => log::debug!("{camera}: Webhook sent {}", event.name()),
+                Err(e) => log::warn!("{camera}: Webhook for {} failed: {e}", event.name()),
+            }
+        });
+    }
+
+    fn body(&self, event: WebhookEvent, details: Details) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        match self.config.body.as_ref() {
+            Some(template) => template
+                .replace("{camera}", &json_escape(&self.camera))
+                .replace("{event}", event.name())
+                .replace("{timestamp}", &timestamp.to_string())
+                .replace(
+                    "{battery}",
+                    &details.battery.map(|b| b.to_string()).unwrap_or_default(),
+                )
+                .replace(
+                    "{clients}",
+                    &details.clients.map(|c| c.to_string()).unwrap_or_default(),
+                ),
+            None => serde_json::json!({
+                "camera": self.camera,
+                "event": event.name(),
+                "timestamp": timestamp,
+                "battery": details.battery,
+                "clients": details.clients,
+            })
+            .to_string(),
+        }
+    }
+}
+
+/// The text as it would be inside a JSON string
+fn json_escape(text: &str) -> String {
+    let quoted = serde_json::Value::from(text).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
@@ -0,0 +1,538 @@
+//! WebRTC viewing of the cameras over WHEP
+//!
+//! When `[webrtc]` is in the config the long running subcommands answer
+//!
+//! - `POST /whep/{camera}/{stream}` with an SDP offer to start watching, the
+//!   reply is the SDP answer and its `Location` ends the session with a
+//!   `DELETE`
+//! - `GET /whep/{camera}/{stream}` with a small page that plays the stream in
+//!   the browser
+//!
+//! The stream is one of `main`, `sub` or `extern`. The H264 from the
+//! camera is sent as is so there is no encoding. Cameras that send H265 cannot
+//! be watched since browsers do not play it over WebRTC.
+//!
+//! Only the video is sent
+//!
+//! When there are `[[users]]` in the config the requests must log in as one
+//! of them with basic auth
+use anyhow::{anyhow, Context};
+use gstreamer::{
+    glib::{self, translate::*},
+    prelude::*,
+    BufferFlags, Caps, Element, MessageView, Pipeline, Promise,
+};
+use gstreamer_app::AppSrc;
+use gstreamer_sdp::SDPMessage;
+use neolink_core::{
+    bc_protocol::StreamKind,
+    bcmedia::model::{BcMedia, BcMediaIframe, BcMediaPframe, VideoType},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    sync::mpsc::Receiver as MpscReceiver,
+    time::{interval, timeout, Duration, Instant, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    basic_auth, make_element, percent_decode, read_request, write_response_with_headers,
+    HttpRequest, NeoReactor,
+};
+use crate::{config::WebRtcConfig, AnyResult};
+
+/// How long to wait for the first iframe of the camera
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a viewer may be disconnected before the session ends
+const DISCONNECTED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `GstWebRTCSDPType`
+const SDP_TYPE_OFFER: i32 = 1;
+/// `GstWebRTCPeerConnectionState`
+const PEER_CONNECTED: i32 = 2;
+const PEER_FAILED: i32 = 4;
+const PEER_CLOSED: i32 = 5;
+/// `GstWebRTCICEGatheringState`
+const ICE_GATHERING_COMPLETE: i32 = 2;
+
+const CORS: [(&str, &str); 4] = [
+    ("Access-Control-Allow-Origin", "*"),
+    ("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS"),
+    (
+        "Access-Control-Allow-Headers",
+        "Authorization, Content-Type",
+    ),
+    ("Access-Control-Expose-Headers", "Location"),
+];
+
+/// `GstWebRTCSessionDescription`, gstreamer-webrtc is not bound
+#[repr(C)]
+struct SessionDescription {
+    sdp_type: i32,
+    sdp: *mut gstreamer_sdp::ffi::GstSDPMessage,
+}
+
+struct WhepServer {
+    config: WebRtcConfig,
+    reactor: NeoReactor,
+    sessions: Mutex<HashMap<String, CancellationToken>>,
+}
+
+/// Serve the WHEP endpoints until an error
+pub(crate) async fn serve_whep(config: WebRtcConfig, reactor: NeoReactor) -> AnyResult<()> {
+    gstreamer::init().context("Gstreamer failed to initialise")?;
+    let listener = TcpListener::bind((config.bind_addr.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Could not bind the webrtc server to {}:{}",
+                config.bind_addr, config.port
+            )
+        })?;
+    log::info!("WebRTC at http://{}:{}/whep", config.bind_addr, config.port);
+
+    let server = Arc::new(WhepServer {
+        config,
+        reactor,
+        sessions: Default::default(),
+    });
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = server.answer(stream).await {
+                log::debug!("WebRTC request from {peer} failed: {e:?}");
+            }
+        });
+    }
+}
+
+impl WhepServer {
+    async fn answer(self: &Arc<Self>, stream: TcpStream) -> AnyResult<()> {
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let request = timeout(Duration::from_secs(10), read_request(&mut read))
+            .await
+            .map_err(|_| anyhow!("Timed out reading the request"))??;
+
+        let config = self.reactor.config().await?;
+        let users = config.borrow().users.clone();
+        let (status, content_type, body, location) = if request.method == "OPTIONS" {
+            (204, "text/plain", String::new(), None)
+        } else if !basic_auth(&request, &users) {
+            (401, "text/plain", "Unauthorized".to_string(), None)
+        } else {
+            match self.route(&request).await {
+                Ok(Reply::Page(page)) => (200, "text/html", page, None),
+                Ok(Reply::Answer { location, sdp }) => {
+                    (201, "application/sdp", sdp, Some(location))
+                }
+                Ok(Reply::Status(status, body)) => (status, "text/plain", body, None),
+                Err(e) => (500, "text/plain", format!("FAIL: {e:?}"), None),
+            }
+        };
+        let mut headers = CORS.to_vec();
+        if let Some(location) = location.as_deref() {
+            headers.push(("Location", location));
+        }
+        write_response_with_headers(&mut write, status, content_type, &headers, body.as_bytes())
+            .await
+    }
+
+    async fn route(self: &Arc<Self>, request: &HttpRequest) -> AnyResult<Reply> {
+        let parts = request
+            .path
+            .trim_matches('/')
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        match (request.method.as_str(), parts.as_slice()) {
+            ("GET", ["whep", name, stream]) => {
+                Ok(Reply::Page(player_page(&format!("/whep/{name}/{stream}"))))
+            }
+            ("POST", ["whep", name, stream]) => {
+                let name = percent_decode(name);
+                let Some(stream) = stream_kind(stream) else {
+                    return Ok(Reply::Status(404, "Unknown stream".to_string()));
+                };
+                self.start(&name, stream, &request.body).await
+            }
+            ("DELETE", ["whep", _, _, id]) => match self.sessions.lock().unwrap().remove(*id) {
+                Some(cancel) => {
+                    cancel.cancel();
+                    Ok(Reply::Status(200, "OK".to_string()))
+                }
+                None => Ok(Reply::Status(404, "Not Found".to_string())),
+            },
+            ("GET", _) | ("POST", _) | ("DELETE", _) => {
+                Ok(Reply::Status(404, "Not Found".to_string()))
+            }
+            _ => Ok(Reply::Status(405, "Method Not Allowed".to_string())),
+        }
+    }
+
+    /// Start a session from the offer of a viewer
+    async fn start(
+        self: &Arc<Self>,
+        name: &str,
+        stream: StreamKind,
+        offer: &str,
+    ) -> AnyResult<Reply> {
+        let config = self.reactor.config().await?;
+        let found = config
+            .borrow()
+            .cameras
+            .iter()
+            .any(|camera| camera.name == name && camera.enabled);
+        if !found {
+            return Ok(Reply::Status(404, "Unknown camera".to_string()));
+        }
+        let Some(payload) = h264_payload(offer) else {
+            return Ok(Reply::Status(
+                415,
+                "The offer has no H264 video".to_string(),
+            ));
+        };
+
+        let camera = self.reactor.get(name).await?;
+        let mut media_rx = camera.stream(stream).await?;
+        let first = timeout(FIRST_FRAME_TIMEOUT, async {
+            while let Some(media) = media_rx.recv().await {
+                if let BcMedia::Iframe(iframe) = media {
+                    return Some(iframe);
+                }
+            }
+            None
+        })
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for the camera"))?
+        .ok_or_else(|| anyhow!("The camera stream ended"))?;
+        if matches!(first.video_type, VideoType::H265) {
+            return Ok(Reply::Status(
+                415,
+                format!("{name} sends H265 which cannot be played over WebRTC"),
+            ));
+        }
+
+        let session = Session::new(payload, self.config.stun_server.as_deref())?;
+        session.push_data(first.data, true)?;
+        let offer = offer.to_string();
+        let webrtc = session.webrtc.clone();
+        let sdp = tokio::task::spawn_blocking(move || negotiate(&webrtc, &offer)).await??;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let cancel = CancellationToken::new();
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id.clone(), cancel.clone());
+        log::info!("{name}::{stream}: New WebRTC viewer {id}");
+
+        let server = self.clone();
+        let thread_id = id.clone();
+        let thread_name = name.to_string();
+        tokio::task::spawn(async move {
+            if let Err(e) = session.run(media_rx, cancel).await {
+                log::debug!("{thread_name}::{stream}: WebRTC viewer {thread_id} failed: {e:?}");
+            }
+            log::info!("{thread_name}::{stream}: WebRTC viewer {thread_id} left");
+            server.sessions.lock().unwrap().remove(&thread_id);
+        });
+
+        let stream_name = match stream {
+            StreamKind::Main => "main",
+            StreamKind::Sub => "sub",
+            StreamKind::Extern => "extern",
+        };
+        Ok(Reply::Answer {
+            location: format!("/whep/{name}/{stream_name}/{id}"),
+            sdp,
+        })
+    }
+}
+
+enum Reply {
+    Page(String),
+    Answer { location: String, sdp: String },
+    Status(u16, String),
+}
+
+/// The pipeline of one viewer
+struct Session {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+    webrtc: Element,
+}
+
+impl Session {
+    fn new(payload: u32, stun_server: Option<&str>) -> AnyResult<Self> {
+        let pipeline = Pipeline::new();
+        let source = make_element("appsrc", "whepsrc")?
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        source.set_caps(Some(
+            &Caps::builder("video/x-h264")
+                .field("stream-format", "byte-stream")
+                .build(),
+        ));
+        source.set_format(gstreamer::Format::Time);
+        source.set_is_live(true);
+        source.set_block(false);
+        source.set_do_timestamp(true);
+        source.set_max_bytes(8 * 1024 * 1024);
+        let source = source
+            .dynamic_cast::<Element>()
+            .map_err(|_| anyhow!("Cannot cast back"))?;
+
+        let parser = make_element("h264parse", "whepparse")?;
+        let payloader = make_element("rtph264pay", "whep_pay")?;
+        // Send the parameter sets with every iframe so the browser can start on any of them
+        payloader.set_property("config-interval", -1i32);
+        payloader.set_property("pt", payload);
+        let filter = make_element("capsfilter", "whepcaps")?;
+        filter.set_property(
+            "caps",
+            Caps::builder("application/x-rtp")
+                .field("media", "video")
+                .field("encoding-name", "H264")
+                .field("clock-rate", 90000i32)
+                .field("payload", payload as i32)
+                .build(),
+        );
+        let webrtc = make_element("webrtcbin", "whepwebrtc")?;
+        webrtc.set_property_from_str("bundle-policy", "max-bundle");
+        if let Some(stun_server) = stun_server {
+            webrtc.set_property("stun-server", stun_server);
+        }
+
+        pipeline.add_many([&source, &parser, &payloader, &filter, &webrtc])?;
+        Element::link_many([&source, &parser, &payloader, &filter, &webrtc])?;
+        pipeline.set_state(gstreamer::State::Playing)?;
+
+        let appsrc = source
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        Ok(Self {
+            pipeline,
+            appsrc,
+            webrtc,
+        })
+    }
+
+    fn push_data(&self, data: Vec<u8>, keyframe: bool) -> AnyResult<()> {
+        let mut buffer = gstreamer::Buffer::from_slice(data);
+        if !keyframe {
+            buffer
+                .get_mut()
+                .ok_or(anyhow!("WebRTC buffer is not writable"))?
+                .set_flags(BufferFlags::DELTA_UNIT);
+        }
+        self.appsrc.push_buffer(buffer)?;
+        Ok(())
+    }
+
+    /// Send the camera to the viewer until they leave
+    async fn run(
+        &self,
+        mut media_rx: MpscReceiver<BcMedia>,
+        cancel: CancellationToken,
+    ) -> AnyResult<()> {
+        let mut check = interval(Duration::from_secs(1));
+        check.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut connected_at = Instant::now();
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                media = media_rx.recv() => {
+                    match media {
+                        Some(BcMedia::Iframe(BcMediaIframe { data, .. })) => self.push_data(data, true)?,
+                        Some(BcMedia::Pframe(BcMediaPframe { data, .. })) => self.push_data(data, false)?,
+                        Some(_) => {}
+                        None => return Err(anyhow!("The camera stream ended")),
+                    }
+                }
+                _ = check.tick() => {
+                    self.check_bus()?;
+                    match enum_property(&self.webrtc, "connection-state") {
+                        PEER_CONNECTED => connected_at = Instant::now(),
+                        PEER_FAILED | PEER_CLOSED => return Ok(()),
+                        _ if connected_at.elapsed() > DISCONNECTED_TIMEOUT => {
+                            return Err(anyhow!("The viewer did not connect"));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_bus(&self) -> AnyResult<()> {
+        let bus = self
+            .pipeline
+            .bus()
+            .ok_or(anyhow!("WebRTC pipeline has no bus"))?;
+        while let Some(msg) = bus.pop() {
+            if let MessageView::Error(e) = msg.view() {
+                return Err(anyhow!("Error in WebRTC pipeline: {}", e.error()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+    }
+}
+
+/// Answer the offer and wait for all of the ice candidates
+///
+/// This blocks on the gstreamer promises
+fn negotiate(webrtc: &Element, offer: &str) -> AnyResult<String> {
+    let offer = SDPMessage::parse_buffer(offer.as_bytes()).context("Invalid SDP offer")?;
+    let offer = session_description(SDP_TYPE_OFFER, offer)?;
+
+    let promise = Promise::new();
+    webrtc.emit_by_name_with_values("set-remote-description", &[offer, promise.to_value()]);
+    promise.wait();
+
+    let promise = Promise::new();
+    webrtc.emit_by_name_with_values(
+        "create-answer",
+        &[None::<gstreamer::Structure>.to_value(), promise.to_value()],
+    );
+    promise.wait();
+    let answer = promise
+        .get_reply()
+        .ok_or_else(|| anyhow!("WebRTC did not create an answer"))?
+        .value("answer")
+        .map_err(|e| anyhow!("WebRTC did not create an answer: {e}"))
+        .map(|answer| glib::Value::clone(answer))?;
+
+    let promise = Promise::new();
+    webrtc.emit_by_name_with_values("set-local-description", &[answer, promise.to_value()]);
+    promise.wait();
+
+    // The answer is sent once with all of the candidates in it
+    for _ in 0..50 {
+        if enum_property(webrtc, "ice-gathering-state") == ICE_GATHERING_COMPLETE {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    sdp_text(&webrtc.property_value("local-description"))
+}
+
+/// Wrap the `sdp` as a `GstWebRTCSessionDescription`
+fn session_description(sdp_type: i32, sdp: SDPMessage) -> AnyResult<glib::Value> {
+    let gtype = glib::Type::from_name("GstWebRTCSessionDescription")
+        .ok_or_else(|| anyhow!("Missing the gstreamer webrtc library"))?;
+    let mut value = glib::Value::from_type(gtype);
+    unsafe {
+        // The boxed type frees this with g_free and the sdp with gst_sdp_message_free
+        let desc = glib::ffi::g_malloc0(std::mem::size_of::<SessionDescription>())
+            as *mut SessionDescription;
+        (*desc).sdp_type = sdp_type;
+        (*desc).sdp = sdp.into_glib_ptr();
+        glib::gobject_ffi::g_value_take_boxed(value.to_glib_none_mut().0, desc as *const _);
+    }
+    Ok(value)
+}
+
+/// The SDP text of a `GstWebRTCSessionDescription`
+fn sdp_text(value: &glib::Value) -> AnyResult<String> {
+    unsafe {
+        let desc = glib::gobject_ffi::g_value_get_boxed(value.to_glib_none().0)
+            as *const SessionDescription;
+        if desc.is_null() || (*desc).sdp.is_null() {
+            return Err(anyhow!("WebRTC has no local description"));
+        }
+        let text: glib::GString =
+            from_glib_full(gstreamer_sdp::ffi::gst_sdp_message_as_text((*desc).sdp));
+        Ok(text.to_string())
+    }
+}
+
+fn enum_property(element: &Element, name: &str) -> i32 {
+    let value = element.property_value(name);
+    unsafe { glib::gobject_ffi::g_value_get_enum(value.to_glib_none().0) }
+}
+
+/// The payload type of the first H264 in the offer that the camera can fill
+fn h264_payload(offer: &str) -> Option<u32> {
+    let payloads = offer
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("a=rtpmap:"))
+        .filter_map(|map| map.split_once(' '))
+        .filter(|(_, codec)| codec.to_ascii_uppercase().starts_with("H264/"))
+        .filter_map(|(payload, _)| payload.parse::<u32>().ok())
+        .collect::<Vec<_>>();
+    let fmtp = |payload: u32| {
+        offer
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix(&format!("a=fmtp:{payload} ")))
+            .next()
+            .map(|fmtp| fmtp.to_string())
+    };
+    // The camera sends more than one NAL per frame
+    payloads
+        .iter()
+        .copied()
+        .find(|payload| fmtp(*payload).is_some_and(|fmtp| fmtp.contains("packetization-mode=1")))
+        .or(payloads.first().copied())
+}
+
+fn stream_kind(name: &str) -> Option<StreamKind> {
+    match name {
+        "main" | "mainStream" | "MainStream" | "Main" => Some(StreamKind::Main),
+        "sub" | "subStream" | "SubStream" | "Sub" => Some(StreamKind::Sub),
+        "extern" | "externStream" | "ExternStream" | "Extern" => Some(StreamKind::Extern),
+        _ => None,
+    }
+}
+
+/// A page that plays `endpoint` in the browser
+fn player_page(endpoint: &str) -> String {
+    // The path comes from the request so it must not break out of the script
+    let endpoint = serde_json::to_string(endpoint)
+        .unwrap_or_default()
+        .replace('<', "\\u003c");
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Neolink</title></head>
+<body style="margin:0;background:#000">
+<video id="video" autoplay muted playsinline controls style="width:100vw;height:100vh"></video>
+<script>
+const pc = new RTCPeerConnection();
+pc.addTransceiver("video", {{ direction: "recvonly" }});
+pc.ontrack = (event) => {{ document.getElementById("video").srcObject = event.streams[0]; }};
+(async () => {{
+  await pc.setLocalDescription(await pc.createOffer());
+  await new Promise((resolve) => {{
+    if (pc.iceGatheringState === "complete") return resolve();
+    pc.onicegatheringstatechange = () => pc.iceGatheringState === "complete" && resolve();
+    setTimeout(resolve, 2000);
+  }});
+  const reply = await fetch({endpoint}, {{
+    method: "POST",
+    headers: {{ "Content-Type": "application/sdp" }},
+    body: pc.localDescription.sdp,
+  }});
+  if (!reply.ok) {{ document.body.textContent = await reply.text(); return; }}
+  const session = reply.headers.get("Location");
+  window.addEventListener("pagehide", () => fetch(session, {{ method: "DELETE", keepalive: true }}));
+  await pc.setRemoteDescription({{ type: "answer", sdp: await reply.text() }});
+}})();
+</script>
+</body>
+</html>
+"#
+    )
+}
@@ -1,18 +1,27 @@
 use crate::mqtt::Discoveries;
-#[cfg(feature = "gstreamer")]
-use neolink_core::bc_protocol::StreamKind;
-use neolink_core::bc_protocol::{DiscoveryMethods, PrintFormat};
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc_protocol::{DiscoveryMethods, PrintFormat, Proxy, StreamKind};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use validator::Validate;
 use validator::ValidationError;
 
 static RE_TLS_CLIENT_AUTH: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^(none|request|require)$").unwrap());
-static RE_PAUSE_MODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(black|still|test|none)$").unwrap());
+    Lazy::new(|| Regex::new(r"^(none|requested?|required?)$").unwrap());
+static RE_PAUSE_MODE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(black|still|test|image|none)$").unwrap());
+static RE_MINSEC: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([nN]one|[Bb][Cc]|[Bb][Cc][Ee][Nn][Cc][Rr][Yy][Pp][Tt]|[Aa][Ee][Ss])$").unwrap()
+});
+static RE_WEBHOOK_URL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[hH][tT][tT][pP][sS]?://").unwrap());
+static RE_TIME_OF_DAY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^([01][0-9]|2[0-3]):[0-5][0-9]$").unwrap());
+static RE_LOGIN_METHOD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^([aA]uto|[mM]odern|[lL]egacy)$").unwrap());
 static RE_MAXENC_SRC: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^([nN]one|[Aa][Ee][Ss]|[Bb][Cc][Ee][Nn][Cc][Rr][Yy][Pp][Tt])$").unwrap()
 });
@@ -22,11 +31,17 @@ pub(crate) struct Config {
     #[validate(nested)]
     pub(crate) cameras: Vec<CameraConfig>,
 
-    #[serde(rename = "bind", default = "default_bind_addr")]
-    pub(crate) bind_addr: String,
+    /// The addresses of the rtsp server, one or a list of them
+    #[validate(length(min = 1, message = "bind needs an address", code = "bind"))]
+    #[serde(
+        rename = "bind",
+        default = "default_bind_addrs",
+        deserialize_with = "deserialize_bind_addrs"
+    )]
+    pub(crate) bind_addrs: Vec<String>,
 
     #[validate(range(min = 0, max = 65535, message = "Invalid port", code = "bind_port"))]
-    #[serde(default = "default_bind_port")]
+    #[serde(default = "default_bind_port", alias = "port")]
     pub(crate) bind_port: u16,
 
     #[serde(default = "default_tokio_console")]
@@ -46,9 +61,220 @@ pub(crate) struct Config {
     #[serde(default = "default_tls_client_auth")]
     pub(crate) tls_client_auth: String,
 
+    /// PEM of the CA certificates that client certificates must be signed by
+    #[serde(default, alias = "tls_client_ca")]
+    pub(crate) tls_ca: Option<String>,
+
     #[validate(nested)]
     #[serde(default)]
     pub(crate) users: Vec<UserConfig>,
+
+    /// Directory used to share camera session usage between neolink processes
+    #[serde(default = "default_session_dir")]
+    pub(crate) session_dir: std::path::PathBuf,
+
+    /// Seconds that the cameras have to stop once neolink is asked to exit
+    #[serde(default = "default_shutdown_timeout")]
+    pub(crate) shutdown_timeout: u64,
+
+    /// Local address for the control socket used by `neolink clients` and `neolink kick`
+    #[serde(default = "Default::default")]
+    pub(crate) control_addr: Option<String>,
+
+    /// New rtsp connections allowed from one ip each minute, the rest are
+    /// refused with a 503
+    #[validate(range(
+        min = 1,
+        message = "rtsp_rate_limit should be at least 1",
+        code = "rtsp_rate_limit"
+    ))]
+    #[serde(default)]
+    pub(crate) rtsp_rate_limit: Option<u32>,
+
+    #[validate(nested)]
+    #[serde(default = "Default::default")]
+    pub(crate) onvif: Option<OnvifConfig>,
+
+    #[validate(nested)]
+    #[serde(default = "Default::default", alias = "zeroconf")]
+    pub(crate) mdns: Option<MdnsConfig>,
+
+    #[validate(nested)]
+    #[serde(default = "Default::default")]
+    pub(crate) health: Option<HealthConfig>,
+
+    #[validate(nested)]
+    #[serde(default = "Default::default")]
+    pub(crate) api: Option<ApiConfig>,
+
+    #[validate(nested)]
+    #[serde(default = "Default::default", alias = "whep")]
+    pub(crate) webrtc: Option<WebRtcConfig>,
+
+    /// Rtsp paths that put several cameras into one frame
+    #[validate(nested)]
+    #[serde(default, alias = "composite")]
+    pub(crate) composites: Vec<CompositeConfig>,
+
+    /// Where the log goes and how much of it, read once at start up
+    #[validate(nested)]
+    #[serde(default = "Default::default", alias = "log")]
+    pub(crate) logging: Option<LoggingConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct HealthConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[validate(range(min = 1, max = 65535, message = "Invalid port", code = "port"))]
+    #[serde(default = "default_health_port")]
+    pub(crate) port: u16,
+
+    /// Seconds that a camera may be disconnected before it is unhealthy
+    #[serde(default = "default_health_grace")]
+    pub(crate) grace: u64,
+
+    /// How many cameras may be unhealthy before `/health` fails
+    #[serde(default)]
+    pub(crate) max_unhealthy: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct ApiConfig {
+    /// Only this machine may use the api by default, it has no login without `[[users]]`
+    #[serde(rename = "bind", default = "default_local_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[validate(range(min = 1, max = 65535, message = "Invalid port", code = "port"))]
+    #[serde(default = "default_api_port")]
+    pub(crate) port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct WebRtcConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[validate(range(min = 1, max = 65535, message = "Invalid port", code = "port"))]
+    #[serde(default = "default_webrtc_port")]
+    pub(crate) port: u16,
+
+    /// Stun server for viewers outside of the lan e.g. `stun://stun.l.google.com:19302`
+    #[serde(default, alias = "stun")]
+    pub(crate) stun_server: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct LoggingConfig {
+    /// The level of every module without its own, `RUST_LOG` still wins
+    #[validate(custom(function = "validate_log_level"))]
+    #[serde(default = "default_log_level")]
+    pub(crate) level: String,
+
+    /// Levels of single modules e.g. `"neolink_core::bc_protocol" = "trace"`
+    #[validate(custom(function = "validate_log_modules"))]
+    #[serde(default)]
+    pub(crate) modules: HashMap<String, String>,
+
+    /// The log is written here as well as to stderr
+    #[serde(default, alias = "path")]
+    pub(crate) file: Option<std::path::PathBuf>,
+
+    /// Megabytes the file may reach before it is rotated, 0 for no limit
+    #[serde(default = "default_log_max_size")]
+    pub(crate) max_size: u64,
+
+    /// Also rotate the file each hour or day (UTC)
+    #[serde(default)]
+    pub(crate) rotate: LogRotation,
+
+    /// How many rotated files are kept as `file.1`, `file.2` ...
+    #[serde(default = "default_log_keep")]
+    pub(crate) keep: usize,
+
+    /// Set false to only log to the file
+    #[serde(default = "default_true")]
+    pub(crate) stderr: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LogRotation {
+    #[default]
+    #[serde(alias = "none")]
+    Never,
+    Hourly,
+    Daily,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct CompositeConfig {
+    /// The rtsp path is `/{name}`
+    pub(crate) name: String,
+
+    /// The cameras in the frame from left to right or top to bottom
+    #[validate(length(min = 2, message = "A composite needs at least two cameras"))]
+    pub(crate) cameras: Vec<String>,
+
+    /// The stream of each camera, one of main, sub or extern
+    #[validate(custom(function = "validate_single_stream"))]
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: StreamConfig,
+
+    #[serde(default = "default_composite_layout")]
+    pub(crate) layout: CompositeLayout,
+
+    /// Bitrate of the combined stream in kbps, the sum of the cameras when unset
+    #[validate(range(min = 1, message = "Invalid bitrate", code = "bitrate"))]
+    #[serde(default)]
+    pub(crate) bitrate: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CompositeLayout {
+    /// Next to each other
+    #[serde(alias = "horizontal", alias = "side-by-side")]
+    SideBySide,
+    /// One above the other
+    #[serde(alias = "vertical")]
+    Stacked,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct OnvifConfig {
+    #[serde(rename = "bind", default = "default_bind_addr")]
+    pub(crate) bind_addr: String,
+
+    #[validate(range(min = 1, max = 65535, message = "Invalid port", code = "port"))]
+    #[serde(default = "default_onvif_port")]
+    pub(crate) port: u16,
+
+    /// Answer WS-Discovery probes on the local network
+    #[serde(default = "default_true")]
+    pub(crate) discovery: bool,
+
+    /// Address given to clients for the services and streams
+    ///
+    /// When unset the address that the client connected to is used
+    #[serde(default = "Default::default")]
+    pub(crate) address: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct MdnsConfig {
+    /// Host name that the streams are on, `.local` is added
+    #[validate(custom(function = "validate_mdns_hostname"))]
+    #[serde(default = "default_mdns_hostname")]
+    pub(crate) hostname: String,
+
+    /// IPv4 address given for the host name
+    ///
+    /// When unset the address that reaches the multicast group is used
+    #[validate(custom(function = "validate_mdns_address"))]
+    #[serde(default = "Default::default")]
+    pub(crate) address: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
@@ -62,6 +288,10 @@ pub(crate) struct MqttServerConfig {
     #[serde(default, skip_serializing)]
     pub(crate) credentials: Option<(String, String)>,
 
+    /// Connect with TLS, this is on when there is a `ca`
+    #[serde(default = "default_false")]
+    pub(crate) tls: bool,
+
     #[serde(default, skip_serializing)]
     pub(crate) ca: Option<std::path::PathBuf>,
 
@@ -69,7 +299,7 @@ pub(crate) struct MqttServerConfig {
     pub(crate) client_auth: Option<(std::path::PathBuf, std::path::PathBuf)>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
 pub(crate) enum StreamConfig {
     #[serde(alias = "none")]
     None,
@@ -95,13 +325,17 @@ pub(crate) enum StreamConfig {
         alias = "extern",
         alias = "externStream",
         alias = "externstream",
-        alias = "ExternStream"
+        alias = "ExternStream",
+        alias = "balanced"
     )]
     Extern,
+    #[serde(alias = "main_extern")]
+    MainExtern,
+    #[serde(alias = "sub_extern")]
+    SubExtern,
 }
 
 impl StreamConfig {
-    #[cfg(feature = "gstreamer")]
     pub(crate) fn as_stream_kinds(&self) -> Vec<StreamKind> {
         match self {
             StreamConfig::All => {
@@ -119,11 +353,34 @@ impl StreamConfig {
             StreamConfig::Extern => {
                 vec![StreamKind::Extern]
             }
+            StreamConfig::MainExtern => {
+                vec![StreamKind::Main, StreamKind::Extern]
+            }
+            StreamConfig::SubExtern => {
+                vec![StreamKind::Extern, StreamKind::Sub]
+            }
             StreamConfig::None => {
                 vec![]
             }
         }
     }
+
+    /// The setting that gives exactly these streams
+    pub(crate) fn from_stream_kinds(kinds: &[StreamKind]) -> Self {
+        let main = kinds.contains(&StreamKind::Main);
+        let sub = kinds.contains(&StreamKind::Sub);
+        let external = kinds.contains(&StreamKind::Extern);
+        match (main, sub, external) {
+            (true, true, true) => StreamConfig::All,
+            (true, true, false) => StreamConfig::Both,
+            (true, false, true) => StreamConfig::MainExtern,
+            (false, true, true) => StreamConfig::SubExtern,
+            (true, false, false) => StreamConfig::Main,
+            (false, true, false) => StreamConfig::Sub,
+            (false, false, true) => StreamConfig::Extern,
+            (false, false, false) => StreamConfig::None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
@@ -131,8 +388,15 @@ impl StreamConfig {
 pub(crate) struct CameraConfig {
     pub(crate) name: String,
 
-    #[serde(rename = "address")]
-    pub(crate) camera_addr: Option<String>,
+    /// One address or a list of them that are tried in order, names are looked
+    /// up again on each connect
+    #[serde(
+        rename = "address",
+        alias = "addresses",
+        default,
+        deserialize_with = "deserialize_camera_addrs"
+    )]
+    pub(crate) camera_addrs: Vec<String>,
 
     #[serde(rename = "uid")]
     pub(crate) camera_uid: Option<String>,
@@ -142,11 +406,24 @@ pub(crate) struct CameraConfig {
     #[serde(alias = "pass", skip_serializing, default)]
     pub(crate) password: Option<String>,
 
-    #[serde(default = "default_stream")]
+    /// The streams served over rtsp, one name or a list of them
+    #[serde(default = "default_stream", deserialize_with = "deserialize_streams")]
     pub(crate) stream: StreamConfig,
 
+    /// The rtsp paths to serve this camera at instead of the usual ones
+    ///
+    /// `{name}` is replaced by the camera name and `{stream}` by main, sub or
+    /// extern. A path without `{stream}` goes to the best of the streams
+    #[validate(custom(function = "validate_paths"))]
+    #[serde(default)]
+    pub(crate) paths: Vec<String>,
+
     pub(crate) permitted_users: Option<Vec<String>>,
 
+    /// Let clients watch this camera over rtsp without logging in
+    #[serde(default = "default_false", alias = "anonymous")]
+    pub(crate) allow_anonymous: bool,
+
     #[validate(range(min = 0, max = 31, message = "Invalid channel", code = "channel_id"))]
     #[serde(default = "default_channel_id", alias = "channel")]
     pub(crate) channel_id: u8,
@@ -162,6 +439,15 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_discovery")]
     pub(crate) discovery: DiscoveryMethods,
 
+    /// Tuning of UID/UDP connections for cameras on poor networks
+    #[validate(nested)]
+    #[serde(default = "default_udp")]
+    pub(crate) udp: UdpConfig,
+
+    /// A socks5:// or http:// proxy for the TCP connection to the camera
+    #[validate(custom(function = "validate_proxy"))]
+    pub(crate) proxy: Option<String>,
+
     #[serde(default = "default_maxenc")]
     #[validate(regex(
         path = *RE_MAXENC_SRC,
@@ -170,6 +456,24 @@ pub(crate) struct CameraConfig {
     ))]
     pub(crate) max_encryption: String,
 
+    /// The login of new firmwares, the legacy one of old firmwares or auto to try both
+    #[serde(default = "default_login_method", alias = "login_method")]
+    #[validate(regex(
+        path = *RE_LOGIN_METHOD,
+        message = "Invalid login method, should be auto, modern or legacy",
+        code = "login"
+    ))]
+    pub(crate) login: String,
+
+    /// Refuse to use the camera if it negotiates weaker encryption than this
+    #[serde(default = "default_minsec", alias = "min_encryption")]
+    #[validate(regex(
+        path = *RE_MINSEC,
+        message = "Invalid minimum protocol security, should be none, bc or aes",
+        code = "min_protocol_security"
+    ))]
+    pub(crate) min_protocol_security: String,
+
     #[serde(default = "default_strict")]
     /// If strict then the media stream will error in the event that the media packets are not as expected
     pub(crate) strict: bool,
@@ -180,6 +484,19 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_update_time", alias = "time")]
     pub(crate) update_time: bool,
 
+    /// Set the camera time when it is more than this many seconds off
+    #[serde(default)]
+    pub(crate) max_time_drift: Option<u64>,
+
+    /// Check the camera time every this many seconds while connected
+    #[validate(range(
+        min = 60,
+        message = "Check the time at most every 60s",
+        code = "time_sync_interval"
+    ))]
+    #[serde(default)]
+    pub(crate) time_sync_interval: Option<u64>,
+
     #[validate(range(
         min = 1,
         max = 15000,
@@ -206,6 +523,46 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_splash", alias = "pattern")]
     pub(crate) splash_pattern: SplashPattern,
 
+    /// Keep the rtsp paths up with an offline splash while the camera is lost
+    #[serde(default = "default_false", alias = "offline_splash")]
+    pub(crate) splash_offline: bool,
+
+    /// The audio codec on the rtsp streams, `false` removes the audio
+    #[serde(default = "default_audio")]
+    pub(crate) audio: AudioCodec,
+
+    /// Re-encode H.265 streams to this codec for clients that cannot play them
+    #[serde(default = "default_transcode")]
+    pub(crate) transcode: TranscodeCodec,
+
+    /// Which streams are re-encoded
+    #[serde(default = "default_stream", deserialize_with = "deserialize_streams")]
+    pub(crate) transcode_streams: StreamConfig,
+
+    /// The gstreamer elements that decode and encode
+    #[serde(default = "default_transcode_encoder", alias = "transcode_hw")]
+    pub(crate) transcode_encoder: TranscodeEncoder,
+
+    /// Bitrate of the re-encoded stream in kbps, the camera's bitrate when unset
+    #[validate(range(min = 1, message = "Invalid bitrate", code = "transcode_bitrate"))]
+    #[serde(default)]
+    pub(crate) transcode_bitrate: Option<u32>,
+
+    /// A gst-launch fragment run on the decoded video of the rtsp streams,
+    /// they are then encoded as H264 like `transcode`
+    #[validate(length(min = 1, message = "The video_filter cannot be empty"))]
+    #[serde(default)]
+    pub(crate) video_filter: Option<String>,
+
+    /// Turn the picture clockwise by 90, 180 or 270 degrees
+    #[validate(custom(function = "validate_rotate"))]
+    #[serde(default)]
+    pub(crate) rotate: Option<u16>,
+
+    /// Mirror the picture after the rotation
+    #[serde(default)]
+    pub(crate) flip: Option<FlipDirection>,
+
     #[serde(
         default = "default_max_discovery_retries",
         alias = "retries",
@@ -216,134 +573,1367 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_true", alias = "push", alias = "push_noti")]
     pub(crate) push_notifications: bool,
 
-    #[serde(default = "default_false", alias = "idle", alias = "idle_disc")]
+    #[serde(
+        default = "default_false",
+        alias = "idle",
+        alias = "idle_disc",
+        alias = "on_demand"
+    )]
     pub(crate) idle_disconnect: bool,
-}
-
-#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq, Hash)]
-pub(crate) struct UserConfig {
-    #[validate(custom(function = "validate_username"))]
-    #[serde(alias = "username")]
-    pub(crate) name: String,
-
-    #[serde(alias = "password", skip_serializing, default)]
-    pub(crate) pass: Option<String>,
-}
 
-#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
-pub(crate) struct MqttConfig {
-    #[serde(default = "default_true")]
-    pub(crate) enable_motion: bool,
-    #[serde(default = "default_true")]
-    pub(crate) enable_light: bool,
-    #[serde(default = "default_true")]
-    pub(crate) enable_battery: bool,
-    /// Update time in ms
-    #[serde(default = "default_2000")]
+    /// Seconds without use before an `idle_disconnect` camera is disconnected
     #[validate(range(
-        min = 500,
-        message = "Update ms should be > 500",
-        code = "battery_update"
+        min = 1,
+        message = "idle_timeout should be at least 1s",
+        code = "idle_timeout"
     ))]
-    pub(crate) battery_update: u64,
-    #[serde(default = "default_true")]
-    pub(crate) enable_preview: bool,
-    /// Update time in ms
+    #[serde(default = "default_idle_timeout", alias = "idle_disconnect_timeout")]
+    pub(crate) idle_timeout: u64,
+
+    /// The most rtsp clients that may watch the camera at once, the rest
+    /// are refused with a 503
     #[validate(range(
-        min = 500,
-        message = "Update ms should be > 500",
-        code = "preview_update"
+        min = 1,
+        message = "max_clients should be at least 1",
+        code = "max_clients"
     ))]
-    #[serde(default = "default_2000")]
-    pub(crate) preview_update: u64,
+    #[serde(default)]
+    pub(crate) max_clients: Option<u32>,
 
-    /// Enable the flood light tasks status
-    /// Will not do anything if no floodlight
-    /// is detected
-    #[serde(default = "default_true")]
-    pub(crate) enable_floodlight: bool,
-    /// Update time in ms
+    /// Turn the status led off while rtsp clients are watching and put it
+    /// back as it was when the last one leaves
+    #[serde(default = "default_false", alias = "led_off_while_streaming")]
+    pub(crate) led_auto_off: bool,
+
+    /// Stay disconnected until a push notification of motion wakes the
+    /// camera, then stay connected for `motion_gated_duration`
+    #[serde(default = "default_false", alias = "motion_gate")]
+    pub(crate) motion_gated: bool,
+
+    /// Seconds that a `motion_gated` camera stays awake after a push notification
     #[validate(range(
-        min = 500,
-        message = "Update ms should be > 500",
-        code = "floodlight_update"
+        min = 1,
+        message = "motion_gated_duration should be at least 1s",
+        code = "motion_gated_duration"
     ))]
-    #[serde(default = "default_2000")]
-    pub(crate) floodlight_update: u64,
+    #[serde(default = "default_motion_gated_duration")]
+    pub(crate) motion_gated_duration: u64,
 
-    #[serde(default)]
-    pub(crate) discovery: Option<MqttDiscoveryConfig>,
-}
+    /// Seconds without any frames before a stream is restarted with a new
+    /// connection, `0` never restarts it
+    #[serde(default = "default_stall_timeout")]
+    pub(crate) stall_timeout: u64,
 
-#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
-pub(crate) struct MqttDiscoveryConfig {
-    pub(crate) topic: String,
+    /// Seconds between logging the fps, bitrate and drops of each rtsp
+    /// stream, `0` never logs them
+    #[serde(default, alias = "stats")]
+    pub(crate) stats_interval: u64,
 
-    pub(crate) features: HashSet<Discoveries>,
-}
+    /// Kilobytes of video that each rtsp client may buffer, defaults to a
+    /// fraction of a second of the bitrate
+    #[validate(range(
+        min = 4,
+        message = "buffer_size is in kB and should be at least 4",
+        code = "buffer_size"
+    ))]
+    #[serde(default)]
+    pub(crate) buffer_size: Option<u32>,
 
-fn validate_mqtt_server(config: &MqttServerConfig) -> Result<(), ValidationError> {
-    if config.ca.is_some() && config.client_auth.is_some() {
-        Err(ValidationError::new(
-            "Cannot have both ca and client_auth set",
-        ))
-    } else {
-        Ok(())
-    }
-}
+    /// Milliseconds of latency that the rtsp sources report to gstreamer
+    #[serde(default)]
+    pub(crate) latency: Option<u32>,
 
-const fn default_true() -> bool {
-    true
-}
+    /// What happens to new frames once a client's buffer is full
+    #[serde(default, alias = "buffer_full")]
+    pub(crate) on_full: BufferFull,
 
-const fn default_false() -> bool {
-    false
-}
+    /// Send the sub stream in place of the main stream when the main stream
+    /// keeps failing
+    #[serde(default = "default_false", alias = "main_fallback")]
+    pub(crate) fallback_to_sub: bool,
 
-fn default_mqtt() -> MqttConfig {
-    MqttConfig {
-        enable_motion: true,
-        enable_light: true,
-        enable_battery: true,
-        battery_update: 2000,
-        enable_preview: true,
-        preview_update: 2000,
-        enable_floodlight: true,
-        floodlight_update: 2000,
-        discovery: Default::default(),
-    }
-}
+    /// Milliseconds to wait before the first reconnect after the camera drops
+    #[serde(default = "default_retry_min")]
+    pub(crate) retry_min: u64,
 
-fn default_print() -> PrintFormat {
-    PrintFormat::None
-}
+    /// Milliseconds that the doubling wait between reconnects stops at
+    #[serde(default = "default_retry_max")]
+    pub(crate) retry_max: u64,
 
-fn default_discovery() -> DiscoveryMethods {
-    DiscoveryMethods::Relay
-}
+    /// Fraction of the wait that is added or taken away at random
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "jitter should be from 0.0 to 1.0",
+        code = "jitter"
+    ))]
+    #[serde(default)]
+    pub(crate) jitter: f64,
 
-fn default_maxenc() -> String {
-    "Aes".to_string()
-}
+    /// Reconnects in a row that may fail before neolink exits, forever when unset
+    #[validate(range(
+        min = 1,
+        message = "max_attempts should be at least 1",
+        code = "max_attempts"
+    ))]
+    #[serde(default)]
+    pub(crate) max_attempts: Option<u32>,
 
-#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
-pub(crate) struct PauseConfig {
-    #[serde(default = "default_on_motion")]
-    pub(crate) on_motion: bool,
+    /// What happens once the camera has failed for good, by default a refused
+    /// login stops the camera and running out of `max_attempts` exits
+    #[serde(default, alias = "on_failure")]
+    pub(crate) failure_policy: Option<FailurePolicy>,
 
-    #[serde(default = "default_on_disconnect", alias = "on_client")]
-    pub(crate) on_disconnect: bool,
+    /// Offer rtsp clients an ONVIF backchannel to talk through the camera
+    ///
+    /// Only `admin` users may use it
+    #[serde(default = "default_false", alias = "talk_backchannel")]
+    pub(crate) backchannel: bool,
 
-    #[serde(default = "default_motion_timeout", alias = "timeout")]
-    pub(crate) motion_timeout: f64,
+    /// Directories and url prefixes that `control/play` may play from
+    #[serde(default, alias = "play_dirs")]
+    pub(crate) play_allowed: Vec<String>,
+
+    /// Maximum number of logins all neolink processes on this machine may hold
+    /// with this camera at once
+    #[validate(range(
+        min = 1,
+        message = "max_own_sessions should be at least 1",
+        code = "max_own_sessions"
+    ))]
+    #[serde(default, alias = "max_sessions")]
+    pub(crate) max_own_sessions: Option<usize>,
+
+    #[validate(nested)]
+    #[serde(default, alias = "sound_detect")]
+    pub(crate) audio_detect: Option<AudioDetectConfig>,
+
+    #[validate(nested)]
+    #[serde(default)]
+    pub(crate) timelapse: Option<TimelapseConfig>,
+
+    #[validate(nested)]
+    #[serde(default, alias = "recording", alias = "recorder")]
+    pub(crate) record: Option<RecordConfig>,
+
+    #[validate(nested)]
+    #[serde(default, alias = "webhook")]
+    pub(crate) webhooks: Option<WebhookConfig>,
+
+    /// Servers to publish the streams to
+    #[validate(nested)]
+    #[serde(default, alias = "publish")]
+    pub(crate) push: Vec<PushConfig>,
+
+    #[validate(nested)]
+    #[serde(default)]
+    pub(crate) hls: Option<HlsConfig>,
+
+    /// Offer the rtsp streams over udp multicast
+    #[validate(nested)]
+    #[serde(default)]
+    pub(crate) multicast: Option<MulticastConfig>,
+
+    /// The text drawn over the video, set each time the camera connects
+    #[validate(nested)]
+    #[serde(default)]
+    pub(crate) osd: Option<OsdConfig>,
+
+    /// The encoder settings of the main stream, set each time the camera connects
+    #[validate(nested)]
+    #[serde(default, alias = "main", alias = "mainStream")]
+    pub(crate) main_stream: Option<EncoderConfig>,
+
+    /// The encoder settings of the sub stream, set each time the camera connects
+    #[validate(nested)]
+    #[serde(default, alias = "sub", alias = "subStream")]
+    pub(crate) sub_stream: Option<EncoderConfig>,
+
+    /// Times when the camera is disconnected such as for privacy
+    #[validate(nested)]
+    #[serde(default, alias = "privacy")]
+    pub(crate) schedule: Option<ScheduleConfig>,
+
+    /// Reboot the camera at these times, a cron line such as `0 4 * * 1`
+    #[validate(custom(function = "validate_cron"))]
+    #[serde(default)]
+    pub(crate) reboot_cron: Option<String>,
+}
+
+impl Config {
+    /// Read and validate a config file
+    pub(crate) fn from_file(path: &std::path::Path) -> Result<Self> {
+        let config = Config::from_toml(
+            &std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?,
+        )
+        .with_context(|| format!("Failed to parse the {:?} config file", path))?;
+
+        config
+            .validate()
+            .with_context(|| format!("Failed to validate the {:?} config file", path))?;
+        Ok(config)
+    }
+
+    /// Parses the config with the `${VAR}` and `password_file` filled in
+    pub(crate) fn from_toml(text: &str) -> Result<Self> {
+        let mut doc: toml::Value = toml::from_str(text)?;
+        if expand_toml(&mut doc)? {
+            Ok(doc.try_into()?)
+        } else {
+            // Straight from the text keeps the line numbers in the errors
+            Ok(toml::from_str(text)?)
+        }
+    }
+}
+
+/// Fills in `${VAR}` in the strings from the environment and replaces each
+/// `password_file` with a `password` read from that file
+///
+/// Returns true if anything was changed
+pub(crate) fn expand_toml(value: &mut toml::Value) -> Result<bool> {
+    match value {
+        toml::Value::String(text) => match expand_env(text)? {
+            Some(expanded) => {
+                *text = expanded;
+                Ok(true)
+            }
+            None => Ok(false),
+        },
+        toml::Value::Array(values) => {
+            let mut changed = false;
+            for value in values.iter_mut() {
+                changed |= expand_toml(value)?;
+            }
+            Ok(changed)
+        }
+        toml::Value::Table(table) => {
+            let mut changed = false;
+            for (_, value) in table.iter_mut() {
+                changed |= expand_toml(value)?;
+            }
+            // After the strings so that it can be `${CREDENTIALS_DIRECTORY}/cam1`
+            if let Some(path) = table.remove("password_file") {
+                let path = path
+                    .as_str()
+                    .ok_or_else(|| anyhow!("password_file should be the path of a file"))?;
+                if table.contains_key("password") {
+                    return Err(anyhow!(
+                        "Only one of password and password_file can be given"
+                    ));
+                }
+                let password = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read the password_file {:?}", path))?;
+                table.insert(
+                    "password".to_string(),
+                    toml::Value::String(password.trim_end_matches(['\r', '\n']).to_string()),
+                );
+                changed = true;
+            }
+            Ok(changed)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Expands `${VAR}` and `${VAR:-default}`, `$${` is a literal `${`
+///
+/// None when there was nothing to expand
+fn expand_env(text: &str) -> Result<Option<String>> {
+    if !text.contains("${") {
+        return Ok(None);
+    }
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| anyhow!("Missing }} after ${{ in {:?}", text))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            match (std::env::var(name), default) {
+                (Ok(value), Some(default)) if value.is_empty() => expanded.push_str(default),
+                (Ok(value), _) => expanded.push_str(&value),
+                (Err(_), Some(default)) => expanded.push_str(default),
+                (Err(e), None) => {
+                    return Err(anyhow!(
+                        "Environment variable {} used in the config: {}",
+                        name,
+                        e
+                    ))
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(Some(expanded))
+}
+
+impl CameraConfig {
+    /// The rtsp paths of a stream
+    ///
+    /// Without `paths` in the config the bare `/{name}` goes to the best of
+    /// the streams
+    pub(crate) fn rtsp_paths(&self, stream: StreamKind) -> Vec<String> {
+        let name = &self.name;
+        let kind = match stream {
+            StreamKind::Main => "main",
+            StreamKind::Sub => "sub",
+            StreamKind::Extern => "extern",
+        };
+        let active_streams = self.stream.as_stream_kinds();
+        let is_best = match stream {
+            StreamKind::Main => true,
+            StreamKind::Sub => !active_streams.contains(&StreamKind::Main),
+            StreamKind::Extern => {
+                !active_streams.contains(&StreamKind::Main)
+                    && !active_streams.contains(&StreamKind::Sub)
+            }
+        };
+        if !self.paths.is_empty() {
+            return self
+                .paths
+                .iter()
+                .filter(|path| is_best || path.contains("{stream}"))
+                .map(|path| path.replace("{name}", name).replace("{stream}", kind))
+                .collect();
+        }
+
+        let mut upper = kind.to_string();
+        upper[..1].make_ascii_uppercase();
+        let mut paths = vec![
+            format!("/{name}/{kind}"),
+            format!("/{name}/{upper}"),
+            format!("/{name}/{kind}Stream"),
+            format!("/{name}/{upper}Stream"),
+            format!("/{name}/{upper}stream"),
+            format!("/{name}/{kind}stream"),
+        ];
+        if stream == StreamKind::Extern {
+            // The name used for it in the reolink apps
+            paths.push(format!("/{name}/balanced"));
+            paths.push(format!("/{name}/Balanced"));
+        }
+        if is_best {
+            paths.push(format!("/{name}"));
+        }
+        paths
+    }
+
+    /// The path that is given out for a stream when `paths` are configured
+    pub(crate) fn custom_path(&self, stream: StreamKind) -> Option<String> {
+        if self.paths.is_empty() {
+            None
+        } else {
+            self.rtsp_paths(stream).into_iter().next()
+        }
+    }
+
+    /// If an H265 `stream` should be encoded again as H264
+    #[cfg(feature = "gstreamer")]
+    pub(crate) fn transcodes(&self, stream: StreamKind) -> bool {
+        self.transcode == TranscodeCodec::H264
+            && self.transcode_streams.as_stream_kinds().contains(&stream)
+    }
+}
+
+impl HlsConfig {
+    /// The directory with the playlist and segments of `camera`
+    pub(crate) fn dir(&self, camera: &str) -> std::path::PathBuf {
+        self.output.join(crate::utils::file_safe_name(camera))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct UserConfig {
+    #[validate(custom(function = "validate_username"))]
+    #[serde(alias = "username")]
+    pub(crate) name: String,
+
+    #[serde(alias = "password", skip_serializing, default)]
+    pub(crate) pass: Option<String>,
+
+    /// Limit the user to these cameras over rtsp, all of them when unset
+    #[serde(default)]
+    pub(crate) cameras: Option<Vec<String>>,
+
+    /// Limit the user to these streams over rtsp, all of them when unset
+    #[serde(default, deserialize_with = "deserialize_optional_streams")]
+    pub(crate) streams: Option<StreamConfig>,
+
+    /// Let the user run controls with the rest api and talk over the rtsp
+    /// backchannel on its `cameras`
+    #[serde(default)]
+    pub(crate) admin: bool,
+}
+
+impl UserConfig {
+    /// Whether the password is the user's, users without a password cannot
+    /// log in with one
+    pub(crate) fn password_matches(&self, pass: &str) -> bool {
+        self.password().is_some_and(|expected| expected == pass)
+    }
+
+    /// The password of the user, None when it is empty
+    pub(crate) fn password(&self) -> Option<&str> {
+        self.pass.as_deref().filter(|pass| !pass.is_empty())
+    }
+
+    /// Whether the user may run controls on the camera
+    pub(crate) fn may_control(&self, camera: &str) -> bool {
+        self.admin
+            && self
+                .cameras
+                .as_ref()
+                .is_none_or(|cameras| cameras.iter().any(|name| name == camera))
+    }
+
+    /// Whether the rtsp acl lets this user watch the stream of the camera
+    #[cfg(feature = "gstreamer")]
+    pub(crate) fn may_watch(&self, camera: &str, stream: StreamKind) -> bool {
+        let camera_ok = self
+            .cameras
+            .as_ref()
+            .is_none_or(|cameras| cameras.iter().any(|name| name == camera));
+        let stream_ok = self
+            .streams
+            .is_none_or(|streams| streams.as_stream_kinds().contains(&stream));
+        camera_ok && stream_ok
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct MqttConfig {
+    #[serde(default = "default_true")]
+    pub(crate) enable_motion: bool,
+    #[serde(default = "default_true")]
+    pub(crate) enable_light: bool,
+    #[serde(default = "default_true")]
+    pub(crate) enable_battery: bool,
+    /// Update time in ms
+    #[serde(default = "default_2000")]
+    #[validate(range(
+        min = 500,
+        message = "Update ms should be > 500",
+        code = "battery_update"
+    ))]
+    pub(crate) battery_update: u64,
+    /// Warn and publish `status/battery_low` below this percent
+    #[validate(range(
+        min = 1,
+        max = 100,
+        message = "battery_low should be a percent",
+        code = "battery_low"
+    ))]
+    #[serde(default)]
+    pub(crate) battery_low: Option<u32>,
+    #[serde(default = "default_true")]
+    pub(crate) enable_preview: bool,
+    /// Update time in ms
+    #[validate(range(
+        min = 500,
+        message = "Update ms should be > 500",
+        code = "preview_update"
+    ))]
+    #[serde(default = "default_2000")]
+    pub(crate) preview_update: u64,
+
+    /// Enable the flood light tasks status
+    /// Will not do anything if no floodlight
+    /// is detected
+    #[serde(default = "default_true")]
+    pub(crate) enable_floodlight: bool,
+    /// Update time in ms
+    #[validate(range(
+        min = 500,
+        message = "Update ms should be > 500",
+        code = "floodlight_update"
+    ))]
+    #[serde(default = "default_2000")]
+    pub(crate) floodlight_update: u64,
+
+    /// Time in ms that motion must be still before `status/motion` is `off`
+    ///
+    /// Motion that starts again within this keeps it `on`
+    #[serde(default)]
+    pub(crate) motion_cooldown: u64,
+
+    /// Publish a jpeg to `status/snap` when motion starts
+    #[serde(default = "default_false", alias = "motion_snap")]
+    pub(crate) enable_motion_snap: bool,
+
+    /// Allow sending any BC message on `query/raw/{msg_id}`
+    #[serde(default = "default_false")]
+    pub(crate) enable_raw: bool,
+
+    #[serde(default)]
+    pub(crate) discovery: Option<MqttDiscoveryConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
+pub(crate) struct MqttDiscoveryConfig {
+    pub(crate) topic: String,
+
+    pub(crate) features: HashSet<Discoveries>,
+}
+
+fn validate_log_level(level: &str) -> Result<(), ValidationError> {
+    level.parse::<log::LevelFilter>().map(|_| ()).map_err(|_| {
+        ValidationError::new("Unknown log level, use off, error, warn, info, debug or trace")
+    })
+}
+
+fn validate_log_modules(modules: &HashMap<String, String>) -> Result<(), ValidationError> {
+    modules
+        .values()
+        .try_for_each(|level| validate_log_level(level))
+}
+
+fn validate_days(days: &str) -> Result<(), ValidationError> {
+    days_of_week(days).map(|_| ()).ok_or_else(|| {
+        ValidationError::new("Unknown schedule days, use * or a list such as mon-fri,sun")
+    })
+}
+
+fn validate_cron(cron: &str) -> Result<(), ValidationError> {
+    cron_due(cron, &time::OffsetDateTime::UNIX_EPOCH)
+        .map(|_| ())
+        .ok_or_else(|| {
+            ValidationError::new(
+                "Unknown cron line, use the minute, hour, day, month and weekday such as 0 4 * * 1",
+            )
+        })
+}
+
+fn validate_mqtt_server(config: &MqttServerConfig) -> Result<(), ValidationError> {
+    if config.ca.is_some() && config.client_auth.is_some() {
+        Err(ValidationError::new(
+            "Cannot have both ca and client_auth set",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+const fn default_true() -> bool {
+    true
+}
+
+const fn default_false() -> bool {
+    false
+}
+
+fn default_mqtt() -> MqttConfig {
+    MqttConfig {
+        enable_motion: true,
+        enable_light: true,
+        enable_battery: true,
+        battery_update: 2000,
+        battery_low: None,
+        enable_preview: true,
+        preview_update: 2000,
+        enable_floodlight: true,
+        floodlight_update: 2000,
+        motion_cooldown: 0,
+        enable_motion_snap: false,
+        enable_raw: false,
+        discovery: Default::default(),
+    }
+}
+
+fn default_print() -> PrintFormat {
+    PrintFormat::None
+}
+
+fn default_discovery() -> DiscoveryMethods {
+    DiscoveryMethods::Relay
+}
+
+fn default_maxenc() -> String {
+    "Aes".to_string()
+}
+
+fn default_login_method() -> String {
+    "auto".to_string()
+}
+
+fn default_minsec() -> String {
+    "none".to_string()
+}
+
+fn default_idle_timeout() -> u64 {
+    30
+}
+
+fn default_motion_gated_duration() -> u64 {
+    60
+}
+
+fn default_stall_timeout() -> u64 {
+    30
+}
+
+fn default_retry_min() -> u64 {
+    50
+}
+
+fn default_retry_max() -> u64 {
+    5000
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct UdpConfig {
+    /// Largest UDP packet in bytes, smaller packets are lost less often on
+    /// weak Wi-Fi
+    #[validate(range(
+        min = 576,
+        max = 1350,
+        message = "The udp mtu should be from 576 to 1350",
+        code = "mtu"
+    ))]
+    #[serde(default = "default_udp_mtu")]
+    pub(crate) mtu: u32,
+
+    /// Milliseconds between the acknowledgements sent to the camera
+    #[validate(range(
+        min = 1,
+        max = 1000,
+        message = "The udp ack_interval should be from 1 to 1000ms",
+        code = "ack_interval"
+    ))]
+    #[serde(default = "default_udp_ack_interval")]
+    pub(crate) ack_interval: u64,
+
+    /// Milliseconds before an unacknowledged packet is sent again, this
+    /// grows each time the same packet is resent
+    #[validate(range(
+        min = 50,
+        max = 10000,
+        message = "The udp resend_interval should be from 50 to 10000ms",
+        code = "resend_interval"
+    ))]
+    #[serde(default = "default_udp_resend_interval", alias = "retransmit_interval")]
+    pub(crate) resend_interval: u64,
+
+    /// The most packets resent at once, 0 for no limit
+    #[serde(default = "default_udp_resend_window", alias = "retransmit_window")]
+    pub(crate) resend_window: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct PauseConfig {
+    #[serde(default = "default_on_motion")]
+    pub(crate) on_motion: bool,
+
+    #[serde(default = "default_on_disconnect", alias = "on_client")]
+    pub(crate) on_disconnect: bool,
+
+    /// Log out of the camera while every stream is paused so that battery
+    /// cameras can sleep, it logs in again to resume
+    #[serde(default = "default_false")]
+    pub(crate) disconnect: bool,
+
+    #[serde(default = "default_motion_timeout", alias = "timeout")]
+    pub(crate) motion_timeout: f64,
 
     #[serde(default = "default_pause_mode")]
     #[validate(regex(
-        path = *RE_PAUSE_MODE,
-        message = "Incorrect pause mode",
-        code = "mode"
+        path = *RE_PAUSE_MODE,
+        message = "Incorrect pause mode",
+        code = "mode"
+    ))]
+    pub(crate) mode: String,
+
+    /// Picture shown with `mode = "image"` while the camera is not streaming
+    #[serde(default)]
+    pub(crate) image: Option<std::path::PathBuf>,
+
+    /// Stream on motion even without rtsp clients
+    #[serde(default)]
+    pub(crate) motion_resume: bool,
+
+    /// Seconds to keep streaming after the last motion with `motion_resume`
+    #[serde(default = "default_motion_resume_duration")]
+    pub(crate) motion_resume_duration: u64,
+
+    /// Changes to the pause of the main stream
+    #[validate(nested)]
+    #[serde(default, alias = "main", alias = "mainStream")]
+    pub(crate) main_stream: Option<PauseOverride>,
+
+    /// Changes to the pause of the sub stream
+    #[validate(nested)]
+    #[serde(default, alias = "sub", alias = "subStream")]
+    pub(crate) sub_stream: Option<PauseOverride>,
+
+    /// Changes to the pause of the extern stream
+    #[validate(nested)]
+    #[serde(default, alias = "extern", alias = "externStream")]
+    pub(crate) extern_stream: Option<PauseOverride>,
+}
+
+/// The pause settings of one stream, unset ones are taken from the camera
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct PauseOverride {
+    #[serde(default)]
+    pub(crate) on_motion: Option<bool>,
+
+    #[serde(default, alias = "on_client")]
+    pub(crate) on_disconnect: Option<bool>,
+
+    #[serde(default, alias = "timeout")]
+    pub(crate) motion_timeout: Option<f64>,
+
+    #[serde(default)]
+    #[validate(regex(
+        path = *RE_PAUSE_MODE,
+        message = "Incorrect pause mode",
+        code = "mode"
+    ))]
+    pub(crate) mode: Option<String>,
+}
+
+impl PauseConfig {
+    /// The pause settings of a stream with its overrides applied
+    #[cfg(feature = "gstreamer")]
+    pub(crate) fn for_stream(&self, stream: StreamKind) -> PauseConfig {
+        let stream_override = match stream {
+            StreamKind::Main => &self.main_stream,
+            StreamKind::Sub => &self.sub_stream,
+            StreamKind::Extern => &self.extern_stream,
+        };
+        let mut pause = self.clone();
+        if let Some(stream_override) = stream_override {
+            pause.on_motion = stream_override.on_motion.unwrap_or(self.on_motion);
+            pause.on_disconnect = stream_override.on_disconnect.unwrap_or(self.on_disconnect);
+            pause.motion_timeout = stream_override
+                .motion_timeout
+                .unwrap_or(self.motion_timeout);
+            if let Some(mode) = stream_override.mode.as_ref() {
+                pause.mode = mode.clone();
+            }
+        }
+        pause
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct AudioDetectConfig {
+    /// Level in dB (0dB is full scale) that counts as a sound
+    #[validate(range(
+        max = 0.0,
+        message = "Audio threshold is in dB and should be <= 0",
+        code = "threshold"
+    ))]
+    #[serde(default = "default_audio_threshold", alias = "level")]
+    pub(crate) threshold: f64,
+
+    /// How long in ms the level must stay above/below the threshold to start/stop a sound event
+    #[serde(default = "default_audio_min_duration", alias = "duration")]
+    pub(crate) min_duration: u64,
+
+    /// Time in ms after a sound event stops before a new one can start
+    #[serde(default = "default_audio_cooldown")]
+    pub(crate) cooldown: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+#[validate(schema(function = "validate_timelapse", skip_on_field_errors = true))]
+pub(crate) struct TimelapseConfig {
+    /// Seconds between each captured frame
+    #[validate(range(
+        min = 1,
+        message = "Timelapse interval should be at least 1s",
+        code = "interval"
+    ))]
+    #[serde(default = "default_timelapse_interval")]
+    pub(crate) interval: u64,
+
+    /// Directory to store the frames and videos in
+    pub(crate) output: std::path::PathBuf,
+
+    /// Take the frames from this stream instead of the snapshot command,
+    /// one of main, sub or extern
+    #[validate(custom(function = "validate_single_stream"))]
+    #[serde(default)]
+    pub(crate) stream: Option<StreamConfig>,
+
+    /// Path of each frame under `<output>/<camera>` with strftime fields
+    #[validate(custom(function = "validate_timelapse_filename"))]
+    #[serde(default = "default_timelapse_filename")]
+    pub(crate) filename: String,
+
+    /// Delete the frames older than this many days
+    #[validate(range(
+        min = 1,
+        message = "Timelapse keep_days should be at least 1",
+        code = "keep_days"
+    ))]
+    #[serde(default)]
+    pub(crate) keep_days: Option<u64>,
+
+    /// Assemble the previous day's frames into an mp4 each day
+    #[serde(default = "default_true", alias = "video")]
+    pub(crate) daily_video: bool,
+
+    /// Time of day as `HH:MM` to assemble the daily video
+    #[validate(regex(
+        path = *RE_TIME_OF_DAY,
+        message = "Incorrect timelapse video_at time, should be HH:MM",
+        code = "video_at"
+    ))]
+    #[serde(default = "default_timelapse_video_at")]
+    pub(crate) video_at: String,
+
+    /// Frames per second of the daily video
+    #[validate(range(min = 1, message = "Timelapse fps should be at least 1", code = "fps"))]
+    #[serde(default = "default_timelapse_fps", alias = "framerate")]
+    pub(crate) fps: u32,
+
+    /// Delete the frames once the daily video is made
+    #[serde(default = "default_false")]
+    pub(crate) prune: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct ScheduleConfig {
+    /// What happens to the camera during the rules
+    #[serde(default)]
+    pub(crate) action: ScheduleAction,
+
+    /// Move a PTZ camera to this preset before it is disconnected
+    #[serde(default, alias = "park")]
+    pub(crate) park_preset: Option<u8>,
+
+    /// Move a PTZ camera to this preset once it is connected again
+    #[serde(default)]
+    pub(crate) return_preset: Option<u8>,
+
+    #[validate(length(min = 1, message = "The schedule needs at least one rule"))]
+    #[validate(nested)]
+    #[serde(alias = "rule")]
+    pub(crate) rules: Vec<ScheduleRule>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScheduleAction {
+    /// Stays disconnected even when a client wants the camera
+    #[default]
+    Disable,
+    /// Disconnected until the camera is used, the same as `control/pause`
+    Pause,
+}
+
+/// A window of time on some days of the week
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct ScheduleRule {
+    /// Such as `*`, `mon-fri` or `sat,sun`
+    #[validate(custom(function = "validate_days"))]
+    #[serde(default = "default_schedule_days")]
+    pub(crate) days: String,
+
+    /// Start as `HH:MM`
+    #[validate(regex(
+        path = *RE_TIME_OF_DAY,
+        message = "Incorrect schedule from time, should be HH:MM",
+        code = "from"
+    ))]
+    #[serde(alias = "start")]
+    pub(crate) from: String,
+
+    /// End as `HH:MM`, when before `from` the rule runs past midnight
+    #[validate(regex(
+        path = *RE_TIME_OF_DAY,
+        message = "Incorrect schedule to time, should be HH:MM",
+        code = "to"
+    ))]
+    #[serde(alias = "end", alias = "until")]
+    pub(crate) to: String,
+}
+
+impl ScheduleRule {
+    /// Whether the rule covers this minute of the day on this day of the
+    /// week, where 0 is monday
+    pub(crate) fn contains(&self, weekday: usize, minute: u16) -> bool {
+        let (Some(days), Some(from), Some(to)) = (
+            days_of_week(&self.days),
+            minute_of_day(&self.from),
+            minute_of_day(&self.to),
+        ) else {
+            return false;
+        };
+        let yesterday = (weekday + 6) % 7;
+        if from < to {
+            days[weekday] && from <= minute && minute < to
+        } else if from > to {
+            (days[weekday] && minute >= from) || (days[yesterday] && minute < to)
+        } else {
+            // The same time is the whole day
+            days[weekday]
+        }
+    }
+}
+
+/// The days in a list such as `mon-fri,sun` with monday first
+fn days_of_week(days: &str) -> Option<[bool; 7]> {
+    const NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+    let day = |name: &str| {
+        let name = name.trim().to_lowercase();
+        NAMES
+            .iter()
+            .position(|day| name.len() >= 3 && day.starts_with(&name[..3]))
+    };
+    let mut found = [false; 7];
+    for part in days.split(',') {
+        if part.trim() == "*" {
+            found = [true; 7];
+        } else if let Some((start, end)) = part.split_once('-') {
+            let (start, end) = (day(start)?, day(end)?);
+            // Ranges such as fri-mon go over the weekend
+            let mut n = start;
+            loop {
+                found[n] = true;
+                if n == end {
+                    break;
+                }
+                n = (n + 1) % 7;
+            }
+        } else {
+            found[day(part)?] = true;
+        }
+    }
+    Some(found)
+}
+
+/// Whether a cron line such as `0 4 * * 1` is due at this minute
+///
+/// The fields are the minute, hour, day of the month, month and day of the
+/// week, where 0 and 7 are sunday. Each is `*`, a number, a range such as
+/// `1-5` or any of those with a step such as `*/15`, or a list of them. Like
+/// cron when both days are given either one of them is enough.
+pub(crate) fn cron_due(cron: &str, when: &time::OffsetDateTime) -> Option<bool> {
+    let fields = cron.split_whitespace().collect::<Vec<_>>();
+    let [minute, hour, day, month, weekday] = fields.as_slice() else {
+        return None;
+    };
+    let minutes = cron_field(minute, 0, 59)?;
+    let hours = cron_field(hour, 0, 23)?;
+    let days = cron_field(day, 1, 31)?;
+    let months = cron_field(month, 1, 12)?;
+    let mut weekdays = cron_field(weekday, 0, 7)?;
+    if weekdays & 1 << 7 != 0 {
+        weekdays |= 1;
+    }
+    let is = |set: u64, value: u8| set & 1 << value != 0;
+    let on_day = is(days, when.day());
+    let on_weekday = is(weekdays, when.weekday().number_days_from_sunday());
+    let day_matches = match (*day == "*", *weekday == "*") {
+        (false, false) => on_day || on_weekday,
+        _ => on_day && on_weekday,
+    };
+    Some(
+        is(minutes, when.minute())
+            && is(hours, when.hour())
+            && is(months, when.month() as u8)
+            && day_matches,
+    )
+}
+
+/// The values of one cron field as bits
+fn cron_field(field: &str, min: u8, max: u8) -> Option<u64> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u8>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().ok()?, end.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            // `5/10` is from 5 to the end like cron
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Some(set)
+}
+
+fn minute_of_day(time_of_day: &str) -> Option<u16> {
+    let (hour, minute) = time_of_day.split_once(':')?;
+    Some(hour.parse::<u16>().ok()? * 60 + minute.parse::<u16>().ok()?)
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct PushConfig {
+    /// The rtsp:// or rtmp:// url to publish to
+    #[validate(custom(function = "validate_push_url"))]
+    pub(crate) url: String,
+
+    /// The stream to publish, one of main, sub or extern
+    #[validate(custom(function = "validate_single_stream"))]
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: StreamConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct OsdConfig {
+    /// The camera name shown on the video
+    #[validate(length(min = 1, message = "The osd name cannot be empty"))]
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+
+    /// Whether the camera name is shown
+    #[serde(default)]
+    pub(crate) show_name: Option<bool>,
+
+    /// Whether the timestamp is shown
+    #[serde(default, alias = "time")]
+    pub(crate) timestamp: Option<bool>,
+
+    /// Whether the Reolink logo is shown
+    #[serde(default, alias = "logo")]
+    pub(crate) watermark: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct EncoderConfig {
+    /// Bitrate in kbps
+    #[validate(range(min = 1, message = "bitrate should be at least 1", code = "bitrate"))]
+    #[serde(default)]
+    pub(crate) bitrate: Option<u32>,
+
+    /// Frames per second
+    #[validate(range(min = 1, message = "fps should be at least 1", code = "fps"))]
+    #[serde(default, alias = "framerate")]
+    pub(crate) fps: Option<u32>,
+
+    /// Size of the picture such as `"2560x1440"`
+    #[validate(custom(function = "validate_resolution"))]
+    #[serde(default)]
+    pub(crate) resolution: Option<String>,
+}
+
+impl EncoderConfig {
+    /// The width and height of `resolution`
+    pub(crate) fn size(&self) -> Option<(u32, u32)> {
+        parse_resolution(self.resolution.as_deref()?)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct HlsConfig {
+    /// Directory to write the playlists and segments to
+    pub(crate) output: std::path::PathBuf,
+
+    /// The stream to write, one of main, sub or extern
+    #[validate(custom(function = "validate_single_stream"))]
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: StreamConfig,
+
+    /// Seconds of each segment
+    #[validate(range(
+        min = 1,
+        max = 60,
+        message = "HLS segment should be between 1s and 60s",
+        code = "segment"
+    ))]
+    #[serde(default = "default_hls_segment", alias = "target_duration")]
+    pub(crate) segment: u32,
+
+    /// Segments in the playlist
+    #[validate(range(min = 1, message = "Invalid playlist length", code = "playlist_length"))]
+    #[serde(default = "default_hls_playlist_length")]
+    pub(crate) playlist_length: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct MulticastConfig {
+    /// The group the streams are sent to such as `239.255.0.1`
+    #[validate(custom(function = "validate_multicast_addr"))]
+    pub(crate) address: String,
+
+    /// The first port, each stream takes a block of ten from here
+    #[serde(default = "default_multicast_port")]
+    pub(crate) port: u16,
+
+    /// How many routers the packets may cross
+    #[validate(range(min = 1, message = "Multicast ttl should be at least 1", code = "ttl"))]
+    #[serde(default = "default_multicast_ttl")]
+    pub(crate) ttl: u8,
+
+    /// The network interface to send on, the os picks one without it
+    #[serde(default)]
+    pub(crate) iface: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct RecordConfig {
+    /// Directory to store the recordings in
+    pub(crate) output: std::path::PathBuf,
+
+    /// The stream to record, one of main, sub or extern
+    #[validate(custom(function = "validate_record_stream"))]
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: StreamConfig,
+
+    /// Container of the recordings
+    #[serde(default = "default_record_format")]
+    pub(crate) format: RecordFormat,
+
+    /// Record on motion or all of the time
+    #[serde(default = "default_record_mode")]
+    pub(crate) mode: RecordMode,
+
+    /// Seconds of each recording when recording all of the time
+    #[validate(range(
+        min = 10,
+        message = "Record segment should be at least 10s",
+        code = "segment"
+    ))]
+    #[serde(default = "default_record_segment", alias = "segment_length")]
+    pub(crate) segment: u64,
+
+    /// Seconds of video before the motion to include
+    #[validate(range(
+        max = 60,
+        message = "Record pre_roll should be at most 60s",
+        code = "pre_roll"
+    ))]
+    #[serde(default = "default_record_pre_roll", alias = "pre_motion")]
+    pub(crate) pre_roll: u64,
+
+    /// Seconds to keep recording after the motion stops
+    #[serde(default = "default_record_post_roll", alias = "post_motion")]
+    pub(crate) post_roll: u64,
+
+    /// Delete recordings older than this many hours
+    #[validate(range(
+        min = 1,
+        message = "Record max_age should be at least 1h",
+        code = "max_age"
+    ))]
+    #[serde(default)]
+    pub(crate) max_age: Option<u64>,
+
+    /// Delete the oldest recordings when all of them take more than this many MB
+    #[validate(range(
+        min = 1,
+        message = "Record max_size should be at least 1MB",
+        code = "max_size"
+    ))]
+    #[serde(default)]
+    pub(crate) max_size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct WebhookConfig {
+    /// Where the events are POSTed to
+    #[validate(regex(
+        path = *RE_WEBHOOK_URL,
+        message = "Webhook url should start with http:// or https://",
+        code = "url"
     ))]
-    pub(crate) mode: String,
+    pub(crate) url: String,
+
+    /// The events to send, all of them by default
+    #[serde(default = "default_webhook_events")]
+    pub(crate) events: HashSet<WebhookEvent>,
+
+    /// Template of the JSON body, `{camera}`, `{event}`, `{timestamp}` and
+    /// `{battery}` are replaced
+    #[serde(default)]
+    pub(crate) body: Option<String>,
+
+    /// Extra headers such as `Authorization`
+    #[serde(default)]
+    pub(crate) headers: HashMap<String, String>,
+
+    /// Send `battery_low` below this percent
+    #[validate(range(
+        min = 1,
+        max = 100,
+        message = "battery_low should be a percent",
+        code = "battery_low"
+    ))]
+    #[serde(default = "default_webhook_battery_low")]
+    pub(crate) battery_low: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookEvent {
+    MotionStart,
+    MotionStop,
+    Connected,
+    Disconnected,
+    BatteryLow,
+    Visitor,
+    ClientConnected,
+    ClientDisconnected,
+}
+
+impl WebhookEvent {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::MotionStart => "motion_start",
+            WebhookEvent::MotionStop => "motion_stop",
+            WebhookEvent::Connected => "connected",
+            WebhookEvent::Disconnected => "disconnected",
+            WebhookEvent::BatteryLow => "battery_low",
+            WebhookEvent::Visitor => "visitor",
+            WebhookEvent::ClientConnected => "client_connected",
+            WebhookEvent::ClientDisconnected => "client_disconnected",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum RecordFormat {
+    #[serde(alias = "mp4", alias = "MP4")]
+    Mp4,
+    #[serde(alias = "mkv", alias = "MKV", alias = "matroska")]
+    Mkv,
+}
+
+/// What to do when a camera fails for good
+///
+/// That is when its login is refused or it runs out of `max_attempts`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FailurePolicy {
+    /// Stop the camera and keep the others running
+    #[serde(alias = "stop")]
+    Continue,
+    /// Exit with the code of the failure
+    Exit,
+    /// Stop the camera and fail `/health` until its config changes
+    Unhealthy,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RecordMode {
+    Motion,
+    #[serde(alias = "always", alias = "24/7")]
+    Continuous,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AudioCodec {
+    /// Raw audio, what neolink has always sent
+    Pcm,
+    /// Sent as is from cameras with AAC, encoded from the others
+    Aac,
+    Opus,
+    /// No audio track
+    None,
+}
+
+impl<'de> Deserialize<'de> for AudioCodec {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Setting {
+            Enabled(bool),
+            Codec(String),
+        }
+        match Setting::deserialize(deserializer)? {
+            Setting::Enabled(true) => Ok(AudioCodec::Pcm),
+            Setting::Enabled(false) => Ok(AudioCodec::None),
+            Setting::Codec(codec) => match codec.to_lowercase().as_str() {
+                "pcm" | "l16" | "raw" => Ok(AudioCodec::Pcm),
+                "aac" => Ok(AudioCodec::Aac),
+                "opus" => Ok(AudioCodec::Opus),
+                "none" | "off" => Ok(AudioCodec::None),
+                _ => Err(serde::de::Error::custom(format!(
+                    "Unknown audio codec {codec}, expected pcm, aac, opus or false"
+                ))),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TranscodeCodec {
+    None,
+    #[serde(alias = "H264", alias = "h.264")]
+    H264,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TranscodeEncoder {
+    /// avdec_h265 and x264enc on the cpu
+    #[serde(alias = "x264", alias = "cpu")]
+    Software,
+    /// Intel and AMD gpus
+    Vaapi,
+    /// Nvidia gpus
+    #[serde(alias = "nvidia")]
+    Nvenc,
+    /// Hardware codecs of boards such as the Raspberry Pi
+    V4l2,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FlipDirection {
+    /// Left to right
+    #[serde(alias = "mirror")]
+    Horizontal,
+    /// Upside down
+    Vertical,
+}
+
+/// What to do with the frames of a client that is not keeping up
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BufferFull {
+    /// Keep buffering past `buffer_size`, the latency grows
+    #[default]
+    Queue,
+    /// Drop the new frames until there is room
+    DropNew,
+    /// Drop the oldest frames so that the client stays live
+    #[serde(alias = "leaky")]
+    DropOld,
+    /// Wait for the client, this holds up the camera stream
+    Block,
+}
+
+impl RecordFormat {
+    #[cfg(feature = "gstreamer")]
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Mp4 => "mp4",
+            RecordFormat::Mkv => "mkv",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
@@ -441,14 +2031,149 @@ fn default_bind_addr() -> String {
     "0.0.0.0".to_string()
 }
 
+fn default_local_bind_addr() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_bind_addrs() -> Vec<String> {
+    vec![default_bind_addr()]
+}
+
+/// `bind` can be one address or a list of them
+fn deserialize_bind_addrs<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Addrs {
+        One(String),
+        Many(Vec<String>),
+    }
+    let addrs = match Addrs::deserialize(deserializer)? {
+        Addrs::One(addr) => vec![addr],
+        Addrs::Many(addrs) => addrs,
+    };
+    // Allow `[::]` as it would be written in a url
+    Ok(addrs
+        .iter()
+        .map(|addr| {
+            addr.trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string()
+        })
+        .collect())
+}
+
+/// One camera address or a list such as `["cam.example.com:9000", "192.168.1.10"]`
+fn deserialize_camera_addrs<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Addrs {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Addrs::deserialize(deserializer)? {
+        Addrs::One(addr) => vec![addr],
+        Addrs::Many(addrs) => addrs,
+    }
+    .iter()
+    .map(|addr| addr.trim().to_string())
+    .filter(|addr| !addr.is_empty())
+    .collect())
+}
+
+/// One stream setting or a list such as `["main", "extern"]`
+fn deserialize_streams<'de, D>(deserializer: D) -> std::result::Result<StreamConfig, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Streams {
+        One(StreamConfig),
+        Many(Vec<StreamConfig>),
+    }
+    Ok(match Streams::deserialize(deserializer)? {
+        Streams::One(stream) => stream,
+        Streams::Many(streams) => StreamConfig::from_stream_kinds(
+            &streams
+                .iter()
+                .flat_map(|stream| stream.as_stream_kinds())
+                .collect::<Vec<_>>(),
+        ),
+    })
+}
+
+fn deserialize_optional_streams<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<StreamConfig>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_streams(deserializer).map(Some)
+}
+
 fn default_bind_port() -> u16 {
     8554
 }
 
+fn default_onvif_port() -> u16 {
+    8000
+}
+
+fn default_mdns_hostname() -> String {
+    "neolink".to_string()
+}
+
+fn default_health_port() -> u16 {
+    8090
+}
+
+fn default_health_grace() -> u64 {
+    60
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_max_size() -> u64 {
+    10
+}
+
+fn default_log_keep() -> usize {
+    5
+}
+
+fn default_api_port() -> u16 {
+    8091
+}
+
+fn default_webrtc_port() -> u16 {
+    8889
+}
+
 fn default_stream() -> StreamConfig {
     StreamConfig::All
 }
 
+fn default_audio() -> AudioCodec {
+    AudioCodec::Pcm
+}
+
+fn default_transcode() -> TranscodeCodec {
+    TranscodeCodec::None
+}
+
+fn default_transcode_encoder() -> TranscodeEncoder {
+    TranscodeEncoder::Software
+}
+
 fn default_certificate() -> Option<String> {
     None
 }
@@ -457,6 +2182,45 @@ fn default_tls_client_auth() -> String {
     "none".to_string()
 }
 
+fn default_webhook_events() -> HashSet<WebhookEvent> {
+    [
+        WebhookEvent::MotionStart,
+        WebhookEvent::MotionStop,
+        WebhookEvent::Connected,
+        WebhookEvent::Disconnected,
+        WebhookEvent::BatteryLow,
+        WebhookEvent::Visitor,
+        WebhookEvent::ClientConnected,
+        WebhookEvent::ClientDisconnected,
+    ]
+    .iter()
+    .copied()
+    .collect()
+}
+
+const fn default_webhook_battery_low() -> u32 {
+    20
+}
+
+/// A directory of this user, the shared temp dir would let others see and
+/// fill the sessions
+fn default_session_dir() -> std::path::PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => std::path::PathBuf::from(dir).join("neolink"),
+        _ => {
+            let user = std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "user".to_string());
+            std::env::temp_dir().join(format!("neolink-{}", crate::utils::file_safe_name(&user)))
+        }
+    }
+    .join("sessions")
+}
+
+fn default_shutdown_timeout() -> u64 {
+    10
+}
+
 fn default_tokio_console() -> bool {
     false
 }
@@ -473,6 +2237,10 @@ fn default_motion_timeout() -> f64 {
     1.
 }
 
+fn default_motion_resume_duration() -> u64 {
+    30
+}
+
 fn default_on_disconnect() -> bool {
     false
 }
@@ -489,15 +2257,123 @@ fn default_strict() -> bool {
     false
 }
 
+fn default_udp_mtu() -> u32 {
+    1350
+}
+
+fn default_udp_ack_interval() -> u64 {
+    10
+}
+
+fn default_udp_resend_interval() -> u64 {
+    500
+}
+
+fn default_udp_resend_window() -> usize {
+    64
+}
+
+fn default_udp() -> UdpConfig {
+    UdpConfig {
+        mtu: default_udp_mtu(),
+        ack_interval: default_udp_ack_interval(),
+        resend_interval: default_udp_resend_interval(),
+        resend_window: default_udp_resend_window(),
+    }
+}
+
 fn default_pause() -> PauseConfig {
     PauseConfig {
         on_motion: default_on_motion(),
         on_disconnect: default_on_disconnect(),
+        disconnect: false,
         motion_timeout: default_motion_timeout(),
         mode: default_pause_mode(),
+        image: None,
+        motion_resume: false,
+        motion_resume_duration: default_motion_resume_duration(),
+        main_stream: None,
+        sub_stream: None,
+        extern_stream: None,
     }
 }
 
+fn default_audio_threshold() -> f64 {
+    -30.
+}
+
+fn default_audio_min_duration() -> u64 {
+    500
+}
+
+fn default_audio_cooldown() -> u64 {
+    5000
+}
+
+fn default_timelapse_interval() -> u64 {
+    300
+}
+
+fn default_timelapse_video_at() -> String {
+    "00:05".to_string()
+}
+
+fn default_schedule_days() -> String {
+    "*".to_string()
+}
+
+fn default_timelapse_fps() -> u32 {
+    25
+}
+
+fn default_timelapse_filename() -> String {
+    "%Y-%m-%d/%H%M%S.jpeg".to_string()
+}
+
+fn default_record_stream() -> StreamConfig {
+    StreamConfig::Main
+}
+
+fn default_composite_layout() -> CompositeLayout {
+    CompositeLayout::SideBySide
+}
+
+fn default_hls_segment() -> u32 {
+    2
+}
+
+fn default_hls_playlist_length() -> u32 {
+    5
+}
+
+fn default_multicast_port() -> u16 {
+    5000
+}
+
+fn default_multicast_ttl() -> u8 {
+    16
+}
+
+fn default_record_format() -> RecordFormat {
+    RecordFormat::Mp4
+}
+
+fn default_record_mode() -> RecordMode {
+    RecordMode::Motion
+}
+
+fn default_record_segment() -> u64 {
+    15 * 60
+}
+
+fn default_record_pre_roll() -> u64 {
+    5
+}
+
+fn default_record_post_roll() -> u64 {
+    10
+}
+
 fn default_buffer_duration() -> u64 {
     3000
 }
@@ -525,11 +2401,292 @@ fn validate_username(name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_record_stream(stream: &StreamConfig) -> Result<(), ValidationError> {
+    match stream {
+        StreamConfig::Main | StreamConfig::Sub | StreamConfig::Extern => Ok(()),
+        _ => Err(ValidationError::new(
+            "Only one of main, sub or extern can be recorded",
+        )),
+    }
+}
+
+fn validate_single_stream(stream: &StreamConfig) -> Result<(), ValidationError> {
+    match stream {
+        StreamConfig::Main | StreamConfig::Sub | StreamConfig::Extern => Ok(()),
+        _ => Err(ValidationError::new(
+            "Only one of main, sub or extern can be used",
+        )),
+    }
+}
+
+fn validate_push_url(url: &str) -> Result<(), ValidationError> {
+    let scheme = url
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_lowercase());
+    match scheme.as_deref() {
+        Some("rtsp" | "rtsps" | "rtmp" | "rtmps") => Ok(()),
+        _ => Err(ValidationError::new(
+            "Push url should start with rtsp://, rtsps://, rtmp:// or rtmps://",
+        )),
+    }
+}
+
+fn validate_timelapse_filename(filename: &str) -> Result<(), ValidationError> {
+    let path = std::path::Path::new(filename);
+    if path.is_absolute()
+        || path
+            .components()
+            .any(|part| !matches!(part, std::path::Component::Normal(_)))
+    {
+        Err(ValidationError::new(
+            "Timelapse filename should be a relative path without ..",
+        ))
+    } else if !path
+        .extension()
+        .is_some_and(|ext| ext == "jpeg" || ext == "jpg")
+    {
+        Err(ValidationError::new(
+            "Timelapse filename should end in .jpeg or .jpg",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_timelapse(config: &TimelapseConfig) -> Result<(), ValidationError> {
+    // The daily video is made from the frames in each day's directory
+    if config.daily_video && !config.filename.starts_with("%Y-%m-%d/") {
+        Err(ValidationError::new(
+            "Timelapse filename should start with %Y-%m-%d/ when daily_video is on",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_paths(paths: &[String]) -> Result<(), ValidationError> {
+    for path in paths.iter() {
+        let rest = path.replace("{name}", "").replace("{stream}", "");
+        if !path.starts_with('/') || path.len() == 1 {
+            return Err(ValidationError::new(
+                "Each of the paths should start with / such as /{name}/{stream}",
+            ));
+        } else if rest.contains(['{', '}']) {
+            return Err(ValidationError::new(
+                "Only {name} and {stream} can be used in the paths",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.split_once(['x', 'X', '*'])?;
+    match (width.trim().parse(), height.trim().parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+fn validate_resolution(resolution: &str) -> Result<(), ValidationError> {
+    match parse_resolution(resolution) {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new(
+            "resolution should be the width and height such as 2560x1440",
+        )),
+    }
+}
+
+fn validate_rotate(rotate: u16) -> Result<(), ValidationError> {
+    match rotate {
+        90 | 180 | 270 => Ok(()),
+        _ => Err(ValidationError::new("rotate should be 90, 180 or 270")),
+    }
+}
+
+fn validate_mdns_hostname(hostname: &str) -> Result<(), ValidationError> {
+    if !hostname.is_empty()
+        && hostname.len() <= 63
+        && hostname
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            "mDNS hostname should be letters, digits and - without .local",
+        ))
+    }
+}
+
+fn validate_mdns_address(address: &str) -> Result<(), ValidationError> {
+    match address.parse::<std::net::Ipv4Addr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new(
+            "mDNS address should be an IPv4 address",
+        )),
+    }
+}
+
+fn validate_proxy(url: &str) -> Result<(), ValidationError> {
+    match url.parse::<Proxy>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new(
+            "Proxy should be a url such as socks5://host:1080 or http://host:8080",
+        )),
+    }
+}
+
+fn validate_multicast_addr(address: &str) -> Result<(), ValidationError> {
+    match address.parse::<std::net::IpAddr>() {
+        Ok(addr) if addr.is_multicast() => Ok(()),
+        _ => Err(ValidationError::new(
+            "Multicast address should be an ip such as 239.255.0.1",
+        )),
+    }
+}
+
 fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), ValidationError> {
-    match (&camera_config.camera_addr, &camera_config.camera_uid) {
-        (None, None) => Err(ValidationError::new(
+    match (
+        camera_config.camera_addrs.is_empty(),
+        &camera_config.camera_uid,
+    ) {
+        (true, None) => Err(ValidationError::new(
             "Either camera address or uid must be given",
         )),
+        _ if camera_config.retry_max < camera_config.retry_min => Err(ValidationError::new(
+            "retry_max should not be less than retry_min",
+        )),
+        _ if camera_config.pause.mode == "image" && camera_config.pause.image.is_none() => Err(
+            ValidationError::new("pause mode image needs the path of the image in pause.image"),
+        ),
         _ => Ok(()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Date, Month, OffsetDateTime};
+
+    fn at(day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        // The first of January 2024 is a monday
+        Date::from_calendar_date(2024, Month::January, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    fn test_cron_field() {
+        assert_eq!(cron_field("*", 0, 3), Some(0b1111));
+        assert_eq!(cron_field("2", 0, 59), Some(1 << 2));
+        assert_eq!(cron_field("1-3", 0, 59), Some(0b1110));
+        assert_eq!(
+            cron_field("*/15", 0, 59),
+            Some(1 | 1 << 15 | 1 << 30 | 1 << 45)
+        );
+        assert_eq!(cron_field("50/5", 0, 59), Some(1 << 50 | 1 << 55));
+        assert_eq!(cron_field("1,4-5", 0, 59), Some(1 << 1 | 1 << 4 | 1 << 5));
+        assert_eq!(cron_field("60", 0, 59), None);
+        assert_eq!(cron_field("0", 1, 31), None);
+        assert_eq!(cron_field("5-1", 0, 59), None);
+        assert_eq!(cron_field("*/0", 0, 59), None);
+        assert_eq!(cron_field("mon", 0, 7), None);
+        assert_eq!(cron_field("", 0, 59), None);
+    }
+
+    #[test]
+    fn test_cron_due() {
+        assert_eq!(cron_due("0 4 * * 1", &at(1, 4, 0)), Some(true));
+        assert_eq!(cron_due("0 4 * * 1", &at(1, 4, 1)), Some(false));
+        assert_eq!(cron_due("0 4 * * 1", &at(2, 4, 0)), Some(false));
+        // 0 and 7 are both sunday
+        assert_eq!(cron_due("0 4 * * 0", &at(7, 4, 0)), Some(true));
+        assert_eq!(cron_due("0 4 * * 7", &at(7, 4, 0)), Some(true));
+        assert_eq!(cron_due("*/30 * * * *", &at(3, 13, 30)), Some(true));
+        assert_eq!(cron_due("0 0 1 1 *", &at(1, 0, 0)), Some(true));
+        assert_eq!(cron_due("0 0 1 2 *", &at(1, 0, 0)), Some(false));
+        // Either day is enough when both are given
+        assert_eq!(cron_due("0 4 15 * 1", &at(1, 4, 0)), Some(true));
+        assert_eq!(cron_due("0 4 15 * 1", &at(2, 4, 0)), Some(false));
+        assert_eq!(cron_due("0 4 2 * *", &at(2, 4, 0)), Some(true));
+        assert_eq!(cron_due("0 4 * *", &at(1, 4, 0)), None);
+        assert_eq!(cron_due("0 24 * * *", &at(1, 4, 0)), None);
+    }
+
+    fn rule(days: &str, from: &str, to: &str) -> ScheduleRule {
+        ScheduleRule {
+            days: days.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_days_of_week() {
+        assert_eq!(days_of_week("*"), Some([true; 7]));
+        assert_eq!(
+            days_of_week("mon-fri"),
+            Some([true, true, true, true, true, false, false])
+        );
+        assert_eq!(
+            days_of_week("Sat, sunday"),
+            Some([false, false, false, false, false, true, true])
+        );
+        // Over the weekend
+        assert_eq!(
+            days_of_week("fri-mon"),
+            Some([true, false, false, false, true, true, true])
+        );
+        assert_eq!(
+            days_of_week("tue,thu-fri"),
+            Some([false, true, false, true, true, false, false])
+        );
+        assert_eq!(days_of_week("weekdays"), None);
+        assert_eq!(days_of_week("mo"), None);
+        assert_eq!(days_of_week(""), None);
+        assert!(validate_days("mon-fri").is_ok());
+        assert!(validate_days("someday").is_err());
+    }
+
+    #[test]
+    fn test_minute_of_day() {
+        assert_eq!(minute_of_day("00:00"), Some(0));
+        assert_eq!(minute_of_day("07:30"), Some(450));
+        assert_eq!(minute_of_day("23:59"), Some(1439));
+        assert_eq!(minute_of_day("0730"), None);
+    }
+
+    #[test]
+    fn test_schedule_rule() {
+        // 0 is monday
+        let work = rule("mon-fri", "09:00", "17:00");
+        assert!(work.contains(0, 9 * 60));
+        assert!(work.contains(4, 17 * 60 - 1));
+        assert!(!work.contains(0, 17 * 60));
+        assert!(!work.contains(0, 9 * 60 - 1));
+        assert!(!work.contains(5, 12 * 60));
+
+        // Past midnight it is still the rule of the day that it started on
+        let night = rule("fri", "22:00", "06:00");
+        assert!(night.contains(4, 23 * 60));
+        assert!(night.contains(5, 5 * 60));
+        assert!(!night.contains(5, 6 * 60));
+        assert!(!night.contains(5, 23 * 60));
+        assert!(!night.contains(4, 5 * 60));
+        // Sunday night runs into monday
+        let sunday = rule("sun", "23:00", "01:00");
+        assert!(sunday.contains(0, 30));
+        assert!(!sunday.contains(6, 30));
+
+        // The same from and to is the whole day
+        let all_day = rule("sat,sun", "00:00", "00:00");
+        assert!(all_day.contains(5, 0));
+        assert!(all_day.contains(6, 1439));
+        assert!(!all_day.contains(0, 0));
+
+        assert!(!rule("someday", "09:00", "17:00").contains(0, 10 * 60));
+    }
+}
@@ -0,0 +1,13 @@
+use clap::Parser;
+
+/// The config-get command prints a section of the camera settings as JSON
+///
+/// The section is one of the names below or the msg id of a BC get message
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// email, email_task, general, led, motion, osd, pir, service_ports,
+    /// video_input, hdd, support, version or a msg id
+    pub section: String,
+}
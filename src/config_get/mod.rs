@@ -0,0 +1,33 @@
+///
+/// # Neolink Config Get
+///
+/// This module prints the settings of the camera as JSON. It sends the BC
+/// get message of the section and turns the xml of the reply into JSON, so
+/// it also works for the settings that neolink has no command for. The
+/// output can be edited and given to `neolink config-set`
+///
+/// # Usage
+///
+/// ```bash
+/// neolink config-get --config=config.toml CameraName email
+/// # Or any get message by its msg id
+/// neolink config-get --config=config.toml CameraName 104
+/// ```
+///
+use anyhow::Result;
+
+mod cmdline;
+
+use crate::common::{get_settings, section_ids, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the config-get subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (get_id, _) = section_ids(&opt.section)?;
+    let camera = reactor.get(&opt.camera).await?;
+    let settings = get_settings(&camera, get_id).await?;
+    println!("{}", serde_json::to_string_pretty(&settings)?);
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The config-set command sends JSON settings to the camera
+///
+/// The JSON is read from stdin unless a file is given, it is usually the
+/// edited output of `neolink config-get`
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// email, email_task, general, led, motion, osd, pir, service_ports,
+    /// video_input or the msg id of a BC set message
+    pub section: String,
+    /// Read the JSON from this file instead of stdin
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub file: Option<PathBuf>,
+}
@@ -0,0 +1,53 @@
+///
+/// # Neolink Config Set
+///
+/// This module sends settings in JSON to the camera. The JSON is turned into
+/// the xml of the BC set message of the section, this is the reverse of
+/// `neolink config-get`. The reply of the camera is printed as JSON
+///
+/// Most cameras expect the whole section so get it first and edit it
+///
+/// # Usage
+///
+/// ```bash
+/// neolink config-get --config=config.toml CameraName email > email.json
+/// # Edit email.json then
+/// neolink config-set --config=config.toml CameraName email < email.json
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use std::io::Read;
+
+mod cmdline;
+
+use crate::common::{section_ids, set_settings, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the config-set subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (_, set_id) = section_ids(&opt.section)?;
+    let set_id = set_id.ok_or_else(|| anyhow!("The {} section cannot be set", opt.section))?;
+
+    let text = match opt.file.as_ref() {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?,
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("Could not read the JSON from stdin")?;
+            text
+        }
+    };
+    let settings: serde_json::Value =
+        serde_json::from_str(&text).context("The settings are not valid JSON")?;
+
+    let camera = reactor.get(&opt.camera).await?;
+    let reply = set_settings(&camera, set_id, &settings).await?;
+    if !reply.as_object().is_some_and(|reply| reply.is_empty()) && reply != "" {
+        println!("{}", serde_json::to_string_pretty(&reply)?);
+    }
+    Ok(())
+}
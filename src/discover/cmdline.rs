@@ -0,0 +1,19 @@
+use clap::Parser;
+
+/// The discover command broadcasts on the lan and lists the reolink cameras
+/// that reply. It does not need a config
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Seconds to wait for the cameras to reply
+    #[arg(long, default_value_t = 5)]
+    pub wait: u64,
+    /// Log into each camera as this user to read its model and name
+    #[arg(short, long)]
+    pub username: Option<String>,
+    /// The password of the user
+    #[arg(short, long, requires = "username")]
+    pub password: Option<String>,
+    /// Print a `[[cameras]]` config of each camera instead of a table
+    #[arg(long)]
+    pub toml: bool,
+}
@@ -0,0 +1,168 @@
+///
+/// # Neolink Discover
+///
+/// This module handles the discover subcommand
+///
+/// The subcommand broadcasts on the lan and lists the cameras that reply
+/// with their address and UID. With a username it also logs into each camera
+/// to read its model and name. It can print a `[[cameras]]` config for each
+/// of them to start a config from.
+///
+/// This is run without a config.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink discover
+/// neolink discover --username=admin --password=password --toml >> config.toml
+/// ```
+///
+use anyhow::Result;
+use log::*;
+use neolink_core::bc_protocol::{
+    scan_lan, BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, LanCamera,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::time::{timeout, Duration};
+
+mod cmdline;
+
+use crate::utils::file_safe_name;
+pub(crate) use cmdline::Opt;
+
+/// How long the login to each camera may take
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A camera that was found with what could be read from it
+struct Found {
+    scanned: LanCamera,
+    model: Option<String>,
+    name: Option<String>,
+}
+
+/// The parts of a camera config that are printed
+#[derive(Serialize)]
+struct SnippetCamera {
+    name: String,
+    username: String,
+    password: String,
+    address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Snippet {
+    cameras: Vec<SnippetCamera>,
+}
+
+/// Entry point for the discover subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: &Opt) -> Result<()> {
+    info!("Looking for cameras for {}s", opt.wait);
+    let scanned = scan_lan(Duration::from_secs(opt.wait)).await?;
+    if scanned.is_empty() {
+        info!("No cameras replied, they must be on the same lan as neolink to be found");
+        return Ok(());
+    }
+
+    let mut found = vec![];
+    for scanned in scanned.into_iter() {
+        let mut camera = Found {
+            scanned,
+            model: None,
+            name: None,
+        };
+        if let Some(username) = opt.username.as_ref() {
+            if let Err(e) = read_camera(&mut camera, username, opt.password.as_ref()).await {
+                warn!("{}: Could not log in: {:?}", camera.scanned.addr, e);
+            }
+        }
+        found.push(camera);
+    }
+
+    if opt.toml {
+        let mut names = HashSet::new();
+        for (i, camera) in found.iter().enumerate() {
+            let mut name = file_safe_name(
+                camera
+                    .name
+                    .as_deref()
+                    .unwrap_or(&format!("Camera{:02}", i + 1)),
+            );
+            while !names.insert(name.clone()) {
+                name.push('_');
+            }
+            println!(
+                "# {} {}",
+                camera.scanned.addr,
+                camera.model.as_deref().unwrap_or("")
+            );
+            println!(
+                "{}",
+                toml::to_string(&Snippet {
+                    cameras: vec![SnippetCamera {
+                        name,
+                        username: opt.username.clone().unwrap_or_else(|| "admin".to_string()),
+                        password: opt
+                            .password
+                            .clone()
+                            .unwrap_or_else(|| "password".to_string()),
+                        address: camera.scanned.addr.to_string(),
+                        uid: camera.scanned.uid.clone(),
+                    }],
+                })?
+            );
+        }
+    } else {
+        println!("{:<16} {:<18} {:<16} NAME", "ADDRESS", "UID", "MODEL");
+        for camera in found.iter() {
+            println!(
+                "{:<16} {:<18} {:<16} {}",
+                camera.scanned.addr,
+                camera.scanned.uid.as_deref().unwrap_or("-"),
+                camera.model.as_deref().unwrap_or("-"),
+                camera.name.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Log into the camera to read its model, name and UID
+async fn read_camera(camera: &mut Found, username: &str, password: Option<&String>) -> Result<()> {
+    let options = BcCameraOpt {
+        name: camera.scanned.addr.to_string(),
+        channel_id: 0,
+        addrs: vec![camera.scanned.addr],
+        port: None,
+        uid: camera.scanned.uid.clone(),
+        protocol: ConnectionProtocol::TcpUdp,
+        discovery: DiscoveryMethods::Local,
+        max_discovery_retries: 1,
+        credentials: Credentials {
+            username: username.to_string(),
+            password: password.cloned(),
+        },
+        debug: false,
+        udp: Default::default(),
+        proxy: None,
+    };
+    timeout(LOGIN_TIMEOUT, async {
+        let bc_camera = BcCamera::new(&options).await?;
+        bc_camera.login().await?;
+        let version = bc_camera.version().await?;
+        camera.model = version.model;
+        camera.name = Some(version.name).filter(|name| !name.is_empty());
+        if camera.scanned.uid.is_none() {
+            camera.scanned.uid = bc_camera.uid().await.ok();
+        }
+        let _ = bc_camera.logout().await;
+        Result::<(), anyhow::Error>::Ok(())
+    })
+    .await??;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use crate::utils::parse_replay_time;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::{bc::xml::ReplayTime, bc_protocol::StreamKind};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn stream_parse(src: &str) -> Result<StreamKind> {
+    match src {
+        "main" | "mainStream" => Ok(StreamKind::Main),
+        "sub" | "subStream" => Ok(StreamKind::Sub),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be main or sub",
+            src
+        )),
+    }
+}
+
+/// The download command will save the recordings on the SD card of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Download the recordings from this local time of the camera e.g. `2023-10-14 08:00`
+    #[arg(long, value_parser = parse_replay_time)]
+    pub start: ReplayTime,
+    /// Download the recordings up to this local time of the camera
+    #[arg(long, value_parser = parse_replay_time)]
+    pub end: ReplayTime,
+    /// The directory to save the recordings in
+    #[arg(short, long, value_parser = PathBuf::from_str, required_unless_present = "list")]
+    pub output: Option<PathBuf>,
+    /// The recorded stream to download, main or sub
+    #[arg(long, default_value = "main", value_parser = stream_parse)]
+    pub stream: StreamKind,
+    /// Only list the recordings instead of downloading them
+    #[arg(long)]
+    pub list: bool,
+}
@@ -0,0 +1,156 @@
+///
+/// # Neolink Download
+///
+/// This module can be used to download the recordings on the SD card
+///
+/// # Usage
+///
+/// ```bash
+/// # Download the recordings of a morning
+/// neolink download --config=config.toml CameraName --start "2023-10-14 08:00" --end "2023-10-14 12:00" --output recordings/
+/// # Or only list them
+/// neolink download --config=config.toml CameraName --start 2023-10-14 --end 2023-10-15 --list
+/// ```
+///
+/// The recordings are saved as the raw H264 or H265 stream of the camera.
+/// Use a tool such as ffmpeg to put them into a container.
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::{
+    bc::xml::{FileInfo, ReplayTime},
+    bc_protocol::Error as BcError,
+    bcmedia::model::{BcMedia, VideoType},
+};
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    time::{timeout, Duration},
+};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    utils::file_safe_name,
+};
+pub(crate) use cmdline::Opt;
+
+/// The camera does not say when a recording has all been sent
+/// so it is done once no more data arrives
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Entry point for the download subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let (stream, start, end) = (opt.stream, opt.start, opt.end);
+    let files = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.replay_file_list(stream, start, end)
+                    .await
+                    .context("Unable to search the recordings on the SD card")
+            })
+        })
+        .await?;
+    log::info!("{}: Found {} recordings", opt.camera, files.len());
+
+    let output = match opt.output {
+        Some(output) if !opt.list => output,
+        _ => {
+            for file in files.iter() {
+                println!("{}", describe(file));
+            }
+            return Ok(());
+        }
+    };
+    tokio::fs::create_dir_all(&output)
+        .await
+        .with_context(|| format!("Could not create {:?}", output))?;
+
+    for file in files.iter() {
+        log::info!("{}: Downloading {}", opt.camera, describe(file));
+        let path = download(&camera, &opt.camera, file, &output).await?;
+        log::info!("{}: Saved {:?}", opt.camera, path);
+    }
+    Ok(())
+}
+
+async fn download(
+    camera: &NeoInstance,
+    name: &str,
+    file: &FileInfo,
+    output: &Path,
+) -> Result<PathBuf> {
+    let stem = output.join(file_stem(name, file));
+    camera
+        .run_task(|cam| {
+            let file = file.clone();
+            let stem = stem.clone();
+            Box::pin(async move {
+                let mut stream = cam.replay_download(file, 0).await?;
+                let mut out: Option<(PathBuf, File)> = None;
+                loop {
+                    let media = match timeout(IDLE_TIMEOUT, stream.get_data()).await {
+                        Err(_) | Ok(Err(BcError::StreamFinished)) => break,
+                        Ok(data) => data??,
+                    };
+                    let (video_type, data) = match media {
+                        BcMedia::Iframe(frame) => (frame.video_type, frame.data),
+                        BcMedia::Pframe(frame) => (frame.video_type, frame.data),
+                        _ => continue,
+                    };
+                    if out.is_none() {
+                        let path = stem.with_extension(match video_type {
+                            VideoType::H264 => "h264",
+                            VideoType::H265 => "h265",
+                        });
+                        let created = File::create(&path)
+                            .await
+                            .with_context(|| format!("Could not create {:?}", path))?;
+                        out = Some((path, created));
+                    }
+                    if let Some((_, out)) = out.as_mut() {
+                        out.write_all(&data).await?;
+                    }
+                }
+                let _ = stream.shutdown().await;
+                match out {
+                    Some((path, mut out)) => {
+                        out.flush().await?;
+                        Ok(path)
+                    }
+                    None => Err(anyhow!("The camera did not send any video")),
+                }
+            })
+        })
+        .await
+}
+
+fn describe(file: &FileInfo) -> String {
+    let time = |t: Option<ReplayTime>| t.map(|t| t.to_string()).unwrap_or_default();
+    format!(
+        "{} - {} {} {} bytes {}",
+        time(file.start_time),
+        time(file.end_time),
+        file.record_type.as_deref().unwrap_or_default(),
+        file.file_size.unwrap_or_default(),
+        file.name.as_deref().unwrap_or_default(),
+    )
+}
+
+fn file_stem(camera: &str, file: &FileInfo) -> String {
+    let start = file.start_time.unwrap_or_default();
+    format!(
+        "{}_{:04}{:02}{:02}_{:02}{:02}{:02}",
+        file_safe_name(camera),
+        start.year,
+        start.month,
+        start.day,
+        start.hour,
+        start.minute,
+        start.second
+    )
+}
@@ -0,0 +1,59 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::StreamKind;
+use tokio::time::Duration;
+
+fn stream_parse(src: &str) -> Result<StreamKind> {
+    match src {
+        "main" | "mainStream" => Ok(StreamKind::Main),
+        "sub" | "subStream" => Ok(StreamKind::Sub),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be main or sub",
+            src
+        )),
+    }
+}
+
+/// Parses a time span such as `90s`, `30m`, `24h` or `7d`
+fn since_parse(src: &str) -> Result<Duration> {
+    let split = src.find(|c: char| !c.is_ascii_digit()).unwrap_or(src.len());
+    let (number, unit) = src.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| anyhow!("Could not understand {}, should be like 24h or 7d", src))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" | "" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(anyhow!(
+                "Could not understand {}, the unit should be s, m, h or d",
+                src
+            ))
+        }
+    };
+    number
+        .checked_mul(scale)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("Could not understand {}, it is too long", src))
+}
+
+/// The events command lists what the camera recorded motion and other alarms for
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// How far back to look such as `30m`, `24h` or `7d`
+    #[arg(long, default_value = "24h", value_parser = since_parse)]
+    pub since: Duration,
+    /// The recorded stream to search, main or sub
+    #[arg(long, default_value = "main", value_parser = stream_parse)]
+    pub stream: StreamKind,
+    /// Also list the recordings of the recording schedule and manual recordings
+    #[arg(long)]
+    pub all: bool,
+    /// Print the events as json
+    #[arg(long)]
+    pub json: bool,
+}
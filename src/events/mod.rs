@@ -0,0 +1,103 @@
+///
+/// # Neolink Events
+///
+/// This module handles the events subcommand
+///
+/// The subcommand lists the motion and other alarm events of a camera along
+/// with when they started and ended. The events are read from the recordings
+/// that the camera made of them on its SD card, so a camera without an SD card
+/// or that does not record on alarms has no events to list.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink events --config=config.toml CameraName --since 24h
+/// neolink events --config=config.toml CameraName --since 7d --json
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc::xml::{FileInfo, ReplayTime};
+use serde::Serialize;
+use std::convert::TryFrom;
+
+mod cmdline;
+
+use crate::{
+    common::NeoReactor,
+    utils::{now_local, replay_time},
+};
+pub(crate) use cmdline::Opt;
+
+/// Record types of recordings that were not made for an event
+const NOT_EVENTS: &[&str] = &["sched", "manual", "timing"];
+
+/// An event as it is printed in json
+#[derive(Serialize)]
+struct Event {
+    start: Option<String>,
+    end: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    size: Option<u64>,
+    file: Option<String>,
+}
+
+impl From<&FileInfo> for Event {
+    fn from(file: &FileInfo) -> Self {
+        Self {
+            start: file.start_time.map(|t| t.to_string()),
+            end: file.end_time.map(|t| t.to_string()),
+            kind: file.record_type.clone().unwrap_or_default(),
+            size: file.file_size,
+            file: file.name.clone(),
+        }
+    }
+}
+
+/// Entry point for the events subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let (stream, since) = (opt.stream, opt.since);
+    let mut files = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                // The recordings are in the local time of the camera so
+                // its clock is used over ours when it can be read
+                let now = match cam.get_time().await {
+                    Ok(Some(now)) => now,
+                    _ => now_local(),
+                };
+                let from = time::Duration::try_from(since)
+                    .ok()
+                    .and_then(|since| now.checked_sub(since))
+                    .context("The --since is too far back")?;
+                cam.replay_file_list(stream, replay_time(&from), replay_time(&now))
+                    .await
+                    .context("Unable to search the recordings on the SD card")
+            })
+        })
+        .await?;
+    if !opt.all {
+        files.retain(|file| !NOT_EVENTS.contains(&file.record_type.as_deref().unwrap_or_default()));
+    }
+    files.sort_by_key(|file| file.start_time);
+    log::info!("{}: Found {} events", opt.camera, files.len());
+
+    if opt.json {
+        let events: Vec<Event> = files.iter().map(Event::from).collect();
+        println!("{}", serde_json::to_string_pretty(&events)?);
+    } else {
+        let time = |t: Option<ReplayTime>| t.map(|t| t.to_string()).unwrap_or_default();
+        for file in files.iter() {
+            println!(
+                "{} - {} {}",
+                time(file.start_time),
+                time(file.end_time),
+                file.record_type.as_deref().unwrap_or_default(),
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::cmdline::CameraSelection;
+use clap::{Parser, ValueEnum};
+
+/// The floodlight command will control the flood light or spotlight of the camera
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Turn the light on or off, or let the camera turn it on at night when there
+    /// is motion. Prints the current settings if neither this nor --brightness is given
+    #[arg(value_enum)]
+    pub state: Option<FloodlightState>,
+    /// The brightness of the light in %
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=100))]
+    pub brightness: Option<u32>,
+    /// How long in seconds the light stays on for
+    #[arg(long, default_value_t = 180)]
+    pub duration: u16,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FloodlightState {
+    /// Turn the light on
+    On,
+    /// Turn the light off and stop it coming on with motion
+    Off,
+    /// Turn the light on with motion at night
+    Auto,
+}
@@ -0,0 +1,84 @@
+///
+/// # Neolink Floodlight
+///
+/// This module handles the controls of the flood light or spotlight
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the light on for 60s
+/// neolink floodlight --config=config.toml CameraName on --duration 60
+/// # Or off
+/// neolink floodlight --config=config.toml CameraName off
+/// # Or on with motion at night with half brightness
+/// neolink floodlight --config=config.toml CameraName auto --brightness 50
+/// # Print the current settings
+/// neolink floodlight --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera};
+use cmdline::FloodlightState;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the floodlight subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (state, brightness, duration) = (opt.state, opt.brightness, opt.duration);
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        if let Some(brightness) = brightness {
+                            cam.floodlight_brightness_set(brightness)
+                                .await
+                                .context("Unable to set the floodlight brightness")?;
+                        }
+                        match state {
+                            Some(FloodlightState::On) => cam
+                                .set_floodlight_manual(true, duration)
+                                .await
+                                .context("Unable to turn the floodlight on")?,
+                            Some(FloodlightState::Off) => {
+                                cam.flightlight_tasks_enable(false)
+                                    .await
+                                    .context("Unable to turn the floodlight tasks off")?;
+                                cam.set_floodlight_manual(false, duration)
+                                    .await
+                                    .context("Unable to turn the floodlight off")?;
+                            }
+                            Some(FloodlightState::Auto) => cam
+                                .flightlight_tasks_enable(true)
+                                .await
+                                .context("Unable to turn the floodlight tasks on")?,
+                            None if brightness.is_none() => {
+                                let tasks = cam
+                                    .get_flightlight_tasks()
+                                    .await
+                                    .context("Unable to get the floodlight settings")?;
+                                return Ok(String::from_utf8({
+                                    let mut buf = bytes::BytesMut::new();
+                                    quick_xml::se::to_writer(&mut buf, &tasks)
+                                        .expect("Should Ser the struct");
+                                    buf.to_vec()
+                                })
+                                .expect("Should be UTF8"));
+                            }
+                            None => {}
+                        }
+                        Ok(String::new())
+                    })
+                })
+                .await
+        },
+    )
+    .await
+}
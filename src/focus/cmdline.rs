@@ -0,0 +1,16 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The focus command will focus a camera with a motorized lens
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera to focus. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// The position of the lens, the range depends on the camera.
+    /// Without this the current position and the range are printed
+    pub position: Option<u32>,
+}
@@ -0,0 +1,66 @@
+///
+/// # Neolink Focus
+///
+/// This module handles the focus of cameras with a motorized lens such as
+/// the RLC-811A and RLC-823A
+///
+/// # Usage
+///
+/// ```bash
+/// # Move the focus to position 120
+/// neolink focus --config=config.toml CameraName 120
+/// # Print the current position and the range of the camera
+/// neolink focus --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use tokio::time::{sleep, Duration};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the focus subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let position = opt.position;
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            if let Some(position) = position {
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.focus_to(position)
+                                .await
+                                .context("Unable to focus the camera")
+                        })
+                    })
+                    .await?;
+                // Give the lens time to move before logging out
+                sleep(Duration::from_secs(1)).await;
+                Ok(String::new())
+            } else {
+                let focus = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.get_zoom()
+                                .await
+                                .context("Unable to get the focus of the camera")
+                        })
+                    })
+                    .await?
+                    .focus;
+                Ok(format!(
+                    "Focus: {} ({} to {})",
+                    focus.cur_pos, focus.min_pos, focus.max_pos
+                ))
+            }
+        },
+    )
+    .await
+}
@@ -8,7 +8,7 @@ pub struct Opt {
     /// The name of the camera to get the image from. Must be a name in the config
     pub camera: String,
     /// The path of the output.
-    #[structopt(short, long, value_parser = PathBuf::from_str)]
+    #[structopt(short, long, visible_alias = "output", short_alias = 'o', value_parser = PathBuf::from_str)]
     pub file_path: PathBuf,
     /// If set then the image will pull from the live stream, if not it will be pulled from the cameras snap feature
     ///
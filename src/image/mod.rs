@@ -10,26 +10,26 @@
 /// ```
 ///
 /// Cameras that do not support the SNAP command need to use `--use_stream`
-/// which will make the camera play the stream and transcode the video into a jpeg
+/// which will make the camera play the stream and transcode the next iframe into a jpeg
 /// e.g.:
 ///
 /// ```bash
 /// neolink image --config=config.toml --use_stream --file-path=filepath CameraName
 /// ```
 ///
-use anyhow::{anyhow, Result};
+use anyhow::{Context, Result};
 use log::*;
 use neolink_core::{
     bc_protocol::*,
-    bcmedia::model::{BcMedia, BcMediaIframe, BcMediaPframe},
+    bcmedia::model::{BcMedia, BcMediaIframe},
 };
-use std::sync::Arc;
-use tokio::{fs::File, io::AsyncWriteExt, sync::RwLock};
+use std::path::{Path, PathBuf};
+use tokio::{fs::File, io::AsyncWriteExt};
 
 mod cmdline;
 mod gst;
 
-use crate::common::NeoReactor;
+use crate::common::{NeoInstance, NeoReactor};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the image subcommand
@@ -37,87 +37,65 @@ pub(crate) use cmdline::Opt;
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
+    let file_path = jpeg_path(&opt.file_path);
 
-    if opt.use_stream {
-        let (stream_data_tx, mut stream_data_rx) = tokio::sync::mpsc::channel(100);
-
-        // Spawn a video stream
-        let thread_camera = camera.clone();
-        let (stream_type_tx, stream_type_rx) = tokio::sync::oneshot::channel();
-        let stream_type_tx = Arc::new(RwLock::new(Some(stream_type_tx)));
-        tokio::task::spawn(async move {
-            thread_camera
-                .run_task(|cam| {
-                    let stream_type_tx = stream_type_tx.clone();
-                    let stream_data_tx = stream_data_tx.clone();
-
-                    Box::pin(async move {
-                        let mut stream = cam.start_video(StreamKind::Main, 100, false).await?;
-                        while let Ok(frame) = stream.get_data().await {
-                            let frame = frame?;
-                            match frame {
-                                BcMedia::Iframe(BcMediaIframe {
-                                    data, video_type, ..
-                                })
-                                | BcMedia::Pframe(BcMediaPframe {
-                                    data, video_type, ..
-                                }) => {
-                                    if let Some(stream_type_tx) =
-                                        stream_type_tx.write().await.take()
-                                    {
-                                        let _ = stream_type_tx.send(video_type);
-                                    }
-                                    stream_data_tx.send(Arc::new(data)).await?;
-                                }
-                                _ => {}
-                            }
-                        }
-                        Result::Ok(())
-                    })
-                })
-                .await
-        });
-
-        let vid_type = stream_type_rx.await?;
-        let buf = stream_data_rx
-            .recv()
-            .await
-            .ok_or(anyhow!("No frames recieved"))?;
-
-        let mut sender = gst::from_input(vid_type, &opt.file_path).await?;
-        sender.send(buf).await?; // Send first iframe
-
-        // Keep sending both IFrame or PFrame until finished
-        while sender.is_finished().await.is_none() {
-            if let Some(buf) = stream_data_rx.recv().await {
-                debug!("Sending frame data to gstreamer");
-                if sender.send(buf).await.is_err() {
-                    // Assume that the sender is closed
-                    // because the pipeline is finished
-                    break;
-                }
-            } else {
-                log::error!("Camera stopped sending frames before decoding could complete");
-                break;
-            }
-        }
-        debug!("Sending EOS");
-        let _ = sender.eos().await; // Ignore return because if pipeline is finished this will error
-        let _ = sender.join().await;
+    let jpeg_data = if opt.use_stream {
+        debug!("Using the stream");
+        jpeg_from_stream(&camera, StreamKind::Main).await
     } else {
         // Simply use the snap command
         debug!("Using the snap command");
-        let file_path = opt.file_path.with_extension("jpeg");
-        let mut buffer = File::create(file_path).await?;
-        let jpeg_data = camera
+        camera
             .run_task(|camera| Box::pin(async move { Ok(camera.get_snapshot().await?) }))
-            .await;
-        if jpeg_data.is_err() {
-            log::debug!("jpeg_data: {:?}", jpeg_data);
-        }
-        let jpeg_data = jpeg_data?;
-        buffer.write_all(jpeg_data.as_slice()).await?;
+            .await
+    };
+    if jpeg_data.is_err() {
+        log::debug!("jpeg_data: {:?}", jpeg_data);
     }
+    let jpeg_data = jpeg_data?;
+
+    let mut buffer = File::create(&file_path)
+        .await
+        .with_context(|| format!("Could not create {:?}", file_path))?;
+    buffer.write_all(jpeg_data.as_slice()).await?;
 
     Ok(())
 }
+
+/// A jpeg of the next iframe of the stream
+///
+/// This is for cameras that do not support the SNAP command. The camera is
+/// only streamed until it sends an iframe.
+pub(crate) async fn jpeg_from_stream(camera: &NeoInstance, stream: StreamKind) -> Result<Vec<u8>> {
+    let strict = camera.config().await?.borrow().strict;
+    let (video_type, iframe) = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                let mut media_stream = cam.start_video(stream, 0, strict).await?;
+                loop {
+                    if let BcMedia::Iframe(BcMediaIframe {
+                        data, video_type, ..
+                    }) = media_stream.get_data().await??
+                    {
+                        break Ok((video_type, data));
+                    }
+                }
+            })
+        })
+        .await?;
+    debug!("Decoding the iframe");
+    tokio::task::spawn_blocking(move || gst::decode_jpeg(video_type, iframe)).await?
+}
+
+/// The file is always a jpeg, `.jpg` is kept but any other extension is
+/// changed to `.jpeg`
+fn jpeg_path(file_path: &Path) -> PathBuf {
+    if file_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+    {
+        file_path.to_path_buf()
+    } else {
+        file_path.with_extension("jpeg")
+    }
+}
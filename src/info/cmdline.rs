@@ -0,0 +1,13 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The info command will print details of the camera such as its
+/// model, firmware, the negotiated encryption and the stream encoding
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+}
@@ -0,0 +1,37 @@
+///
+/// # Neolink Info
+///
+/// This module handles the info subcommand, it can also be called as
+/// `neolink status`
+///
+/// The subcommand logs into the camera and prints its model, firmware,
+/// hardware version, serial, the encryption level that was negotiated
+/// during login and the resolution, fps and bitrate of each stream.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink info --config=config.toml CameraName
+/// neolink status --config=config.toml CameraName
+/// ```
+///
+use anyhow::Result;
+
+mod cmdline;
+
+use crate::{
+    common::{device_info, NeoInstance, NeoReactor},
+    utils::for_each_camera,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the info subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    for_each_camera(&reactor, opt.camera.as_deref(), &opt.select, camera_main).await
+}
+
+async fn camera_main(camera: NeoInstance) -> Result<String> {
+    Ok(device_info(&camera).await?.to_string())
+}
@@ -0,0 +1,31 @@
+use crate::cmdline::CameraSelection;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::LightState;
+
+fn state_parse(src: &str) -> Result<LightState> {
+    match src {
+        "true" | "on" | "yes" => Ok(LightState::On),
+        "false" | "off" | "no" => Ok(LightState::Off),
+        "auto" => Ok(LightState::Auto),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be on, off or auto",
+            src
+        )),
+    }
+}
+
+/// The ir command will control the IR night vision lights of the camera
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera to change the lights of. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Turn the IR lights on, off or let the camera choose by the light level.
+    /// Without this the current state is printed
+    #[arg(value_parser = state_parse, action = clap::ArgAction::Set, name = "on|off|auto")]
+    pub state: Option<LightState>,
+}
@@ -0,0 +1,76 @@
+///
+/// # Neolink IR
+///
+/// This module handles the controls of the IR night vision lights
+///
+/// The camera switches into night mode when the IR lights are on. In auto
+/// it chooses by the light level.
+///
+/// # Usage
+///
+/// ```bash
+/// # To force night mode
+/// neolink ir --config=config.toml CameraName on
+/// # Or day mode
+/// neolink ir --config=config.toml CameraName off
+/// # Or let the camera choose
+/// neolink ir --config=config.toml CameraName auto
+/// # Print the current state
+/// neolink ir --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera_control};
+pub(crate) use cmdline::Opt;
+use neolink_core::bc_protocol::LightState;
+
+/// Entry point for the ir subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let state = opt.state;
+    for_each_camera_control(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        state.map(|state| ("ir", ir_state_name(state))),
+        |camera| async move {
+            if let Some(state) = state {
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.irled_light_set(state)
+                                .await
+                                .context("Unable to set camera IR state")
+                        })
+                    })
+                    .await?;
+                Ok(String::new())
+            } else {
+                let state = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.irled_light_get()
+                                .await
+                                .context("Unable to get camera IR state")
+                        })
+                    })
+                    .await?;
+                Ok(ir_state_name(state).to_string())
+            }
+        },
+    )
+    .await
+}
+
+/// The name of the state as used on the command line
+fn ir_state_name(state: LightState) -> &'static str {
+    match state {
+        LightState::On => "on",
+        LightState::Off => "off",
+        LightState::Auto => "auto",
+    }
+}
@@ -0,0 +1,11 @@
+use clap::Parser;
+
+/// The kick command will disconnect an rtsp client from a running
+/// `neolink rtsp` through its control socket
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The address of the client as `ip:port` or `ip` to disconnect all clients from that host
+    pub client: String,
+}
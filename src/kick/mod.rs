@@ -0,0 +1,53 @@
+///
+/// # Neolink Kick
+///
+/// This module handles the kick subcommand
+///
+/// The subcommand asks a running `neolink rtsp` to disconnect a client
+/// and tear down its sessions. This frees the camera stream that a stuck
+/// client would otherwise hold until its session times out.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink kick --config=config.toml CameraName 192.168.1.20:50312
+/// ```
+///
+use anyhow::{anyhow, Result};
+
+mod cmdline;
+
+use crate::common::{ControlReply, ControlRequest, ControlSocket, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the kick subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    if !config.cameras.iter().any(|cam| cam.name == opt.camera) {
+        return Err(anyhow!("Camera `{}` not found in config", opt.camera));
+    }
+    let control = ControlSocket::from_config(&config)
+        .ok_or_else(|| anyhow!("`control_addr` must be set in the config to kick clients"))?;
+
+    let ControlReply::Kicked { count } = control
+        .query(&ControlRequest::Kick {
+            camera: opt.camera.clone(),
+            client: opt.client.clone(),
+        })
+        .await?
+    else {
+        return Err(anyhow!("Unexpected reply from the control socket"));
+    };
+
+    if count == 0 {
+        return Err(anyhow!(
+            "{}: No client connected from {}",
+            opt.camera,
+            opt.client
+        ));
+    }
+    println!("{}: Disconnected {} clients", opt.camera, count);
+    Ok(())
+}
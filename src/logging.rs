@@ -0,0 +1,167 @@
+//! Sets up the logger from the `[logging]` config
+//!
+//! The log always goes to stderr unless turned off and can also go to a
+//! file which is rotated by size and, if asked, each hour or day. Rotated
+//! files are renamed `neolink.log.1`, `neolink.log.2` ... with the oldest
+//! removed once there are more than `keep`
+use anyhow::Context;
+use env_logger::{Builder, Env, Target};
+use log::LevelFilter;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::{LogRotation, LoggingConfig};
+use crate::AnyResult;
+
+/// Start the logger
+///
+/// Without a config this is the same as the plain `RUST_LOG` logger
+pub(crate) fn init_logging(config: Option<&LoggingConfig>) -> AnyResult<()> {
+    let Some(config) = config else {
+        Builder::from_env(Env::default().default_filter_or("info")).init();
+        return Ok(());
+    };
+
+    let mut builder = Builder::new();
+    builder.filter_level(LevelFilter::from_str(&config.level)?);
+    for (module, level) in config.modules.iter() {
+        builder.filter_module(module, LevelFilter::from_str(level)?);
+    }
+    // Still allow a quick `RUST_LOG=debug` without editing the config
+    if let Ok(filters) = std::env::var(env_logger::DEFAULT_FILTER_ENV) {
+        builder.parse_filters(&filters);
+    }
+
+    if let Some(path) = config.file.as_ref() {
+        let file = RotatingFile::open(path, config)?;
+        builder.target(Target::Pipe(Box::new(LogWriter {
+            file,
+            stderr: config.stderr,
+        })));
+    }
+    builder.init();
+    Ok(())
+}
+
+/// Writes each line to the file and to stderr if wanted
+struct LogWriter {
+    file: RotatingFile,
+    stderr: bool,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.stderr {
+            // The log should not stop because stderr is closed
+            let _ = io::stderr().write_all(buf);
+        }
+        if let Err(e) = self.file.write_all(buf) {
+            let _ = writeln!(io::stderr(), "Could not write to the log file: {e}");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    /// Bytes in the current file
+    size: u64,
+    /// Bytes before it is rotated, 0 for no limit
+    max_size: u64,
+    rotate: LogRotation,
+    /// The hour or day of the current file
+    period: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, config: &LoggingConfig) -> AnyResult<Self> {
+        let file =
+            open_append(path).with_context(|| format!("Could not open the log file {path:?}"))?;
+        let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            size,
+            max_size: config.max_size.saturating_mul(1024 * 1024),
+            rotate: config.rotate,
+            period: current_period(config.rotate),
+            keep: config.keep,
+        })
+    }
+
+    fn needs_rotation(&self, incoming: usize) -> bool {
+        let too_big =
+            self.max_size > 0 && self.size > 0 && self.size + incoming as u64 > self.max_size;
+        too_big || current_period(self.rotate) != self.period
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+        } else {
+            let _ = std::fs::remove_file(numbered(&self.path, self.keep));
+            for n in (1..self.keep).rev() {
+                let from = numbered(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(&from, numbered(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, numbered(&self.path, 1))?;
+            self.file = open_append(&self.path)?;
+        }
+        self.size = 0;
+        self.period = current_period(self.rotate);
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `neolink.log` with `n` is `neolink.log.n`
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// The hour or day since the epoch, the file is rotated when it changes
+fn current_period(rotate: LogRotation) -> u64 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    match rotate {
+        LogRotation::Never => 0,
+        LogRotation::Hourly => secs / 3600,
+        LogRotation::Daily => secs / 86400,
+    }
+}
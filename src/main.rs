@@ -31,38 +31,92 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use env_logger::Env;
 use log::*;
-use std::fs;
-use validator::Validate;
+use tokio::time::{timeout_at, Duration, Instant};
 
 mod battery;
+mod camtime;
+mod check_config;
+#[cfg(feature = "gstreamer")]
+mod clients;
 mod cmdline;
 mod common;
 mod config;
+mod config_get;
+mod config_set;
+mod discover;
+mod download;
+mod events;
+mod floodlight;
+mod focus;
 #[cfg(feature = "gstreamer")]
 mod image;
+mod info;
+mod ir;
+#[cfg(feature = "gstreamer")]
+mod kick;
+mod logging;
+mod motion_config;
 mod mqtt;
+#[cfg(feature = "gstreamer")]
+mod onvif;
+mod osd;
 mod pir;
 mod ptz;
+mod quickreply;
 mod reboot;
 #[cfg(feature = "gstreamer")]
+mod record;
+#[cfg(feature = "gstreamer")]
 mod rtsp;
+mod sdcard;
 mod services;
+mod sessions;
+mod siren;
 mod statusled;
 #[cfg(feature = "gstreamer")]
 mod talk;
+mod tracking;
 mod utils;
+mod zoom;
 
 use cmdline::{Command, Opt};
-use common::NeoReactor;
+use common::{serve_api, serve_health, NeoReactor};
 use config::Config;
 
 pub(crate) type AnyResult<T> = Result<T, anyhow::Error>;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+fn main() -> Result<()> {
+    // Before the runtime starts its threads
+    let local_offset = utils::read_local_offset();
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async_main(local_offset))
+}
+
+async fn async_main(local_offset: bool) -> Result<()> {
+    let opt = Opt::parse();
+
+    // Discover is used to start a config so it runs without one
+    if let Some(Command::Discover(opts)) = opt.cmd.as_ref() {
+        logging::init_logging(None)?;
+        return discover::main(opts).await;
+    }
+    // Reports all the problems of the config rather than stopping at the first
+    if let Some(Command::CheckConfig(opts)) = opt.cmd.as_ref() {
+        logging::init_logging(None)?;
+        let conf_path = opt.config.as_ref().context("Must supply --config file")?;
+        return check_config::main(opts, conf_path).await;
+    }
+
+    let conf_path = opt.config.context("Must supply --config file")?;
+    let config = Config::from_file(&conf_path)?;
+
+    logging::init_logging(config.logging.as_ref())?;
+    if !local_offset {
+        warn!("Could not read the local time zone, local times are in UTC");
+    }
 
     info!(
         "Neolink {} {}",
@@ -70,20 +124,64 @@ async fn main() -> Result<()> {
         env!("NEOLINK_PROFILE")
     );
 
-    let opt = Opt::parse();
-
-    let conf_path = opt.config.context("Must supply --config file")?;
-    let config: Config = toml::from_str(
-        &fs::read_to_string(&conf_path)
-            .with_context(|| format!("Failed to read {:?}", conf_path))?,
-    )
-    .with_context(|| format!("Failed to parse the {:?} config file", conf_path))?;
-
-    config
-        .validate()
-        .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
+    let neo_reactor = NeoReactor::new(config.clone(), conf_path.clone()).await;
 
-    let neo_reactor = NeoReactor::new(config.clone()).await;
+    // Answer the health checks, the rest api, systemd and webrtc alongside the long running subcommands
+    //
+    // Without a command it is the rtsp or mqtt service
+    let is_service = match opt.cmd.as_ref() {
+        Some(cmd) => cmd.is_service(),
+        None => true,
+    };
+    if is_service {
+        // Stop the cameras cleanly on SIGINT or SIGTERM, see the end of main
+        let stopping = neo_reactor.stopping();
+        tokio::task::spawn(async move {
+            if let Err(e) = stop_signal().await {
+                error!("Could not listen for the stop signals: {e:?}");
+                return;
+            }
+            info!("Stopping, send the signal again to exit straight away");
+            stopping.cancel();
+            if stop_signal().await.is_ok() {
+                std::process::exit(130);
+            }
+        });
+        if let Some(health_config) = config.health.clone() {
+            let health_reactor = neo_reactor.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = serve_health(health_config, health_reactor).await {
+                    error!("Health server stopped: {e:?}");
+                }
+            });
+        }
+        if let Some(api_config) = config.api.clone() {
+            let api_reactor = neo_reactor.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = serve_api(api_config, api_reactor).await {
+                    error!("Api server stopped: {e:?}");
+                }
+            });
+        }
+        #[cfg(unix)]
+        {
+            let systemd_reactor = neo_reactor.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = common::serve_systemd(systemd_reactor).await {
+                    error!("Systemd notifications stopped: {e:?}");
+                }
+            });
+        }
+        #[cfg(feature = "gstreamer")]
+        if let Some(webrtc_config) = config.webrtc.clone() {
+            let webrtc_reactor = neo_reactor.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = common::serve_whep(webrtc_config, webrtc_reactor).await {
+                    error!("WebRTC server stopped: {e:?}");
+                }
+            });
+        }
+    }
 
     match opt.cmd {
         #[cfg(feature = "gstreamer")]
@@ -110,15 +208,60 @@ async fn main() -> Result<()> {
         Some(Command::StatusLight(opts)) => {
             statusled::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Ir(opts)) => {
+            ir::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::ConfigGet(opts)) => {
+            config_get::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::ConfigSet(opts)) => {
+            config_set::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::MotionConfig(opts)) => {
+            motion_config::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Osd(opts)) => {
+            osd::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Time(opts)) => {
+            camtime::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Sdcard(opts)) => {
+            sdcard::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Reboot(opts)) => {
             reboot::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Floodlight(opts)) => {
+            floodlight::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Siren(opts)) => {
+            siren::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Quickreply(opts)) => {
+            quickreply::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Download(opts)) => {
+            download::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Events(opts)) => {
+            events::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Pir(opts)) => {
             pir::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Ptz(opts)) => {
             ptz::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Zoom(opts)) => {
+            zoom::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Focus(opts)) => {
+            focus::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Tracking(opts)) => {
+            tracking::main(opts, neo_reactor.clone()).await?;
+        }
         #[cfg(feature = "gstreamer")]
         Some(Command::Talk(opts)) => {
             talk::main(opts, neo_reactor.clone()).await?;
@@ -128,22 +271,76 @@ async fn main() -> Result<()> {
         }
         #[cfg(feature = "gstreamer")]
         Some(Command::MqttRtsp(opts)) => {
-            tokio::select! {
-                v = mqtt::main(opts, neo_reactor.clone()) => v,
-                v = rtsp::main(rtsp::Opt {}, neo_reactor.clone()) => v,
-            }?;
+            // Both are waited on so that rtsp can close its clients when stopping
+            tokio::try_join!(
+                mqtt::main(opts, neo_reactor.clone()),
+                rtsp::main(rtsp::Opt {}, neo_reactor.clone()),
+            )?;
         }
         #[cfg(feature = "gstreamer")]
         Some(Command::Image(opts)) => {
             image::main(opts, neo_reactor.clone()).await?;
         }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Record(opts)) => {
+            record::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Info(opts)) => {
+            info::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Battery(opts)) => {
             battery::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Services(opts)) => {
             services::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Sessions(opts)) => {
+            sessions::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Clients(opts)) => {
+            clients::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Kick(opts)) => {
+            kick::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Discover(_)) => unreachable!("Discover has already run"),
+        Some(Command::CheckConfig(_)) => unreachable!("Check config has already run"),
     }
 
+    if is_service {
+        // Give the cameras the chance to finish their recordings before exit
+        let wait = Duration::from_secs(neo_reactor.config().await?.borrow().shutdown_timeout);
+        let deadline = Instant::now() + wait;
+        // The reactor reports on each camera at the deadline, this is in case it cannot
+        if timeout_at(
+            deadline + Duration::from_secs(1),
+            neo_reactor.shutdown(deadline),
+        )
+        .await
+        .is_err()
+        {
+            warn!("The cameras did not stop within {}s", wait.as_secs());
+        }
+        info!("Stopped");
+    }
+
+    Ok(())
+}
+
+/// Resolves on the first SIGINT or SIGTERM
+async fn stop_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            v = tokio::signal::ctrl_c() => v?,
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
     Ok(())
 }
@@ -0,0 +1,34 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The motion-config command will print or change the motion detection
+/// sensitivity and zones
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Turn motion detection on or off
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub enable: Option<bool>,
+    /// The sensitivity from 1 to 50, higher detects smaller movements
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=50))]
+    pub sensitivity: Option<u8>,
+    /// Set the detection zones from this file
+    #[arg(long, value_name = "FILE")]
+    pub zones: Option<PathBuf>,
+    /// Write the detection zones of the camera to this file
+    #[arg(long, value_name = "FILE")]
+    pub export: Option<PathBuf>,
+}
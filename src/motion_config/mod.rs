@@ -0,0 +1,189 @@
+///
+/// # Neolink Motion Config
+///
+/// This module handles the motion detection sensitivity and zones
+///
+/// The zones are a grid of cells that are either detected or ignored. The
+/// zone file has one line per row of the grid and one character per cell,
+/// `#` detects motion in that cell and `.` ignores it. Empty lines and
+/// lines starting with `;` are skipped. The size of the grid depends on
+/// the camera, use `--export` to get a file of the right size to edit.
+///
+/// ```text
+/// ; Ignore the road at the top
+/// ................
+/// ................
+/// ################
+/// ################
+/// ```
+///
+/// # Usage
+///
+/// ```bash
+/// # Print the current settings
+/// neolink motion-config --config=config.toml CameraName
+/// # Save the zones, edit them and send them back
+/// neolink motion-config --config=config.toml CameraName --export zones.txt
+/// neolink motion-config --config=config.toml CameraName --zones zones.txt --sensitivity 30
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::{Md, MdArea};
+use std::path::Path;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the motion-config subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let mut md = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.get_md_alarm()
+                    .await
+                    .context("Unable to get the motion detection settings")
+            })
+        })
+        .await?;
+
+    if opt.enable.is_some() || opt.sensitivity.is_some() || opt.zones.is_some() {
+        if let Some(enable) = opt.enable {
+            md.enable = if enable { 1 } else { 0 };
+        }
+        if let Some(sensitivity) = opt.sensitivity {
+            let sens_list = md
+                .sens_list
+                .as_mut()
+                .ok_or_else(|| anyhow!("The camera did not report a sensitivity to change"))?;
+            for sens in sens_list.sens.iter_mut() {
+                sens.sensitivity = sensitivity;
+            }
+        }
+        if let Some(zones) = opt.zones.as_ref() {
+            let area = md
+                .area
+                .as_mut()
+                .ok_or_else(|| anyhow!("The camera did not report any zones to change"))?;
+            let data = tokio::fs::read_to_string(zones)
+                .await
+                .with_context(|| format!("Could not read the zone file {:?}", zones))?;
+            area.area = parse_zones(&data, area.width, area.height)
+                .with_context(|| format!("Could not understand the zone file {:?}", zones))?;
+        }
+        let set_md = md.clone();
+        camera
+            .run_task(|cam| {
+                let md = set_md.clone();
+                Box::pin(async move {
+                    cam.set_md_alarm(md)
+                        .await
+                        .context("Unable to set the motion detection settings")
+                })
+            })
+            .await?;
+    } else {
+        print_md(&md);
+    }
+
+    if let Some(export) = opt.export.as_ref() {
+        write_zones(&md, export).await?;
+    }
+
+    Ok(())
+}
+
+fn print_md(md: &Md) {
+    println!("enable: {}", if md.enable == 0 { "off" } else { "on" });
+    if let Some(sens_list) = md.sens_list.as_ref() {
+        for sens in sens_list.sens.iter() {
+            println!(
+                "sensitivity: {} ({:02}:{:02}-{:02}:{:02})",
+                sens.sensitivity,
+                sens.begin_hour,
+                sens.begin_minute,
+                sens.end_hour,
+                sens.end_minute
+            );
+        }
+    }
+    if let Some(area) = md.area.as_ref() {
+        println!("zones: {}x{}", area.width, area.height);
+        println!("{}", format_zones(area));
+    }
+}
+
+async fn write_zones(md: &Md, path: &Path) -> Result<()> {
+    let area = md
+        .area
+        .as_ref()
+        .ok_or_else(|| anyhow!("The camera did not report any zones"))?;
+    let data = format!(
+        "; {}x{} motion zones, # detects and . ignores\n{}\n",
+        area.width,
+        area.height,
+        format_zones(area)
+    );
+    tokio::fs::write(path, data)
+        .await
+        .with_context(|| format!("Could not write the zone file {:?}", path))
+}
+
+/// The grid as the lines of a zone file
+fn format_zones(area: &MdArea) -> String {
+    let cells = area
+        .area
+        .chars()
+        .map(|cell| if cell == '0' { '.' } else { '#' })
+        .collect::<Vec<_>>();
+    cells
+        .chunks(area.width.max(1) as usize)
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The lines of a zone file as the grid of the camera
+fn parse_zones(data: &str, width: u32, height: u32) -> Result<String> {
+    let rows = data
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .collect::<Vec<_>>();
+    if rows.len() != height as usize {
+        return Err(anyhow!(
+            "The camera has {} rows of zones but the file has {}",
+            height,
+            rows.len()
+        ));
+    }
+    let mut area = String::with_capacity((width * height) as usize);
+    for (i, row) in rows.iter().enumerate() {
+        if row.chars().count() != width as usize {
+            return Err(anyhow!(
+                "Row {} should have {} cells but it has {}",
+                i + 1,
+                width,
+                row.chars().count()
+            ));
+        }
+        for cell in row.chars() {
+            match cell {
+                '#' | '1' => area.push('1'),
+                '.' | '0' => area.push('0'),
+                _ => {
+                    return Err(anyhow!(
+                        "Row {} has {:?}, cells should be # to detect or . to ignore",
+                        i + 1,
+                        cell
+                    ))
+                }
+            }
+        }
+    }
+    Ok(area)
+}
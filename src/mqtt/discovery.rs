@@ -5,6 +5,7 @@
 use anyhow::{Context, Result};
 use heck::ToTitleCase;
 use log::*;
+use tokio::time::{timeout, Duration};
 
 use super::mqttc::MqttInstance;
 use crate::{common::NeoInstance, config::MqttDiscoveryConfig};
@@ -16,8 +17,14 @@ pub(crate) enum Discoveries {
     Floodlight,
     #[serde(alias = "camera", alias = "preview", alias = "Preview")]
     Camera,
+    #[serde(alias = "snap", alias = "motion_snap")]
+    Snap,
     #[serde(alias = "motion", alias = "md", alias = "pir")]
     Motion,
+    #[serde(alias = "visitor", alias = "doorbell")]
+    Visitor,
+    #[serde(alias = "ai", alias = "person")]
+    Ai,
     #[serde(alias = "led")]
     Led,
     #[serde(alias = "ir")]
@@ -58,6 +65,10 @@ struct DiscoveryDevice {
     model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     sw_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hw_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial_number: Option<String>,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -164,6 +175,8 @@ struct DiscoveryBinarySensor {
     device: DiscoveryDevice,
     availability: DiscoveryAvaliablity,
     // BinarySensor specific
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<String>,
     payload_off: String,
     payload_on: String,
     // - State
@@ -192,7 +205,9 @@ struct DiscoverySensor {
     icon: Option<String>,
     device: DiscoveryDevice,
     availability: DiscoveryAvaliablity,
-    // Button specific
+    // Sensor specific
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<String>,
     state_topic: String,
     state_class: String,
     unit_of_measurement: String,
@@ -208,7 +223,7 @@ pub(crate) async fn enable_discovery(
     debug!("Enabling MQTT discovery for {}", cam_config.name);
 
     let mut connections = vec![];
-    if let Some(addr) = &cam_config.camera_addr {
+    for addr in cam_config.camera_addrs.iter() {
         connections.push(DiscoveryConnection {
             connection_type: "camera_addr".to_string(),
             connection_id: addr.clone(),
@@ -229,20 +244,53 @@ pub(crate) async fn enable_discovery(
         return Ok(());
     }
 
+    // The camera's own details for the device, if it answers in time
+    let version = timeout(
+        Duration::from_secs(10),
+        camera.run_task(|cam| Box::pin(async move { Ok(cam.version().await?) })),
+    )
+    .await
+    .ok()
+    .and_then(|version| version.ok());
+    if version.is_none() {
+        debug!(
+            "{}: Camera info not available, using defaults for the discovery device",
+            cam_config.name
+        );
+    }
+
     let friendly_name = cam_config.name.replace('_', " ").to_title_case();
     let device = DiscoveryDevice {
         name: friendly_name.clone(),
         connections,
         identifiers: vec![format!("neolink_{}", cam_config.name)],
         manufacturer: Some("Reolink".to_string()),
-        model: Some("Neolink".to_string()),
-        sw_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        model: Some(
+            version
+                .as_ref()
+                .and_then(|version| version.model.clone())
+                .unwrap_or("Neolink".to_string()),
+        ),
+        sw_version: Some(
+            version
+                .as_ref()
+                .map(|version| version.firmwareVersion.clone())
+                .unwrap_or(env!("CARGO_PKG_VERSION").to_string()),
+        ),
+        hw_version: version
+            .as_ref()
+            .map(|version| version.hardwareVersion.clone())
+            .filter(|hw| !hw.is_empty()),
+        serial_number: version
+            .as_ref()
+            .map(|version| version.serialNumber.clone())
+            .filter(|serial| !serial.is_empty()),
     };
 
     let availability = DiscoveryAvaliablity {
         topic: format!("neolink/{}/status", cam_config.name),
         payload_available: Some("connected".to_string()),
-        payload_not_available: None,
+        payload_not_available: Some("disconnected".to_string()),
     };
 
     for feature in &discovery_config.features {
@@ -369,6 +417,40 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Snap => {
+                let config_data = DiscoveryCamera {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} Motion Snapshot", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_snap", cam_config.name),
+                    icon: Some("mdi:camera-burst".to_string()),
+
+                    // Camera specific
+                    topic: format!("neolink/{}/status/snap", cam_config.name),
+                    image_encoding: Encoding::Base64,
+                };
+
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/camera/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data)
+                        .with_context(|| "Cound not serialise discovery snap config into json")?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish snap auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
             Discoveries::Led => {
                 let config_data = DiscoverySwitch {
                     // Common across all potential features
@@ -422,7 +504,7 @@ pub(crate) async fn enable_discovery(
                     // Switch specific
                     command_topic: format!("neolink/{}/control/ir", cam_config.name),
                     options: vec!["on".to_string(), "off".to_string(), "auto".to_string()],
-                    state_topic: None,
+                    state_topic: Some(format!("neolink/{}/status/ir", cam_config.name)),
                 };
 
                 // Each feature needs to be individually registered
@@ -455,7 +537,8 @@ pub(crate) async fn enable_discovery(
                     unique_id: format!("neolink_{}_md", cam_config.name),
                     icon: Some("mdi:motion-sensor".to_string()),
 
-                    // Switch specific
+                    // Binary sensor specific
+                    device_class: Some("motion".to_string()),
                     state_topic: format!("neolink/{}/status/motion", cam_config.name),
                     payload_off: "off".to_string(),
                     payload_on: "on".to_string(),
@@ -480,6 +563,87 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Visitor => {
+                let config_data = DiscoveryBinarySensor {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} Visitor", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_visitor", cam_config.name),
+                    icon: Some("mdi:doorbell".to_string()),
+
+                    // Binary sensor specific
+                    device_class: None,
+                    state_topic: format!("neolink/{}/status/visitor", cam_config.name),
+                    payload_off: "off".to_string(),
+                    payload_on: "on".to_string(),
+                };
+
+                // Each feature needs to be individually registered
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/binary_sensor/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data).with_context(|| {
+                        "Cound not serialise discovery visitor config into json"
+                    })?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish visitor auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
+            Discoveries::Ai => {
+                for (class, icon, device_class) in [
+                    ("person", "mdi:account", Some("occupancy")),
+                    ("vehicle", "mdi:car", Some("motion")),
+                    ("pet", "mdi:paw", Some("motion")),
+                ] {
+                    let config_data = DiscoveryBinarySensor {
+                        // Common across all potential features
+                        device: device.clone(),
+                        availability: availability.clone(),
+
+                        // Identifiers
+                        name: format!("{} {}", friendly_name.as_str(), class.to_title_case()),
+                        unique_id: format!("neolink_{}_ai_{}", cam_config.name, class),
+                        icon: Some(icon.to_string()),
+
+                        // Binary sensor specific
+                        device_class: device_class.map(|device_class| device_class.to_string()),
+                        state_topic: format!("neolink/{}/status/ai/{}", cam_config.name, class),
+                        payload_off: "off".to_string(),
+                        payload_on: "on".to_string(),
+                    };
+
+                    // Each feature needs to be individually registered
+                    mqtt.send_message_with_root_topic(
+                        &format!(
+                            "{}/binary_sensor/{}",
+                            discovery_config.topic, &config_data.unique_id
+                        ),
+                        "config",
+                        &serde_json::to_string(&config_data)
+                            .with_context(|| "Cound not serialise discovery ai config into json")?,
+                        true,
+                    )
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "Failed to publish ai auto-discover data on over MQTT for {}",
+                            cam_config.name
+                        )
+                    })?;
+                }
+            }
             Discoveries::Reboot => {
                 let config_data = DiscoveryButton {
                     // Common across all potential features
@@ -563,7 +727,8 @@ pub(crate) async fn enable_discovery(
                     unique_id: format!("neolink_{}_battery", cam_config.name),
                     icon: Some("mdi:battery".to_string()),
 
-                    // Camera specific
+                    // Sensor specific
+                    device_class: Some("battery".to_string()),
                     state_topic: format!("neolink/{}/status/battery_level", cam_config.name),
                     state_class: "measurement".to_string(),
                     unit_of_measurement: "%".to_string(),
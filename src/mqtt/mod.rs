@@ -10,29 +10,77 @@
 //! Control messages:
 //!
 //! - `/control/floodlight [on|off]` Turns floodlight (if equipped) on/off
+//! - `/control/floodlight_tasks [on|off]` Turns the floodlight on with motion at night
+//! - `/control/floodlight/brightness [1-100]` Sets the floodlight brightness in %
 //! - `/control/led [on|off]` Turns status LED on/off
 //! - `/control/pir [on|off]` Turns PIR on/off
+//! - `/control/tracking [on|off]` Turns the AI auto tracking on/off
 //! - `/control/ir [on|off|auto]` Turn IR lights on/off or automatically via light detection
-//! - `/control/reboot` Reboot the camera
-//! - `/control/ptz` [up|down|left|right|in|out] (amount) Control the PTZ movements, amount defaults to 32.0
-//! - `/control/ptz/preset` [id] Move the camera to a known preset
+//! - `/control/siren [on|off|secs]` Play the siren once, stop it or keep it on for up to 120 seconds
+//! - `/control/quickreply [id]` Play a quick reply message stored on a doorbell
+//! - `/control/say [text]` Say the text on the camera speaker (requires the espeak gstreamer plugin)
+//! - `/control/play [file|url]` Play an audio file from `play_allowed` on the camera speaker
+//! - `/control/pause` `/control/resume` Disconnect from the camera and connect again
+//! - `/control/disable` `/control/enable` Disconnect from the camera until it is enabled, even when
+//!   a client wants it
+//! - `/control/reboot` Reboot the camera (send `safe` to first wait up to
+//!   120s for it to finish recording)
+//! - `/control/ptz` [up|down|left|right|stop] (amount) (speed) Control the PTZ movements, amount and speed default to 32.0
+//! - `/control/ptz/preset` [id|goto id|save id name] Move the camera to a known preset or save one
 //! - `/control/ptz/assign` [id] [name] Assign the current ptz position to an ID and name
+//! - `/control/zoom` (amount) Zoom a motorized lens, 1.0 is no zoom
+//! - `/control/focus` (position) Move the focus of a motorized lens
 //!
 //! Status Messages:
 //!
 //! `/status offline` Sent when the neolink goes offline this is a LastWill message
 //! `/status disconnected` Sent when the camera goes offline
+//! `/status/availability [online|offline]` `online` while neolink is connected to
+//!    the broker for this camera, `offline` is its LastWill message
+//! `/status/info` The model, firmware, hardware, serial and stream encoding as
+//!    json, sent when the camera connects
 //! `/status/battery` Sent in reply to a `/query/battery`
+//! `/status/battery_level` The battery percent, sent every `battery_update` ms
+//! `/status/battery_low [on|off]` Sent when the battery goes below or back above
+//!    `battery_low` percent
 //! `/status/pir` Sent in reply to a `/query/pir`
+//! `/status/ir [on|off|auto]` Sent in reply to a `/query/ir` and after a `/control/ir`
 //! `/status/ptz/preset` Sent in reply to a `/query/ptz/preset`
+//! `/status/sdcard` Sent in reply to a `/query/sdcard`
+//! `/status/motion [on|off]` Sent when motion starts and once it has been still
+//!    for `motion_cooldown` ms (requires `enable_motion`)
+//! `/status/last_motion` The unix time of the latest motion (requires `enable_motion`)
+//! `/status/recording [on|off]` Sent when the camera starts or stops writing a clip
+//!    to its storage (requires `enable_motion`)
+//! `/status/visitor [on|off]` Sent when the doorbell button is pressed or released
+//!    (requires `enable_motion`)
+//! `/status/ai/person`, `/status/ai/vehicle` and `/status/ai/pet` `[on|off]` Sent when
+//!    the AI of the camera starts or stops detecting one (requires `enable_motion`)
+//! `/status/snap` A base64 encoded jpeg sent when motion starts (requires `enable_motion_snap`)
+//! `/status/sound [on|off]` Sent when the audio detection hears a sound
+//! `/status/sound_level` Sent in reply to a `/query/sound_level`
+//! `/status/clients` The number of rtsp clients watching the camera, this is only
+//!    updated by `neolink mqtt-rtsp` where the rtsp server is in the same process
+//! `/status/fallback [on|off]` `on` while the sub stream is sent in place of the
+//!    main stream (requires `fallback_to_sub` and `neolink mqtt-rtsp`)
+//! `/status/stats/{main,sub,extern}` The fps, kbps, seconds between keyframes and
+//!    dropped buffers of the rtsp stream as json, sent every `stats_interval`
+//!    seconds by `neolink mqtt-rtsp`
 //!
 //! Query Messages:
 //!
 //! `/query/battery` Request that the camera reports its battery level
 //! `/query/pir` Request that the camera reports its pir status
+//! `/query/ir` Request that the camera reports the state of its IR lights
 //! `/query/ptz/preset` Request that the camera reports the PTZ presets
+//! `/query/sdcard` Request that the camera reports its SD cards and their space
+//! `/query/sound_level` Request the current audio level in dB, useful to choose
+//!    an `audio_detect` threshold
 //! `/query/preview` Request that the camera post a base64 encoded jpeg
 //!    of the stream to `/status/preview`
+//! `/query/raw/{msg_id}` Send the message body as the xml of a BC message with
+//!    this id and post the xml of the reply to `/status/raw/{msg_id}`
+//!    (requires `enable_raw`)
 //!
 //!
 //! # Usage
@@ -60,24 +108,27 @@
 //! `credentials` are the username and password required to identify with the mqtt server
 //!
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::{
     sync::mpsc::channel as mpsc,
     task::JoinSet,
-    time::{interval, sleep, Duration, MissedTickBehavior},
+    time::{interval, sleep, timeout, Duration, MissedTickBehavior},
 };
 use tokio_stream::{wrappers::IntervalStream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use validator::Validate;
 
-use neolink_core::bc_protocol::{Direction as BcDirection, LightState};
-
 mod cmdline;
 mod discovery;
 mod mqttc;
 
+#[cfg(feature = "gstreamer")]
+use crate::common::SoundState;
 use crate::{
-    common::{MdState, NeoInstance, NeoReactor},
+    common::{device_info, run_control, MdState, NeoInstance, NeoReactor, StreamStatsMap},
     config::Config,
     AnyResult,
 };
@@ -86,12 +137,16 @@ pub(crate) use cmdline::Opt;
 pub(crate) use discovery::Discoveries;
 use log::*;
 use mqttc::{Mqtt, MqttReplyRef};
+use neolink_core::bc_protocol::{AiDetection, LightState, StreamKind};
 
 use self::{
     discovery::enable_discovery,
     mqttc::{MqttInstance, MqttReply},
 };
 
+/// How long a camera that failed waits before it is started again
+const CAMERA_RESTART_DELAY: Duration = Duration::from_secs(5);
+
 /// Entry point for the mqtt subcommand
 ///
 /// Opt is the command line options
@@ -133,9 +188,9 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                             let mqtt_instance = thread_instance.subscribe(name).await?;
                             let name = name.clone();
                             set.spawn(async move {
+                                // Each camera is started again on its own when it fails
+                                // so that it does not hold up the others
                                 loop {
-                                    let camera = thread_reactor2.get(&name).await?;
-                                    let mqtt_instance = mqtt_instance.resubscribe().await?;
                                     let r = tokio::select!{
                                         _ = thread_global_cancel.cancelled() => {
                                             AnyResult::Ok(())
@@ -143,25 +198,35 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                                         _ = local_cancel.cancelled() => {
                                             AnyResult::Ok(())
                                         },
-                                        v = listen_on_camera(camera, mqtt_instance) => {
+                                        v = async {
+                                            let mqtt_instance = mqtt_instance.resubscribe().await?;
+                                            listen_on_camera(thread_reactor2.get(&name).await?, mqtt_instance).await
+                                        } => {
                                             v
                                         },
                                     };
-                                    if let Ok(()) = &r {
-                                        break r
-                                    } else {
-                                        continue;
+                                    match r {
+                                        Ok(()) => break AnyResult::Ok(()),
+                                        Err(e) => log::warn!("{name}: MQTT failed, restarting in {}s: {e:?}", CAMERA_RESTART_DELAY.as_secs()),
+                                    }
+                                    tokio::select!{
+                                        _ = thread_global_cancel.cancelled() => break AnyResult::Ok(()),
+                                        _ = local_cancel.cancelled() => break AnyResult::Ok(()),
+                                        _ = sleep(CAMERA_RESTART_DELAY) => {},
                                     }
                                 }
                             }) ;
                         }
                     }
 
-                    for (running_name, token) in cameras.iter() {
-                        if ! config_names.contains(running_name) {
+                    // Forget the removed cameras so that they start again if they come back
+                    cameras.retain(|running_name, token| {
+                        let keep = config_names.contains(running_name);
+                        if !keep {
                             token.cancel();
                         }
-                    }
+                        keep
+                    });
                 }
             } => v,
         }
@@ -272,6 +337,17 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // Stop the cameras' tasks when neolink is stopping
+    let stopping = reactor.stopping();
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {},
+            _ = stopping.cancelled() => thread_cancel.cancel(),
+        }
+        AnyResult::Ok(())
+    });
+
     while let Some(result) = set.join_next().await {
         if let Err(_) | Ok(Err(_)) = &result {
             global_cancel.cancel();
@@ -303,6 +379,7 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         .await
                         .with_context(|| format!("Failed to publish status for {}", camera_name))?;
                 let _drop_message = mqtt_instance.last_will("status", "disconnected").await?;
+                let _availability = mqtt_instance.availability().await?;
                 mqtt_instance
                     .send_message("status/motion", "unknown", true)
                     .await
@@ -323,6 +400,7 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let mut set_msg = JoinSet::new();
 
                 let mut camera_watch = camera.camera();
+                let camera_info = camera.clone();
                 let mqtt_watch = mqtt_instance.resubscribe().await?;
 
                 let camera_floodlight = camera.clone();
@@ -331,6 +409,32 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let camera_motion = camera.clone();
                 let mqtt_motion = mqtt_instance.resubscribe().await?;
 
+                let camera_motion_snap = camera.clone();
+                let mqtt_motion_snap = mqtt_instance.resubscribe().await?;
+
+                let camera_recording = camera.clone();
+                let mqtt_recording = mqtt_instance.resubscribe().await?;
+
+                let camera_visitor = camera.clone();
+                let mqtt_visitor = mqtt_instance.resubscribe().await?;
+
+                let camera_ai = camera.clone();
+                let mqtt_ai = mqtt_instance.resubscribe().await?;
+
+                let camera_clients = camera.clone();
+                let mqtt_clients = mqtt_instance.resubscribe().await?;
+
+                let camera_stats = camera.clone();
+                let mqtt_stats = mqtt_instance.resubscribe().await?;
+
+                let camera_fallback = camera.clone();
+                let mqtt_fallback = mqtt_instance.resubscribe().await?;
+
+                #[cfg(feature = "gstreamer")]
+                let camera_sound = camera.clone();
+                #[cfg(feature = "gstreamer")]
+                let mqtt_sound = mqtt_instance.resubscribe().await?;
+
                 #[cfg(feature = "pushnoti")]
                 let camera_pn = camera.clone();
                 #[cfg(feature = "pushnoti")]
@@ -391,6 +495,16 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             mqtt_watch.send_message("status", "connected", true).await.with_context(|| {
                                 format!("{}: Failed to publish connected", camera_name)
                             })?;
+                            // Do not hold up the disconnect if the camera drops while we ask
+                            match timeout(Duration::from_secs(10), device_info(&camera_info)).await {
+                                Ok(Ok(info)) => {
+                                    mqtt_watch.send_message("status/info", &serde_json::to_string(&info)?, true).await.with_context(|| {
+                                        format!("{}: Failed to publish info", camera_name)
+                                    })?;
+                                }
+                                Ok(Err(e)) => log::debug!("{}: Could not get the camera info: {:?}", camera_name, e),
+                                Err(_) => log::debug!("{}: Timed out getting the camera info", camera_name),
+                            }
                             camera_watch.wait_for(|cam| cam.upgrade().is_none()).await.with_context(|| {
                                 format!("{}: Disconnect Watch Dropped", camera_name)
                             })?;
@@ -460,9 +574,28 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                 mqtt_motion.send_message("status/motion", "on", true).await.with_context(|| {
                                     format!("{}: Failed to publish motion start", camera_name)
                                 })?;
-                                md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
-                                    format!("{}: MdStop Watch Dropped", camera_name)
-                                })?;
+                                publish_last_motion(&mqtt_motion).await?;
+                                loop {
+                                    md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
+                                        format!("{}: MdStop Watch Dropped", camera_name)
+                                    })?;
+                                    // Motion that starts again within the cool down keeps it on
+                                    let retriggered = tokio::select! {
+                                        _ = sleep(Duration::from_millis(config.motion_cooldown)) => false,
+                                        v = async {
+                                            md.wait_for(|state| matches!(state, MdState::Start(_))).await.map(|_| ())
+                                        } => {
+                                            v.with_context(|| {
+                                                format!("{}: MdStart Watch Dropped", camera_name)
+                                            })?;
+                                            true
+                                        }
+                                    };
+                                    if !retriggered {
+                                        break;
+                                    }
+                                    publish_last_motion(&mqtt_motion).await?;
+                                }
                                 mqtt_motion.send_message("status/motion", "off", true).await.with_context(|| {
                                     format!("{}: Failed to publish motion stop", camera_name)
                                 })?;
@@ -476,6 +609,155 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             }?;
                         }
                     }, if config.enable_motion => v,
+                    // Handle the snapshot on motion
+                    v = async {
+                        let mut md = camera_motion_snap.motion().await?;
+                        loop {
+                            md.wait_for(|state| matches!(state, MdState::Start(_))).await.with_context(|| {
+                                format!("{}: MdStart Watch Dropped", camera_name)
+                            })?;
+                            match motion_snapshot(&camera_motion_snap).await {
+                                Ok(image) => {
+                                    mqtt_motion_snap.send_message("status/snap", BASE64.encode(image).as_str(), true).await.with_context(|| {
+                                        format!("{}: Failed to publish motion snapshot", camera_name)
+                                    })?;
+                                }
+                                Err(e) => log::debug!("{}: Could not get a snapshot of the motion: {e:?}", camera_name),
+                            }
+                            md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
+                                format!("{}: MdStop Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion_snap => v,
+                    // Handle the recording status, this comes with the motion messages
+                    v = async {
+                        let mut recording = camera_recording.recording().await?;
+                        loop {
+                            let state = *recording.borrow_and_update();
+                            if let Some(state) = state {
+                                mqtt_recording.send_message("status/recording", if state { "on" } else { "off" }, true).await.with_context(|| {
+                                    format!("{}: Failed to publish recording status", camera_name)
+                                })?;
+                            }
+                            recording.changed().await.with_context(|| {
+                                format!("{}: Recording Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle the doorbell button, this also comes with the motion messages
+                    v = async {
+                        let mut visitor = camera_visitor.visitor().await?;
+                        loop {
+                            let state = *visitor.borrow_and_update();
+                            if let Some(state) = state {
+                                mqtt_visitor.send_message("status/visitor", if state { "on" } else { "off" }, true).await.with_context(|| {
+                                    format!("{}: Failed to publish visitor status", camera_name)
+                                })?;
+                            }
+                            visitor.changed().await.with_context(|| {
+                                format!("{}: Visitor Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle what the AI classified the motion as, this also comes with the motion messages
+                    v = async {
+                        let mut ai = camera_ai.ai().await?;
+                        let mut last: Option<AiDetection> = None;
+                        loop {
+                            let detection = *ai.borrow_and_update();
+                            if let Some(detection) = detection {
+                                for (class, detected, was) in [
+                                    ("person", detection.person, last.map(|last| last.person)),
+                                    ("vehicle", detection.vehicle, last.map(|last| last.vehicle)),
+                                    ("pet", detection.pet, last.map(|last| last.pet)),
+                                ] {
+                                    // Only publish the classes that changed
+                                    if was != Some(detected) {
+                                        mqtt_ai.send_message(&format!("status/ai/{class}"), if detected { "on" } else { "off" }, true).await.with_context(|| {
+                                            format!("{}: Failed to publish ai {class} status", camera_name)
+                                        })?;
+                                    }
+                                }
+                                last = Some(detection);
+                            }
+                            ai.changed().await.with_context(|| {
+                                format!("{}: AI Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle the number of rtsp clients watching
+                    v = async {
+                        let mut clients = camera_clients.rtsp_clients().await?;
+                        loop {
+                            let count = *clients.borrow_and_update();
+                            mqtt_clients.send_message("status/clients", &count.to_string(), true).await.with_context(|| {
+                                format!("{}: Failed to publish rtsp clients", camera_name)
+                            })?;
+                            clients.changed().await.with_context(|| {
+                                format!("{}: Rtsp Clients Watch Dropped", camera_name)
+                            })?;
+                        }
+                    } => v,
+                    // Handle the stats of the rtsp streams
+                    v = async {
+                        let mut stats = camera_stats.stream_stats().await?;
+                        let mut last = StreamStatsMap::new();
+                        loop {
+                            let current = stats.borrow_and_update().clone();
+                            for (stream, stream_stats) in current.iter() {
+                                if last.get(stream) != Some(stream_stats) {
+                                    let stream_name = match stream {
+                                        StreamKind::Main => "main",
+                                        StreamKind::Sub => "sub",
+                                        StreamKind::Extern => "extern",
+                                    };
+                                    mqtt_stats.send_message(&format!("status/stats/{stream_name}"), &serde_json::to_string(stream_stats)?, false).await.with_context(|| {
+                                        format!("{}: Failed to publish stream stats", camera_name)
+                                    })?;
+                                }
+                            }
+                            last = current;
+                            stats.changed().await.with_context(|| {
+                                format!("{}: Stream Stats Watch Dropped", camera_name)
+                            })?;
+                        }
+                    } => v,
+                    // Handle the main stream falling back to the sub stream
+                    v = async {
+                        let mut fallback = camera_fallback.fallback().await?;
+                        loop {
+                            let falling_back = fallback.borrow_and_update().is_some();
+                            mqtt_fallback.send_message("status/fallback", if falling_back { "on" } else { "off" }, true).await.with_context(|| {
+                                format!("{}: Failed to publish fallback", camera_name)
+                            })?;
+                            fallback.changed().await.with_context(|| {
+                                format!("{}: Fallback Watch Dropped", camera_name)
+                            })?;
+                        }
+                    } => v,
+                    // Handle the sound messages from the audio detection
+                    v = async {
+                        #[cfg(feature = "gstreamer")]
+                        {
+                            let mut sound = camera_sound.sound().await?;
+                            loop {
+                                sound.wait_for(|state| matches!(state, SoundState::Start(_))).await.with_context(|| {
+                                    format!("{}: SoundStart Watch Dropped", camera_name)
+                                })?;
+                                mqtt_sound.send_message("status/sound", "on", true).await.with_context(|| {
+                                    format!("{}: Failed to publish sound start", camera_name)
+                                })?;
+                                sound.wait_for(|state| matches!(state, SoundState::Stop(_))).await.with_context(|| {
+                                    format!("{}: SoundStop Watch Dropped", camera_name)
+                                })?;
+                                mqtt_sound.send_message("status/sound", "off", true).await.with_context(|| {
+                                    format!("{}: Failed to publish sound stop", camera_name)
+                                })?;
+                            }
+                        }
+                        #[cfg(not(feature = "gstreamer"))]
+                        unreachable!()
+                    }, if cfg!(feature = "gstreamer") => v,
                     // Handle the SNAP (image preview)
                     v = async {
                         let mut wait = IntervalStream::new({
@@ -528,6 +810,7 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         });
 
                         let v = async {
+                            let mut was_low = None;
                             while wait.next().await.is_some() {
                                 let xml = camera_battery.run_passive_task(|cam| {
                                     Box::pin(async move {
@@ -552,6 +835,21 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                         .with_context(|| {
                                             format!("{}: Failed to publish battery", camera_name)
                                         })?;
+                                if let Some(battery_low) = config.battery_low {
+                                    let is_low = xml.battery_percent < battery_low;
+                                    if was_low != Some(is_low) {
+                                        if is_low {
+                                            log::warn!("{}: Battery is low at {}%", camera_name, xml.battery_percent);
+                                        }
+                                        mqtt_battery
+                                            .send_message("status/battery_low", if is_low { "on" } else { "off" }, true)
+                                            .await
+                                            .with_context(|| {
+                                                format!("{}: Failed to publish battery low", camera_name)
+                                            })?;
+                                        was_low = Some(is_low);
+                                    }
+                                }
                             }
                             AnyResult::Ok(())
                         }.await;
@@ -641,6 +939,34 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
     Ok(())
 }
 
+/// Publish the unix time of the latest motion to `status/last_motion`
+async fn publish_last_motion(mqtt: &MqttInstance) -> AnyResult<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    mqtt.send_message("status/last_motion", &now.to_string(), true)
+        .await
+        .context("Failed to publish the last motion")
+}
+
+/// A jpeg from the camera or, when it cannot snap, from the stream
+async fn motion_snapshot(camera: &NeoInstance) -> AnyResult<Vec<u8>> {
+    let image = camera
+        .run_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+        .await;
+    #[cfg(feature = "gstreamer")]
+    let image = match image {
+        Err(e) => {
+            log::debug!("Snapshot failed, using the stream instead: {e:?}");
+            crate::image::jpeg_from_stream(camera, neolink_core::bc_protocol::StreamKind::Main)
+                .await
+        }
+        image => image,
+    };
+    image
+}
+
 async fn handle_mqtt_message(
     msg: MqttReply,
     mqtt: &MqttInstance,
@@ -663,505 +989,53 @@ async fn handle_mqtt_message(
             // Do nothing for the success/fail replies
         }
         MqttReplyRef {
-            topic: "control/floodlight",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(true, 180).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/floodlight", &reply, false)
-                .await
-                .with_context(|| "Failed to publish camera status light on")?;
-        }
-        MqttReplyRef {
-            topic: "control/floodlight",
-            message: "off",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(false, 180).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/floodlight", &reply, false)
-                .await
-                .with_context(|| "Failed to publish camera status light off")?;
-        }
-        MqttReplyRef {
-            topic: "control/led",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.led_light_set(true).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/led", &reply, false)
-                .await
-                .with_context(|| "Failed to publish led on")?;
-        }
-        MqttReplyRef {
-            topic: "control/led",
-            message: "off",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.led_light_set(false).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/led", &reply, false)
-                .await
-                .with_context(|| "Failed to publish led off")?;
-        }
-        MqttReplyRef {
-            topic: "control/ir",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::On).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the ir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ir on")?;
-        }
-        MqttReplyRef {
-            topic: "control/ir",
-            message: "off",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::Off).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the ir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ir off")?;
-        }
-        MqttReplyRef {
-            topic: "control/ir",
-            message: "auto",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.irled_light_set(LightState::Auto).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn set to auto on the led: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ir auto")?;
-        }
-        MqttReplyRef {
-            topic: "control/reboot",
-            ..
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.reboot().await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to reboot the camera: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish reboot on the camera")?;
-        }
-        MqttReplyRef {
-            topic: "control/zoom",
+            topic: "control/siren",
             message,
-        } => {
-            let reply = if let Ok(amount) = message.parse::<f32>() {
-                if let Err(e) = camera
-                    .run_task(|cam| {
-                        Box::pin(async move {
-                            cam.zoom_to((amount * 1000.0) as u32).await?;
-                            AnyResult::Ok(())
-                        })
-                    })
-                    .await
-                {
-                    error!("Failed to send PTZ: {:?}", e);
+        } if message.parse::<u64>().is_ok_and(|secs| secs > 0) => {
+            let duration = Duration::from_secs(message.parse::<u64>()?);
+            // Turned off in the background so that other messages are not held up
+            let siren_camera = camera.clone();
+            let siren_mqtt = mqtt.resubscribe().await?;
+            tokio::task::spawn(async move {
+                let reply = if let Err(e) = crate::siren::siren_for(&siren_camera, duration).await {
+                    error!("Failed to sound siren: {:?}", e);
                     format!("FAIL: {e:?}")
                 } else {
                     "OK".to_string()
-                }
-            } else {
-                "FAIL: Could not convert message to number".to_string()
-            };
-
-            mqtt.send_message("control/zoom", &reply, false)
-                .await
-                .with_context(|| "Failed to publish zoom on the camera")?;
-        }
-        MqttReplyRef {
-            topic: "control/ptz",
-            message,
-        }
-        | MqttReplyRef {
-            topic: "control/pt",
-            message,
-        } => {
-            let lowercase_message = message.to_lowercase();
-            let mut words = lowercase_message.split_whitespace();
-            let reply = if let Some(direction_txt) = words.next() {
-                // Target amount to move
-                let speed = 32f32;
-                let amount = words.next().unwrap_or("32.0");
-
-                if let Ok(amount) = amount.parse::<f32>() {
-                    let seconds = amount / speed;
-                    // range checking on seconds so that you can't sleep for 3.4E+38 seconds
-                    let seconds = match seconds {
-                        x if (0.0..10.0).contains(&x) => Some(seconds),
-                        _ => {
-                            error!("seconds was not a valid number (out of range)");
-                            None
-                        }
-                    };
-
-                    let bc_direction = match direction_txt {
-                        "up" => Some(BcDirection::Up),
-                        "down" => Some(BcDirection::Down),
-                        "left" => Some(BcDirection::Left),
-                        "right" => Some(BcDirection::Right),
-                        n => {
-                            error!("Unrecognized PTZ direction \"{}\"", n);
-                            None
-                        }
-                    };
-
-                    if let (Some(seconds), Some(bc_direction)) = (seconds, bc_direction) {
-                        // On drop send the stop command again just to make sure it stops
-                        let _drop_command = camera.clone().drop_command(
-                            move |cam| {
-                                Box::pin(async move {
-                                    cam.send_ptz(BcDirection::Stop, speed).await?;
-                                    AnyResult::Ok(())
-                                })
-                            },
-                            Duration::from_millis(100),
-                        );
-                        if let Err(e) = camera
-                            .run_task(|cam| {
-                                Box::pin(async move {
-                                    cam.send_ptz(bc_direction, speed).await?;
-                                    sleep(Duration::from_secs_f32(seconds)).await;
-                                    cam.send_ptz(BcDirection::Stop, speed).await?;
-                                    AnyResult::Ok(())
-                                })
-                            })
-                            .await
-                        {
-                            error!("Failed to send PTZ: {:?}", e);
-                            "FAIL"
-                        } else {
-                            "OK"
-                        }
-                    } else {
-                        "FAIL"
-                    }
-                } else {
-                    error!("No PTZ speed as a valid number");
-                    "FAIL"
-                }
-            } else {
-                error!("No PTZ Direction given. Please add up/down/left/right/in/out");
-                "FAIL"
-            }
-            .to_string();
-
-            mqtt.send_message("control/ptz", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ptz on the camera")?;
-        }
-        MqttReplyRef {
-            topic: "control/ptz/preset",
-            message,
-        } => {
-            let reply = if let Ok(id) = message.parse::<u8>() {
-                let res = camera
-                    .run_task(|cam| {
-                        Box::pin(async move {
-                            cam.moveto_ptz_preset(id).await?;
-                            AnyResult::Ok(())
-                        })
-                    })
-                    .await;
-                if res.is_err() {
-                    error!("Failed to move to ptz preset: {:?}", res.err());
-                    "FAIL"
-                } else {
-                    "OK"
-                }
-            } else {
-                error!("PTZ preset was not a valid number");
-                "FAIL"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ptz move")?;
-        }
-        MqttReplyRef {
-            topic: "control/ptz/assign",
-            message,
-        } => {
-            let mut words = message.split_whitespace();
-            let id = words.next();
-            let name = words.next();
-
-            let reply = if let (Some(Ok(id)), Some(name)) = (id.map(|id| id.parse::<u8>()), name) {
-                let name = name.to_owned();
-                let res = camera
-                    .run_task(|cam| {
-                        let name = name.clone();
-                        Box::pin(async move {
-                            cam.set_ptz_preset(id, name).await?;
-                            AnyResult::Ok(())
-                        })
-                    })
+                };
+                let _ = siren_mqtt
+                    .send_message("control/siren", &reply, false)
                     .await;
-                if res.is_err() {
-                    error!("Failed to assign ptz preset: {:?}", res.err());
-                    "FAIL"
-                } else {
-                    "OK"
-                }
-            } else if let (Some(Err(_)), _) = (id.map(|id| id.parse::<u8>()), name) {
-                error!("PTZ preset was not a valid number");
-                "FAIL"
-            } else if let (_, None) = (id.map(|id| id.parse::<u8>()), name) {
-                error!("PTZ preset was not given a name");
-                "FAIL"
-            } else {
-                "FAIL"
-            }
-            .to_string();
-            mqtt.send_message("control/ir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish ptz move")?;
-        }
-        MqttReplyRef {
-            topic: "control/pir",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.pir_set(true).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the pir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/pir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish pir on")?;
-        }
-        MqttReplyRef {
-            topic: "control/pir",
-            message: "off",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.pir_set(false).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the pir: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/pir", &reply, false)
-                .await
-                .with_context(|| "Failed to publish pir off")?;
+            });
         }
         MqttReplyRef {
-            topic: "control/wakeup",
+            topic: topic @ ("control/say" | "control/play"),
             message,
         } => {
-            let reply = match message.parse::<u64>() {
-                Ok(secs) => {
-                    if let Ok(permit) = camera.permit().await {
-                        // This task waits for the `run_task` to send the OK then starts the countdown
-                        // to drop the permit
-                        let camera = camera.clone();
-                        tokio::task::spawn(async move {
-                            // Wait for connection then start the countdown
-                            // By using a run_task we can delay the countdown until AFTER we are connected
-                            let _ = camera
-                                .run_task(|_cam| Box::pin(async move { AnyResult::Ok(()) }))
-                                .await;
-
-                            sleep(Duration::from_secs(secs * 60)).await;
-
-                            drop(permit);
-                        });
-                        "OK"
-                    } else {
-                        "FAIL: Camera shutting down"
-                    }
-                    .to_string()
-                }
-                Err(e) => {
-                    error!("Failed to parse minutes: {:?}", e);
-                    format!("FAIL: '{message}' => {e:?}")
+            // Played in the background so that other messages are not held up
+            let talk_camera = camera.clone();
+            let talk_mqtt = mqtt.resubscribe().await?;
+            let topic = topic.to_string();
+            let message = message.to_string();
+            tokio::task::spawn(async move {
+                if let Some(reply) =
+                    run_control(&talk_camera, &topic["control/".len()..], &message).await
+                {
+                    let _ = talk_mqtt.send_message(&topic, &reply, false).await;
                 }
-            };
-
-            mqtt.send_message("control/wakeup", &reply, false)
-                .await
-                .with_context(|| "Failed to publish wakeup")?;
+            });
         }
-        MqttReplyRef {
-            topic: "control/floodlight_tasks",
-            message,
-        } => {
-            let state = match message.to_lowercase().as_ref() {
-                "on" => Ok(true),
-                "off" => Ok(false),
-                n => match n.parse::<bool>() {
-                    Ok(state) => Ok(state),
-                    Err(e) => AnyResult::Err(e.into()),
-                },
-            };
-
-            let reply = match state {
-                Ok(state) => {
-                    if let Err(e) = camera
-                        .run_task(|cam| {
-                            Box::pin(async move {
-                                cam.flightlight_tasks_enable(state).await?;
-                                AnyResult::Ok(())
-                            })
-                        })
+        MqttReplyRef { topic, message } if topic.starts_with("control/") => {
+            if let Some(reply) = run_control(camera, &topic["control/".len()..], message).await {
+                mqtt.send_message(topic, &reply, false)
+                    .await
+                    .with_context(|| format!("Failed to publish {topic}"))?;
+                if topic == "control/ir" && reply == "OK" {
+                    mqtt.send_message("status/ir", message, true)
                         .await
-                    {
-                        format!("FAIL: {e:?}")
-                    } else {
-                        "OK".to_string()
-                    }
+                        .with_context(|| "Failed to publish ir state")?;
                 }
-                Err(e) => format!("FAIL: Could not parse message to {e:?}"),
-            };
-
-            mqtt.send_message("control/floodlight_tasks", &reply, false)
-                .await
-                .with_context(|| "Failed to publish floodlight_tasks")?;
-        }
-        MqttReplyRef {
-            topic: "control/siren",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.siren().await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if let Err(e) = res {
-                error!("Failed to trigger siren: {:?}", e);
-                format!("FAIL: {e:?}")
-            } else {
-                "OK".to_string()
-            };
-
-            mqtt.send_message("control/siren", &reply, false)
-                .await
-                .with_context(|| "Failed to publish siren")?;
+            }
         }
         MqttReplyRef {
             topic: "query/battery",
@@ -1256,6 +1130,86 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish pir query")?;
         }
+        MqttReplyRef {
+            topic: "query/ir", ..
+        } => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let state = cam.irled_light_get().await?;
+                        AnyResult::Ok(state)
+                    })
+                })
+                .await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to get ir state: {:?}", e);
+                    "FAIL"
+                }
+                Ok(state) => {
+                    let state = match state {
+                        LightState::On => "on",
+                        LightState::Off => "off",
+                        LightState::Auto => "auto",
+                    };
+                    mqtt.send_message("status/ir", state, true)
+                        .await
+                        .with_context(|| "Failed to publish ir state")?;
+                    "OK"
+                }
+            }
+            .to_string();
+            mqtt.send_message("query/ir", &reply, false)
+                .await
+                .with_context(|| "Failed to publish ir query")?;
+        }
+        MqttReplyRef {
+            topic: "query/sdcard",
+            ..
+        } => {
+            let res = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        let xml = cam.get_hdd_info().await?;
+                        AnyResult::Ok(xml)
+                    })
+                })
+                .await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to get sdcard xml: {:?}", e);
+                    "FAIL"
+                }
+                Ok(xml) => {
+                    let ser_xml = {
+                        let mut buf = bytes::BytesMut::new();
+                        quick_xml::se::to_writer(&mut buf, &xml).map(|_| buf.to_vec())
+                    };
+                    match ser_xml {
+                        Ok(bytes) => match String::from_utf8(bytes) {
+                            Ok(str) => {
+                                mqtt.send_message("status/sdcard", &str, false)
+                                    .await
+                                    .with_context(|| "Failed to publish sdcard info")?;
+                                "OK"
+                            }
+                            Err(_) => {
+                                error!("Failed to encode sdcard status");
+                                "FAIL"
+                            }
+                        },
+                        Err(_) => {
+                            error!("Failed to serialise sdcard status");
+                            "FAIL"
+                        }
+                    }
+                }
+            }
+            .to_string();
+            mqtt.send_message("query/sdcard", &reply, false)
+                .await
+                .with_context(|| "Failed to publish sdcard query")?;
+        }
         MqttReplyRef {
             topic: "query/ptz/preset",
             ..
@@ -1303,6 +1257,25 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish ptz query")?;
         }
+        #[cfg(feature = "gstreamer")]
+        MqttReplyRef {
+            topic: "query/sound_level",
+            ..
+        } => {
+            let level = *camera.sound_level().await?.borrow();
+            let reply = match level {
+                Some(level) => {
+                    mqtt.send_message("status/sound_level", &format!("{level:.1}"), false)
+                        .await
+                        .with_context(|| "Failed to publish sound level")?;
+                    "OK".to_string()
+                }
+                None => "FAIL: Audio detection is not running".to_string(),
+            };
+            mqtt.send_message("query/sound_level", &reply, false)
+                .await
+                .with_context(|| "Failed to publish sound level query")?;
+        }
         MqttReplyRef {
             topic: "query/preview",
             ..
@@ -1338,6 +1311,39 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish preview query")?;
         }
+        MqttReplyRef { topic, message } if topic.starts_with("query/raw/") => {
+            let id = &topic["query/raw/".len()..];
+            let reply = if !camera.config().await?.borrow().mqtt.enable_raw {
+                "FAIL: Raw messages are disabled, set enable_raw in [cameras.mqtt]".to_string()
+            } else if let Ok(msg_id) = id.parse::<u32>() {
+                let xml = message.to_string();
+                let res = camera
+                    .run_task(|cam| {
+                        let xml = xml.clone();
+                        Box::pin(
+                            async move { AnyResult::Ok(cam.send_raw_xml(msg_id, &xml).await?) },
+                        )
+                    })
+                    .await;
+                match res {
+                    Ok(reply_xml) => {
+                        mqtt.send_message(&format!("status/raw/{msg_id}"), &reply_xml, false)
+                            .await
+                            .with_context(|| "Failed to publish raw reply")?;
+                        "OK".to_string()
+                    }
+                    Err(e) => {
+                        error!("Failed to send raw message {msg_id}: {:?}", e);
+                        format!("FAIL: {e:?}")
+                    }
+                }
+            } else {
+                format!("FAIL: {id} is not a message id")
+            };
+            mqtt.send_message(topic, &reply, false)
+                .await
+                .with_context(|| "Failed to publish raw query")?;
+        }
         _ => {}
     }
     Ok(())
@@ -6,6 +6,7 @@ use anyhow::{anyhow, Context, Result};
 use futures::future::FutureExt;
 use log::*;
 use rumqttc::{
+    tokio_rustls::rustls::{ClientConfig, RootCertStore},
     AsyncClient, ConnectReturnCode, Event, Incoming, LastWill, MqttOptions, QoS, TlsConfiguration,
     Transport,
 };
@@ -18,12 +19,17 @@ use tokio::{
         oneshot::{channel as oneshot, Sender as OneshotSender},
         watch::Receiver as WatchReceiver,
     },
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// The first wait before connecting to the broker again
+const MQTT_RETRY_MIN: Duration = Duration::from_secs(2);
+/// The wait between connecting to the broker doubles up to this
+const MQTT_RETRY_MAX: Duration = Duration::from_secs(60);
+
 pub(crate) struct Mqtt {
     cancel: CancellationToken,
     outgoing_tx: MpscSender<MqttRequest>,
@@ -45,6 +51,8 @@ impl Mqtt {
         let thread_outgoing_tx = outgoing_tx.clone();
         set.spawn(async move {
             let mut mqtt_config = thread_config.borrow().mqtt.clone();
+            let mut backoff = MQTT_RETRY_MIN;
+            let mut started = Instant::now();
             let r = loop {
                 break tokio::select! {
                     _ = thread_cancel.cancelled() => AnyResult::Ok(()),
@@ -54,6 +62,7 @@ impl Mqtt {
                         continue;
                     }
                     v = async {
+                        started = Instant::now();
                         let mut backend = MqttBackend {
                             incomming_tx: thread_incoming_tx.clone(),
                             outgoing_rx: &mut outgoing_rx,
@@ -64,8 +73,17 @@ impl Mqtt {
                         backend.run().await
                     }, if mqtt_config.is_some() => {
                         if let Err(e) = &v {
-                            log::error!("MQTT Client Connection Failed: {:?}", e);
-                            sleep(Duration::from_secs(2)).await;
+                            // A connection that lasted a while starts the backoff again
+                            if started.elapsed() > MQTT_RETRY_MAX {
+                                backoff = MQTT_RETRY_MIN;
+                            }
+                            log::error!(
+                                "MQTT Client Connection Failed, retrying in {}s: {:?}",
+                                backoff.as_secs(),
+                                e
+                            );
+                            sleep(backoff).await;
+                            backoff = (backoff * 2).min(MQTT_RETRY_MAX);
                             continue;
                         }
                         v
@@ -133,33 +151,9 @@ impl<'a> MqttBackend<'a> {
         let max_size = 100 * (1024 * 1024);
         mqttoptions.set_max_packet_size(max_size, max_size);
 
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &self.config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &self.config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, key_buf))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
+        if let Some(transport) = tls_transport(self.config)? {
+            mqttoptions.set_transport(transport);
+        }
 
         if let Some((username, password)) = &self.config.credentials {
             mqttoptions.set_credentials(username, password);
@@ -262,11 +256,12 @@ impl<'a> MqttBackend<'a> {
                                         };
                                         let _ = reply.send(Ok(instance));
                                     },
-                                    MqttRequest::LastWill{topic, message, reply} => {
+                                    MqttRequest::LastWill{topic, message, online, reply} => {
                                         let last_will = LastWillMqtt::new(
                                             &server_config,
                                             topic,
                                             message,
+                                            online,
                                         ).await;
                                         let _ = reply.send(last_will);
                                     }
@@ -458,6 +453,23 @@ impl MqttInstance {
     }
 
     pub(crate) async fn last_will(&self, topic: &str, message: &str) -> AnyResult<LastWillMqtt> {
+        self.last_will_with_online(topic, message, None).await
+    }
+
+    /// Publish `online` to `status/availability` while this is held and
+    /// have the broker publish `offline` once it is dropped or neolink is lost
+    pub(crate) async fn availability(&self) -> AnyResult<LastWillMqtt> {
+        self.last_will_with_online("status/availability", "offline", Some("online"))
+            .await
+    }
+
+    /// A last will that also publishes `online` each time that it connects
+    async fn last_will_with_online(
+        &self,
+        topic: &str,
+        message: &str,
+        online: Option<&str>,
+    ) -> AnyResult<LastWillMqtt> {
         let topic = if self.name.is_empty() {
             format!("neolink/{}", topic)
         } else {
@@ -469,6 +481,7 @@ impl MqttInstance {
             .send(MqttRequest::LastWill {
                 topic,
                 message: message.to_string(),
+                online: online.map(|online| online.to_string()),
                 reply: tx,
             })
             .await?;
@@ -504,6 +517,7 @@ enum MqttRequest {
     LastWill {
         topic: String,
         message: String,
+        online: Option<String>,
         reply: OneshotSender<Result<LastWillMqtt>>,
     },
 }
@@ -517,6 +531,7 @@ impl LastWillMqtt {
         config: &MqttServerConfig,
         topic: String,
         message: String,
+        online: Option<String>,
     ) -> AnyResult<Self> {
         log::trace!("Run MQTT Last Will");
         let mut mqttoptions = MqttOptions::new(
@@ -527,33 +542,9 @@ impl LastWillMqtt {
         let max_size = 100 * (1024 * 1024);
         mqttoptions.set_max_packet_size(max_size, max_size);
 
-        // Use TLS if ca path is set
-        if let Some(ca_path) = &config.ca {
-            if let Ok(ca) = std::fs::read(ca_path) {
-                // Use client_auth if they have cert and key
-                let client_auth = if let Some((cert_path, key_path)) = &config.client_auth {
-                    if let (Ok(cert_buf), Ok(key_buf)) =
-                        (std::fs::read(cert_path), std::fs::read(key_path))
-                    {
-                        Some((cert_buf, key_buf))
-                    } else {
-                        error!("Failed to set client tls");
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let transport = Transport::Tls(TlsConfiguration::Simple {
-                    ca,
-                    alpn: None,
-                    client_auth,
-                });
-                mqttoptions.set_transport(transport);
-            } else {
-                error!("Failed to set CA");
-            }
-        };
+        if let Some(transport) = tls_transport(config)? {
+            mqttoptions.set_transport(transport);
+        }
 
         if let Some((username, password)) = &config.credentials {
             mqttoptions.set_credentials(username, password);
@@ -562,7 +553,12 @@ impl LastWillMqtt {
         mqttoptions.set_keep_alive(Duration::from_secs(5));
 
         // On unclean disconnect send this
-        mqttoptions.set_last_will(LastWill::new(topic, message, QoS::AtLeastOnce, true));
+        mqttoptions.set_last_will(LastWill::new(
+            topic.clone(),
+            message,
+            QoS::AtLeastOnce,
+            true,
+        ));
 
         let (client, mut connection) = AsyncClient::new(mqttoptions, 100);
         let client = Arc::new(client);
@@ -570,19 +566,33 @@ impl LastWillMqtt {
         let thread_cancel = cancel.clone();
 
         tokio::task::spawn(async move {
+            let mut backoff = MQTT_RETRY_MIN;
             loop {
-                let r = tokio::select! {
-                    _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+                tokio::select! {
+                    _ = thread_cancel.cancelled() => break,
                     v = connection.poll() =>  {
-                        v?;
-                        AnyResult::Ok(())
+                        match v {
+                            Ok(Event::Incoming(Incoming::ConnAck(connected))) if connected.code == ConnectReturnCode::Success => {
+                                backoff = MQTT_RETRY_MIN;
+                                if let Some(online) = online.as_ref() {
+                                    // The client is handled by this same poll so do not wait on it
+                                    let _ = client.try_publish(topic.clone(), QoS::AtLeastOnce, true, online.clone());
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(e) => {
+                                // Polling again reconnects and registers the last will again
+                                log::debug!("MQTT last will for {topic} lost, retrying in {}s: {e:?}", backoff.as_secs());
+                                tokio::select! {
+                                    _ = thread_cancel.cancelled() => break,
+                                    _ = sleep(backoff) => {}
+                                }
+                                backoff = (backoff * 2).min(MQTT_RETRY_MAX);
+                            }
+                        }
                     },
                 };
-                if r.is_ok() {
-                    continue;
-                }
-                break r;
-            }?;
+            }
             drop(client);
             AnyResult::Ok(())
         });
@@ -596,3 +606,61 @@ impl Drop for LastWillMqtt {
         self.cancel.cancel();
     }
 }
+
+/// The TLS transport for the broker
+///
+/// This is None for plain TCP. TLS is used when `tls = true` or a `ca` is
+/// given, without a `ca` the system's CAs check the broker
+fn tls_transport(config: &MqttServerConfig) -> AnyResult<Option<Transport>> {
+    if !config.tls && config.ca.is_none() {
+        if config.client_auth.is_some() {
+            warn!("client_auth is ignored without tls = true or a ca");
+        }
+        return Ok(None);
+    }
+    let client_auth = match &config.client_auth {
+        Some((cert_path, key_path)) => Some((
+            std::fs::read(cert_path)
+                .with_context(|| format!("Could not read the mqtt client cert {:?}", cert_path))?,
+            std::fs::read(key_path)
+                .with_context(|| format!("Could not read the mqtt client key {:?}", key_path))?,
+        )),
+        None => None,
+    };
+
+    if let Some(ca_path) = &config.ca {
+        let ca = std::fs::read(ca_path)
+            .with_context(|| format!("Could not read the mqtt ca {:?}", ca_path))?;
+        return Ok(Some(Transport::Tls(TlsConfiguration::Simple {
+            ca,
+            alpn: None,
+            client_auth,
+        })));
+    }
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()
+        .context("Could not load the system's CAs for mqtt")?
+    {
+        // Skip any that rustls does not understand, as the browsers do
+        let _ = roots.add(cert);
+    }
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let tls_config = match client_auth {
+        Some((cert, key)) => {
+            let certs = rustls_pemfile::certs(&mut cert.as_slice())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .context("Could not understand the mqtt client cert")?;
+            let key = rustls_pemfile::private_key(&mut key.as_slice())
+                .context("Could not understand the mqtt client key")?
+                .ok_or_else(|| anyhow!("No private key in the mqtt client key"))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .context("Could not use the mqtt client cert")?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Some(Transport::Tls(TlsConfiguration::Rustls(Arc::new(
+        tls_config,
+    )))))
+}
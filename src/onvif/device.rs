@@ -0,0 +1,114 @@
+//! The ONVIF Device service
+//!
+//! This describes neolink itself and where the other services are
+use time::OffsetDateTime;
+
+use super::{soap::xml, Client, OnvifServer, SoapFault};
+use crate::AnyResult;
+
+/// The scopes that we advertise in discovery and `GetScopes`
+pub(super) const SCOPES: &[&str] = &[
+    "onvif://www.onvif.org/type/video_encoder",
+    "onvif://www.onvif.org/Profile/Streaming",
+    "onvif://www.onvif.org/name/Neolink",
+    "onvif://www.onvif.org/hardware/Neolink",
+];
+
+pub(super) fn handle(action: &str, client: &Client, server: &OnvifServer) -> AnyResult<String> {
+    let base = format!("http://{}:{}/onvif", client.host, server.config.port);
+    Ok(match action {
+        "GetSystemDateAndTime" => {
+            let now = OffsetDateTime::now_utc();
+            format!(
+                concat!(
+                    "<tds:GetSystemDateAndTimeResponse><tds:SystemDateAndTime>",
+                    "<tt:DateTimeType>NTP</tt:DateTimeType><tt:DaylightSavings>false</tt:DaylightSavings>",
+                    "<tt:TimeZone><tt:TZ>UTC0</tt:TZ></tt:TimeZone><tt:UTCDateTime>",
+                    "<tt:Date><tt:Year>{}</tt:Year><tt:Month>{}</tt:Month><tt:Day>{}</tt:Day></tt:Date>",
+                    "<tt:Time><tt:Hour>{}</tt:Hour><tt:Minute>{}</tt:Minute><tt:Second>{}</tt:Second></tt:Time>",
+                    "</tt:UTCDateTime></tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>"
+                ),
+                now.year(),
+                now.month() as u8,
+                now.day(),
+                now.hour(),
+                now.minute(),
+                now.second()
+            )
+        }
+        "GetDeviceInformation" => format!(
+            concat!(
+                "<tds:GetDeviceInformationResponse><tds:Manufacturer>Neolink</tds:Manufacturer>",
+                "<tds:Model>Neolink</tds:Model><tds:FirmwareVersion>{}</tds:FirmwareVersion>",
+                "<tds:SerialNumber>{}</tds:SerialNumber><tds:HardwareId>neolink</tds:HardwareId>",
+                "</tds:GetDeviceInformationResponse>"
+            ),
+            env!("CARGO_PKG_VERSION"),
+            xml(server.device_id.trim_start_matches("urn:uuid:"))
+        ),
+        "GetCapabilities" => format!(
+            concat!(
+                "<tds:GetCapabilitiesResponse><tds:Capabilities>",
+                "<tt:Device><tt:XAddr>{base}/device_service</tt:XAddr></tt:Device>",
+                "<tt:Events><tt:XAddr>{base}/event_service</tt:XAddr>",
+                "<tt:WSSubscriptionPolicySupport>false</tt:WSSubscriptionPolicySupport>",
+                "<tt:WSPullPointSupport>true</tt:WSPullPointSupport>",
+                "<tt:WSPausableSubscriptionManagerInterfaceSupport>false</tt:WSPausableSubscriptionManagerInterfaceSupport>",
+                "</tt:Events>",
+                "<tt:Media><tt:XAddr>{base}/media_service</tt:XAddr><tt:StreamingCapabilities>",
+                "<tt:RTPMulticast>false</tt:RTPMulticast><tt:RTP_TCP>true</tt:RTP_TCP>",
+                "<tt:RTP_RTSP_TCP>true</tt:RTP_RTSP_TCP></tt:StreamingCapabilities></tt:Media>",
+                "</tds:Capabilities></tds:GetCapabilitiesResponse>"
+            ),
+            base = xml(&base)
+        ),
+        "GetServices" => {
+            let mut body = String::from("<tds:GetServicesResponse>");
+            for (namespace, path) in [
+                ("http://www.onvif.org/ver10/device/wsdl", "device_service"),
+                ("http://www.onvif.org/ver10/media/wsdl", "media_service"),
+                ("http://www.onvif.org/ver10/events/wsdl", "event_service"),
+            ] {
+                body.push_str(&format!(
+                    concat!(
+                        "<tds:Service><tds:Namespace>{}</tds:Namespace><tds:XAddr>{}/{}</tds:XAddr>",
+                        "<tds:Version><tt:Major>2</tt:Major><tt:Minor>5</tt:Minor></tds:Version>",
+                        "</tds:Service>"
+                    ),
+                    namespace,
+                    xml(&base),
+                    path
+                ));
+            }
+            body.push_str("</tds:GetServicesResponse>");
+            body
+        }
+        "GetServiceCapabilities" => concat!(
+            "<tds:GetServiceCapabilitiesResponse><tds:Capabilities>",
+            r#"<tds:Network NTP="0" HostnameFromDHCP="false" Dot11Configuration="false"/>"#,
+            r#"<tds:Security UsernameToken="true" HttpDigest="false"/>"#,
+            r#"<tds:System DiscoveryResolve="false" DiscoveryBye="false" RemoteDiscovery="false""#,
+            r#" SystemBackup="false" SystemLogging="false" FirmwareUpgrade="false"/>"#,
+            "</tds:Capabilities></tds:GetServiceCapabilitiesResponse>"
+        )
+        .to_string(),
+        "GetScopes" => {
+            let mut body = String::from("<tds:GetScopesResponse>");
+            for scope in SCOPES {
+                body.push_str(&format!(
+                    "<tds:Scopes><tt:ScopeDef>Fixed</tt:ScopeDef><tt:ScopeItem>{scope}</tt:ScopeItem></tds:Scopes>"
+                ));
+            }
+            body.push_str("</tds:GetScopesResponse>");
+            body
+        }
+        "GetHostname" => concat!(
+            "<tds:GetHostnameResponse><tds:HostnameInformation><tt:FromDHCP>false</tt:FromDHCP>",
+            "<tt:Name>neolink</tt:Name></tds:HostnameInformation></tds:GetHostnameResponse>"
+        )
+        .to_string(),
+        // The network is not ours to describe
+        "GetNetworkInterfaces" => "<tds:GetNetworkInterfacesResponse/>".to_string(),
+        action => return Err(SoapFault::not_supported(action).into()),
+    })
+}
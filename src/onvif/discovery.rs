@@ -0,0 +1,140 @@
+//! WS-Discovery so that clients can find neolink on the local network
+//!
+//! We announce ourselves with a `Hello` on start and answer the `Probe`s
+//! for network video transmitters that arrive on the multicast group.
+use anyhow::Context;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+use super::{
+    device::SCOPES,
+    soap::{xml, SoapRequest},
+    OnvifServer,
+};
+use crate::AnyResult;
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const MULTICAST_PORT: u16 = 3702;
+
+pub(super) async fn discovery(server: &OnvifServer) -> AnyResult<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, MULTICAST_PORT))
+        .await
+        .with_context(|| {
+            format!("Could not bind to port {MULTICAST_PORT}, is another ONVIF service running?")
+        })?;
+    let interface = match server.config.bind_addr.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip,
+        _ => Ipv4Addr::UNSPECIFIED,
+    };
+    socket
+        .join_multicast_v4(MULTICAST_ADDR, interface)
+        .context("Could not join the WS-Discovery multicast group")?;
+
+    let group = SocketAddr::from((MULTICAST_ADDR, MULTICAST_PORT));
+    let hello = message(
+        server,
+        "Hello",
+        None,
+        &xaddrs(server, local_ip_for(group).await?),
+    );
+    socket.send_to(hello.as_bytes(), group).await?;
+    log::debug!("ONVIF discovery listening");
+
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let Ok(request) = std::str::from_utf8(&buf[..len])
+            .map_err(anyhow::Error::from)
+            .and_then(SoapRequest::parse)
+        else {
+            continue;
+        };
+        if request.action != "Probe" {
+            continue;
+        }
+        // An empty Types matches every device
+        let wanted = request.field("Types").unwrap_or_default();
+        if !wanted.is_empty()
+            && !wanted.contains("NetworkVideoTransmitter")
+            && !wanted.contains("Device")
+        {
+            continue;
+        }
+        log::trace!("ONVIF probe from {peer}");
+        let reply = message(
+            server,
+            "ProbeMatches",
+            request.field("MessageID"),
+            &xaddrs(server, local_ip_for(peer).await?),
+        );
+        if let Err(e) = socket.send_to(reply.as_bytes(), peer).await {
+            log::debug!("Could not answer the ONVIF probe from {peer}: {e:?}");
+        }
+    }
+}
+
+/// The address of ours that can reach the peer
+async fn local_ip_for(peer: SocketAddr) -> AnyResult<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(peer).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+fn xaddrs(server: &OnvifServer, ip: IpAddr) -> String {
+    let host = server
+        .config
+        .address
+        .clone()
+        .unwrap_or_else(|| ip.to_string());
+    format!(
+        "http://{}:{}/onvif/device_service",
+        host, server.config.port
+    )
+}
+
+/// A `Hello` or a `ProbeMatches` reply
+fn message(server: &OnvifServer, kind: &str, relates_to: Option<&str>, xaddrs: &str) -> String {
+    let (to, relates_to) = match relates_to {
+        Some(id) => (
+            "http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous".to_string(),
+            format!("<a:RelatesTo>{}</a:RelatesTo>", xml(id)),
+        ),
+        None => (
+            "urn:schemas-xmlsoap-org:ws:2005:04:discovery".to_string(),
+            String::new(),
+        ),
+    };
+    let details = format!(
+        concat!(
+            "<a:EndpointReference><a:Address>{}</a:Address></a:EndpointReference>",
+            "<d:Types>dn:NetworkVideoTransmitter tds:Device</d:Types>",
+            "<d:Scopes>{}</d:Scopes><d:XAddrs>{}</d:XAddrs>",
+            "<d:MetadataVersion>1</d:MetadataVersion>"
+        ),
+        server.device_id,
+        SCOPES.join(" "),
+        xml(xaddrs)
+    );
+    let body = match kind {
+        "Hello" => format!("<d:Hello>{details}</d:Hello>"),
+        _ => format!("<d:ProbeMatches><d:ProbeMatch>{details}</d:ProbeMatch></d:ProbeMatches>"),
+    };
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope""#,
+            r#" xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing""#,
+            r#" xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery""#,
+            r#" xmlns:dn="http://www.onvif.org/ver10/network/wsdl""#,
+            r#" xmlns:tds="http://www.onvif.org/ver10/device/wsdl">"#,
+            "<s:Header><a:MessageID>urn:uuid:{}</a:MessageID>{}<a:To>{}</a:To>",
+            "<a:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/{}</a:Action>",
+            "</s:Header><s:Body>{}</s:Body></s:Envelope>"
+        ),
+        uuid::Uuid::new_v4(),
+        relates_to,
+        to,
+        kind,
+        body
+    )
+}
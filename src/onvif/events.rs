@@ -0,0 +1,311 @@
+//! The ONVIF Events service
+//!
+//! Only pull point subscriptions are offered. The motion of each camera is
+//! sent as `tns1:VideoSource/MotionAlarm` with the camera as the source.
+//! Motion from the PIR of battery cameras arrives the same way.
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use time::OffsetDateTime;
+use tokio::{
+    sync::{watch::Receiver as WatchReceiver, Mutex as AsyncMutex},
+    time::{sleep_until, timeout_at, Duration, Instant},
+};
+
+use super::{
+    soap::{parse_duration, xml, xml_time, SoapRequest},
+    Client, SoapFault,
+};
+use crate::{
+    common::{MdState, NeoReactor},
+    config::{CameraConfig, OnvifConfig},
+    AnyResult,
+};
+
+/// Lifetime of a subscription that did not ask for one
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(60);
+/// Longest that a single `PullMessages` may wait
+const MAX_PULL_WAIT: Duration = Duration::from_secs(60);
+/// Longest lifetime that a subscription may ask for
+const MAX_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub(super) struct PullPoints {
+    next_id: AtomicU64,
+    subscriptions: Mutex<HashMap<u64, Arc<AsyncMutex<PullPoint>>>>,
+}
+
+struct PullPoint {
+    expires: Instant,
+    sources: Vec<Source>,
+}
+
+struct Source {
+    camera: String,
+    motion: WatchReceiver<MdState>,
+    /// The state last sent, `None` until the initial state is sent
+    reported: Option<bool>,
+}
+
+impl PullPoints {
+    pub(super) fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            subscriptions: Default::default(),
+        }
+    }
+
+    pub(super) async fn create(
+        &self,
+        request: &SoapRequest,
+        cameras: &[CameraConfig],
+        reactor: &NeoReactor,
+        client: &Client,
+        config: &OnvifConfig,
+    ) -> AnyResult<String> {
+        self.prune();
+        let lifetime = lifetime(request.field("InitialTerminationTime"));
+        let mut sources = vec![];
+        for camera in cameras.iter() {
+            let motion = match reactor.get(&camera.name).await {
+                Ok(instance) => instance.motion().await,
+                Err(e) => Err(e),
+            };
+            match motion {
+                Ok(motion) => sources.push(Source {
+                    camera: camera.name.clone(),
+                    motion,
+                    reported: None,
+                }),
+                Err(e) => log::debug!("{}: No motion for onvif events: {e:?}", camera.name),
+            }
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let expires = expires_in(lifetime);
+        self.subscriptions.lock().unwrap().insert(
+            id,
+            Arc::new(AsyncMutex::new(PullPoint { expires, sources })),
+        );
+        log::debug!("ONVIF pull point {id} created");
+
+        Ok(format!(
+            concat!(
+                "<tev:CreatePullPointSubscriptionResponse><tev:SubscriptionReference>",
+                "<wsa:Address>http://{}:{}/onvif/pullpoint/{}</wsa:Address></tev:SubscriptionReference>",
+                "<wsnt:CurrentTime>{}</wsnt:CurrentTime><wsnt:TerminationTime>{}</wsnt:TerminationTime>",
+                "</tev:CreatePullPointSubscriptionResponse>"
+            ),
+            xml(&client.host),
+            config.port,
+            id,
+            xml_time(OffsetDateTime::now_utc()),
+            termination_time(expires)
+        ))
+    }
+
+    /// Requests to the subscription manager of a pull point
+    pub(super) async fn handle(&self, request: &SoapRequest, id: &str) -> AnyResult<String> {
+        let id = id.parse::<u64>().unwrap_or_default();
+        let pull_point = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| SoapFault::invalid_arg(format!("No subscription {id}")))?;
+        let mut pull_point = pull_point.lock().await;
+        if pull_point.expires <= Instant::now() {
+            self.subscriptions.lock().unwrap().remove(&id);
+            return Err(SoapFault::invalid_arg(format!("Subscription {id} has expired")).into());
+        }
+        Ok(match request.action.as_str() {
+            "PullMessages" => pull_point.pull(request).await,
+            "Renew" => {
+                pull_point.expires = expires_in(lifetime(request.field("TerminationTime")));
+                format!(
+                    concat!(
+                        "<wsnt:RenewResponse><wsnt:TerminationTime>{}</wsnt:TerminationTime>",
+                        "<wsnt:CurrentTime>{}</wsnt:CurrentTime></wsnt:RenewResponse>"
+                    ),
+                    termination_time(pull_point.expires),
+                    xml_time(OffsetDateTime::now_utc())
+                )
+            }
+            "Unsubscribe" => {
+                self.subscriptions.lock().unwrap().remove(&id);
+                log::debug!("ONVIF pull point {id} removed");
+                "<wsnt:UnsubscribeResponse/>".to_string()
+            }
+            "SetSynchronizationPoint" => {
+                // Send every state again on the next pull
+                for source in pull_point.sources.iter_mut() {
+                    source.reported = None;
+                }
+                "<tev:SetSynchronizationPointResponse/>".to_string()
+            }
+            action => return Err(SoapFault::not_supported(action).into()),
+        })
+    }
+
+    /// Drop the expired subscriptions
+    ///
+    /// Those that are locked are in use and so not expired
+    fn prune(&self) {
+        let now = Instant::now();
+        self.subscriptions.lock().unwrap().retain(|_, pull_point| {
+            pull_point
+                .try_lock()
+                .map(|pull_point| pull_point.expires > now)
+                .unwrap_or(true)
+        });
+    }
+}
+
+impl PullPoint {
+    async fn pull(&mut self, request: &SoapRequest) -> String {
+        let wait = request
+            .field("Timeout")
+            .and_then(parse_duration)
+            .unwrap_or(Duration::from_secs(10))
+            .min(MAX_PULL_WAIT);
+        let limit = request
+            .field("MessageLimit")
+            .and_then(|limit| limit.parse::<usize>().ok())
+            .unwrap_or(100)
+            .max(1);
+        let deadline = expires_in(wait);
+        let messages = loop {
+            let messages = self.messages(limit);
+            if !messages.is_empty() {
+                break messages;
+            }
+            if self.sources.is_empty() {
+                sleep_until(deadline).await;
+                break messages;
+            }
+            let changed = futures::future::select_all(
+                self.sources
+                    .iter_mut()
+                    .map(|source| Box::pin(source.motion.changed())),
+            );
+            match timeout_at(deadline, changed)
+                .await
+                .map(|(r, index, _)| (r.is_err(), index))
+            {
+                Err(_) => break messages,
+                Ok((true, index)) => {
+                    // The camera was removed
+                    self.sources.remove(index);
+                }
+                Ok((false, _)) => {}
+            }
+        };
+        format!(
+            concat!(
+                "<tev:PullMessagesResponse><tev:CurrentTime>{}</tev:CurrentTime>",
+                "<tev:TerminationTime>{}</tev:TerminationTime>{}</tev:PullMessagesResponse>"
+            ),
+            xml_time(OffsetDateTime::now_utc()),
+            termination_time(self.expires),
+            messages.join("")
+        )
+    }
+
+    /// The notifications of the states not yet sent
+    fn messages(&mut self, limit: usize) -> Vec<String> {
+        let now = xml_time(OffsetDateTime::now_utc());
+        let mut messages = vec![];
+        for source in self.sources.iter_mut() {
+            if messages.len() >= limit {
+                break;
+            }
+            let state = match *source.motion.borrow_and_update() {
+                MdState::Start(_) => true,
+                MdState::Stop(_) => false,
+                MdState::Unknown => continue,
+            };
+            let operation = match source.reported {
+                None => "Initialized",
+                Some(reported) if reported != state => "Changed",
+                Some(_) => continue,
+            };
+            source.reported = Some(state);
+            messages.push(format!(
+                concat!(
+                    "<wsnt:NotificationMessage>",
+                    r#"<wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">"#,
+                    "tns1:VideoSource/MotionAlarm</wsnt:Topic><wsnt:Message>",
+                    r#"<tt:Message UtcTime="{}" PropertyOperation="{}">"#,
+                    r#"<tt:Source><tt:SimpleItem Name="Source" Value="{}"/></tt:Source>"#,
+                    r#"<tt:Data><tt:SimpleItem Name="State" Value="{}"/></tt:Data>"#,
+                    "</tt:Message></wsnt:Message></wsnt:NotificationMessage>"
+                ),
+                now,
+                operation,
+                xml(&source.camera),
+                state
+            ));
+        }
+        messages
+    }
+}
+
+/// The lifetime asked for by `InitialTerminationTime` or `TerminationTime`
+///
+/// Absolute times are not supported and get the default
+fn lifetime(requested: Option<&str>) -> Duration {
+    requested
+        .and_then(parse_duration)
+        .unwrap_or(DEFAULT_LIFETIME)
+        .clamp(Duration::from_secs(1), MAX_LIFETIME)
+}
+
+fn expires_in(lifetime: Duration) -> Instant {
+    let now = Instant::now();
+    now.checked_add(lifetime).unwrap_or(now)
+}
+
+fn termination_time(expires: Instant) -> String {
+    let now = OffsetDateTime::now_utc();
+    let left = expires.saturating_duration_since(Instant::now());
+    xml_time(
+        time::Duration::try_from(left)
+            .ok()
+            .and_then(|left| now.checked_add(left))
+            .unwrap_or(now),
+    )
+}
+
+pub(super) fn event_properties() -> String {
+    concat!(
+        "<tev:GetEventPropertiesResponse>",
+        "<tev:TopicNamespaceLocation>http://www.onvif.org/onvif/ver10/topics/topicns.xml</tev:TopicNamespaceLocation>",
+        "<wsnt:FixedTopicSet>true</wsnt:FixedTopicSet>",
+        r#"<wstop:TopicSet><tns1:VideoSource><MotionAlarm wstop:topic="true">"#,
+        r#"<tt:MessageDescription IsProperty="true">"#,
+        r#"<tt:Source><tt:SimpleItemDescription Name="Source" Type="tt:ReferenceToken"/></tt:Source>"#,
+        r#"<tt:Data><tt:SimpleItemDescription Name="State" Type="xs:boolean"/></tt:Data>"#,
+        "</tt:MessageDescription></MotionAlarm></tns1:VideoSource></wstop:TopicSet>",
+        "<wsnt:TopicExpressionDialect>http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet</wsnt:TopicExpressionDialect>",
+        "<wsnt:TopicExpressionDialect>http://docs.oasis-open.org/wsn/t-1/TopicExpression/Concrete</wsnt:TopicExpressionDialect>",
+        "<tev:MessageContentFilterDialect>http://www.onvif.org/ver10/tev/messageContentFilter/ItemFilter</tev:MessageContentFilterDialect>",
+        "<tev:MessageContentSchemaLocation>http://www.onvif.org/onvif/ver10/schema/onvif.xsd</tev:MessageContentSchemaLocation>",
+        "</tev:GetEventPropertiesResponse>"
+    )
+    .to_string()
+}
+
+pub(super) fn service_capabilities() -> String {
+    concat!(
+        "<tev:GetServiceCapabilitiesResponse>",
+        r#"<tev:Capabilities WSSubscriptionPolicySupport="false" WSPullPointSupport="true""#,
+        r#" WSPausableSubscriptionManagerInterfaceSupport="false" MaxNotificationProducers="0""#,
+        r#" MaxPullPoints="0" PersistentNotificationStorage="false"/>"#,
+        "</tev:GetServiceCapabilitiesResponse>"
+    )
+    .to_string()
+}
@@ -0,0 +1,231 @@
+//! The ONVIF Media service
+//!
+//! Each camera is a video source and each of its streams a profile
+use neolink_core::bc_protocol::StreamKind;
+
+use super::{
+    soap::{xml, SoapRequest},
+    Client, OnvifServer, SoapFault,
+};
+use crate::{
+    common::percent_encode,
+    config::{CameraConfig, Config, OnvifConfig},
+    AnyResult,
+};
+
+/// A profile of the media service
+struct Profile<'a> {
+    token: String,
+    camera: &'a CameraConfig,
+    stream: StreamKind,
+}
+
+impl Profile<'_> {
+    /// The rtsp path of the stream
+    fn path(&self) -> String {
+        if let Some(path) = self.camera.custom_path(self.stream) {
+            return path
+                .split('/')
+                .map(percent_encode)
+                .collect::<Vec<_>>()
+                .join("/");
+        }
+        let stream = match self.stream {
+            StreamKind::Main => "mainStream",
+            StreamKind::Sub => "subStream",
+            StreamKind::Extern => "externStream",
+        };
+        format!("/{}/{}", percent_encode(&self.camera.name), stream)
+    }
+}
+
+fn profiles(cameras: &[CameraConfig]) -> Vec<Profile<'_>> {
+    cameras
+        .iter()
+        .flat_map(|camera| {
+            camera
+                .stream
+                .as_stream_kinds()
+                .into_iter()
+                .map(move |stream| Profile {
+                    token: format!(
+                        "{}_{}",
+                        camera.name,
+                        match stream {
+                            StreamKind::Main => "main",
+                            StreamKind::Sub => "sub",
+                            StreamKind::Extern => "extern",
+                        }
+                    ),
+                    camera,
+                    stream,
+                })
+        })
+        .collect()
+}
+
+fn find_profile<'a>(request: &SoapRequest, cameras: &'a [CameraConfig]) -> AnyResult<Profile<'a>> {
+    let token = request
+        .field("ProfileToken")
+        .ok_or_else(|| SoapFault::invalid_arg("ProfileToken is required"))?;
+    profiles(cameras)
+        .into_iter()
+        .find(|profile| profile.token == token)
+        .ok_or_else(|| {
+            SoapFault {
+                code: "Sender",
+                subcode: "ter:NoProfile",
+                reason: format!("No profile {token}"),
+            }
+            .into()
+        })
+}
+
+impl OnvifServer {
+    pub(super) async fn get_profiles(&self, cameras: &[CameraConfig]) -> AnyResult<String> {
+        let mut body = String::from("<trt:GetProfilesResponse>");
+        for profile in profiles(cameras) {
+            body.push_str(&self.profile_xml("trt:Profiles", &profile).await);
+        }
+        body.push_str("</trt:GetProfilesResponse>");
+        Ok(body)
+    }
+
+    pub(super) async fn get_profile(
+        &self,
+        request: &SoapRequest,
+        cameras: &[CameraConfig],
+    ) -> AnyResult<String> {
+        let profile = find_profile(request, cameras)?;
+        Ok(format!(
+            "<trt:GetProfileResponse>{}</trt:GetProfileResponse>",
+            self.profile_xml("trt:Profile", &profile).await
+        ))
+    }
+
+    pub(super) async fn get_video_sources(&self, cameras: &[CameraConfig]) -> AnyResult<String> {
+        let mut body = String::from("<trt:GetVideoSourcesResponse>");
+        for camera in cameras {
+            let resolutions = self.resolutions(camera).await;
+            let (width, height) = camera
+                .stream
+                .as_stream_kinds()
+                .iter()
+                .find_map(|stream| resolutions.get(stream))
+                .copied()
+                .unwrap_or_default();
+            body.push_str(&format!(
+                concat!(
+                    r#"<trt:VideoSources token="{token}"><tt:Framerate>25</tt:Framerate>"#,
+                    "<tt:Resolution><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:Resolution>",
+                    "</trt:VideoSources>"
+                ),
+                token = xml(&camera.name),
+                width = width,
+                height = height,
+            ));
+        }
+        body.push_str("</trt:GetVideoSourcesResponse>");
+        Ok(body)
+    }
+
+    async fn profile_xml(&self, element: &str, profile: &Profile<'_>) -> String {
+        let name = xml(&profile.camera.name);
+        let token = xml(&profile.token);
+        let (width, height) = self
+            .resolutions(profile.camera)
+            .await
+            .get(&profile.stream)
+            .copied()
+            .unwrap_or_default();
+        let use_count = profile.camera.stream.as_stream_kinds().len();
+        // Media 1 can only describe H264, H265 cameras are still listed as
+        // H264 so that clients do not skip them and take the codec from the rtsp
+        format!(
+            concat!(
+                r#"<{element} token="{token}" fixed="true"><tt:Name>{token}</tt:Name>"#,
+                r#"<tt:VideoSourceConfiguration token="{name}"><tt:Name>{name}</tt:Name>"#,
+                "<tt:UseCount>{use_count}</tt:UseCount><tt:SourceToken>{name}</tt:SourceToken>",
+                r#"<tt:Bounds x="0" y="0" width="{width}" height="{height}"/></tt:VideoSourceConfiguration>"#,
+                r#"<tt:VideoEncoderConfiguration token="{token}"><tt:Name>{token}</tt:Name>"#,
+                "<tt:UseCount>1</tt:UseCount><tt:Encoding>H264</tt:Encoding>",
+                "<tt:Resolution><tt:Width>{width}</tt:Width><tt:Height>{height}</tt:Height></tt:Resolution>",
+                "<tt:Quality>5</tt:Quality><tt:Multicast><tt:Address><tt:Type>IPv4</tt:Type>",
+                "<tt:IPv4Address>0.0.0.0</tt:IPv4Address></tt:Address><tt:Port>0</tt:Port>",
+                "<tt:TTL>0</tt:TTL><tt:AutoStart>false</tt:AutoStart></tt:Multicast>",
+                "<tt:SessionTimeout>PT60S</tt:SessionTimeout></tt:VideoEncoderConfiguration>",
+                "</{element}>"
+            ),
+            element = element,
+            token = token,
+            name = name,
+            use_count = use_count,
+            width = width,
+            height = height,
+        )
+    }
+}
+
+pub(super) fn stream_uri(
+    request: &SoapRequest,
+    cameras: &[CameraConfig],
+    client: &Client,
+    config: &Config,
+) -> AnyResult<String> {
+    let profile = find_profile(request, cameras)?;
+    let scheme = if config.certificate.is_some() {
+        "rtsps"
+    } else {
+        "rtsp"
+    };
+    let uri = format!(
+        "{}://{}:{}{}",
+        scheme,
+        client.host,
+        config.bind_port,
+        profile.path()
+    );
+    Ok(format!(
+        concat!(
+            "<trt:GetStreamUriResponse><trt:MediaUri><tt:Uri>{}</tt:Uri>",
+            "<tt:InvalidAfterConnect>false</tt:InvalidAfterConnect>",
+            "<tt:InvalidAfterReboot>false</tt:InvalidAfterReboot>",
+            "<tt:Timeout>PT0S</tt:Timeout></trt:MediaUri></trt:GetStreamUriResponse>"
+        ),
+        xml(&uri)
+    ))
+}
+
+pub(super) fn snapshot_uri(
+    request: &SoapRequest,
+    cameras: &[CameraConfig],
+    client: &Client,
+    config: &OnvifConfig,
+) -> AnyResult<String> {
+    let profile = find_profile(request, cameras)?;
+    let uri = format!(
+        "http://{}:{}/onvif/snapshot/{}",
+        client.host,
+        config.port,
+        percent_encode(&profile.camera.name)
+    );
+    Ok(format!(
+        concat!(
+            "<trt:GetSnapshotUriResponse><trt:MediaUri><tt:Uri>{}</tt:Uri>",
+            "<tt:InvalidAfterConnect>false</tt:InvalidAfterConnect>",
+            "<tt:InvalidAfterReboot>false</tt:InvalidAfterReboot>",
+            "<tt:Timeout>PT0S</tt:Timeout></trt:MediaUri></trt:GetSnapshotUriResponse>"
+        ),
+        xml(&uri)
+    ))
+}
+
+pub(super) fn service_capabilities() -> String {
+    concat!(
+        r#"<trt:GetServiceCapabilitiesResponse><trt:Capabilities SnapshotUri="true">"#,
+        r#"<trt:ProfileCapabilities MaximumNumberOfProfiles="0"/>"#,
+        r#"<trt:StreamingCapabilities RTPMulticast="false" RTP_TCP="true" RTP_RTSP_TCP="true"/>"#,
+        "</trt:Capabilities></trt:GetServiceCapabilitiesResponse>"
+    )
+    .to_string()
+}
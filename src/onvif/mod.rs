@@ -0,0 +1,443 @@
+//! ONVIF services for the rtsp server
+//!
+//! When `[onvif]` is in the config the `rtsp` subcommand also serves the
+//! ONVIF Device, Media and Events services over http. This lets NVRs and
+//! Home Assistant find the rtsp streams of the cameras without entering
+//! each url by hand.
+//!
+//! Neolink appears as a single ONVIF device. Each camera is a video source
+//! with one profile per stream of the camera, the profiles point at the
+//! usual rtsp paths. Motion of the cameras is sent as
+//! `tns1:VideoSource/MotionAlarm` events on pull point subscriptions.
+//!
+//! When there are `[[users]]` in the config the requests must carry a
+//! WS-Security UsernameToken of one of them and only the cameras that the
+//! user is permitted to see are listed.
+use anyhow::{anyhow, Context};
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpStream},
+    time::{timeout, Duration},
+};
+
+mod device;
+mod discovery;
+mod events;
+mod media;
+mod soap;
+
+use crate::{
+    common::{
+        login_user, percent_decode, read_request, write_response_with_headers, HttpRequest,
+        NeoReactor,
+    },
+    config::{CameraConfig, Config, OnvifConfig},
+    image::jpeg_from_stream,
+    AnyResult,
+};
+use events::PullPoints;
+use soap::{envelope, fault, SeenNonces, SoapRequest};
+
+/// Actions that ONVIF clients may use before they have credentials
+const UNAUTHENTICATED_ACTIONS: &[&str] = &[
+    "GetSystemDateAndTime",
+    "GetCapabilities",
+    "GetServices",
+    "GetServiceCapabilities",
+    "GetWsdlUrl",
+    "GetHostname",
+    "GetEndpointReference",
+];
+
+/// Raised for requests that should be answered with a SOAP fault
+#[derive(Debug)]
+pub(super) struct SoapFault {
+    /// `Sender` or `Receiver`
+    pub(super) code: &'static str,
+    /// One of the ONVIF `ter:` codes
+    pub(super) subcode: &'static str,
+    pub(super) reason: String,
+}
+
+impl SoapFault {
+    pub(super) fn not_supported(action: &str) -> Self {
+        Self {
+            code: "Receiver",
+            subcode: "ter:ActionNotSupported",
+            reason: format!("Optional Action Not Implemented: {action}"),
+        }
+    }
+
+    pub(super) fn invalid_arg(reason: impl Into<String>) -> Self {
+        Self {
+            code: "Sender",
+            subcode: "ter:InvalidArgVal",
+            reason: reason.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for SoapFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({})", self.reason, self.subcode)
+    }
+}
+
+impl std::error::Error for SoapFault {}
+
+/// Where the client reached us
+pub(super) struct Client {
+    /// Host for our urls, as the client should connect to it
+    pub(super) host: String,
+    /// The user that the request authenticated as
+    pub(super) user: Option<String>,
+}
+
+pub(super) struct OnvifServer {
+    config: OnvifConfig,
+    reactor: NeoReactor,
+    /// Our `urn:uuid:` endpoint reference
+    device_id: String,
+    pull_points: PullPoints,
+    /// Stream resolutions by camera, found from the stream info
+    resolutions: Mutex<HashMap<String, Resolutions>>,
+    nonces: SeenNonces,
+}
+
+/// The width and height of each stream of a camera
+type Resolutions = HashMap<StreamKind, (u32, u32)>;
+
+/// Serve the ONVIF services until an error
+pub(crate) async fn serve_onvif(config: OnvifConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let listener = TcpListener::bind((config.bind_addr.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Could not bind the onvif server to {}:{}",
+                config.bind_addr, config.port
+            )
+        })?;
+    log::info!(
+        "ONVIF device service at http://{}:{}/onvif/device_service",
+        config.bind_addr,
+        config.port
+    );
+
+    let server = Arc::new(OnvifServer {
+        device_id: device_id(&config),
+        config,
+        reactor,
+        pull_points: PullPoints::new(),
+        resolutions: Default::default(),
+        nonces: Default::default(),
+    });
+
+    if server.config.discovery {
+        let thread_server = server.clone();
+        tokio::task::spawn(async move {
+            // Without discovery the device can still be added by its address
+            if let Err(e) = discovery::discovery(&thread_server).await {
+                log::warn!("ONVIF discovery stopped: {e:?}");
+            }
+        });
+    }
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = server.answer(stream).await {
+                log::debug!("ONVIF request from {peer} failed: {e:?}");
+            }
+        });
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn soap(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/soap+xml; charset=utf-8",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: vec![],
+        }
+    }
+}
+
+impl OnvifServer {
+    async fn answer(&self, stream: TcpStream) -> AnyResult<()> {
+        let local = stream.local_addr()?;
+        let (read, mut write) = stream.into_split();
+        let mut read = BufReader::new(read);
+        let request = timeout(Duration::from_secs(10), read_request(&mut read))
+            .await
+            .map_err(|_| anyhow!("Timed out reading the request"))??;
+        let host = self.host(local);
+
+        let response = match request.method.as_str() {
+            "GET" => match request.path.strip_prefix("/onvif/snapshot/") {
+                Some(camera) => self.snapshot(&request, &percent_decode(camera)).await,
+                None => HttpResponse::status(404),
+            },
+            "POST" => self.soap(&request, host).await,
+            _ => HttpResponse::status(405),
+        };
+
+        write_response_with_headers(
+            &mut write,
+            response.status,
+            response.content_type,
+            &[],
+            &response.body,
+        )
+        .await
+    }
+
+    async fn soap(&self, request: &HttpRequest, host: String) -> HttpResponse {
+        let soap_request = match SoapRequest::parse(&request.body) {
+            Ok(soap_request) => soap_request,
+            Err(e) => {
+                return HttpResponse::soap(
+                    400,
+                    fault("Sender", "ter:WellFormed", &format!("{e:#}")),
+                )
+            }
+        };
+        log::debug!("ONVIF {} on {}", soap_request.action, request.path);
+
+        let config = match self.reactor.config().await {
+            Ok(config) => config.borrow().clone(),
+            Err(e) => {
+                return HttpResponse::soap(500, fault("Receiver", "ter:Action", &e.to_string()))
+            }
+        };
+        let user = soap_request.authenticated_user(&config.users, &self.nonces);
+        if !config.users.is_empty()
+            && user.is_none()
+            && !UNAUTHENTICATED_ACTIONS.contains(&soap_request.action.as_str())
+        {
+            return HttpResponse::soap(
+                400,
+                fault(
+                    "Sender",
+                    "ter:NotAuthorized",
+                    "Sender not Authorized: a valid UsernameToken is required",
+                ),
+            );
+        }
+        let client = Client { host, user };
+
+        match self
+            .dispatch(&soap_request, &request.path, &client, &config)
+            .await
+        {
+            Ok(body) => HttpResponse::soap(200, envelope(&body)),
+            Err(e) => match e.downcast::<SoapFault>() {
+                Ok(f) => {
+                    log::debug!("ONVIF {} failed: {f}", soap_request.action);
+                    let status = if f.code == "Sender" { 400 } else { 500 };
+                    HttpResponse::soap(status, fault(f.code, f.subcode, &f.reason))
+                }
+                Err(e) => {
+                    log::warn!("ONVIF {} failed: {e:?}", soap_request.action);
+                    HttpResponse::soap(500, fault("Receiver", "ter:Action", &format!("{e:#}")))
+                }
+            },
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        request: &SoapRequest,
+        path: &str,
+        client: &Client,
+        config: &Config,
+    ) -> AnyResult<String> {
+        let cameras = visible_cameras(config, &client.user);
+        if let Some(id) = path.strip_prefix("/onvif/pullpoint/") {
+            return self.pull_points.handle(request, id).await;
+        }
+        match (path, request.action.as_str()) {
+            ("/onvif/media_service", "GetServiceCapabilities") => Ok(media::service_capabilities()),
+            ("/onvif/event_service", "GetServiceCapabilities") => {
+                Ok(events::service_capabilities())
+            }
+            (_, "GetProfiles") => self.get_profiles(&cameras).await,
+            (_, "GetProfile") => self.get_profile(request, &cameras).await,
+            (_, "GetVideoSources") => self.get_video_sources(&cameras).await,
+            (_, "GetStreamUri") => media::stream_uri(request, &cameras, client, config),
+            (_, "GetSnapshotUri") => media::snapshot_uri(request, &cameras, client, &self.config),
+            (_, "GetEventProperties") => Ok(events::event_properties()),
+            (_, "CreatePullPointSubscription") => {
+                self.pull_points
+                    .create(request, &cameras, &self.reactor, client, &self.config)
+                    .await
+            }
+            (_, action) => device::handle(action, client, self),
+        }
+    }
+
+    /// A still of the camera for `GetSnapshotUri`
+    ///
+    /// This uses http basic auth as the ONVIF clients do not send a
+    /// UsernameToken for the snapshot
+    async fn snapshot(&self, request: &HttpRequest, camera: &str) -> HttpResponse {
+        let config = match self.reactor.config().await {
+            Ok(config) => config.borrow().clone(),
+            Err(_) => return HttpResponse::status(500),
+        };
+        let user = login_user(request, &config.users).map(|user| user.name.clone());
+        if !config.users.is_empty() && user.is_none() {
+            return HttpResponse::status(401);
+        }
+        let Some(camera) = visible_cameras(&config, &user)
+            .into_iter()
+            .find(|cam| cam.name == camera)
+        else {
+            return HttpResponse::status(404);
+        };
+
+        let jpeg = match self.reactor.get(&camera.name).await {
+            Ok(instance) => timeout(Duration::from_secs(30), async {
+                let snap = instance
+                    .run_passive_task(|cam| Box::pin(async move { Ok(cam.get_snapshot().await?) }))
+                    .await;
+                match snap {
+                    Ok(jpeg) => Ok(jpeg),
+                    Err(e) => {
+                        // Not every camera has the SNAP command
+                        log::debug!("{}: Snapshot from the stream: {e:?}", camera.name);
+                        jpeg_from_stream(&instance, StreamKind::Main).await
+                    }
+                }
+            })
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the snapshot"))
+            .and_then(|r| r),
+            Err(e) => Err(e),
+        };
+        match jpeg {
+            Ok(jpeg) => HttpResponse {
+                status: 200,
+                content_type: "image/jpeg",
+                body: jpeg,
+            },
+            Err(e) => {
+                log::debug!("{}: ONVIF snapshot failed: {e:?}", camera.name);
+                HttpResponse::status(500)
+            }
+        }
+    }
+
+    /// The resolutions of the streams of a camera
+    ///
+    /// Cameras that cannot be asked report none
+    async fn resolutions(&self, camera: &CameraConfig) -> Resolutions {
+        if let Some(known) = self.resolutions.lock().unwrap().get(&camera.name) {
+            return known.clone();
+        }
+        let info = match self.reactor.get(&camera.name).await {
+            Ok(instance) => timeout(
+                Duration::from_secs(5),
+                instance.run_passive_task(|cam| {
+                    Box::pin(async move { Ok(cam.get_stream_info().await?) })
+                }),
+            )
+            .await
+            .ok()
+            .and_then(|r| r.ok()),
+            Err(_) => None,
+        };
+        let Some(info) = info else {
+            return Default::default();
+        };
+        let found = info
+            .stream_infos
+            .iter()
+            .flat_map(|stream_info| stream_info.encode_tables.iter())
+            .filter_map(|encode| {
+                let kind = match encode.name.as_str() {
+                    "mainStream" => StreamKind::Main,
+                    "subStream" => StreamKind::Sub,
+                    "externStream" => StreamKind::Extern,
+                    _ => return None,
+                };
+                Some((kind, (encode.resolution.width, encode.resolution.height)))
+            })
+            .collect::<HashMap<_, _>>();
+        self.resolutions
+            .lock()
+            .unwrap()
+            .insert(camera.name.clone(), found.clone());
+        found
+    }
+
+    /// The host that clients should use for our urls
+    fn host(&self, local: SocketAddr) -> String {
+        if let Some(address) = self.config.address.as_ref() {
+            return address.clone();
+        }
+        match local.ip() {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => ip.to_string(),
+                None => format!("[{ip}]"),
+            },
+        }
+    }
+}
+
+/// The enabled cameras that the user may see
+///
+/// This follows the `permitted_users` of the rtsp streams
+fn visible_cameras(config: &Config, user: &Option<String>) -> Vec<CameraConfig> {
+    config
+        .cameras
+        .iter()
+        .filter(|camera| camera.enabled)
+        .filter(|camera| match (user, &camera.permitted_users) {
+            (Some(user), Some(permitted)) => permitted.iter().any(|p| p == "anyone" || p == user),
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// A stable endpoint reference for this machine and port
+///
+/// Clients recognise devices by this so it must not change on restart
+fn device_id(config: &OnvifConfig) -> String {
+    let machine = ["/etc/machine-id", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .unwrap_or_default();
+    let hash = ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("neolink-onvif:{}:{}", machine.trim(), config.port).as_bytes(),
+    );
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&hash.as_ref()[..16]);
+    format!(
+        "urn:uuid:{}",
+        uuid::Builder::from_sha1_bytes(bytes).into_uuid()
+    )
+}
@@ -0,0 +1,222 @@
+//! Parsing of SOAP requests and building of the replies
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use quick_xml::{escape::escape, events::Event, Reader};
+use std::{collections::HashMap, sync::Mutex};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::time::{Duration, Instant};
+
+use crate::{config::UserConfig, AnyResult};
+
+const PASSWORD_DIGEST: &str =
+    "http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordDigest";
+
+/// How far the `Created` time of a digest may be from our clock
+const CREATED_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// The nonces of the digests that were accepted recently
+///
+/// A digest is only valid once so a captured request cannot be replayed
+#[derive(Default)]
+pub(super) struct SeenNonces(Mutex<HashMap<Vec<u8>, Instant>>);
+
+impl SeenNonces {
+    /// False when the nonce was already used
+    fn insert(&self, nonce: Vec<u8>) -> bool {
+        let mut seen = self.0.lock().unwrap();
+        // Older nonces are refused by their `Created` time anyway
+        seen.retain(|_, at| at.elapsed() < CREATED_WINDOW * 2);
+        seen.insert(nonce, Instant::now()).is_none()
+    }
+}
+
+/// A SOAP request reduced to what the services need
+pub(super) struct SoapRequest {
+    /// Local name of the first element in the body e.g. `GetProfiles`
+    pub(super) action: String,
+    /// Text of every element with text by local name in document order
+    fields: Vec<(String, String)>,
+    /// The `Type` of the UsernameToken password
+    password_type: Option<String>,
+}
+
+impl SoapRequest {
+    pub(super) fn parse(body: &str) -> AnyResult<Self> {
+        let mut reader = Reader::from_str(body);
+        reader.config_mut().trim_text(true);
+
+        let mut stack: Vec<String> = vec![];
+        let mut action = None;
+        let mut fields = vec![];
+        let mut password_type = None;
+        loop {
+            match reader.read_event().context("Invalid SOAP request")? {
+                Event::Start(e) => {
+                    let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                    if action.is_none() && stack.last().is_some_and(|p| p == "Body") {
+                        action = Some(name.clone());
+                    }
+                    if name == "Password" {
+                        password_type = e
+                            .attributes()
+                            .flatten()
+                            .find(|a| a.key.local_name().as_ref() == b"Type")
+                            .and_then(|a| a.unescape_value().ok())
+                            .map(|v| v.to_string());
+                    }
+                    stack.push(name);
+                }
+                Event::Empty(e) => {
+                    let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                    if action.is_none() && stack.last().is_some_and(|p| p == "Body") {
+                        action = Some(name);
+                    }
+                }
+                Event::Text(e) => {
+                    if let Some(name) = stack.last() {
+                        fields.push((name.clone(), e.unescape()?.to_string()));
+                    }
+                }
+                Event::End(_) => {
+                    stack.pop();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(Self {
+            action: action.ok_or_else(|| anyhow!("SOAP request has no body"))?,
+            fields,
+            password_type,
+        })
+    }
+
+    /// The text of the first element with this local name
+    pub(super) fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The user of a valid WS-Security UsernameToken
+    ///
+    /// Users without a password cannot log in this way. A digest must be
+    /// `Created` within a few minutes of now and its nonce is only accepted once
+    pub(super) fn authenticated_user(
+        &self,
+        users: &[UserConfig],
+        nonces: &SeenNonces,
+    ) -> Option<String> {
+        let username = self.field("Username")?;
+        let password = self.field("Password").filter(|p| !p.is_empty())?;
+        let user = users.iter().find(|user| user.name == username)?;
+        let expected = user.password()?;
+        let valid = if self.password_type.as_deref() == Some(PASSWORD_DIGEST) {
+            // Digest = Base64(SHA1(nonce + created + password))
+            let nonce = BASE64.decode(self.field("Nonce")?).ok()?;
+            let created = self.field("Created")?;
+            let age = OffsetDateTime::now_utc() - OffsetDateTime::parse(created, &Rfc3339).ok()?;
+            if nonce.is_empty() || age.unsigned_abs() > CREATED_WINDOW {
+                return None;
+            }
+            let mut context = ring::digest::Context::new(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY);
+            context.update(&nonce);
+            context.update(created.as_bytes());
+            context.update(expected.as_bytes());
+            BASE64.encode(context.finish()) == password && nonces.insert(nonce)
+        } else {
+            user.password_matches(password)
+        };
+        valid.then(|| user.name.clone())
+    }
+}
+
+/// Wrap the body of a reply in a SOAP envelope
+pub(super) fn envelope(body: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope""#,
+            r#" xmlns:xs="http://www.w3.org/2001/XMLSchema""#,
+            r#" xmlns:tt="http://www.onvif.org/ver10/schema""#,
+            r#" xmlns:tds="http://www.onvif.org/ver10/device/wsdl""#,
+            r#" xmlns:trt="http://www.onvif.org/ver10/media/wsdl""#,
+            r#" xmlns:tev="http://www.onvif.org/ver10/events/wsdl""#,
+            r#" xmlns:ter="http://www.onvif.org/ver10/error""#,
+            r#" xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2""#,
+            r#" xmlns:wstop="http://docs.oasis-open.org/wsn/t-1""#,
+            r#" xmlns:wsa="http://www.w3.org/2005/08/addressing""#,
+            r#" xmlns:tns1="http://www.onvif.org/ver10/topics">"#,
+            "<s:Body>{}</s:Body></s:Envelope>"
+        ),
+        body
+    )
+}
+
+/// A SOAP fault
+///
+/// `code` is `Sender` or `Receiver` and `subcode` one of the ONVIF `ter:` codes
+pub(super) fn fault(code: &str, subcode: &str, reason: &str) -> String {
+    envelope(&format!(
+        concat!(
+            "<s:Fault><s:Code><s:Value>s:{}</s:Value>",
+            "<s:Subcode><s:Value>{}</s:Value></s:Subcode></s:Code>",
+            r#"<s:Reason><s:Text xml:lang="en">{}</s:Text></s:Reason></s:Fault>"#
+        ),
+        code,
+        subcode,
+        xml(reason)
+    ))
+}
+
+/// Escape text for use in xml
+pub(super) fn xml(text: &str) -> String {
+    escape(text).to_string()
+}
+
+/// Format as an `xs:dateTime` in UTC
+pub(super) fn xml_time(time: OffsetDateTime) -> String {
+    let time = time.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+/// Parse an `xs:duration` such as `PT60S` or `PT1H30M`
+///
+/// Years and months are not supported
+pub(super) fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().strip_prefix('P')?;
+    let mut seconds = 0f64;
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in text.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            unit => {
+                let value = number.parse::<f64>().ok()?;
+                number.clear();
+                seconds += value
+                    * match (unit, in_time) {
+                        ('D', false) => 86400.0,
+                        ('H', true) => 3600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return None;
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
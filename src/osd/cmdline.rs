@@ -0,0 +1,32 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The osd command will change the text drawn over the video
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The camera name to show on the video
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Whether to show the camera name
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub show_name: Option<bool>,
+    /// Whether to show the timestamp
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub timestamp: Option<bool>,
+    /// Whether to show the Reolink logo
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub watermark: Option<bool>,
+}
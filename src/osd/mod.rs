@@ -0,0 +1,69 @@
+///
+/// # Neolink OSD
+///
+/// This module handles the text drawn over the video such as the camera
+/// name and the timestamp
+///
+/// # Usage
+///
+/// ```bash
+/// # Print the current settings
+/// neolink osd --config=config.toml CameraName
+/// # Rename the camera and hide the timestamp
+/// neolink osd --config=config.toml CameraName --name Driveway --timestamp off
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{
+    common::{update_camera_osd, NeoReactor},
+    config::OsdConfig,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the osd subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let osd = OsdConfig {
+        name: opt.name,
+        show_name: opt.show_name,
+        timestamp: opt.timestamp,
+        watermark: opt.watermark,
+    };
+
+    if osd.name.is_some()
+        || osd.show_name.is_some()
+        || osd.timestamp.is_some()
+        || osd.watermark.is_some()
+    {
+        camera
+            .run_task(|cam| {
+                let osd = osd.clone();
+                Box::pin(async move {
+                    update_camera_osd(cam, &osd)
+                        .await
+                        .context("Unable to set camera osd")
+                })
+            })
+            .await?;
+    } else {
+        let (channel_name, datetime) = camera
+            .run_task(|cam| {
+                Box::pin(async move { cam.get_osd().await.context("Unable to get camera osd") })
+            })
+            .await?;
+        let onoff = |enable: u8| if enable == 0 { "off" } else { "on" };
+        println!("name: {}", channel_name.name);
+        println!("show_name: {}", onoff(channel_name.enable));
+        println!("timestamp: {}", onoff(datetime.enable));
+        if let Some(watermark) = channel_name.watermark {
+            println!("watermark: {}", onoff(watermark));
+        }
+    }
+
+    Ok(())
+}
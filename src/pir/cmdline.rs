@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use std::path::PathBuf;
 
 fn onoff_parse(src: &str) -> Result<bool> {
     match src {
@@ -20,4 +21,10 @@ pub struct Opt {
     /// Whether to turn the PIR ON or OFF
     #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
     pub on: Option<bool>,
+    /// The sensitivity from 1 to 100, higher detects smaller or further movements
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub sensitivity: Option<u8>,
+    /// A toml file of the hours that the PIR is active
+    #[arg(long, value_name = "FILE")]
+    pub schedule: Option<PathBuf>,
 }
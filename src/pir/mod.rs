@@ -3,6 +3,22 @@
 ///
 /// This module handles the controls of the pir sensor alarm
 ///
+/// Along with turning it on and off the sensitivity and the schedule of
+/// when it is active can be set. The schedule is a toml file of blocks of
+/// hours, days that are not in any block are off
+///
+/// ```toml
+/// # Every night
+/// [[blocks]]
+/// begin_hour = 0
+/// end_hour = 7
+///
+/// # All day at the weekend
+/// [[blocks]]
+/// days = ["Saturday", "Sunday"]
+/// begin_hour = 0
+/// end_hour = 23
+/// ```
 ///
 /// # Usage
 ///
@@ -11,26 +27,124 @@
 /// neolink pir --config=config.toml CameraName on
 /// # Or off
 /// neolink pir --config=config.toml CameraName off
+/// # Change the sensitivity and schedule
+/// neolink pir --config=config.toml CameraName --sensitivity 50 --schedule pir.toml
 /// ```
 ///
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::{TimeBlock, TimeBlockList};
+use serde::Deserialize;
 
 mod cmdline;
 
 use crate::common::NeoReactor;
 pub(crate) use cmdline::Opt;
 
+const WEEK_DAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// The schedule file of `--schedule`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PirSchedule {
+    #[serde(default)]
+    blocks: Vec<PirScheduleBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PirScheduleBlock {
+    /// Every day if empty
+    #[serde(default)]
+    days: Vec<String>,
+    begin_hour: u8,
+    end_hour: u8,
+}
+
+impl PirSchedule {
+    fn time_blocks(&self) -> Result<TimeBlockList> {
+        let mut time_block = vec![];
+        for block in self.blocks.iter() {
+            if block.begin_hour > block.end_hour || block.end_hour > 23 {
+                return Err(anyhow!(
+                    "The hours {}-{} should be from 0 to 23 and begin before they end",
+                    block.begin_hour,
+                    block.end_hour
+                ));
+            }
+            let days = if block.days.is_empty() {
+                WEEK_DAYS.to_vec()
+            } else {
+                block
+                    .days
+                    .iter()
+                    .map(|day| {
+                        WEEK_DAYS
+                            .iter()
+                            .find(|week_day| week_day.eq_ignore_ascii_case(day))
+                            .copied()
+                            .ok_or_else(|| anyhow!("{} is not a day of the week", day))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            };
+            for day in days {
+                time_block.push(TimeBlock {
+                    enable: 1,
+                    week_day: day.to_string(),
+                    begin_hour: block.begin_hour,
+                    end_hour: block.end_hour,
+                });
+            }
+        }
+        Ok(TimeBlockList { time_block })
+    }
+}
+
 /// Entry point for the pir subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    if let Some(on) = opt.on {
+    let schedule = match opt.schedule.as_ref() {
+        Some(path) => {
+            let data = tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Could not read the schedule {:?}", path))?;
+            let schedule: PirSchedule = toml::from_str(&data)
+                .with_context(|| format!("Could not understand the schedule {:?}", path))?;
+            Some(schedule.time_blocks()?)
+        }
+        None => None,
+    };
+
+    if opt.on.is_some() || opt.sensitivity.is_some() || schedule.is_some() {
+        let (on, sensitivity) = (opt.on, opt.sensitivity);
         camera
             .run_task(|cam| {
+                let schedule = schedule.clone();
                 Box::pin(async move {
-                    cam.pir_set(on)
+                    let mut pir_state = cam
+                        .get_pirstate()
+                        .await
+                        .context("Unable to get camera PIR state")?;
+                    if let Some(on) = on {
+                        pir_state.enable = if on { 1 } else { 0 };
+                    }
+                    if let Some(sensitivity) = sensitivity {
+                        pir_state.sensitivity = sensitivity;
+                    }
+                    if let Some(schedule) = schedule {
+                        pir_state.time_block_list = schedule;
+                    }
+                    cam.set_pirstate(pir_state)
                         .await
                         .context("Unable to set camera PIR state")
                 })
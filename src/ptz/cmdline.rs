@@ -1,3 +1,4 @@
+use crate::cmdline::CameraSelection;
 use clap::Parser;
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -13,16 +14,24 @@ pub enum CmdDirection {
 #[derive(Parser, Debug)]
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
-    pub camera: String,
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
 
     #[command(subcommand)]
     pub cmd: PtzCommand,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub enum PtzCommand {
-    /// Move to a stored preset
-    Preset { preset_id: Option<u8> },
+    /// Move to a stored preset or list them when no preset is given
+    #[command(args_conflicts_with_subcommands = true)]
+    Preset {
+        preset_id: Option<u8>,
+        #[command(subcommand)]
+        action: Option<PresetAction>,
+    },
     /// Assign the current position to a preset with a given name
     Assign { preset_id: u8, name: String },
     /// Performs a movement in the given direction
@@ -39,4 +48,23 @@ pub enum PtzCommand {
         /// The amount to zoom to
         amount: f32,
     },
+    /// Starts moving in the given direction until `move stop`
+    Move {
+        /// The direction command
+        #[clap(value_enum)]
+        command: CmdDirection,
+        /// The speed to move at
+        #[arg(long)]
+        speed: Option<u32>,
+    },
+}
+
+#[derive(Parser, Debug, Clone)]
+pub enum PresetAction {
+    /// List the stored presets
+    List,
+    /// Save the current position as a preset with a given name
+    Save { preset_id: u8, name: String },
+    /// Move to a stored preset
+    Goto { preset_id: u8 },
 }
@@ -16,6 +16,13 @@
 /// neolink ptz --config=config.toml CameraName preset 0
 /// # Save the current position as preset ID 0 with name PresetName
 /// neolink ptz --config=config.toml CameraName assign 0 PresetName
+/// # The same with the preset subcommands
+/// neolink ptz --config=config.toml CameraName preset list
+/// neolink ptz --config=config.toml CameraName preset save 0 PresetName
+/// neolink ptz --config=config.toml CameraName preset goto 0
+/// # Keep rotating left until stopped
+/// neolink ptz --config=config.toml CameraName move left --speed 10
+/// neolink ptz --config=config.toml CameraName move stop
 /// ```
 ///
 use anyhow::{Context, Result};
@@ -23,9 +30,10 @@ use tokio::time::{sleep, Duration};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::common::{NeoInstance, NeoReactor};
 use crate::ptz::cmdline::CmdDirection;
-use crate::ptz::cmdline::PtzCommand;
+use crate::ptz::cmdline::{PresetAction, PtzCommand};
+use crate::utils::for_each_camera;
 pub(crate) use cmdline::Opt;
 use neolink_core::bc_protocol::Direction;
 
@@ -33,10 +41,38 @@ use neolink_core::bc_protocol::Direction;
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
+    for_each_camera(&reactor, opt.camera.as_deref(), &opt.select, |camera| {
+        camera_main(&opt, camera)
+    })
+    .await
+}
 
-    match opt.cmd {
-        PtzCommand::Preset { preset_id } => {
+async fn camera_main(opt: &Opt, camera: NeoInstance) -> Result<String> {
+    let mut output = String::new();
+    match opt.cmd.clone() {
+        PtzCommand::Preset {
+            action: Some(PresetAction::Save { preset_id, name }),
+            ..
+        }
+        | PtzCommand::Assign { preset_id, name } => {
+            camera
+                .run_task(|cam| {
+                    let name = name.clone();
+                    Box::pin(async move {
+                        cam.set_ptz_preset(preset_id, name)
+                            .await
+                            .context("Unable to set PTZ preset")?;
+                        Ok(())
+                    })
+                })
+                .await?;
+        }
+        PtzCommand::Preset { preset_id, action } => {
+            let preset_id = match action {
+                Some(PresetAction::Goto { preset_id }) => Some(preset_id),
+                Some(PresetAction::List) => None,
+                _ => preset_id,
+            };
             if let Some(preset_id) = preset_id {
                 camera
                     .run_task(|cam| {
@@ -61,37 +97,18 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                     })
                     .await?;
 
-                println!("Available presets:\nID Name");
+                output.push_str("Available presets:\nID Name");
                 for preset in preset_list.preset_list.preset {
-                    println!("{:<2} {:?}", preset.id, preset.name);
+                    output.push_str(&format!("\n{:<2} {:?}", preset.id, preset.name));
                 }
             }
         }
-        PtzCommand::Assign { preset_id, name } => {
-            camera
-                .run_task(|cam| {
-                    let name = name.clone();
-                    Box::pin(async move {
-                        cam.set_ptz_preset(preset_id, name)
-                            .await
-                            .context("Unable to set PTZ preset")?;
-                        Ok(())
-                    })
-                })
-                .await?;
-        }
         PtzCommand::Control {
             amount,
             command,
             speed,
         } => {
-            let direction = match command {
-                CmdDirection::Left => Direction::Left,
-                CmdDirection::Right => Direction::Right,
-                CmdDirection::Up => Direction::Up,
-                CmdDirection::Down => Direction::Down,
-                CmdDirection::Stop => Direction::Stop,
-            };
+            let direction = command.as_direction();
             let speed = speed.unwrap_or(32) as f32;
             let seconds = amount as f32 / speed;
             let duration = Duration::from_secs_f32(seconds);
@@ -118,6 +135,20 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                 })
                 .await?;
         }
+        PtzCommand::Move { command, speed } => {
+            let direction = command.as_direction();
+            let speed = speed.unwrap_or(32) as f32;
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.send_ptz(direction, speed)
+                            .await
+                            .context("Unable to execute PTZ move command")?;
+                        Ok(())
+                    })
+                })
+                .await?;
+        }
         PtzCommand::Zoom { amount } => {
             camera
                 .run_task(|cam| {
@@ -133,5 +164,17 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         }
     };
 
-    Ok(())
+    Ok(output)
+}
+
+impl CmdDirection {
+    fn as_direction(&self) -> Direction {
+        match self {
+            CmdDirection::Left => Direction::Left,
+            CmdDirection::Right => Direction::Right,
+            CmdDirection::Up => Direction::Up,
+            CmdDirection::Down => Direction::Down,
+            CmdDirection::Stop => Direction::Stop,
+        }
+    }
 }
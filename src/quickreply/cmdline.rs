@@ -0,0 +1,16 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The quickreply command will play a quick reply message of a doorbell
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// The id of the quick reply message as stored on the doorbell
+    #[arg(long)]
+    pub id: u32,
+}
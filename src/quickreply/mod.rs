@@ -0,0 +1,43 @@
+///
+/// # Neolink Quick Reply
+///
+/// This module handles playing the quick reply messages of a doorbell
+///
+/// # Usage
+///
+/// ```bash
+/// # Play the quick reply message with the id 1
+/// neolink quickreply --config=config.toml CameraName --id 1
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the quickreply subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let id = opt.id;
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.quick_reply(id)
+                            .await
+                            .context("Unable to play the quick reply")
+                    })
+                })
+                .await?;
+            Ok(String::new())
+        },
+    )
+    .await
+}
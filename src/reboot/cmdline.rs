@@ -1,8 +1,22 @@
+use crate::cmdline::CameraSelection;
 use clap::Parser;
 
 /// The reboot command will reboot the camera
 #[derive(Parser, Debug)]
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
-    pub camera: String,
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Wait for the camera to finish writing any clip to its storage first
+    #[arg(long)]
+    pub safe: bool,
+    /// How long in seconds `--safe` waits for the camera to stop recording
+    #[arg(long, default_value_t = 120, requires = "safe")]
+    pub wait: u64,
+    /// Reboot straight away without waiting for the camera to stop recording,
+    /// even with `--safe`
+    #[arg(long)]
+    pub force: bool,
 }
@@ -9,31 +9,49 @@
 ///
 /// ```bash
 /// neolink reboot --config=config.toml CameraName
+/// # Wait for any recording to finish first
+/// neolink reboot --config=config.toml --safe CameraName
 /// ```
 ///
 use anyhow::{Context, Result};
+use std::time::Duration;
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{
+    common::NeoReactor,
+    utils::{for_each_camera_control, wait_until_idle},
+};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the reboot subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
-
-    camera
-        .run_task(|camera| {
-            Box::pin(async move {
-                camera
-                    .reboot()
-                    .await
-                    .context("Could not send reboot command to the camera")
-            })
-        })
-        .await?;
-
-    Ok(())
+    let (safe, wait, force) = (opt.safe, Duration::from_secs(opt.wait), opt.force);
+    // A running neolink waits for its own two minutes with `safe`
+    let mode = if safe && !force { "safe" } else { "force" };
+    for_each_camera_control(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        Some(("reboot", mode)),
+        |camera| async move {
+            if safe {
+                wait_until_idle(&camera, wait, force).await?;
+            }
+            camera
+                .run_task(|camera| {
+                    Box::pin(async move {
+                        camera
+                            .reboot()
+                            .await
+                            .context("Could not send reboot command to the camera")
+                    })
+                })
+                .await?;
+            Ok(String::new())
+        },
+    )
+    .await
 }
@@ -0,0 +1,33 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::StreamKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn stream_parse(src: &str) -> Result<StreamKind> {
+    match src {
+        "main" | "mainStream" => Ok(StreamKind::Main),
+        "sub" | "subStream" => Ok(StreamKind::Sub),
+        "extern" | "externStream" => Ok(StreamKind::Extern),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be main, sub or extern",
+            src
+        )),
+    }
+}
+
+/// The record command will save the live stream of the camera to a file
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The stream to record, main, sub or extern
+    #[arg(long, default_value = "main", value_parser = stream_parse)]
+    pub stream: StreamKind,
+    /// Seconds to record for, without it the recording runs until Ctrl-C
+    #[arg(short, long)]
+    pub duration: Option<u64>,
+    /// The file to save to, the extension picks the format: `.mp4` or `.mkv`
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: PathBuf,
+}
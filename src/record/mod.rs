@@ -0,0 +1,106 @@
+///
+/// # Neolink Record
+///
+/// This module handles the record subcommand
+///
+/// The subcommand saves the live stream of a camera into an mp4 or mkv file
+/// without the rtsp server or ffmpeg. It stops after `--duration` seconds or
+/// on Ctrl-C, the file is finished either way.
+///
+/// Only the video is recorded
+///
+/// # Usage
+///
+/// ```bash
+/// neolink record --config=config.toml CameraName --stream main --duration 60 --output out.mkv
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use std::path::Path;
+use tokio::time::{Duration, Instant};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoReactor, Recording},
+    config::RecordFormat,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the record subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let format = record_format(&opt.output)?;
+    let camera = reactor.get(&opt.camera).await?;
+    let mut media_rx = camera.stream(opt.stream).await?;
+    let mut recording = Recording::new(opt.output.clone(), format);
+
+    match opt.duration {
+        Some(duration) => info!(
+            "{}: Recording {} for {}s to {:?}",
+            opt.camera, opt.stream, duration, opt.output
+        ),
+        None => info!(
+            "{}: Recording {} to {:?}, press Ctrl-C to stop",
+            opt.camera, opt.stream, opt.output
+        ),
+    }
+    // The duration is counted from the first frame that is written, not from
+    // the time taken to connect
+    let duration = opt.duration.map(Duration::from_secs);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let r = loop {
+        let stop_at = recording
+            .started()
+            .zip(duration)
+            .map(|(started, duration)| started + duration);
+        tokio::select! {
+            media = media_rx.recv() => {
+                let Some(media) = media else {
+                    break Err(anyhow!("The camera stream ended"));
+                };
+                if let Err(e) = recording.push(Instant::now(), media) {
+                    break Err(e);
+                }
+            }
+            _ = sleep_until_some(stop_at) => break Ok(()),
+            v = &mut ctrl_c => {
+                info!("{}: Stopping the recording", opt.camera);
+                break v.context("Could not listen for Ctrl-C");
+            }
+        }
+    };
+
+    // Keep what was recorded even when the stream failed
+    match recording.finish().await? {
+        Some(path) => info!("{}: Recording saved to {:?}", opt.camera, path),
+        None => warn!("{}: No video was recorded", opt.camera),
+    }
+    r
+}
+
+async fn sleep_until_some(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn record_format(output: &Path) -> Result<RecordFormat> {
+    match output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .as_deref()
+    {
+        Some("mp4") => Ok(RecordFormat::Mp4),
+        Some("mkv") => Ok(RecordFormat::Mkv),
+        _ => Err(anyhow!(
+            "Could not tell the format of {:?}, it should end in .mp4 or .mkv",
+            output
+        )),
+    }
+}
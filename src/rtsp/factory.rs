@@ -1,18 +1,48 @@
 use gstreamer::ClockTime;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::{TryFrom, TryInto},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{anyhow, Context, Result};
-use gstreamer::{prelude::*, Bin, Caps, Element, ElementFactory, FlowError, GhostPad};
-use gstreamer_app::{AppSrc, AppSrcCallbacks, AppStreamType};
+use gstreamer::{prelude::*, Bin, Caps, Element, FlowError, GhostPad};
+use gstreamer_app::{AppLeakyType, AppSink, AppSrc, AppSrcCallbacks, AppStreamType};
 use neolink_core::{
-    bc_protocol::StreamKind,
-    bcmedia::model::{
-        BcMedia, BcMediaIframe, BcMediaInfoV1, BcMediaInfoV2, BcMediaPframe, VideoType,
+    bc::xml::{FileInfo, ReplayTime, TalkConfig},
+    bc_protocol::{Error as BcError, StreamKind},
+    bcmedia::{
+        clock::MediaClock,
+        model::{
+            BcMedia, BcMediaAac, BcMediaAdpcm, BcMediaIframe, BcMediaInfoV1, BcMediaInfoV2,
+            BcMediaPframe, VideoType,
+        },
+    },
+};
+use time::{Date as TimeDate, Month as TimeMonth};
+use tokio::{
+    sync::{
+        mpsc::{channel as mpsc, Sender as MpscSender},
+        watch::channel as watch,
     },
+    task::JoinHandle,
+    time::timeout,
 };
-use tokio::{sync::mpsc::channel as mpsc, task::JoinHandle};
 
-use crate::{common::NeoInstance, rtsp::gst::NeoMediaFactory, AnyResult};
+use crate::{
+    common::{make_element, NeoInstance, StreamCounter},
+    config::{
+        AudioCodec, BufferFull, CameraConfig, CompositeConfig, CompositeLayout, FlipDirection,
+        TranscodeEncoder,
+    },
+    rtsp::gst::NeoMediaFactory,
+    AnyResult,
+};
 
 #[derive(Clone, Debug)]
 pub enum AudioType {
@@ -22,7 +52,6 @@ pub enum AudioType {
 
 #[derive(Clone, Debug)]
 struct StreamConfig {
-    #[allow(dead_code)]
     resolution: [u32; 2],
     bitrate: u32,
     fps: u32,
@@ -30,9 +59,15 @@ struct StreamConfig {
     fps_table: Vec<u32>,
     vid_type: Option<VideoType>,
     aud_type: Option<AudioType>,
+    /// The `buffer_size` of the camera in bytes
+    buffer_size: Option<u32>,
+    /// The `latency` of the camera in ms
+    latency: Option<u32>,
+    on_full: BufferFull,
 }
 impl StreamConfig {
     async fn new(instance: &NeoInstance, name: StreamKind) -> AnyResult<Self> {
+        let camera_config = instance.config().await?.borrow().clone();
         let (resolution, bitrate, fps, fps_table, bitrate_table) = instance
             .run_passive_task(|cam| {
                 Box::pin(async move {
@@ -92,9 +127,43 @@ impl StreamConfig {
             bitrate_table,
             vid_type: None,
             aud_type: None,
+            buffer_size: camera_config
+                .buffer_size
+                .map(|size| size.saturating_mul(1024)),
+            latency: camera_config.latency,
+            on_full: camera_config.on_full,
         })
     }
 
+    /// Bytes of video buffered by the appsrc and its queue
+    fn video_buffer_size(&self) -> u32 {
+        self.buffer_size
+            .unwrap_or_else(|| buffer_size(self.bitrate))
+    }
+
+    /// Set the latency and what happens when full on an appsrc
+    fn tune_appsrc(&self, source: &AppSrc) {
+        match self.latency {
+            Some(latency) => source.set_min_latency(latency as i64 * 1_000_000),
+            None => source.set_min_latency(1000 / (self.fps.max(1) as i64)),
+        }
+        source.set_block(self.on_full == BufferFull::Block);
+        match self.on_full {
+            BufferFull::DropNew => source.set_leaky_type(AppLeakyType::Upstream),
+            BufferFull::DropOld => source.set_leaky_type(AppLeakyType::Downstream),
+            BufferFull::Queue | BufferFull::Block => source.set_leaky_type(AppLeakyType::None),
+        }
+    }
+
+    /// The queue after an appsrc drops the same way as the appsrc
+    fn tune_queue(&self, queue: &Element) {
+        match self.on_full {
+            BufferFull::DropNew => queue.set_property_from_str("leaky", "upstream"),
+            BufferFull::DropOld => queue.set_property_from_str("leaky", "downstream"),
+            BufferFull::Queue | BufferFull::Block => {}
+        }
+    }
+
     fn update_fps(&mut self, fps: u32) {
         let new_fps = self.fps_table.get(fps as usize).copied().unwrap_or(fps);
         self.fps = new_fps;
@@ -111,8 +180,23 @@ impl StreamConfig {
 
     fn update_from_media(&mut self, media: &BcMedia) {
         match media {
-            BcMedia::InfoV1(BcMediaInfoV1 { fps, .. })
-            | BcMedia::InfoV2(BcMediaInfoV2 { fps, .. }) => self.update_fps(*fps as u32),
+            BcMedia::InfoV1(BcMediaInfoV1 {
+                fps,
+                video_width,
+                video_height,
+                ..
+            })
+            | BcMedia::InfoV2(BcMediaInfoV2 {
+                fps,
+                video_width,
+                video_height,
+                ..
+            }) => {
+                self.update_fps(*fps as u32);
+                if *video_width > 0 && *video_height > 0 {
+                    self.resolution = [*video_width, *video_height];
+                }
+            }
             BcMedia::Aac(_) => {
                 self.aud_type = Some(AudioType::Aac);
             }
@@ -127,16 +211,127 @@ impl StreamConfig {
     }
 }
 
+/// The frames of a stream since its last keyframe
+///
+/// Each client starts its own stream from the camera which can begin part
+/// way through a GOP. A new client is sent these first so that it can decode
+/// from a keyframe, the frames of one client at a time are kept
+#[derive(Default)]
+struct GopCache {
+    /// The client whose frames are kept
+    feeder: Option<u64>,
+    updated: Option<Instant>,
+    frames: Vec<BcMedia>,
+}
+
+impl GopCache {
+    /// Frames older than this are not sent to a new client
+    const MAX_AGE: Duration = Duration::from_secs(1);
+    /// Stop keeping frames if a keyframe never comes
+    const MAX_FRAMES: usize = 600;
+
+    fn is_fresh(&self) -> bool {
+        self.updated
+            .is_some_and(|updated| updated.elapsed() < Self::MAX_AGE)
+    }
+
+    /// True while the frames of `client` are the ones kept
+    fn push(&mut self, client: u64, media: &BcMedia) -> bool {
+        if self.feeder.is_some_and(|feeder| feeder != client) && self.is_fresh() {
+            return false;
+        }
+        if self.feeder != Some(client) {
+            self.feeder = Some(client);
+            self.frames.clear();
+        }
+        match media {
+            BcMedia::Iframe(_) => self.frames.clear(),
+            // Nothing can be decoded before the first keyframe
+            _ if self.frames.is_empty() => return true,
+            BcMedia::InfoV1(_) | BcMedia::InfoV2(_) => return true,
+            _ if self.frames.len() >= Self::MAX_FRAMES => return true,
+            _ => {}
+        }
+        self.frames.push(media.clone());
+        self.updated = Some(Instant::now());
+        true
+    }
+
+    /// Push from a client's own thread
+    ///
+    /// All the clients of a stream share the cache. The others only take the
+    /// lock on a keyframe, which is the only point that they can take over
+    /// from, so the lock is not fought over for every frame.
+    fn push_shared(cache: &StdMutex<Self>, feeding: &mut bool, client: u64, media: &BcMedia) {
+        if *feeding || matches!(media, BcMedia::Iframe(_)) {
+            if let Ok(mut gop) = cache.lock() {
+                *feeding = gop.push(client, media);
+            }
+        }
+    }
+
+    /// The frames from the last keyframe while they are still current
+    fn frames(&self) -> Vec<BcMedia> {
+        if self.is_fresh() {
+            self.frames.clone()
+        } else {
+            vec![]
+        }
+    }
+
+    fn leave(&mut self, client: u64) {
+        if self.feeder == Some(client) {
+            *self = Default::default();
+        }
+    }
+}
+
+/// What is shown while a stream is not ready
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Splash {
+    /// One of the `videotestsrc` patterns
+    Pattern { pattern: String, text: String },
+    /// A picture from `pause.image`
+    Image { path: PathBuf, text: String },
+}
+
+impl Splash {
+    pub(super) fn from_config(config: &CameraConfig) -> Self {
+        match config.pause.image.as_ref() {
+            Some(path) if config.pause.mode == "image" => Splash::Image {
+                path: path.clone(),
+                text: config.name.clone(),
+            },
+            _ => Splash::Pattern {
+                pattern: match config.pause.mode.as_str() {
+                    "black" => "black".to_string(),
+                    _ => config.splash_pattern.to_string(),
+                },
+                text: "Stream not Ready".to_string(),
+            },
+        }
+    }
+
+    /// The splash shown while the camera is lost
+    pub(super) fn offline(config: &CameraConfig) -> Self {
+        let text = format!("{}: Offline", config.name);
+        match Self::from_config(config) {
+            Splash::Pattern { pattern, .. } => Splash::Pattern { pattern, text },
+            Splash::Image { path, .. } => Splash::Image { path, text },
+        }
+    }
+}
+
 pub(super) async fn make_dummy_factory(
     use_splash: bool,
-    pattern: String,
+    splash: Splash,
 ) -> AnyResult<NeoMediaFactory> {
     NeoMediaFactory::new_with_callback(move |element| {
         clear_bin(&element)?;
         if !use_splash {
             Ok(None)
         } else {
-            build_unknown(&element, &pattern)?;
+            build_unknown(&element, &splash)?;
             Ok(Some(element))
         }
     })
@@ -153,11 +348,16 @@ enum ClientMsg {
 pub(super) async fn make_factory(
     camera: NeoInstance,
     stream: StreamKind,
+    talkers: HashSet<String>,
+    sent: Arc<AtomicU64>,
 ) -> AnyResult<(NeoMediaFactory, JoinHandle<AnyResult<()>>)> {
     let (client_tx, mut client_rx) = mpsc(100);
+    let talk_camera = camera.clone();
     // Create the task that creates the pipelines
     let thread = tokio::task::spawn(async move {
         let name = camera.config().await?.borrow().name.clone();
+        let gop = Arc::new(StdMutex::new(GopCache::default()));
+        let mut next_client = 0u64;
 
         while let Some(msg) = client_rx.recv().await {
             match msg {
@@ -165,6 +365,10 @@ pub(super) async fn make_factory(
                     log::debug!("New client for {name}::{stream}");
                     let camera = camera.clone();
                     let name = name.clone();
+                    let gop = gop.clone();
+                    let sent = sent.clone();
+                    let client = next_client;
+                    next_client += 1;
                     tokio::task::spawn(async move {
                         clear_bin(&element)?;
                         log::trace!("{name}::{stream}: Starting camera");
@@ -173,12 +377,26 @@ pub(super) async fn make_factory(
                         let config = camera.config().await?.borrow().clone();
                         let mut media_rx = camera.stream_while_live(stream).await?;
 
+                        let mut stream_config = StreamConfig::new(&camera, stream).await?;
+
+                        // Start on the frames since the last keyframe of another client
+                        // so that there is a picture straight away
+                        let cached = gop.lock().map(|gop| gop.frames()).unwrap_or_default();
+                        if !cached.is_empty() {
+                            log::debug!(
+                                "{name}::{stream}: Starting on {} cached frames",
+                                cached.len()
+                            );
+                        }
+                        for media in cached.iter() {
+                            stream_config.update_from_media(media);
+                        }
+
                         log::trace!("{name}::{stream}: Learning camera stream type");
                         // Learn the camera data type
                         let mut buffer = vec![];
                         let mut frame_count = 0usize;
 
-                        let mut stream_config = StreamConfig::new(&camera, stream).await?;
                         while let Some(media) = media_rx.recv().await {
                             stream_config.update_from_media(&media);
                             buffer.push(media);
@@ -192,30 +410,57 @@ pub(super) async fn make_factory(
                         }
 
                         log::trace!("{name}::{stream}: Building the pipeline");
+                        let filter = video_filter(&camera, &config).await;
                         // Build the right video pipeline
                         let vid_src = match stream_config.vid_type.as_ref() {
+                            Some(video_type) if filter.is_some() => {
+                                let src = build_reencoded(
+                                    &element,
+                                    &stream_config,
+                                    &config,
+                                    *video_type,
+                                    filter.as_deref(),
+                                )?;
+                                AnyResult::Ok(Some(src))
+                            }
                             Some(VideoType::H264) => {
                                 let src = build_h264(&element, &stream_config)?;
                                 AnyResult::Ok(Some(src))
                             }
+                            Some(VideoType::H265) if config.transcodes(stream) => {
+                                let src = build_reencoded(
+                                    &element,
+                                    &stream_config,
+                                    &config,
+                                    VideoType::H265,
+                                    None,
+                                )?;
+                                AnyResult::Ok(Some(src))
+                            }
                             Some(VideoType::H265) => {
                                 let src = build_h265(&element, &stream_config)?;
                                 AnyResult::Ok(Some(src))
                             }
                             None => {
-                                build_unknown(&element, &config.splash_pattern.to_string())?;
+                                build_unknown(&element, &Splash::from_config(&config))?;
                                 AnyResult::Ok(None)
                             }
                         }?;
 
                         // Build the right audio pipeline
                         let aud_src = match stream_config.aud_type.as_ref() {
+                            _ if config.audio == AudioCodec::None => AnyResult::Ok(None),
                             Some(AudioType::Aac) => {
-                                let src = build_aac(&element, &stream_config)?;
+                                let src = build_aac(&element, &stream_config, config.audio)?;
                                 AnyResult::Ok(Some(src))
                             }
                             Some(AudioType::Adpcm(block_size)) => {
-                                let src = build_adpcm(&element, *block_size, &stream_config)?;
+                                let src = build_adpcm(
+                                    &element,
+                                    *block_size,
+                                    &stream_config,
+                                    config.audio,
+                                )?;
                                 AnyResult::Ok(Some(src))
                             }
                             None => AnyResult::Ok(None),
@@ -236,6 +481,9 @@ pub(super) async fn make_factory(
                             );
                         }
 
+                        let stats_tx = camera.stream_stats_sender().await?;
+                        let stats_interval = Duration::from_secs(config.stats_interval);
+
                         log::trace!("{name}::{stream}: Sending pipeline to gstreamer");
                         // Send the pipeline back to the factory so it can start
                         let _ = reply.send(element);
@@ -243,41 +491,62 @@ pub(super) async fn make_factory(
                         // Run blocking code on a seperate thread
                         // This is not an async thread
                         std::thread::spawn(move || {
-                            let mut aud_ts = 0u32;
-                            let mut vid_ts = 0u32;
+                            let mut clock = MediaClock::new(stream_config.fps);
                             let mut pools = Default::default();
+                            let mut feeding = false;
+
+                            log::trace!("{name}::{stream}: Sending cached frames");
+                            for cached in cached.into_iter() {
+                                send_to_sources(
+                                    cached, &mut pools, &vid_src, &aud_src, &mut clock, &sent,
+                                )?;
+                            }
 
                             log::trace!("{name}::{stream}: Sending buffered frames");
                             for buffered in buffer.drain(..) {
+                                GopCache::push_shared(&gop, &mut feeding, client, &buffered);
                                 send_to_sources(
-                                    buffered,
-                                    &mut pools,
-                                    &vid_src,
-                                    &aud_src,
-                                    &mut vid_ts,
-                                    &mut aud_ts,
-                                    &stream_config,
+                                    buffered, &mut pools, &vid_src, &aud_src, &mut clock, &sent,
                                 )?;
                             }
 
                             log::trace!("{name}::{stream}: Sending new frames");
-                            while let Some(data) = media_rx.blocking_recv() {
+                            let mut counter = StreamCounter::new();
+                            let res = loop {
+                                let Some(data) = media_rx.blocking_recv() else {
+                                    break AnyResult::Ok(());
+                                };
+                                if !stats_interval.is_zero() {
+                                    counter.media(&data);
+                                }
+                                GopCache::push_shared(&gop, &mut feeding, client, &data);
                                 let r = send_to_sources(
-                                    data,
-                                    &mut pools,
-                                    &vid_src,
-                                    &aud_src,
-                                    &mut vid_ts,
-                                    &mut aud_ts,
-                                    &stream_config,
+                                    data, &mut pools, &vid_src, &aud_src, &mut clock, &sent,
                                 );
-                                if let Err(r) = &r {
-                                    log::info!("Failed to send to source: {r:?}");
+                                match r {
+                                    Ok(true) => counter.dropped(),
+                                    Ok(false) => {}
+                                    Err(r) => {
+                                        log::info!("Failed to send to source: {r:?}");
+                                        break Err(r);
+                                    }
                                 }
-                                r?;
+                                if !stats_interval.is_zero() && counter.elapsed() >= stats_interval
+                                {
+                                    let stats = counter.take();
+                                    log::info!("{name}::{stream}: {stats}");
+                                    stats_tx.send_modify(|all| {
+                                        all.insert(stream, stats);
+                                    });
+                                }
+                            };
+                            // Do not leave old stats behind once the client goes
+                            stats_tx.send_if_modified(|all| all.remove(&stream).is_some());
+                            if let Ok(mut gop) = gop.lock() {
+                                gop.leave(client);
                             }
                             log::trace!("All media recieved");
-                            AnyResult::Ok(())
+                            res
                         });
                         AnyResult::Ok(())
                     });
@@ -288,6 +557,131 @@ pub(super) async fn make_factory(
     });
 
     // Now setup the factory
+    let factory = NeoMediaFactory::new_with_callback(move |element| {
+        let (reply, new_element) = tokio::sync::oneshot::channel();
+        client_tx.blocking_send(ClientMsg::NewClient { element, reply })?;
+
+        let element = new_element.blocking_recv()?;
+        Ok(Some(element))
+    })
+    .await?;
+
+    // Offer the backchannel once we know that the camera can talk
+    let talk_factory = factory.clone();
+    tokio::task::spawn(async move {
+        let config = talk_camera.config().await?.borrow().clone();
+        if !config.backchannel {
+            return AnyResult::Ok(());
+        }
+        let talk_ability = talk_camera
+            .run_passive_task(|cam| Box::pin(async move { Ok(cam.talk_ability().await?) }))
+            .await;
+        match talk_ability
+            .and_then(|talk_ability| crate::talk::talk_config(config.channel_id, &talk_ability))
+        {
+            Ok((talk_config, block_size, sample_rate)) => {
+                log::debug!("{}: Offering the talk backchannel", config.name);
+                let runtime = tokio::runtime::Handle::current();
+                let name = config.name.clone();
+                talk_factory
+                    .set_backchannel(move |element, role| {
+                        if !talkers.contains(role) {
+                            return Err(anyhow!("{role} may not talk through {name}"));
+                        }
+                        build_backchannel(
+                            element,
+                            &talk_camera,
+                            &talk_config,
+                            block_size,
+                            sample_rate,
+                            &runtime,
+                        )
+                    })
+                    .await;
+            }
+            Err(e) => log::debug!("{}: No talk backchannel: {e:?}", config.name),
+        }
+        AnyResult::Ok(())
+    });
+    Ok((factory, thread))
+}
+
+/// Put the `stream` of several cameras into one frame
+///
+/// Each camera is decoded and the frame is encoded again as H264
+pub(super) async fn make_composite_factory(
+    cameras: Vec<NeoInstance>,
+    stream: StreamKind,
+    config: CompositeConfig,
+) -> AnyResult<(NeoMediaFactory, JoinHandle<AnyResult<()>>)> {
+    let (client_tx, mut client_rx) = mpsc(100);
+    let thread = tokio::task::spawn(async move {
+        let name = config.name.clone();
+        while let Some(msg) = client_rx.recv().await {
+            match msg {
+                ClientMsg::NewClient { element, reply } => {
+                    log::debug!("New client for {name}");
+                    let cameras = cameras.clone();
+                    let config = config.clone();
+                    let name = name.clone();
+                    tokio::task::spawn(async move {
+                        clear_bin(&element)?;
+
+                        log::trace!("{name}: Learning the camera stream types");
+                        let mut inputs = vec![];
+                        for camera in cameras.iter() {
+                            let mut media_rx = camera.stream_while_live(stream).await?;
+                            let mut stream_config = StreamConfig::new(camera, stream).await?;
+                            let mut buffer = vec![];
+                            while let Some(media) = media_rx.recv().await {
+                                stream_config.update_from_media(&media);
+                                buffer.push(media);
+                                if stream_config.vid_type.is_some() || buffer.len() > 10 {
+                                    break;
+                                }
+                            }
+                            inputs.push((media_rx, buffer, stream_config));
+                        }
+
+                        log::trace!("{name}: Building the composite pipeline");
+                        let stream_configs = inputs
+                            .iter()
+                            .map(|(_, _, stream_config)| stream_config)
+                            .collect::<Vec<_>>();
+                        let sources = build_composite(&element, &stream_configs, &config)?;
+                        let _ = reply.send(element);
+
+                        for ((mut media_rx, mut buffer, stream_config), vid_src) in
+                            inputs.into_iter().zip(sources)
+                        {
+                            // Run blocking code on a seperate thread
+                            std::thread::spawn(move || {
+                                let vid_src = Some(vid_src);
+                                let mut clock = MediaClock::new(stream_config.fps);
+                                let mut pools = Default::default();
+                                // Only the streams of the cameras are reported
+                                let sent = AtomicU64::new(0);
+                                for data in buffer.drain(..) {
+                                    send_to_sources(
+                                        data, &mut pools, &vid_src, &None, &mut clock, &sent,
+                                    )?;
+                                }
+                                while let Some(data) = media_rx.blocking_recv() {
+                                    send_to_sources(
+                                        data, &mut pools, &vid_src, &None, &mut clock, &sent,
+                                    )?;
+                                }
+                                AnyResult::Ok(())
+                            });
+                        }
+                        AnyResult::Ok(())
+                    });
+                }
+            }
+        }
+        AnyResult::Ok(())
+    });
+
     let factory = NeoMediaFactory::new_with_callback(move |element| {
         let (reply, new_element) = tokio::sync::oneshot::channel();
         client_tx.blocking_send(ClientMsg::NewClient { element, reply })?;
@@ -299,65 +693,492 @@ pub(super) async fn make_factory(
     Ok((factory, thread))
 }
 
+/// Decode each camera into a compositor and encode the frame as H264
+fn build_composite(
+    bin: &Element,
+    stream_configs: &[&StreamConfig],
+    config: &CompositeConfig,
+) -> Result<Vec<AppSrc>> {
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building Composite Pipeline");
+
+    let compositor = make_element("compositor", "compositor")?;
+    compositor.set_property_from_str("background", "black");
+    bin.add_many([&compositor])?;
+
+    let mut offset = 0u32;
+    let mut sources = vec![];
+    for (i, stream_config) in stream_configs.iter().enumerate() {
+        let (parser, decoder) = match stream_config.vid_type {
+            Some(VideoType::H264) => ("h264parse", "avdec_h264"),
+            Some(VideoType::H265) => ("h265parse", "avdec_h265"),
+            None => return Err(anyhow!("Camera {} of {} has no video", i, config.name)),
+        };
+        let buffer_size = stream_config.video_buffer_size();
+        let source = make_element("appsrc", &format!("vidsrc{i}"))?
+            .dynamic_cast::<AppSrc>()
+            .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+        source.set_is_live(false);
+        stream_config.tune_appsrc(&source);
+        source.set_property("emit-signals", false);
+        source.set_max_bytes(buffer_size as u64);
+        source.set_do_timestamp(false);
+        source.set_stream_type(AppStreamType::Stream);
+        source.set_callbacks(
+            AppSrcCallbacks::builder()
+                .seek_data(move |_, _seek_pos| true)
+                .build(),
+        );
+        let source = source
+            .dynamic_cast::<Element>()
+            .map_err(|_| anyhow!("Cannot cast back"))?;
+
+        let queue = make_queue(&format!("source_queue{i}"), buffer_size)?;
+        stream_config.tune_queue(&queue);
+        let parser = make_element(parser, &format!("parser{i}"))?;
+        let decoder = make_element(decoder, &format!("decoder{i}"))?;
+        let convert = make_element("videoconvert", &format!("convert{i}"))?;
+        bin.add_many([&source, &queue, &parser, &decoder, &convert])?;
+        Element::link_many([&source, &queue, &parser, &decoder, &convert])?;
+
+        let pad = compositor
+            .request_pad_simple("sink_%u")
+            .ok_or_else(|| anyhow!("Compositor has no free pad"))?;
+        let [width, height] = stream_config.resolution;
+        match config.layout {
+            CompositeLayout::SideBySide => {
+                pad.set_property("xpos", offset as i32);
+                offset += width;
+            }
+            CompositeLayout::Stacked => {
+                pad.set_property("ypos", offset as i32);
+                offset += height;
+            }
+        }
+        convert
+            .static_pad("src")
+            .ok_or_else(|| anyhow!("Convert is missing its pad"))?
+            .link(&pad)?;
+
+        sources.push(
+            source
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| anyhow!("Cannot convert appsrc"))?,
+        );
+    }
+
+    // Kbps like the camera's bitrate table
+    let bitrate = config
+        .bitrate
+        .unwrap_or_else(|| {
+            stream_configs
+                .iter()
+                .map(|stream_config| stream_config.bitrate / 1024)
+                .sum()
+        })
+        .max(1);
+    let fps = stream_configs
+        .iter()
+        .map(|stream_config| stream_config.fps)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let convert = make_element("videoconvert", "composite_convert")?;
+    let queue = make_queue("composite_queue", buffer_size(bitrate * 1024))?;
+    let encoder = make_element("x264enc", "encoder")?;
+    encoder.set_property_from_str("tune", "zerolatency");
+    encoder.set_property_from_str("speed-preset", "ultrafast");
+    encoder.set_property("bitrate", bitrate);
+    encoder.set_property("key-int-max", fps * 2);
+    let parser = make_element("h264parse", "composite_parser")?;
+    let payload = make_element("rtph264pay", "pay0")?;
+    bin.add_many([&convert, &queue, &encoder, &parser, &payload])?;
+    Element::link_many([&compositor, &convert, &queue, &encoder, &parser, &payload])?;
+    Ok(sources)
+}
+
+/// The camera does not say when a recording has all been sent
+/// so it is done once no more data arrives
+const PLAYBACK_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum PlaybackMsg {
+    /// The media after this starts at this far into the playback
+    Seek(Duration),
+    Media(BcMedia),
+}
+
+/// A recording on the SD card and how far into the playback it starts
+struct PlaybackFile {
+    file: FileInfo,
+    offset: Duration,
+}
+
+/// Play the recordings on the SD card from `start` until the end of that day
+///
+/// A seek restarts from the beginning of the recording that has the new position
+pub(super) async fn make_playback_factory(
+    camera: NeoInstance,
+    stream: StreamKind,
+    start: ReplayTime,
+) -> AnyResult<NeoMediaFactory> {
+    let runtime = tokio::runtime::Handle::current();
+    NeoMediaFactory::new_with_callback(move |element| {
+        clear_bin(&element)?;
+        runtime.block_on(build_playback(&element, &camera, stream, start))?;
+        Ok(Some(element))
+    })
+    .await
+}
+
+async fn build_playback(
+    element: &Element,
+    camera: &NeoInstance,
+    stream: StreamKind,
+    start: ReplayTime,
+) -> AnyResult<()> {
+    let config = camera.config().await?.borrow().clone();
+    let name = config.name.clone();
+    log::debug!("New playback client for {name}::{stream} at {start}");
+
+    let end = ReplayTime {
+        hour: 23,
+        minute: 59,
+        second: 59,
+        ..start
+    };
+    let mut found = camera
+        .run_task(|cam| {
+            Box::pin(async move { Ok(cam.replay_file_list(stream, start, end).await?) })
+        })
+        .await?;
+    found.sort_by_key(|file| file.start_time);
+    let files = found
+        .drain(..)
+        .filter(|file| file.end_time.is_some_and(|end| end > start))
+        .map(|file| PlaybackFile {
+            offset: file
+                .start_time
+                .map(|time| replay_offset(start, time))
+                .unwrap_or_default(),
+            file,
+        })
+        .collect::<Vec<_>>();
+    let duration = files
+        .iter()
+        .filter_map(|file| file.file.end_time)
+        .map(|time| replay_offset(start, time))
+        .max()
+        .ok_or_else(|| anyhow!("{name}: No recordings after {start}"))?;
+
+    log::trace!("{name}::{stream}: Learning recording stream type");
+    let learning = StreamConfig::new(camera, stream).await?;
+    let first = files[0].file.clone();
+    let stream_config = camera
+        .run_task(|cam| {
+            let first = first.clone();
+            let mut stream_config = learning.clone();
+            Box::pin(async move {
+                let mut data = cam.replay_download(first, 0).await?;
+                let mut frame_count = 0usize;
+                while let Ok(Ok(Ok(media))) = timeout(PLAYBACK_IDLE_TIMEOUT, data.get_data()).await
+                {
+                    stream_config.update_from_media(&media);
+                    if frame_count > 10
+                        || (stream_config.vid_type.is_some() && stream_config.aud_type.is_some())
+                    {
+                        break;
+                    }
+                    frame_count += 1;
+                }
+                let _ = data.shutdown().await;
+                Ok(stream_config)
+            })
+        })
+        .await?;
+
+    log::trace!("{name}::{stream}: Building the playback pipeline");
+    let filter = video_filter(camera, &config).await;
+    let vid_src = match stream_config.vid_type.as_ref() {
+        Some(video_type) if filter.is_some() => build_reencoded(
+            element,
+            &stream_config,
+            &config,
+            *video_type,
+            filter.as_deref(),
+        )?,
+        Some(VideoType::H264) => build_h264(element, &stream_config)?,
+        Some(VideoType::H265) if config.transcodes(stream) => {
+            build_reencoded(element, &stream_config, &config, VideoType::H265, None)?
+        }
+        Some(VideoType::H265) => build_h265(element, &stream_config)?,
+        None => return Err(anyhow!("{name}: The recording has no video")),
+    };
+    let aud_src = match stream_config.aud_type.as_ref() {
+        _ if config.audio == AudioCodec::None => None,
+        Some(AudioType::Aac) => Some(build_aac(element, &stream_config, config.audio)?),
+        Some(AudioType::Adpcm(block_size)) => Some(build_adpcm(
+            element,
+            *block_size,
+            &stream_config,
+            config.audio,
+        )?),
+        None => None,
+    };
+
+    // Both sources are told of the seek, only the first one is acted on
+    let (seek_tx, mut seek_rx) = watch(Duration::ZERO);
+    let seek_tx = Arc::new(seek_tx);
+    for app in std::iter::once(&vid_src).chain(aud_src.as_ref()) {
+        app.set_stream_type(AppStreamType::Seekable);
+        app.set_format(gstreamer::Format::Time);
+        app.set_duration(ClockTime::from_useconds(duration.as_micros() as u64));
+        // Unlike a live stream we can wait for the client
+        app.set_block(true);
+        let seek_tx = seek_tx.clone();
+        app.set_callbacks(
+            AppSrcCallbacks::builder()
+                .seek_data(move |_, seek_pos| {
+                    let position = Duration::from_nanos(seek_pos);
+                    seek_tx.send_if_modified(|old| {
+                        if *old != position {
+                            *old = position;
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                    true
+                })
+                .build(),
+        );
+    }
+
+    let (media_tx, mut media_rx) = mpsc(100);
+    let thread_name = name.clone();
+    // Run blocking code on a seperate thread
+    // This is not an async thread
+    std::thread::spawn(move || {
+        let vid_src = Some(vid_src);
+        let mut clock = MediaClock::new(stream_config.fps);
+        let mut pools = Default::default();
+        // Only the live streams are reported
+        let sent = AtomicU64::new(0);
+        while let Some(msg) = media_rx.blocking_recv() {
+            match msg {
+                PlaybackMsg::Seek(offset) => {
+                    clock.seek(offset.as_micros() as u64);
+                }
+                PlaybackMsg::Media(media) => {
+                    if let Err(e) =
+                        send_to_sources(media, &mut pools, &vid_src, &aud_src, &mut clock, &sent)
+                    {
+                        log::debug!("{thread_name}: Playback stopped: {e:?}");
+                        return;
+                    }
+                }
+            }
+        }
+        log::trace!("{thread_name}: All recordings sent");
+        for app in vid_src.iter().chain(aud_src.iter()) {
+            let _ = app.end_of_stream();
+        }
+    });
+
+    let camera = camera.clone();
+    tokio::task::spawn(async move {
+        loop {
+            let position = *seek_rx.borrow_and_update();
+            let index = files
+                .iter()
+                .rposition(|file| file.offset <= position)
+                .unwrap_or(0);
+            if media_tx
+                .send(PlaybackMsg::Seek(files[index].offset))
+                .await
+                .is_err()
+            {
+                break;
+            }
+            tokio::select! {
+                v = seek_rx.changed() => {
+                    if v.is_err() {
+                        // The pipeline has gone
+                        break;
+                    }
+                },
+                v = play_files(&camera, &files[index..], &media_tx) => {
+                    if let Err(e) = v {
+                        log::debug!("{name}: Playback ended: {e:?}");
+                    }
+                    break;
+                },
+            }
+        }
+        AnyResult::Ok(())
+    });
+    Ok(())
+}
+
+/// Download the recordings one after the other into the playback
+async fn play_files(
+    camera: &NeoInstance,
+    files: &[PlaybackFile],
+    media_tx: &MpscSender<PlaybackMsg>,
+) -> AnyResult<()> {
+    for file in files.iter() {
+        camera
+            .run_task(|cam| {
+                let file = file.file.clone();
+                let media_tx = media_tx.clone();
+                Box::pin(async move {
+                    let mut data = cam.replay_download(file, 0).await?;
+                    let r = loop {
+                        let media = match timeout(PLAYBACK_IDLE_TIMEOUT, data.get_data()).await {
+                            Err(_) | Ok(Err(BcError::StreamFinished)) => break Ok(()),
+                            Ok(media) => media??,
+                        };
+                        if media_tx.send(PlaybackMsg::Media(media)).await.is_err() {
+                            break Err(anyhow!("The playback client has gone"));
+                        }
+                    };
+                    let _ = data.shutdown().await;
+                    r
+                })
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+/// How far `time` is after `start`, or zero if it is before it
+fn replay_offset(start: ReplayTime, time: ReplayTime) -> Duration {
+    let as_datetime = |time: ReplayTime| {
+        let month = TimeMonth::try_from(time.month).ok()?;
+        TimeDate::from_calendar_date(time.year, month, time.day)
+            .ok()?
+            .with_hms(time.hour, time.minute, time.second)
+            .ok()
+    };
+    match (as_datetime(start), as_datetime(time)) {
+        (Some(start), Some(time)) => (time - start).try_into().unwrap_or_default(),
+        _ => Duration::ZERO,
+    }
+}
+
+/// The ONVIF backchannel sends the client's audio to the camera
+///
+/// The client sends us PCMU which we turn into the adpcm for the talk messages
+fn build_backchannel(
+    bin: &Element,
+    camera: &NeoInstance,
+    talk_config: &TalkConfig,
+    block_size: u16,
+    sample_rate: u16,
+    runtime: &tokio::runtime::Handle,
+) -> Result<()> {
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building Backchannel Pipeline");
+    // The rtsp server finds the stream from the element named `depay_backchannel`
+    // inside of the bin named `onvif-backchannel`
+    let backchannel = gstreamer::parse::bin_from_description(
+        &format!(
+            "capsfilter name=depay_backchannel \
+            caps=\"application/x-rtp,media=audio,payload=0,clock-rate=8000,encoding-name=PCMU\" \
+            ! rtppcmudepay \
+            ! mulawdec \
+            ! audioconvert \
+            ! audioresample \
+            ! audio/x-raw,rate={},channels=1 \
+            ! queue \
+            ! adpcmenc blockalign={} layout=dvi \
+            ! appsink name=backchannel_sink async=false sync=false",
+            sample_rate, block_size
+        ),
+        false,
+    )
+    .context("Unable to build the backchannel ensure all gstreamer plugins are installed")?;
+    backchannel.set_property("name", "onvif-backchannel");
+    bin.add(&backchannel)?;
+    let appsink = backchannel
+        .by_name("backchannel_sink")
+        .and_then(|sink| sink.dynamic_cast::<AppSink>().ok())
+        .ok_or_else(|| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))?;
+
+    let rx = crate::talk::from_appsink(&appsink);
+    let camera = camera.clone();
+    let talk_config = talk_config.clone();
+    runtime.spawn(async move {
+        let name = camera.config().await?.borrow().name.clone();
+        log::info!("{name}: Talk backchannel started");
+        let r = camera
+            .run_task(|cam| {
+                let rx = rx.clone();
+                let talk_config = talk_config.clone();
+                Box::pin(async move {
+                    cam.talk_stream(rx, talk_config).await?;
+                    Ok(())
+                })
+            })
+            .await;
+        match &r {
+            Ok(()) => log::info!("{name}: Talk backchannel ended"),
+            Err(e) => log::info!("{name}: Talk backchannel failed: {e:?}"),
+        }
+        r
+    });
+    Ok(())
+}
+
+/// Send the media to its appsrc and add the bytes pushed to `sent`
 fn send_to_sources(
     data: BcMedia,
     pools: &mut HashMap<usize, gstreamer::BufferPool>,
     vid_src: &Option<AppSrc>,
     aud_src: &Option<AppSrc>,
-    vid_ts: &mut u32,
-    aud_ts: &mut u32,
-    stream_config: &StreamConfig,
-) -> AnyResult<()> {
-    // Update TS
+    clock: &mut MediaClock,
+    sent: &AtomicU64,
+) -> AnyResult<bool> {
+    // Times come from the camera so that the audio and video stay in step
+    let Some(ts) = clock.media(&data) else {
+        return Ok(false);
+    };
+    let ts = Duration::from_micros(ts);
     match data {
-        BcMedia::Aac(aac) => {
-            let duration = aac.duration().expect("Could not calculate AAC duration");
-            if let Some(aud_src) = aud_src.as_ref() {
-                log::debug!("Sending AAC: {:?}", Duration::from_micros(*aud_ts as u64));
-                send_to_appsrc(
-                    aud_src,
-                    aac.data,
-                    Duration::from_micros(*aud_ts as u64),
-                    pools,
-                )?;
-            }
-            *aud_ts += duration;
-        }
-        BcMedia::Adpcm(adpcm) => {
-            let duration = adpcm
-                .duration()
-                .expect("Could not calculate ADPCM duration");
-            if let Some(aud_src) = aud_src.as_ref() {
-                log::trace!("Sending ADPCM: {:?}", Duration::from_micros(*aud_ts as u64));
-                send_to_appsrc(
-                    aud_src,
-                    adpcm.data,
-                    Duration::from_micros(*aud_ts as u64),
-                    pools,
-                )?;
+        BcMedia::Aac(BcMediaAac { data, .. }) | BcMedia::Adpcm(BcMediaAdpcm { data, .. }) => {
+            match aud_src.as_ref() {
+                Some(aud_src) => {
+                    log::trace!("Sending AUD: {ts:?}");
+                    send_to_appsrc(aud_src, data, ts, pools, sent)
+                }
+                None => Ok(false),
             }
-            *aud_ts += duration;
         }
         BcMedia::Iframe(BcMediaIframe { data, .. })
-        | BcMedia::Pframe(BcMediaPframe { data, .. }) => {
-            if let Some(vid_src) = vid_src.as_ref() {
-                log::trace!("Sending VID: {:?}", Duration::from_micros(*vid_ts as u64));
-                send_to_appsrc(vid_src, data, Duration::from_micros(*vid_ts as u64), pools)?;
+        | BcMedia::Pframe(BcMediaPframe { data, .. }) => match vid_src.as_ref() {
+            Some(vid_src) => {
+                log::trace!("Sending VID: {ts:?}");
+                send_to_appsrc(vid_src, data, ts, pools, sent)
             }
-            const MICROSECONDS: u32 = 1000000;
-            *vid_ts += MICROSECONDS / stream_config.fps;
-        }
-        _ => {}
+            None => Ok(false),
+        },
+        _ => Ok(false),
     }
-    Ok(())
 }
 
+/// True when the buffer was dropped because the appsrc is full
 fn send_to_appsrc(
     appsrc: &AppSrc,
     data: Vec<u8>,
     mut ts: Duration,
     pools: &mut HashMap<usize, gstreamer::BufferPool>,
-) -> AnyResult<()> {
+    sent: &AtomicU64,
+) -> AnyResult<bool> {
     check_live(appsrc)?; // Stop if appsrc is dropped
 
     // In live mode we follow the advice in
@@ -373,11 +1194,11 @@ fn send_to_appsrc(
                 ts = Duration::from_micros(time.useconds());
             } else {
                 // Not playing
-                return Ok(());
+                return Ok(false);
             }
         } else {
             // Clock not up yet
-            return Ok(());
+            return Ok(false);
         }
     }
     let buf = {
@@ -412,15 +1233,17 @@ fn send_to_appsrc(
     };
 
     // Push buffer into the appsrc
-    match appsrc.push_buffer(buf) {
+    let len = buf.size() as u64;
+    let dropped = match appsrc.push_buffer(buf) {
         Ok(_) => {
+            sent.fetch_add(len, Ordering::Relaxed);
             // log::info!(
             //     "Send {}{} on {}",
             //     data.data.len(),
             //     if data.keyframe { " (keyframe)" } else { "" },
             //     appsrc.name()
             // );
-            Ok(())
+            Ok(false)
         }
         Err(FlowError::Flushing) => {
             // Buffer is full just skip
@@ -428,7 +1251,7 @@ fn send_to_appsrc(
                 "Buffer full on {} pausing stream until client consumes frames",
                 appsrc.name()
             );
-            Ok(())
+            Ok(true)
         }
         Err(e) => Err(anyhow!("Error in streaming: {e:?}")),
     }?;
@@ -442,7 +1265,7 @@ fn send_to_appsrc(
     {
         appsrc.set_state(gstreamer::State::Paused).unwrap();
     }
-    Ok(())
+    Ok(dropped)
 }
 fn check_live(app: &AppSrc) -> Result<()> {
     app.bus().ok_or(anyhow!("App source is closed"))?;
@@ -466,35 +1289,67 @@ fn clear_bin(bin: &Element) -> Result<()> {
     Ok(())
 }
 
-fn build_unknown(bin: &Element, pattern: &str) -> Result<()> {
+fn build_unknown(bin: &Element, splash: &Splash) -> Result<()> {
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
     log::debug!("Building Unknown Pipeline");
-    let source = make_element("videotestsrc", "testvidsrc")?;
-    source.set_property_from_str("pattern", pattern);
-    source.set_property("num-buffers", 500i32); // Send buffers then EOS
     let queue = make_queue("queue0", 1024 * 1024 * 4)?;
-
     let overlay = make_element("textoverlay", "overlay")?;
-    overlay.set_property("text", "Stream not Ready");
     overlay.set_property_from_str("valignment", "top");
     overlay.set_property_from_str("halignment", "left");
     overlay.set_property("font-desc", "Sans, 16");
     let encoder = make_element("jpegenc", "encoder")?;
     let payload = make_element("rtpjpegpay", "pay0")?;
-
-    bin.add_many([&source, &queue, &overlay, &encoder, &payload])?;
-    source.link_filtered(
-        &queue,
-        &Caps::builder("video/x-raw")
-            .field("format", "YUY2")
-            .field("width", 896i32)
-            .field("height", 512i32)
-            .field("framerate", gstreamer::Fraction::new(25, 1))
-            .build(),
-    )?;
+    let caps = Caps::builder("video/x-raw")
+        .field("format", "YUY2")
+        .field("width", 896i32)
+        .field("height", 512i32)
+        .field("framerate", gstreamer::Fraction::new(25, 1))
+        .build();
+
+    match splash {
+        Splash::Pattern { pattern, text } => {
+            let source = make_element("videotestsrc", "testvidsrc")?;
+            source.set_property_from_str("pattern", pattern);
+            source.set_property("num-buffers", 500i32); // Send buffers then EOS
+            overlay.set_property("text", text);
+
+            bin.add_many([&source, &queue, &overlay, &encoder, &payload])?;
+            source.link_filtered(&queue, &caps)?;
+        }
+        Splash::Image { path, text } => {
+            let source = make_element("filesrc", "imagesrc")?;
+            source.set_property("location", path.to_string_lossy().as_ref());
+            let decoder = make_element("decodebin", "imagedec")?;
+            let freeze = make_element("imagefreeze", "imagefreeze")?;
+            freeze.set_property("num-buffers", 500i32); // Send buffers then EOS
+            let convert = make_element("videoconvert", "imageconvert")?;
+            let scale = make_element("videoscale", "imagescale")?;
+            let rate = make_element("videorate", "imagerate")?;
+            overlay.set_property("text", text);
+
+            bin.add_many([
+                &source, &decoder, &freeze, &convert, &scale, &rate, &queue, &overlay, &encoder,
+                &payload,
+            ])?;
+            source.link(&decoder)?;
+            // The decoder only has a pad once it knows the kind of image
+            let freeze_sink = freeze
+                .static_pad("sink")
+                .ok_or(anyhow!("imagefreeze has no sink pad"))?;
+            decoder.connect_pad_added(move |_, pad| {
+                if !freeze_sink.is_linked() {
+                    if let Err(e) = pad.link(&freeze_sink) {
+                        log::warn!("Could not use the pause image: {e:?}");
+                    }
+                }
+            });
+            Element::link_many([&freeze, &convert, &scale, &rate])?;
+            rate.link_filtered(&queue, &caps)?;
+        }
+    }
     Element::link_many([&queue, &overlay, &encoder, &payload])?;
 
     Ok(())
@@ -506,7 +1361,7 @@ struct Linked {
 }
 
 fn pipe_h264(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+    let buffer_size = stream_config.video_buffer_size();
     log::debug!(
         "buffer_size: {buffer_size}, bitrate: {}",
         stream_config.bitrate
@@ -521,8 +1376,7 @@ fn pipe_h264(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
     source.set_is_live(false);
-    source.set_block(false);
-    source.set_min_latency(1000 / (stream_config.fps as i64));
+    stream_config.tune_appsrc(&source);
     source.set_property("emit-signals", false);
     source.set_max_bytes(buffer_size as u64);
     source.set_do_timestamp(false);
@@ -532,6 +1386,7 @@ fn pipe_h264(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
     let queue = make_queue("source_queue", buffer_size)?;
+    stream_config.tune_queue(&queue);
     let parser = make_element("h264parse", "parser")?;
     // let stamper = make_element("h264timestamper", "stamper")?;
 
@@ -562,7 +1417,7 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
 }
 
 fn pipe_h265(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+    let buffer_size = stream_config.video_buffer_size();
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
@@ -572,8 +1427,7 @@ fn pipe_h265(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
     source.set_is_live(false);
-    source.set_block(false);
-    source.set_min_latency(1000 / (stream_config.fps as i64));
+    stream_config.tune_appsrc(&source);
     source.set_property("emit-signals", false);
     source.set_max_bytes(buffer_size as u64);
     source.set_do_timestamp(false);
@@ -583,6 +1437,7 @@ fn pipe_h265(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
     let queue = make_queue("source_queue", buffer_size)?;
+    stream_config.tune_queue(&queue);
     let parser = make_element("h265parse", "parser")?;
     // let stamper = make_element("h265timestamper", "stamper")?;
 
@@ -612,6 +1467,163 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(linked.appsrc)
 }
 
+/// The gst-launch fragment to run on the decoded video
+///
+/// This is the `rotate` and `flip` followed by the camera's `video_filter`.
+/// A half turn and the flips are left to the camera's own mirror and flip
+/// settings when it has them so that the video is not re-encoded, note
+/// that those settings stay on the camera.
+async fn video_filter(camera: &NeoInstance, config: &CameraConfig) -> Option<String> {
+    let horizontal = matches!(config.flip, Some(FlipDirection::Horizontal));
+    let vertical = matches!(config.flip, Some(FlipDirection::Vertical));
+    let quarter = match config.rotate {
+        Some(90) => Some("clockwise"),
+        Some(270) => Some("counterclockwise"),
+        _ => None,
+    };
+    let half = config.rotate == Some(180);
+
+    let mut on_camera = false;
+    if quarter.is_none() && (half || config.flip.is_some()) {
+        // A half turn is the same as both a mirror and a flip
+        let mirror = half != horizontal;
+        let flip = half != vertical;
+        let result = camera
+            .run_passive_task(|cam| {
+                Box::pin(async move {
+                    if cam.get_mirror_flip().await? != (mirror, flip) {
+                        cam.set_mirror_flip(mirror, flip).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await;
+        match result {
+            Ok(()) => on_camera = true,
+            Err(e) => log::debug!(
+                "{}: Could not turn the picture on the camera, using gstreamer: {:?}",
+                config.name,
+                e
+            ),
+        }
+    }
+
+    let mut fragments = vec![];
+    if !on_camera {
+        if let Some(method) = quarter {
+            fragments.push(format!("videoflip method={method}"));
+        }
+        if half {
+            fragments.push("videoflip method=rotate-180".to_string());
+        }
+        if horizontal {
+            fragments.push("videoflip method=horizontal-flip".to_string());
+        }
+        if vertical {
+            fragments.push("videoflip method=vertical-flip".to_string());
+        }
+    }
+    fragments.extend(config.video_filter.clone());
+    if fragments.is_empty() {
+        None
+    } else {
+        Some(fragments.join(" ! "))
+    }
+}
+
+/// Decode the video and encode it again as H264
+///
+/// This is for clients that cannot play H265 and to run the `filter`
+/// fragment on the decoded video
+fn build_reencoded(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    config: &CameraConfig,
+    video_type: VideoType,
+    filter: Option<&str>,
+) -> Result<AppSrc> {
+    let linked = match video_type {
+        VideoType::H264 => pipe_h264(bin, stream_config)?,
+        VideoType::H265 => pipe_h265(bin, stream_config)?,
+    };
+    let h265 = matches!(video_type, VideoType::H265);
+
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    // Kbps like the camera's bitrate table
+    let bitrate = config
+        .transcode_bitrate
+        .unwrap_or(stream_config.bitrate / 1024)
+        .max(1);
+    log::debug!(
+        "Building {video_type:?} to H264 transcode with {:?} at {bitrate}kbps",
+        config.transcode_encoder
+    );
+
+    let (decoder, encoder) = match config.transcode_encoder {
+        TranscodeEncoder::Software => {
+            let decoder = make_element(if h265 { "avdec_h265" } else { "avdec_h264" }, "decoder")?;
+            let encoder = make_element("x264enc", "encoder")?;
+            encoder.set_property_from_str("tune", "zerolatency");
+            encoder.set_property_from_str("speed-preset", "ultrafast");
+            encoder.set_property("bitrate", bitrate);
+            encoder.set_property("key-int-max", stream_config.fps.max(1) * 2);
+            (decoder, encoder)
+        }
+        TranscodeEncoder::Vaapi => {
+            let decoder = make_element(
+                if h265 { "vaapih265dec" } else { "vaapih264dec" },
+                "decoder",
+            )?;
+            let encoder = make_element("vaapih264enc", "encoder")?;
+            encoder.set_property("bitrate", bitrate);
+            encoder.set_property("keyframe-period", stream_config.fps.max(1) * 2);
+            (decoder, encoder)
+        }
+        TranscodeEncoder::Nvenc => {
+            let decoder = make_element(if h265 { "nvh265dec" } else { "nvh264dec" }, "decoder")?;
+            let encoder = make_element("nvh264enc", "encoder")?;
+            encoder.set_property("bitrate", bitrate);
+            encoder.set_property("gop-size", (stream_config.fps.max(1) * 2) as i32);
+            (decoder, encoder)
+        }
+        TranscodeEncoder::V4l2 => {
+            let decoder =
+                make_element(if h265 { "v4l2h265dec" } else { "v4l2h264dec" }, "decoder")?;
+            let encoder = make_element("v4l2h264enc", "encoder")?;
+            // The v4l2 controls are in bits per second
+            let controls = gstreamer::Structure::builder("controls")
+                .field("video_bitrate", (bitrate * 1000) as i32)
+                .build();
+            encoder.set_property("extra-controls", controls);
+            (decoder, encoder)
+        }
+    };
+    let convert = make_element("videoconvert", "transcode_convert")?;
+    let queue = make_queue("transcode_queue", buffer_size(bitrate * 1024))?;
+    let parser = make_element("h264parse", "transcode_parser")?;
+    let payload = make_element("rtph264pay", "pay0")?;
+    bin.add_many([&decoder, &convert, &queue, &encoder, &parser, &payload])?;
+    Element::link_many([&linked.output, &decoder, &convert])?;
+
+    let mut filtered = convert;
+    if let Some(fragment) = filter {
+        log::debug!("Adding the video filter `{fragment}`");
+        let filter = gstreamer::parse::bin_from_description(fragment, true)
+            .with_context(|| format!("Could not use the video_filter `{fragment}`"))?;
+        filter.set_property("name", "video_filter");
+        // The filter may change the format to one that the encoder cannot take
+        let filter_convert = make_element("videoconvert", "video_filter_convert")?;
+        bin.add_many([filter.upcast_ref(), &filter_convert])?;
+        Element::link_many([&filtered, filter.upcast_ref(), &filter_convert])?;
+        filtered = filter_convert;
+    }
+    Element::link_many([&filtered, &queue, &encoder, &parser, &payload])?;
+    Ok(linked.appsrc)
+}
+
 fn pipe_aac(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
     // Audio seems to run at about 800kbs
     let buffer_size = 512 * 1416;
@@ -625,8 +1637,7 @@ fn pipe_aac(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
     source.set_is_live(false);
-    source.set_block(false);
-    source.set_min_latency(1000 / (stream_config.fps as i64));
+    stream_config.tune_appsrc(&source);
     source.set_property("emit-signals", false);
     source.set_max_bytes(buffer_size as u64);
     source.set_do_timestamp(false);
@@ -637,6 +1648,7 @@ fn pipe_aac(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
     let queue = make_queue("audqueue", buffer_size)?;
+    stream_config.tune_queue(&queue);
     let parser = make_element("aacparse", "audparser")?;
     let decoder = match make_element("faad", "auddecoder_faad") {
         Ok(ele) => Ok(ele),
@@ -679,18 +1691,81 @@ fn pipe_aac(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
     })
 }
 
-fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
+fn build_aac(bin: &Element, stream_config: &StreamConfig, codec: AudioCodec) -> Result<AppSrc> {
+    if codec == AudioCodec::Aac {
+        return build_aac_passthrough(bin, stream_config);
+    }
     let linked = pipe_aac(bin, stream_config)?;
+    payload_audio(bin, &linked.output, codec)?;
+    Ok(linked.appsrc)
+}
 
+/// Send the AAC from the camera without decoding it
+fn build_aac_passthrough(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
+    let buffer_size = 512 * 1416;
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building Aac passthrough pipeline");
+    let source = make_element("appsrc", "audsrc")?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
-    let payload = make_element("rtpL16pay", "pay1")?;
-    bin.add_many([&payload])?;
-    Element::link_many([&linked.output, &payload])?;
-    Ok(linked.appsrc)
+    source.set_is_live(false);
+    stream_config.tune_appsrc(&source);
+    source.set_property("emit-signals", false);
+    source.set_max_bytes(buffer_size as u64);
+    source.set_do_timestamp(false);
+    source.set_stream_type(AppStreamType::Stream);
+
+    let source = source
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot cast back"))?;
+    let queue = make_queue("audqueue", buffer_size)?;
+    stream_config.tune_queue(&queue);
+    let parser = make_element("aacparse", "audparser")?;
+    let payload = make_element("rtpmp4gpay", "pay1")?;
+    bin.add_many([&source, &queue, &parser, &payload])?;
+    Element::link_many([&source, &queue, &parser, &payload])?;
+
+    source
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot convert appsrc"))
+}
+
+/// Encode the raw audio from `output` to `codec` and add the payloader
+fn payload_audio(bin: &Element, output: &Element, codec: AudioCodec) -> Result<()> {
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+
+    match codec {
+        AudioCodec::Pcm | AudioCodec::None => {
+            let payload = make_element("rtpL16pay", "pay1")?;
+            bin.add_many([&payload])?;
+            Element::link_many([output, &payload])?;
+        }
+        AudioCodec::Aac => {
+            let resample = make_element("audioresample", "audresample")?;
+            let encoder = match make_element("avenc_aac", "audaacenc") {
+                Ok(ele) => Ok(ele),
+                Err(_) => make_element("voaacenc", "audaacenc"),
+            }?;
+            let payload = make_element("rtpmp4gpay", "pay1")?;
+            bin.add_many([&resample, &encoder, &payload])?;
+            Element::link_many([output, &resample, &encoder, &payload])?;
+        }
+        AudioCodec::Opus => {
+            let resample = make_element("audioresample", "audresample")?;
+            let encoder = make_element("opusenc", "audopusenc")?;
+            let payload = make_element("rtpopuspay", "pay1")?;
+            bin.add_many([&resample, &encoder, &payload])?;
+            Element::link_many([output, &resample, &encoder, &payload])?;
+        }
+    }
+    Ok(())
 }
 
 fn pipe_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> Result<Linked> {
@@ -711,8 +1786,7 @@ fn pipe_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> R
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
     source.set_is_live(false);
-    source.set_block(false);
-    source.set_min_latency(1000 / (stream_config.fps as i64));
+    stream_config.tune_appsrc(&source);
     source.set_property("emit-signals", false);
     source.set_max_bytes(buffer_size as u64);
     source.set_do_timestamp(false);
@@ -732,6 +1806,7 @@ fn pipe_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> R
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
     let queue = make_queue("audqueue", buffer_size)?;
+    stream_config.tune_queue(&queue);
     let decoder = make_element("decodebin", "auddecoder")?;
     let encoder = make_element("audioconvert", "audencoder")?;
     let encoder_out = encoder.clone();
@@ -755,17 +1830,14 @@ fn pipe_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> R
     })
 }
 
-fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> Result<AppSrc> {
+fn build_adpcm(
+    bin: &Element,
+    block_size: u32,
+    stream_config: &StreamConfig,
+    codec: AudioCodec,
+) -> Result<AppSrc> {
     let linked = pipe_adpcm(bin, block_size, stream_config)?;
-
-    let bin = bin
-        .clone()
-        .dynamic_cast::<Bin>()
-        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
-
-    let payload = make_element("rtpL16pay", "pay1")?;
-    bin.add_many([&payload])?;
-    Element::link_many([&linked.output, &payload])?;
+    payload_audio(bin, &linked.output, codec)?;
     Ok(linked.appsrc)
 }
 
@@ -783,8 +1855,7 @@ fn pipe_silence(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
 
     source.set_is_live(false);
-    source.set_block(false);
-    source.set_min_latency(1000 / (stream_config.fps as i64));
+    stream_config.tune_appsrc(&source);
     source.set_property("emit-signals", false);
     source.set_max_bytes(buffer_size as u64);
     source.set_do_timestamp(false);
@@ -795,6 +1866,7 @@ fn pipe_silence(bin: &Element, stream_config: &StreamConfig) -> Result<Linked> {
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
     let sink_queue = make_queue("audsinkqueue", buffer_size)?;
+    stream_config.tune_queue(&sink_queue);
     let sink = make_element("fakesink", "silence_sink")?;
 
     let silence = make_element("audiotestsrc", "audsilence")?;
@@ -865,38 +1937,6 @@ struct AppSrcPair {
 //     })
 // }
 
-// Convenice funcion to make an element or provide a message
-// about what plugin is missing
-fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
-    ElementFactory::make_with_name(kind, Some(name)).with_context(|| {
-        let plugin = match kind {
-            "appsrc" => "app (gst-plugins-base)",
-            "audioconvert" => "audioconvert (gst-plugins-base)",
-            "adpcmdec" => "Required for audio",
-            "h264parse" => "videoparsersbad (gst-plugins-bad)",
-            "h265parse" => "videoparsersbad (gst-plugins-bad)",
-            "rtph264pay" => "rtp (gst-plugins-good)",
-            "rtph265pay" => "rtp (gst-plugins-good)",
-            "rtpjitterbuffer" => "rtp (gst-plugins-good)",
-            "aacparse" => "audioparsers (gst-plugins-good)",
-            "rtpL16pay" => "rtp (gst-plugins-good)",
-            "x264enc" => "x264 (gst-plugins-ugly)",
-            "x265enc" => "x265 (gst-plugins-bad)",
-            "avdec_h264" => "libav (gst-libav)",
-            "avdec_h265" => "libav (gst-libav)",
-            "videotestsrc" => "videotestsrc (gst-plugins-base)",
-            "imagefreeze" => "imagefreeze (gst-plugins-good)",
-            "audiotestsrc" => "audiotestsrc (gst-plugins-base)",
-            "decodebin" => "playback (gst-plugins-good)",
-            _ => "Unknown",
-        };
-        format!(
-            "Missing required gstreamer plugin `{}` for `{}` element",
-            plugin, kind
-        )
-    })
-}
-
 #[allow(dead_code)]
 fn make_dbl_queue(name: &str, buffer_size: u32) -> AnyResult<Element> {
     let queue = make_element("queue", &format!("queue1_{}", name))?;
@@ -2,10 +2,12 @@
 //! data using an ordinary std::io::Write interface.
 
 mod factory;
+mod mounts;
 mod server;
 mod shared;
 
 pub(crate) use factory::*;
+pub(crate) use mounts::NeoMountPoints;
 
 pub(crate) use self::server::NeoRtspServer;
 
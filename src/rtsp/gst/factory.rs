@@ -4,26 +4,33 @@
 //! expect issues
 
 use super::AnyResult;
+use crate::config::MulticastConfig;
 use gstreamer::glib::object_subclass;
 use gstreamer::Element;
 use gstreamer::{
     glib::{self, Object},
     Structure,
 };
-use gstreamer_rtsp::RTSPUrl;
+use gstreamer_rtsp::{RTSPLowerTrans, RTSPUrl};
 use gstreamer_rtsp_server::prelude::*;
 use gstreamer_rtsp_server::subclass::prelude::*;
-use gstreamer_rtsp_server::RTSPMediaFactory;
 use gstreamer_rtsp_server::RTSPTransportMode;
-use gstreamer_rtsp_server::{RTSP_PERM_MEDIA_FACTORY_ACCESS, RTSP_PERM_MEDIA_FACTORY_CONSTRUCT};
+use gstreamer_rtsp_server::{
+    RTSPAddressPool, RTSPContext, RTSPMediaFactory, RTSPOnvifMediaFactory,
+};
+use gstreamer_rtsp_server::{
+    RTSP_PERM_MEDIA_FACTORY_ACCESS, RTSP_PERM_MEDIA_FACTORY_CONSTRUCT,
+    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+};
 use log::*;
+use neolink_core::bc_protocol::StreamKind;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 glib::wrapper! {
     /// The wrapped RTSPMediaFactory
-    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPMediaFactory;
+    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPOnvifMediaFactory, RTSPMediaFactory;
 }
 
 impl Default for NeoMediaFactory {
@@ -54,6 +61,53 @@ impl NeoMediaFactory {
         Ok(factory)
     }
 
+    /// Offer an ONVIF audio backchannel to clients that ask for one
+    ///
+    /// The callback adds the backchannel to the bin of each such client, it
+    /// is given the role that the client logged in as
+    pub(crate) async fn set_backchannel<F>(&self, callback: F)
+    where
+        F: Fn(&Element, &str) -> AnyResult<()> + Send + Sync + 'static,
+    {
+        self.imp()
+            .backchannel
+            .lock()
+            .await
+            .replace(Arc::new(callback));
+    }
+
+    /// Let clients ask for the stream over udp multicast
+    ///
+    /// The media is shared so that all the clients get the same packets.
+    /// Each stream kind gets its own block of ten ports so that the main and
+    /// sub stream of a camera never take the same ones
+    pub(crate) fn set_multicast(
+        &self,
+        config: &MulticastConfig,
+        stream: StreamKind,
+    ) -> AnyResult<()> {
+        let offset = match stream {
+            StreamKind::Main => 0,
+            StreamKind::Sub => 10,
+            StreamKind::Extern => 20,
+        };
+        let min_port = config.port.saturating_add(offset);
+        let max_port = min_port.saturating_add(9);
+        let pool = RTSPAddressPool::new();
+        pool.add_range(
+            &config.address,
+            &config.address,
+            min_port,
+            max_port,
+            config.ttl,
+        )?;
+        self.set_address_pool(Some(&pool));
+        self.set_multicast_iface(config.iface.as_deref());
+        self.set_protocols(RTSPLowerTrans::UDP | RTSPLowerTrans::UDP_MCAST | RTSPLowerTrans::TCP);
+        self.set_shared(true);
+        Ok(())
+    }
+
     pub(crate) fn add_permitted_roles<T: AsRef<str>>(&self, permitted_roles: &HashSet<T>) {
         for permitted_role in permitted_roles {
             let s = permitted_role.as_ref();
@@ -100,6 +154,8 @@ unsafe impl Sync for NeoMediaFactory {}
 pub(crate) struct NeoMediaFactoryImpl {
     #[allow(clippy::type_complexity)]
     call_back: Arc<Mutex<Option<Arc<dyn Fn(Element) -> AnyResult<Option<Element>> + Send + Sync>>>>,
+    #[allow(clippy::type_complexity)]
+    backchannel: Arc<Mutex<Option<Arc<dyn Fn(&Element, &str) -> AnyResult<()> + Send + Sync>>>>,
 }
 
 impl Default for NeoMediaFactoryImpl {
@@ -108,6 +164,7 @@ impl Default for NeoMediaFactoryImpl {
         // Prepare thread that sends data into the appsrcs
         Self {
             call_back: Arc::new(Mutex::new(None)),
+            backchannel: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -134,6 +191,34 @@ impl NeoMediaFactoryImpl {
             None => Ok(None),
         }
     }
+
+    /// Add the backchannel if the current client asked for it
+    ///
+    /// A client that asks for it cannot play without it so the media fails
+    /// if it could not be added
+    fn add_backchannel(&self, media: Element) -> Option<Element> {
+        let factory = self.obj();
+        let (wanted, role) = RTSPContext::with_current_context(|ctx| {
+            let role = ctx
+                .token()
+                .and_then(|token| token.string(RTSP_TOKEN_MEDIA_FACTORY_ROLE))
+                .map(|role| role.to_string())
+                .unwrap_or_else(|| "anonymous".to_string());
+            (factory.requires_backchannel(ctx), role)
+        })
+        .unwrap_or((false, "anonymous".to_string()));
+        if !wanted {
+            return Some(media);
+        }
+        let backchannel = self.backchannel.blocking_lock().clone()?;
+        match backchannel(&media, &role) {
+            Ok(()) => Some(media),
+            Err(e) => {
+                log::info!("Could not add the talk backchannel: {e:?}");
+                None
+            }
+        }
+    }
 }
 
 impl ObjectImpl for NeoMediaFactoryImpl {}
@@ -141,6 +226,12 @@ impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
     fn create_element(&self, url: &RTSPUrl) -> Option<Element> {
         self.parent_create_element(url)
             .and_then(|orig| self.build_pipeline(orig).expect("Could not build pipeline"))
+            .and_then(|media| self.add_backchannel(media))
+    }
+}
+impl RTSPOnvifMediaFactoryImpl for NeoMediaFactoryImpl {
+    fn has_backchannel_support(&self) -> bool {
+        self.backchannel.blocking_lock().is_some()
     }
 }
 
@@ -148,5 +239,5 @@ impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
 impl ObjectSubclass for NeoMediaFactoryImpl {
     const NAME: &'static str = "NeoMediaFactory";
     type Type = super::NeoMediaFactory;
-    type ParentType = RTSPMediaFactory;
+    type ParentType = RTSPOnvifMediaFactory;
 }
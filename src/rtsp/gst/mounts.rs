@@ -0,0 +1,130 @@
+//! Attempts to subclass RTSPMountPoints
+//!
+//! The mount points can only match paths that were mounted beforehand. The
+//! playback paths have a time in them so they are mounted when they are
+//! first asked for. Only the last few times of each prefix stay mounted.
+
+use gstreamer::glib::{self, object_subclass, GString, Object};
+use gstreamer_rtsp::RTSPUrl;
+use gstreamer_rtsp_server::prelude::*;
+use gstreamer_rtsp_server::subclass::prelude::*;
+use gstreamer_rtsp_server::RTSPMountPoints;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use super::NeoMediaFactory;
+
+/// How many playback times of a prefix are mounted at once
+///
+/// The oldest asked for is unmounted to make room. Its clients that are
+/// already playing carry on.
+const MAX_PLAYBACK_MOUNTS: usize = 8;
+
+type PlaybackMaker = Arc<dyn Fn(&str) -> Option<NeoMediaFactory> + Send + Sync>;
+
+glib::wrapper! {
+    /// The wrapped RTSPMountPoints
+    pub(crate) struct NeoMountPoints(ObjectSubclass<NeoMountPointsImpl>) @extends RTSPMountPoints;
+}
+
+impl Default for NeoMountPoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NeoMountPoints {
+    pub(crate) fn new() -> Self {
+        Object::new::<NeoMountPoints>()
+    }
+
+    /// Mount `{prefix}/{time}` with the factory from the maker when it is first asked for
+    ///
+    /// The maker is given the `{time}` part of the path
+    pub(crate) fn add_playback<F>(&self, prefix: &str, maker: F)
+    where
+        F: Fn(&str) -> Option<NeoMediaFactory> + Send + Sync + 'static,
+    {
+        let old = self.imp().playbacks.lock().unwrap().insert(
+            prefix.to_string(),
+            Playback {
+                maker: Arc::new(maker),
+                mounted: Default::default(),
+            },
+        );
+        if let Some(old) = old {
+            self.remove_mounted(old);
+        }
+    }
+
+    /// Stop mounting `{prefix}/{time}` and remove those that were mounted
+    pub(crate) fn remove_playback(&self, prefix: &str) {
+        let old = self.imp().playbacks.lock().unwrap().remove(prefix);
+        if let Some(old) = old {
+            self.remove_mounted(old);
+        }
+    }
+
+    fn remove_mounted(&self, playback: Playback) {
+        for path in playback.mounted.iter() {
+            self.remove_factory(path);
+        }
+    }
+}
+
+unsafe impl Send for NeoMountPoints {}
+unsafe impl Sync for NeoMountPoints {}
+
+struct Playback {
+    maker: PlaybackMaker,
+    /// The mounted paths, the most recently asked for last
+    mounted: VecDeque<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct NeoMountPointsImpl {
+    playbacks: Mutex<HashMap<String, Playback>>,
+}
+
+impl ObjectImpl for NeoMountPointsImpl {}
+impl RTSPMountPointsImpl for NeoMountPointsImpl {
+    fn make_path(&self, url: &RTSPUrl) -> Option<GString> {
+        let path = self.parent_make_path(url)?;
+        let mut playbacks = self.playbacks.lock().unwrap();
+        for (prefix, playback) in playbacks.iter_mut() {
+            // The time is the next part, after it would be the control e.g. `stream=0`
+            let time = match path
+                .strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .and_then(|rest| rest.split('/').next())
+            {
+                Some(time) if !time.is_empty() => time,
+                _ => continue,
+            };
+            let mount = format!("{prefix}/{time}");
+            if let Some(i) = playback.mounted.iter().position(|m| m == &mount) {
+                let mount = playback.mounted.remove(i).expect("Found above");
+                playback.mounted.push_back(mount);
+            } else if let Some(factory) = (playback.maker)(time) {
+                log::debug!("Mounting playback at {mount}");
+                self.obj().add_factory(&mount, factory);
+                playback.mounted.push_back(mount);
+                while playback.mounted.len() > MAX_PLAYBACK_MOUNTS {
+                    if let Some(old) = playback.mounted.pop_front() {
+                        log::debug!("Unmounting playback at {old}");
+                        self.obj().remove_factory(&old);
+                    }
+                }
+            }
+            break;
+        }
+        Some(path)
+    }
+}
+
+#[object_subclass]
+impl ObjectSubclass for NeoMountPointsImpl {
+    const NAME: &'static str = "NeoMountPoints";
+    type Type = super::NeoMountPoints;
+    type ParentType = RTSPMountPoints;
+}
@@ -3,26 +3,36 @@
 //! We are now messing with gstreamer glib objects
 //! expect issues
 
-use super::AnyResult;
-use crate::config::*;
+use super::{AnyResult, NeoMountPoints};
+use crate::{
+    common::{RtspClientInfo, RtspStreamInfo},
+    config::*,
+};
 
 use anyhow::Context;
-use gstreamer::glib::{self, object_subclass, MainLoop, Object};
-use gstreamer_rtsp::RTSPAuthMethod;
+use gstreamer::glib::{self, object_subclass, translate::*, MainLoop, Object, WeakRef};
+use gstreamer_rtsp::{RTSPAuthMethod, RTSPStatusCode};
 use gstreamer_rtsp_server::{
-    gio::{TlsAuthenticationMode, TlsCertificate},
+    gio::{self, TlsAuthenticationMode, TlsCertificate, TlsFileDatabase},
     prelude::*,
     subclass::prelude::*,
-    RTSPAuth, RTSPFilterResult, RTSPServer, RTSPToken, RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+    RTSPAuth, RTSPClient, RTSPContext, RTSPFilterResult, RTSPOnvifServer, RTSPServer, RTSPToken,
+    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
 };
 use log::*;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::CStr,
     fs,
-    sync::Arc,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    sync::RwLock,
+    sync::{watch::Sender as WatchSender, RwLock},
     task::JoinSet,
     time::{timeout, Duration},
 };
@@ -30,7 +40,7 @@ use tokio_util::sync::CancellationToken;
 
 glib::wrapper! {
     /// The wrapped RTSPServer
-    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPServer;
+    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPOnvifServer, RTSPServer;
 }
 
 impl Default for NeoRtspServer {
@@ -56,8 +66,44 @@ impl NeoRtspServer {
             .build();
         auth.set_default_token(Some(&mut un_authtoken));
         factory.set_auth(Some(&auth));
+        factory.set_mount_points(Some(&NeoMountPoints::new()));
+
+        factory.connect_client_connected(|server, client| {
+            let id = server.imp().add_client(client);
+
+            let weak_server = server.downgrade();
+            client.connect_pre_describe_request(move |_, ctx| {
+                weak_server
+                    .upgrade()
+                    .map(|server| server.imp().admit_client(id, ctx))
+                    .unwrap_or(RTSPStatusCode::Ok)
+            });
+            let weak_server = server.downgrade();
+            client.connect_pre_setup_request(move |_, ctx| {
+                weak_server
+                    .upgrade()
+                    .map(|server| server.imp().admit_client(id, ctx))
+                    .unwrap_or(RTSPStatusCode::Ok)
+            });
+            let weak_server = server.downgrade();
+            client.connect_describe_request(move |_, ctx| {
+                if let Some(server) = weak_server.upgrade() {
+                    server.imp().update_client(id, ctx, false);
+                }
+            });
+            let weak_server = server.downgrade();
+            client.connect_setup_request(move |_, ctx| {
+                if let Some(server) = weak_server.upgrade() {
+                    server.imp().update_client(id, ctx, true);
+                }
+            });
+            let weak_server = server.downgrade();
+            client.connect_closed(move |_| {
+                if let Some(server) = weak_server.upgrade() {
+                    server.imp().remove_client(id);
+                }
+            });
 
-        factory.connect_client_connected(|_, client| {
             client.connect_new_session(|_, session| {
                 log::debug!("New Session");
                 // Session timeout too small causes us to drop
@@ -72,12 +118,24 @@ impl NeoRtspServer {
         Ok(factory)
     }
 
-    pub(crate) async fn run(&self, bind_addr: &str, bind_port: u16) -> AnyResult<()> {
+    /// Listen on each of the addresses at the port
+    ///
+    /// `::` listens on ipv4 too where the os is dual stack, so it should
+    /// not be paired with `0.0.0.0`
+    pub(crate) async fn run(&self, bind_addrs: &[String], bind_port: u16) -> AnyResult<()> {
         let server = self;
-        server.set_address(bind_addr);
         server.set_service(&format!("{}", bind_port));
-        // Attach server to default Glib context
-        let _ = server.attach(None);
+        for bind_addr in bind_addrs.iter() {
+            // The socket is made from the address at the time the source is created
+            server.set_address(bind_addr);
+            let source = server
+                .create_source(gio::Cancellable::NONE)
+                .with_context(|| {
+                    format!("Could not bind the rtsp server to {bind_addr} port {bind_port}")
+                })?;
+            // Attach server to default Glib context
+            source.attach(None);
+        }
         let main_loop = Arc::new(MainLoop::new(None, false));
 
         // Run the Glib main loop.
@@ -113,6 +171,16 @@ impl NeoRtspServer {
                         RTSPFilterResult::Keep
                     }));
                 }
+                for client in clean_up_server.clients() {
+                    log::debug!(
+                        "Client {} as {} on {} over {} since {}",
+                        client.address,
+                        client.user.as_deref().unwrap_or("unknown"),
+                        client.path.as_deref().unwrap_or("unknown"),
+                        client.transport.as_deref().unwrap_or("unknown"),
+                        client.connected,
+                    );
+                }
                 std::thread::sleep(Duration::from_secs(5));
             }
             AnyResult::Ok(())
@@ -145,10 +213,20 @@ impl NeoRtspServer {
         Ok(())
     }
 
+    /// The mount points that can also mount the playback paths
+    pub(crate) fn playback_mounts(&self) -> Option<NeoMountPoints> {
+        self.mount_points()?.downcast::<NeoMountPoints>().ok()
+    }
+
     pub(crate) fn set_up_tls(&self, config: &Config) -> AnyResult<()> {
         self.imp().set_up_tls(config)
     }
 
+    /// Take `max_clients` of the cameras and `rtsp_rate_limit` from the config
+    pub(crate) fn set_client_limits(&self, config: &Config) {
+        self.imp().set_client_limits(config)
+    }
+
     pub(crate) async fn add_user(&self, username: &str, password: &str) -> AnyResult<()> {
         self.imp().add_user(username, password).await
     }
@@ -160,26 +238,118 @@ impl NeoRtspServer {
     pub(crate) async fn get_users(&self) -> AnyResult<HashSet<String>> {
         self.imp().get_users().await
     }
+
+    /// The rtsp clients that are currently connected
+    pub(crate) fn clients(&self) -> Vec<RtspClientInfo> {
+        self.imp().clients()
+    }
+
+    /// Disconnect the clients of a camera that connect from the address
+    ///
+    /// The address can be either `ip:port` or just the `ip`
+    /// to disconnect all the clients from that host.
+    /// Returns the number of clients that were disconnected
+    pub(crate) fn kick(&self, camera: &str, address: &str) -> usize {
+        self.imp().kick(camera, address)
+    }
+
+    /// Disconnect all of the clients, returns how many there were
+    pub(crate) fn close_all(&self) -> usize {
+        self.imp().close_all()
+    }
+
+    /// Keep the number of clients watching the camera in the watch
+    pub(crate) fn track_clients(&self, camera: &str, count: Arc<WatchSender<u32>>) {
+        self.imp().track_clients(camera, count)
+    }
+
+    /// The counter of the bytes sent to the clients of a stream
+    ///
+    /// The same counter is given back when the stream is set up again so it
+    /// counts from when neolink started
+    pub(crate) fn sent_counter(
+        &self,
+        camera: &str,
+        stream: &str,
+        paths: &[String],
+    ) -> Arc<AtomicU64> {
+        let mut sent = self.imp().sent.lock().unwrap();
+        let entry = sent
+            .entry((camera.to_string(), stream.to_string()))
+            .or_insert_with(|| (vec![], Arc::new(AtomicU64::new(0))));
+        entry.0 = paths.to_vec();
+        entry.1.clone()
+    }
+
+    /// The streams with the bytes sent to their clients
+    pub(crate) fn streams(&self) -> Vec<RtspStreamInfo> {
+        let mut streams = self
+            .imp()
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((camera, stream), (paths, sent))| RtspStreamInfo {
+                camera: camera.clone(),
+                stream: stream.clone(),
+                paths: paths.clone(),
+                bytes_sent: sent.load(Ordering::Relaxed),
+            })
+            .collect::<Vec<_>>();
+        streams.sort_by(|a, b| (&a.camera, &a.stream).cmp(&(&b.camera, &b.stream)));
+        streams
+    }
 }
 
 unsafe impl Send for NeoRtspServer {}
 unsafe impl Sync for NeoRtspServer {}
 
+struct TrackedClient {
+    client: WeakRef<RTSPClient>,
+    info: RtspClientInfo,
+    /// Connected over the `rtsp_rate_limit` so all its requests are refused
+    throttled: bool,
+}
+
+/// The limits from the config that are checked as clients connect
+#[derive(Default)]
+struct ClientLimits {
+    max_clients: HashMap<String, u32>,
+    rate_limit: Option<u32>,
+    /// When the recent connections of each ip were let in
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+/// The window of `rtsp_rate_limit`
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
 #[derive(Default)]
 pub(crate) struct NeoRtspServerImpl {
     threads: RwLock<JoinSet<AnyResult<()>>>,
     users: RwLock<HashMap<String, String>>,
     main_loop: RwLock<Option<Arc<MainLoop>>>,
+    // These are updated from the glib signals which are not async
+    // so a std Mutex is used
+    clients: Mutex<HashMap<u64, TrackedClient>>,
+    /// The client count of each camera by name
+    client_counts: Mutex<HashMap<String, Arc<WatchSender<u32>>>>,
+    limits: Mutex<ClientLimits>,
+    next_client_id: AtomicU64,
+    sent: Mutex<SentCounters>,
 }
 
+/// The paths and bytes sent of each stream by camera name and stream
+type SentCounters = HashMap<(String, String), (Vec<String>, Arc<AtomicU64>)>;
+
 impl ObjectImpl for NeoRtspServerImpl {}
 impl RTSPServerImpl for NeoRtspServerImpl {}
+impl RTSPOnvifServerImpl for NeoRtspServerImpl {}
 
 #[object_subclass]
 impl ObjectSubclass for NeoRtspServerImpl {
     const NAME: &'static str = "NeoRtspServer";
     type Type = NeoRtspServer;
-    type ParentType = RTSPServer;
+    type ParentType = RTSPOnvifServer;
 }
 
 impl NeoRtspServerImpl {
@@ -187,6 +357,7 @@ impl NeoRtspServerImpl {
         &self,
         cert_file: &str,
         client_auth: TlsAuthenticationMode,
+        ca_file: Option<&str>,
     ) -> AnyResult<()> {
         debug!("Setting up TLS using {}", cert_file);
         let auth = self.obj().auth().unwrap_or_default();
@@ -197,6 +368,12 @@ impl NeoRtspServerImpl {
             .with_context(|| "Not a valid TLS certificate")?;
         auth.set_tls_certificate(Some(&cert));
         auth.set_tls_authentication_mode(client_auth);
+        if let Some(ca_file) = ca_file {
+            // Without this the client certificates are checked against the system CAs
+            let database = TlsFileDatabase::new(ca_file)
+                .with_context(|| format!("Not a valid TLS CA file {ca_file}"))?;
+            auth.set_tls_database(Some(&database));
+        }
 
         self.obj().set_auth(Some(&auth));
         Ok(())
@@ -204,13 +381,13 @@ impl NeoRtspServerImpl {
 
     pub(crate) fn set_up_tls(&self, config: &Config) -> AnyResult<()> {
         let tls_client_auth = match &config.tls_client_auth as &str {
-            "request" => TlsAuthenticationMode::Requested,
-            "require" => TlsAuthenticationMode::Required,
+            "request" | "requested" => TlsAuthenticationMode::Requested,
+            "require" | "required" => TlsAuthenticationMode::Required,
             "none" => TlsAuthenticationMode::None,
             _ => unreachable!(),
         };
         if let Some(cert_path) = &config.certificate {
-            self.set_tls(cert_path, tls_client_auth)
+            self.set_tls(cert_path, tls_client_auth, config.tls_ca.as_deref())
                 .with_context(|| "Failed to set up TLS")?;
         }
         Ok(())
@@ -257,4 +434,298 @@ impl NeoRtspServerImpl {
         let locked_users = self.users.read().await;
         Ok(locked_users.keys().cloned().collect())
     }
+
+    fn add_client(&self, client: &RTSPClient) -> u64 {
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        let info = RtspClientInfo {
+            address: client_address(client).unwrap_or_else(|| "unknown".to_string()),
+            user: None,
+            path: None,
+            transport: None,
+            connected: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+        log::debug!("Client connected from {}", info.address);
+        let throttled = !self.within_rate_limit(&info.address);
+        if throttled {
+            log::warn!(
+                "Refusing rtsp client from {}, it is over the rtsp_rate_limit",
+                info.address
+            );
+        }
+        self.clients.lock().unwrap().insert(
+            id,
+            TrackedClient {
+                client: client.downgrade(),
+                info,
+                throttled,
+            },
+        );
+        id
+    }
+
+    fn set_client_limits(&self, config: &Config) {
+        let mut limits = self.limits.lock().unwrap();
+        limits.max_clients = config
+            .cameras
+            .iter()
+            .filter_map(|camera| Some((camera.name.clone(), camera.max_clients?)))
+            .collect();
+        limits.rate_limit = config.rtsp_rate_limit;
+        if limits.rate_limit.is_none() {
+            limits.recent.clear();
+        }
+    }
+
+    /// Note the new connection from the address if it is under the rate limit
+    fn within_rate_limit(&self, address: &str) -> bool {
+        let mut limits = self.limits.lock().unwrap();
+        let (Some(rate_limit), Ok(addr)) = (limits.rate_limit, address.parse::<SocketAddr>())
+        else {
+            return true;
+        };
+        let now = Instant::now();
+        limits
+            .recent
+            .retain(|_, times| times.back().is_some_and(|t| now - *t < RATE_LIMIT_WINDOW));
+        let times = limits.recent.entry(addr.ip()).or_default();
+        while times.front().is_some_and(|t| now - *t >= RATE_LIMIT_WINDOW) {
+            times.pop_front();
+        }
+        if times.len() as u32 >= rate_limit {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+
+    /// Refuse the request with a 503 when the client is throttled or its
+    /// camera already has `max_clients`
+    fn admit_client(&self, id: u64, ctx: &RTSPContext) -> RTSPStatusCode {
+        let clients = self.clients.lock().unwrap();
+        let Some(tracked) = clients.get(&id) else {
+            return RTSPStatusCode::Ok;
+        };
+        if tracked.throttled {
+            return RTSPStatusCode::ServiceUnavailable;
+        }
+        // Already let in on an earlier request
+        if tracked.info.path.is_some() {
+            return RTSPStatusCode::Ok;
+        }
+        let Some(path) = ctx.uri().and_then(|uri| url_path(&uri.request_uri())) else {
+            return RTSPStatusCode::Ok;
+        };
+        let Some(camera) = path_camera(&path) else {
+            return RTSPStatusCode::Ok;
+        };
+        let Some(max_clients) = self.limits.lock().unwrap().max_clients.get(camera).copied() else {
+            return RTSPStatusCode::Ok;
+        };
+        let watching = clients
+            .iter()
+            .filter(|(other, _)| **other != id)
+            .filter(|(_, other)| other.info.path.as_deref().and_then(path_camera) == Some(camera))
+            .count() as u32;
+        if watching >= max_clients {
+            log::warn!(
+                "{camera}: Refusing rtsp client from {}, it already has {max_clients} clients",
+                tracked.info.address
+            );
+            RTSPStatusCode::ServiceUnavailable
+        } else {
+            RTSPStatusCode::Ok
+        }
+    }
+
+    fn update_client(&self, id: u64, ctx: &RTSPContext, is_setup: bool) {
+        let mut clients = self.clients.lock().unwrap();
+        let Some(tracked) = clients.get_mut(&id) else {
+            return;
+        };
+        if let Some(role) = ctx
+            .token()
+            .and_then(|token| token.string(RTSP_TOKEN_MEDIA_FACTORY_ROLE))
+        {
+            tracked.info.user = Some(role);
+        }
+        // The setup uri has the stream control appended so prefer the describe one
+        if tracked.info.path.is_none() || !is_setup {
+            if let Some(path) = ctx.uri().and_then(|uri| url_path(&uri.request_uri())) {
+                tracked.info.path = Some(path);
+            }
+        }
+        if is_setup {
+            if let Some(transport) = request_transport(ctx) {
+                tracked.info.transport = Some(transport);
+            }
+        }
+        drop(clients);
+        self.update_client_counts();
+    }
+
+    fn remove_client(&self, id: u64) {
+        if let Some(tracked) = self.clients.lock().unwrap().remove(&id) {
+            log::debug!("Client disconnected from {}", tracked.info.address);
+        }
+        self.update_client_counts();
+    }
+
+    fn track_clients(&self, camera: &str, count: Arc<WatchSender<u32>>) {
+        self.client_counts
+            .lock()
+            .unwrap()
+            .insert(camera.to_string(), count);
+        self.update_client_counts();
+    }
+
+    /// Count the clients on each camera, a client is only on a camera once
+    /// it has asked for one of its paths
+    fn update_client_counts(&self) {
+        let mut counts = HashMap::<String, u32>::new();
+        for tracked in self.clients.lock().unwrap().values() {
+            if let Some(camera) = tracked.info.path.as_deref().and_then(path_camera) {
+                *counts.entry(camera.to_string()).or_default() += 1;
+            }
+        }
+        for (camera, count) in self.client_counts.lock().unwrap().iter() {
+            let new_count = counts.get(camera).copied().unwrap_or(0);
+            count.send_if_modified(|old| {
+                if *old != new_count {
+                    *old = new_count;
+                    true
+                } else {
+                    false
+                }
+            });
+        }
+    }
+
+    pub(crate) fn clients(&self) -> Vec<RtspClientInfo> {
+        let mut clients = self
+            .clients
+            .lock()
+            .unwrap()
+            .values()
+            .map(|tracked| tracked.info.clone())
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.connected);
+        clients
+    }
+
+    pub(crate) fn kick(&self, camera: &str, address: &str) -> usize {
+        let to_kick = self
+            .clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|tracked| {
+                let info = &tracked.info;
+                let on_camera = info
+                    .path
+                    .as_deref()
+                    .and_then(path_camera)
+                    .is_some_and(|name| name == camera);
+                let from_address = info.address == address
+                    || info
+                        .address
+                        .parse::<SocketAddr>()
+                        .is_ok_and(|addr| addr.ip().to_string() == address);
+                on_camera && from_address
+            })
+            .filter_map(|tracked| tracked.client.upgrade())
+            .collect::<Vec<_>>();
+        self.close(&to_kick);
+        to_kick.len()
+    }
+
+    pub(crate) fn close_all(&self) -> usize {
+        let clients = self
+            .clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|tracked| tracked.client.upgrade())
+            .collect::<Vec<_>>();
+        self.close(&clients);
+        clients.len()
+    }
+
+    fn close(&self, clients: &[RTSPClient]) {
+        let pool = self.obj().session_pool();
+        for client in clients.iter() {
+            // Removing the sessions from the pool tears down their media
+            // straight away rather than waiting for the session timeout
+            client.session_filter(Some(&mut |_, session| {
+                if let Some(pool) = pool.as_ref() {
+                    let _ = pool.remove(session);
+                }
+                RTSPFilterResult::Remove
+            }));
+            client.close();
+        }
+    }
+}
+
+/// The remote `ip:port` of the client
+fn client_address(client: &RTSPClient) -> Option<String> {
+    // RTSPClient::connection is not bound so reach the socket through the C api
+    let socket: Option<gio::Socket> = unsafe {
+        let conn =
+            gstreamer_rtsp_server::ffi::gst_rtsp_client_get_connection(client.to_glib_none().0);
+        if conn.is_null() {
+            return None;
+        }
+        from_glib_none(gstreamer_rtsp::ffi::gst_rtsp_connection_get_read_socket(
+            conn,
+        ))
+    };
+    let addr = socket?
+        .remote_address()
+        .ok()?
+        .downcast::<gio::InetSocketAddress>()
+        .ok()?;
+    Some(SocketAddr::from(addr).to_string())
+}
+
+/// The camera name of an rtsp path, its first part
+fn path_camera(path: &str) -> Option<&str> {
+    path.trim_start_matches('/').split('/').next()
+}
+
+/// The path part of an rtsp url e.g. `/Garage/subStream`
+fn url_path(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let start = rest.find('/')?;
+    Some(rest[start..].to_string())
+}
+
+/// Summarise the Transport header of the request as `TCP`, `UDP` or `UDP multicast`
+fn request_transport(ctx: &RTSPContext) -> Option<String> {
+    let request = ctx.request()?;
+    let transport = unsafe {
+        let mut value = std::ptr::null_mut();
+        let res = gstreamer_rtsp::ffi::gst_rtsp_message_get_header(
+            request.to_glib_none().0,
+            gstreamer_rtsp::ffi::GST_RTSP_HDR_TRANSPORT,
+            &mut value,
+            0,
+        );
+        if res != gstreamer_rtsp::ffi::GST_RTSP_OK || value.is_null() {
+            return None;
+        }
+        CStr::from_ptr(value).to_string_lossy().to_ascii_uppercase()
+    };
+    Some(
+        if transport.contains("RTP/AVP/TCP") {
+            "TCP"
+        } else if transport.contains("MULTICAST") {
+            "UDP multicast"
+        } else {
+            "UDP"
+        }
+        .to_string(),
+    )
 }
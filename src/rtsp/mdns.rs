@@ -0,0 +1,493 @@
+//! Advertises the rtsp streams with mDNS so that clients can find them
+//!
+//! With `[mdns]` in the config each enabled camera is announced as a
+//! `_rtsp._tcp` service named after the camera. Its SRV record points at our
+//! host name and rtsp port and its TXT record has the `path=/<camera>` of the
+//! best stream, or the first of its `paths` when they are set. The records
+//! are announced on start and when the cameras in the config change, and then
+//! given to anyone who queries for them.
+//!
+//! Only as much of mDNS as is needed to answer is done. There is no probing
+//! for a name that is already taken, no known answer suppression and only
+//! IPv4 is used.
+use anyhow::{anyhow, Context};
+use neolink_core::bc_protocol::StreamKind;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::{net::UdpSocket, time::Duration};
+
+use crate::{
+    common::NeoReactor,
+    config::{Config, MdnsConfig},
+    AnyResult,
+};
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MULTICAST_PORT: u16 = 5353;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Marks the records that only we give answers for
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// TTL of the records with our address or port, these go stale quickest
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+
+/// Longest label allowed by DNS
+const MAX_LABEL: usize = 63;
+
+const SERVICE: &[&str] = &["_rtsp", "_tcp", "local"];
+const SERVICES: &[&str] = &["_services", "_dns-sd", "_udp", "local"];
+
+type Name = Vec<String>;
+
+struct Record {
+    name: Name,
+    rtype: u16,
+    /// Whether the cache flush bit is set
+    unique: bool,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+struct Question {
+    name: Name,
+    qtype: u16,
+    qclass: u16,
+}
+
+pub(super) async fn serve_mdns(config: MdnsConfig, reactor: NeoReactor) -> AnyResult<()> {
+    let socket = bind()?;
+    let group = SocketAddr::from((MULTICAST_ADDR, MULTICAST_PORT));
+    let mut config_rx = reactor.config().await?;
+    log::info!(
+        "Advertising the rtsp streams over mDNS on {}.local",
+        config.hostname
+    );
+
+    let rtsp_config = config_rx.borrow_and_update().clone();
+    let mut cameras = camera_names(&rtsp_config);
+    announce(&socket, &config, &rtsp_config, group).await?;
+
+    let mut buf = vec![0u8; 9000];
+    loop {
+        tokio::select! {
+            v = config_rx.changed() => {
+                v?;
+                let rtsp_config = config_rx.borrow_and_update().clone();
+                let new_cameras = camera_names(&rtsp_config);
+                if new_cameras != cameras {
+                    cameras = new_cameras;
+                    announce(&socket, &config, &rtsp_config, group).await?;
+                }
+            }
+            v = socket.recv_from(&mut buf) => {
+                let (len, peer) = v?;
+                let Some((id, questions)) = parse_query(&buf[..len]) else {
+                    continue;
+                };
+                let rtsp_config = config_rx.borrow().clone();
+                let records = records(&config, &rtsp_config, local_ip(&config, peer).await?);
+                let (answers, additional): (Vec<&Record>, Vec<&Record>) = records
+                    .iter()
+                    .partition(|record| questions.iter().any(|question| answers(question, record)));
+                if answers.is_empty() {
+                    continue;
+                }
+                log::trace!("mDNS query from {peer} matched {} records", answers.len());
+                // A client that does not use the mDNS port is a plain dns
+                // resolver, it wants a direct reply that repeats the question
+                let (reply, to) = if peer.port() == MULTICAST_PORT {
+                    (packet(0, &[], &answers, &additional), group)
+                } else {
+                    (packet(id, &questions, &answers, &additional), peer)
+                };
+                if let Err(e) = socket.send_to(&reply, to).await {
+                    log::debug!("Could not answer the mDNS query from {peer}: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+/// Bind the mDNS port along side any other responder on this machine
+fn bind() -> AnyResult<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MULTICAST_PORT)).into())
+        .with_context(|| format!("Could not bind to the mDNS port {MULTICAST_PORT}"))?;
+    socket
+        .join_multicast_v4(&MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)
+        .context("Could not join the mDNS multicast group")?;
+    socket.set_multicast_ttl_v4(255)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Send all of the records unasked, twice as mDNS asks
+async fn announce(
+    socket: &UdpSocket,
+    config: &MdnsConfig,
+    rtsp_config: &Config,
+    group: SocketAddr,
+) -> AnyResult<()> {
+    let records = records(config, rtsp_config, local_ip(config, group).await?);
+    let answers = records.iter().collect::<Vec<_>>();
+    let announcement = packet(0, &[], &answers, &[]);
+    for i in 0..2 {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        socket.send_to(&announcement, group).await?;
+    }
+    log::debug!("mDNS announced {} records", records.len());
+    Ok(())
+}
+
+/// The cameras that have an rtsp stream with the path of their best stream
+fn camera_names(config: &Config) -> Vec<(String, String)> {
+    config
+        .cameras
+        .iter()
+        .filter(|camera| camera.enabled && !camera.stream.as_stream_kinds().is_empty())
+        .map(|camera| {
+            let path = [StreamKind::Main, StreamKind::Sub, StreamKind::Extern]
+                .iter()
+                .find(|stream| camera.stream.as_stream_kinds().contains(stream))
+                .and_then(|stream| camera.custom_path(*stream))
+                .unwrap_or_else(|| format!("/{}", camera.name));
+            (camera.name.clone(), path)
+        })
+        .collect()
+}
+
+/// The address of ours that is given for the host name
+async fn local_ip(config: &MdnsConfig, peer: SocketAddr) -> AnyResult<Ipv4Addr> {
+    if let Some(address) = config.address.as_ref() {
+        return Ok(address.parse()?);
+    }
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(peer).await?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(anyhow!("mDNS needs an IPv4 address")),
+    }
+}
+
+fn records(config: &MdnsConfig, rtsp_config: &Config, ip: Ipv4Addr) -> Vec<Record> {
+    let host = vec![config.hostname.clone(), "local".to_string()];
+    let service = to_name(SERVICE);
+    let mut records = vec![
+        Record {
+            name: host.clone(),
+            rtype: TYPE_A,
+            unique: true,
+            ttl: HOST_TTL,
+            data: ip.octets().to_vec(),
+        },
+        Record {
+            name: to_name(SERVICES),
+            rtype: TYPE_PTR,
+            unique: false,
+            ttl: OTHER_TTL,
+            data: encode_name(&service),
+        },
+    ];
+    for (camera, path) in camera_names(rtsp_config) {
+        let mut instance = vec![camera.clone()];
+        instance.extend(service.iter().cloned());
+
+        records.push(Record {
+            name: service.clone(),
+            rtype: TYPE_PTR,
+            unique: false,
+            ttl: OTHER_TTL,
+            data: encode_name(&instance),
+        });
+
+        // Priority and weight are unused with one server
+        let mut srv = vec![0, 0, 0, 0];
+        srv.extend_from_slice(&rtsp_config.bind_port.to_be_bytes());
+        srv.extend(encode_name(&host));
+        records.push(Record {
+            name: instance.clone(),
+            rtype: TYPE_SRV,
+            unique: true,
+            ttl: HOST_TTL,
+            data: srv,
+        });
+
+        let path = format!("path={path}");
+        let path = &path.as_bytes()[..path.len().min(255)];
+        let mut txt = vec![path.len() as u8];
+        txt.extend_from_slice(path);
+        records.push(Record {
+            name: instance,
+            rtype: TYPE_TXT,
+            unique: true,
+            ttl: OTHER_TTL,
+            data: txt,
+        });
+    }
+    records
+}
+
+fn answers(question: &Question, record: &Record) -> bool {
+    // The top bit asks for a unicast reply which we do not need to honour
+    let class = question.qclass & !CACHE_FLUSH;
+    (class == CLASS_IN || class == TYPE_ANY)
+        && (question.qtype == TYPE_ANY || question.qtype == record.rtype)
+        && same_name(&question.name, &record.name)
+}
+
+fn same_name(a: &Name, b: &Name) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+}
+
+fn to_name(labels: &[&str]) -> Name {
+    labels.iter().map(|label| label.to_string()).collect()
+}
+
+fn encode_name(name: &Name) -> Vec<u8> {
+    let mut out = vec![];
+    for label in name.iter() {
+        // Cut long names on a character boundary
+        let mut end = label.len().min(MAX_LABEL);
+        while !label.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push(end as u8);
+        out.extend_from_slice(&label.as_bytes()[..end]);
+    }
+    out.push(0);
+    out
+}
+
+/// A response with our answers, and the questions for a plain dns resolver
+fn packet(id: u16, questions: &[Question], answers: &[&Record], additional: &[&Record]) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&id.to_be_bytes());
+    // A response that we are the authority for
+    out.extend_from_slice(&0x8400u16.to_be_bytes());
+    out.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(additional.len() as u16).to_be_bytes());
+    for question in questions.iter() {
+        out.extend(encode_name(&question.name));
+        out.extend_from_slice(&question.qtype.to_be_bytes());
+        out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    for record in answers.iter().chain(additional.iter()) {
+        let class = if record.unique {
+            CLASS_IN | CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        out.extend(encode_name(&record.name));
+        out.extend_from_slice(&record.rtype.to_be_bytes());
+        out.extend_from_slice(&class.to_be_bytes());
+        out.extend_from_slice(&record.ttl.to_be_bytes());
+        out.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+        out.extend_from_slice(&record.data);
+    }
+    out
+}
+
+/// The id and questions of a query, None for responses and bad packets
+fn parse_query(packet: &[u8]) -> Option<(u16, Vec<Question>)> {
+    if packet.len() < 12 {
+        return None;
+    }
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    // Only standard queries
+    if flags & 0xf800 != 0 {
+        return None;
+    }
+    let count = u16::from_be_bytes([packet[4], packet[5]]);
+    let mut pos = 12;
+    let mut questions = vec![];
+    for _ in 0..count {
+        let (name, next) = read_name(packet, pos)?;
+        let fields = packet.get(next..next + 4)?;
+        questions.push(Question {
+            name,
+            qtype: u16::from_be_bytes([fields[0], fields[1]]),
+            qclass: u16::from_be_bytes([fields[2], fields[3]]),
+        });
+        pos = next + 4;
+    }
+    Some((id, questions))
+}
+
+/// Read the name at `pos`, also returns where the name ends
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(Name, usize)> {
+    let mut name = vec![];
+    let mut end = None;
+    // Each pointer must go back so a loop cannot go on forever
+    let mut limit = pos;
+    loop {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((name, end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let target = ((len & 0x3f) << 8) | *packet.get(pos + 1)? as usize;
+                if target >= limit {
+                    return None;
+                }
+                end.get_or_insert(pos + 2);
+                limit = target;
+                pos = target;
+            }
+            len if len <= MAX_LABEL => {
+                let label = packet.get(pos + 1..pos + 1 + len)?;
+                name.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(id: u16, names: &[&[&str]], qtype: u16) -> Vec<u8> {
+        let mut out = vec![];
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&(names.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0; 6]);
+        for name in names.iter() {
+            out.extend(encode_name(&to_name(name)));
+            out.extend_from_slice(&qtype.to_be_bytes());
+            out.extend_from_slice(&(CLASS_IN | CACHE_FLUSH).to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_name() {
+        let name = to_name(&["Garage", "_rtsp", "_tcp", "local"]);
+        let encoded = encode_name(&name);
+        assert_eq!(&encoded[..7], b"\x06Garage");
+        assert_eq!(encoded.last(), Some(&0));
+        assert_eq!(read_name(&encoded, 0), Some((name, encoded.len())));
+
+        // Long labels are cut on a character boundary
+        let long = vec!["é".repeat(40)];
+        let encoded = encode_name(&long);
+        assert_eq!(encoded[0], 62);
+        assert_eq!(read_name(&encoded, 0).unwrap().0, vec!["é".repeat(31)]);
+    }
+
+    #[test]
+    fn test_read_name_pointer() {
+        // local at 0 then _tcp.local as a pointer back to it
+        let mut packet = encode_name(&to_name(&["local"]));
+        let start = packet.len();
+        packet.extend_from_slice(b"\x04_tcp\xc0\x00");
+        assert_eq!(
+            read_name(&packet, start),
+            Some((to_name(&["_tcp", "local"]), packet.len()))
+        );
+        // Pointers that do not go back are refused so a loop cannot hang
+        assert_eq!(read_name(b"\x04_tcp\xc0\x00", 0), None);
+        assert_eq!(read_name(b"\xc0\x00", 0), None);
+        // Truncated
+        assert_eq!(read_name(b"\x05loc", 0), None);
+        assert_eq!(read_name(b"\x40", 0), None);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let (id, questions) =
+            parse_query(&query(7, &[SERVICE, &["neolink", "local"]], TYPE_PTR)).unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(questions.len(), 2);
+        assert!(same_name(&questions[0].name, &to_name(SERVICE)));
+        assert_eq!(questions[1].name, to_name(&["neolink", "local"]));
+        assert_eq!(questions[0].qtype, TYPE_PTR);
+
+        // Our own responses are not queries
+        let record = Record {
+            name: to_name(SERVICE),
+            rtype: TYPE_PTR,
+            unique: false,
+            ttl: OTHER_TTL,
+            data: encode_name(&to_name(&["Garage", "_rtsp", "_tcp", "local"])),
+        };
+        assert!(parse_query(&packet(7, &[], &[&record], &[])).is_none());
+        assert!(parse_query(&[0; 11]).is_none());
+        // More questions than are in the packet
+        let mut short = query(7, &[SERVICE], TYPE_PTR);
+        short[5] = 2;
+        assert!(parse_query(&short).is_none());
+    }
+
+    #[test]
+    fn test_answers() {
+        let record = Record {
+            name: to_name(SERVICE),
+            rtype: TYPE_PTR,
+            unique: false,
+            ttl: OTHER_TTL,
+            data: vec![],
+        };
+        let question = |name: &[&str], qtype: u16, qclass: u16| Question {
+            name: to_name(name),
+            qtype,
+            qclass,
+        };
+        // Names are not case sensitive and the unicast bit is ignored
+        assert!(answers(
+            &question(
+                &["_RTSP", "_tcp", "local"],
+                TYPE_PTR,
+                CLASS_IN | CACHE_FLUSH
+            ),
+            &record
+        ));
+        assert!(answers(&question(SERVICE, TYPE_ANY, CLASS_IN), &record));
+        assert!(!answers(&question(SERVICE, TYPE_SRV, CLASS_IN), &record));
+        assert!(!answers(&question(SERVICE, TYPE_PTR, 3), &record));
+        assert!(!answers(
+            &question(&["_rtsp", "_udp", "local"], TYPE_PTR, CLASS_IN),
+            &record
+        ));
+    }
+
+    #[test]
+    fn test_packet() {
+        let record = Record {
+            name: to_name(&["neolink", "local"]),
+            rtype: TYPE_A,
+            unique: true,
+            ttl: HOST_TTL,
+            data: vec![192, 168, 1, 2],
+        };
+        let out = packet(9, &[], &[&record], &[]);
+        assert_eq!(&out[..12], &[0, 9, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        let (name, pos) = read_name(&out, 12).unwrap();
+        assert_eq!(name, record.name);
+        assert_eq!(
+            &out[pos..],
+            &[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 2]
+        );
+    }
+}
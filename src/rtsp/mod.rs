@@ -41,6 +41,8 @@
 //   on_client = false
 //   mode = "none"
 //   timeout = 1.0
+//   [cameras.pause.sub]
+//   on_motion = false
 // ```
 //
 // - When `on_motion` is true the camera will pause streaming when motion is stopped and resume it when motion is started
@@ -50,7 +52,14 @@
 //   - `"black"`: Switches to a black screen. Requires more cpu as the stream is fully reencoded
 //   - `"still"`: Switches to a still image. Requires more cpu as the stream is fully reencoded
 //   - `"test"`: Switches to the gstreamer test image. Requires more cpu as the stream is fully reencoded
+//   - `"image"`: Switches to the picture at `image`, with the camera name on it
 //   - `"none"`: Resends the last iframe the camera. This does not reencode at all.  **Most use cases should use this one as it has the least effort on the cpu and gives what you would expect**
+// - `[cameras.pause.main]`, `[cameras.pause.sub]` and `[cameras.pause.extern]` change any of
+//   these for one stream
+// - When `motion_resume` is true motion starts the first stream for `motion_resume_duration`
+//   seconds even when there is no client, so the camera stays awake and connected
+// - When `disconnect` is true the camera is logged out of while every stream is paused and
+//   logged into again to resume. This is for the whole camera and not each stream
 //
 use anyhow::{anyhow, Context, Result};
 use gstreamer_rtsp_server::prelude::*;
@@ -61,7 +70,7 @@ use std::sync::Arc;
 use tokio::{
     sync::watch::channel as watch,
     task::JoinSet,
-    time::{interval, Duration},
+    time::{interval, sleep, Duration},
 };
 use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
@@ -70,18 +79,26 @@ use tokio_util::sync::CancellationToken;
 mod cmdline;
 mod factory;
 mod gst;
+mod mdns;
 mod stream;
 
-use crate::common::{NeoInstance, NeoReactor};
+use crate::common::{
+    run_control, ControlReply, ControlRequest, ControlSocket, NeoInstance, NeoReactor,
+};
+use crate::onvif::serve_onvif;
 use factory::*;
+use mdns::serve_mdns;
 use stream::*;
 
-use super::config::UserConfig;
+use super::config::{CompositeConfig, UserConfig};
 pub(crate) use cmdline::Opt;
 use gst::NeoRtspServer;
 
 type AnyResult<T> = anyhow::Result<T, anyhow::Error>;
 
+/// How long a camera that failed waits before it is started again
+const CAMERA_RESTART_DELAY: Duration = Duration::from_secs(5);
+
 /// Entry point for the rtsp subcommand
 ///
 /// Opt is the command line options
@@ -111,6 +128,23 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // Thread for the client limits from the config
+    let mut thread_config = reactor.config().await?;
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    thread_rtsp.set_client_limits(&thread_config.borrow_and_update().clone());
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                loop {
+                    thread_config.changed().await?;
+                    thread_rtsp.set_client_limits(&thread_config.borrow().clone());
+                }
+            } => v
+        }
+    });
+
     // Thread for the Users from the config
     let mut thread_config = reactor.config().await?;
     let thread_cancel = global_cancel.clone();
@@ -170,42 +204,191 @@ pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
                             let thread_reactor2 = thread_reactor.clone();
                             let name = name.clone();
                             set.spawn(async move {
-                                let camera = thread_reactor2.get(&name).await?;
-                                tokio::select!(
-                                    _ = thread_global_cancel.cancelled() => {
-                                        AnyResult::Ok(())
-                                    },
-                                    _ = local_cancel.cancelled() => {
-                                        AnyResult::Ok(())
-                                    },
-                                    v = camera_main(camera, &thread_rtsp2) => v,
-                                )
+                                // Each camera is started again on its own when it fails
+                                // so that it does not hold up the others
+                                loop {
+                                    let r = tokio::select!(
+                                        _ = thread_global_cancel.cancelled() => return AnyResult::Ok(()),
+                                        _ = local_cancel.cancelled() => return AnyResult::Ok(()),
+                                        v = rtsp_camera_main(&name, &thread_reactor2, &thread_rtsp2) => v,
+                                    );
+                                    match r {
+                                        Ok(()) => return AnyResult::Ok(()),
+                                        Err(e) => log::warn!("{name}: Rtsp failed, restarting in {}s: {e:?}", CAMERA_RESTART_DELAY.as_secs()),
+                                    }
+                                    tokio::select!(
+                                        _ = thread_global_cancel.cancelled() => return AnyResult::Ok(()),
+                                        _ = local_cancel.cancelled() => return AnyResult::Ok(()),
+                                        _ = sleep(CAMERA_RESTART_DELAY) => {},
+                                    );
+                                }
                             }) ;
                         }
                     }
 
-                    for (running_name, token) in cameras.iter() {
-                        if ! config_names.contains(running_name) {
+                    // Forget the removed cameras so that they start again if they come back
+                    cameras.retain(|running_name, token| {
+                        let keep = config_names.contains(running_name);
+                        if !keep {
                             token.cancel();
                         }
+                        keep
+                    });
+                }
+            } => v,
+        }
+    });
+
+    // Mount the composites and restart them when they change
+    let mut thread_config = reactor.config().await?;
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    let thread_reactor = reactor.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                let mut composites = vec![];
+                loop {
+                    composites = thread_config
+                        .wait_for(|config| config.composites != composites)
+                        .await?
+                        .composites
+                        .clone();
+                    let mut set = JoinSet::new();
+                    for composite in composites.iter().cloned() {
+                        let rtsp = thread_rtsp.clone();
+                        let reactor = thread_reactor.clone();
+                        set.spawn(async move {
+                            loop {
+                                if let Err(e) = composite_camera_main(&composite, &reactor, &rtsp).await {
+                                    log::warn!("{}: Error in composite Restarting: {:?}", composite.name, e);
+                                }
+                                sleep(Duration::from_secs(10)).await;
+                            }
+                        });
                     }
+
+                    // Config changed go back and restart with the new one
+                    let r = thread_config
+                        .wait_for(|config| config.composites != composites)
+                        .await
+                        .map(|_| ());
+                    set.shutdown().await;
+                    r?;
                 }
             } => v,
         }
     });
 
     let rtsp_config = reactor.config().await?.borrow().clone();
+    let bind_port = rtsp_config.bind_port;
     info!(
-        "Starting RTSP Server at {}:{}",
-        &rtsp_config.bind_addr, rtsp_config.bind_port,
+        "Starting RTSP Server at {}",
+        rtsp_config
+            .bind_addrs
+            .iter()
+            .map(|addr| socket_text(addr, bind_port))
+            .collect::<Vec<_>>()
+            .join(", "),
     );
 
-    let bind_addr = rtsp_config.bind_addr.clone();
-    let bind_port = rtsp_config.bind_port;
-    rtsp.run(&bind_addr, bind_port).await?;
+    rtsp.run(&rtsp_config.bind_addrs, bind_port).await?;
     let thread_rtsp = rtsp.clone();
     set.spawn(async move { thread_rtsp.join().await });
 
+    // Answer `neolink clients`, `neolink kick` and the one shot controls from the control socket
+    if let Some(control) = ControlSocket::from_config(&rtsp_config) {
+        let thread_cancel = global_cancel.clone();
+        let thread_rtsp = rtsp.clone();
+        let thread_reactor = reactor.clone();
+        set.spawn(async move {
+            tokio::select! {
+                _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+                v = control.serve(move |request| {
+                    let rtsp = thread_rtsp.clone();
+                    let reactor = thread_reactor.clone();
+                    async move {
+                        match request {
+                            ControlRequest::Clients => ControlReply::Clients {
+                                clients: rtsp.clients(),
+                            },
+                            ControlRequest::Kick { camera, client } => {
+                                let count = rtsp.kick(&camera, &client);
+                                log::info!("{camera}: Kicked {count} rtsp clients from {client}");
+                                ControlReply::Kicked { count }
+                            }
+                            ControlRequest::Control { camera, control, message } => {
+                                log::info!("{camera}: Control socket {control} {message}");
+                                let reply = match reactor.get(&camera).await {
+                                    Ok(instance) => run_control(&instance, &control, &message).await,
+                                    Err(e) => Some(format!("FAIL: {e:?}")),
+                                };
+                                match reply {
+                                    Some(reply) => ControlReply::Controlled { reply },
+                                    None => ControlReply::Error {
+                                        message: format!("Unknown control {control} {message}"),
+                                    },
+                                }
+                            }
+                            ControlRequest::Streams => ControlReply::Streams {
+                                streams: rtsp.streams(),
+                            },
+                        }
+                    }
+                }) => v,
+            }
+        });
+    }
+
+    // Serve the ONVIF services that point at our mounts
+    if let Some(onvif_config) = rtsp_config.onvif.clone() {
+        let thread_cancel = global_cancel.clone();
+        let thread_reactor = reactor.clone();
+        set.spawn(async move {
+            tokio::select! {
+                _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+                v = serve_onvif(onvif_config, thread_reactor) => v,
+            }
+        });
+    }
+
+    // Advertise our mounts on the lan
+    if let Some(mdns_config) = rtsp_config.mdns.clone() {
+        let thread_cancel = global_cancel.clone();
+        let thread_reactor = reactor.clone();
+        set.spawn(async move {
+            tokio::select! {
+                _ = thread_cancel.cancelled() => {},
+                v = serve_mdns(mdns_config, thread_reactor) => {
+                    // The streams still work without it
+                    if let Err(e) = v {
+                        log::warn!("mDNS advertisement stopped: {e:?}");
+                    }
+                },
+            }
+            AnyResult::Ok(())
+        });
+    }
+
+    // Tear down the clients when neolink is stopping rather than leaving
+    // them to find the connection gone
+    let stopping = reactor.stopping();
+    let thread_cancel = global_cancel.clone();
+    let thread_rtsp = rtsp.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => {},
+            _ = stopping.cancelled() => {
+                let count = thread_rtsp.close_all();
+                log::info!("Closed {count} rtsp clients");
+                thread_cancel.cancel();
+                thread_rtsp.quit().await?;
+            }
+        }
+        AnyResult::Ok(())
+    });
+
     while let Some(joined) = set
         .join_next()
         .await
@@ -247,16 +430,132 @@ async fn apply_users(rtsp: &NeoRtspServer, curr_users: &HashSet<UserConfig>) ->
     Ok(())
 }
 
+/// The users that may watch a camera given its `permitted_users`
+///
+/// With `allow_anonymous` clients that do not log in may watch it too
+async fn permitted_users(
+    rtsp: &NeoRtspServer,
+    camera_users: &Option<Vec<String>>,
+    allow_anonymous: bool,
+) -> AnyResult<HashSet<String>> {
+    let all_users = rtsp
+        .get_users()
+        .await?
+        .iter()
+        .filter(|a| *a != "anyone" && *a != "anonymous")
+        .cloned()
+        .collect::<HashSet<_>>();
+    let mut users = match camera_users {
+        // If in the camera config there is the user "anyone", or if none is specified but users
+        // are defined at all, then we add all users to the camera's allowed list.
+        Some(p) if p.iter().any(|u| u == "anyone") => all_users,
+        None if !all_users.is_empty() => all_users,
+
+        // The user specified permitted_users
+        Some(p) => p.iter().cloned().collect(),
+
+        // The user didn't specify permitted_users, and there are none defined anyway
+        None => ["anonymous".to_string()].iter().cloned().collect(),
+    };
+    if allow_anonymous {
+        users.insert("anonymous".to_string());
+    }
+    Ok(users)
+}
+
+/// The users that may talk over the backchannel of a camera
+///
+/// These are the `admin` users out of those that may watch, never anonymous
+fn talk_users(users: &HashSet<String>, acls: &[UserConfig], camera: &str) -> HashSet<String> {
+    users
+        .iter()
+        .filter(|user| {
+            acls.iter()
+                .any(|acl| &acl.name == *user && acl.may_control(camera))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The `addr:port` with the brackets that an ipv6 address needs
+fn socket_text(addr: &str, port: u16) -> String {
+    if addr.contains(':') {
+        format!("[{addr}]:{port}")
+    } else {
+        format!("{addr}:{port}")
+    }
+}
+
+/// Composite entry point
+///
+/// Only those who may watch all of the cameras may watch the composite
+async fn composite_camera_main(
+    composite: &CompositeConfig,
+    reactor: &NeoReactor,
+    rtsp: &NeoRtspServer,
+) -> Result<()> {
+    let acls = reactor.config().await?.borrow().users.clone();
+    let stream = composite
+        .stream
+        .as_stream_kinds()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No stream for the composite"))?;
+    let mut cameras = vec![];
+    let mut users: Option<HashSet<String>> = None;
+    for name in composite.cameras.iter() {
+        let camera_config = reactor
+            .config()
+            .await?
+            .borrow()
+            .cameras
+            .iter()
+            .find(|camera| &camera.name == name && camera.enabled)
+            .cloned()
+            .ok_or_else(|| anyhow!("No enabled camera called {name}"))?;
+        let camera_users = stream_users(
+            &permitted_users(
+                rtsp,
+                &camera_config.permitted_users,
+                camera_config.allow_anonymous,
+            )
+            .await?,
+            &acls,
+            name,
+            stream,
+        );
+        users = Some(match users {
+            Some(users) => users.intersection(&camera_users).cloned().collect(),
+            None => camera_users,
+        });
+        cameras.push(reactor.get(name).await?);
+    }
+    composite_main(cameras, composite.clone(), rtsp, &users.unwrap_or_default()).await
+}
+
+/// Serve one camera from the config
+async fn rtsp_camera_main(name: &str, reactor: &NeoReactor, rtsp: &NeoRtspServer) -> Result<()> {
+    let camera = reactor.get(name).await?;
+    camera_main(camera, reactor, rtsp).await
+}
+
 /// Top level camera entry point
 ///
 /// It checks which streams are supported and then starts them
-async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
+async fn camera_main(
+    camera: NeoInstance,
+    reactor: &NeoReactor,
+    rtsp: &NeoRtspServer,
+) -> Result<()> {
     let name = camera.config().await?.borrow().name.clone();
     log::debug!("{name}: Camera Main");
+    rtsp.track_clients(&name, camera.rtsp_clients_sender().await?);
     let later_camera = camera.clone();
     let (supported_streams_tx, supported_streams) = watch(HashSet::<StreamKind>::new());
 
     let mut set = JoinSet::new();
+    set.spawn(motion_resume_main(camera.clone()));
+    set.spawn(led_auto_off_main(camera.clone()));
     set.spawn(async move {
         let mut i = IntervalStream::new(interval(Duration::from_secs(15)));
         while i.next().await.is_some() {
@@ -291,19 +590,28 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
     });
 
     let mut camera_config = camera.config().await?.clone();
+    let mut global_config = reactor.config().await?;
     loop {
+        let acls = global_config.borrow_and_update().users.clone();
         let prev_stream_config = camera_config.borrow_and_update().stream;
         let prev_stream_users = camera_config.borrow().permitted_users.clone();
+        let allow_anonymous = camera_config.borrow().allow_anonymous;
         let active_streams = prev_stream_config
             .as_stream_kinds()
             .drain(..)
             .collect::<HashSet<_>>();
         let use_splash = camera_config.borrow().use_splash;
-        let splash_pattern = camera_config.borrow().splash_pattern.to_string();
+        let splash = Splash::from_config(&camera_config.borrow());
+        let splash_offline = camera_config.borrow().splash_offline;
+        let multicast = camera_config.borrow().multicast.clone();
+        let paths = camera_config.borrow().paths.clone();
+        let main_paths = camera_config.borrow().rtsp_paths(StreamKind::Main);
+        let sub_paths = camera_config.borrow().rtsp_paths(StreamKind::Sub);
+        let extern_paths = camera_config.borrow().rtsp_paths(StreamKind::Extern);
 
         // This select is for changes to camera_config.stream
         break tokio::select! {
-            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.permitted_users != prev_stream_users || config.use_splash != use_splash) => {
+            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.permitted_users != prev_stream_users || config.allow_anonymous != allow_anonymous || config.use_splash != use_splash || Splash::from_config(config) != splash || config.splash_offline != splash_offline || config.multicast != multicast || config.paths != paths) => {
                 if let Err(e) = v {
                     AnyResult::Err(e.into())
                 } else {
@@ -311,43 +619,28 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                     continue;
                 }
             },
+            v = global_config.wait_for(|config| config.users != acls) => {
+                if let Err(e) = v {
+                    AnyResult::Err(e.into())
+                } else {
+                    // The users or their acls changed restart
+                    continue;
+                }
+            },
             v = async {
                 // This select handles enabling the right stream
                 // and setting up the users
-                let all_users = rtsp.get_users().await?.iter().filter(|a| *a != "anyone" && *a != "anonymous").cloned().collect::<HashSet<_>>();
-                let permitted_users: HashSet<String> = match &prev_stream_users {
-                    // If in the camera config there is the user "anyone", or if none is specified but users
-                    // are defined at all, then we add all users to the camera's allowed list.
-                    Some(p) if p.iter().any(|u| u == "anyone") => all_users,
-                    None if !all_users.is_empty() => all_users,
-
-                    // The user specified permitted_users
-                    Some(p) => p.iter().cloned().collect(),
-
-                    // The user didn't specify permitted_users, and there are none defined anyway
-                    None => ["anonymous".to_string()].iter().cloned().collect(),
-                };
-
-                // Create the dummy factory
-                let dummy_factory = make_dummy_factory(use_splash, splash_pattern).await?;
-                dummy_factory.add_permitted_roles(&permitted_users);
+                let permitted_users = permitted_users(rtsp, &prev_stream_users, allow_anonymous).await?;
+
                 let mut supported_streams_1 = supported_streams.clone();
                 let mut supported_streams_2 = supported_streams.clone();
                 let mut supported_streams_3 = supported_streams.clone();
                 tokio::select! {
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/main"),
-                            format!("/{name}/Main"),
-                            format!("/{name}/mainStream"),
-                            format!("/{name}/MainStream"),
-                            format!("/{name}/Mainstream"),
-                            format!("/{name}/mainstream"),
-                        ];
-                        paths.push(
-                            format!("/{name}")
-                        );
+                        let paths = &main_paths;
+                        let users = stream_users(&permitted_users, &acls, &name, StreamKind::Main);
+                        let dummy_factory = make_dummy_factory(use_splash, splash.clone()).await?;
+                        dummy_factory.add_permitted_roles(&users);
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
                         //
@@ -363,23 +656,13 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_1.wait_for(|ss| ss.contains(&StreamKind::Main)).await?;
-                        stream_main(camera.clone(), StreamKind::Main, rtsp, &permitted_users, &paths).await
+                        stream_main(camera.clone(), StreamKind::Main, rtsp, &users, &talk_users(&users, &acls, &name), paths).await
                     }, if active_streams.contains(&StreamKind::Main) => v,
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/sub"),
-                            format!("/{name}/Sub"),
-                            format!("/{name}/subStream"),
-                            format!("/{name}/SubStream"),
-                            format!("/{name}/Substream"),
-                            format!("/{name}/substream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
+                        let paths = &sub_paths;
+                        let users = stream_users(&permitted_users, &acls, &name, StreamKind::Sub);
+                        let dummy_factory = make_dummy_factory(use_splash, splash.clone()).await?;
+                        dummy_factory.add_permitted_roles(&users);
 
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
@@ -389,7 +672,6 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         let mounts = rtsp
                             .mount_points()
                             .ok_or(anyhow!("RTSP server lacks mount point"))?;
-                        // Create the dummy factory
                         for path in paths.iter() {
                             log::debug!("Path: {}", path);
                             mounts.add_factory(path, dummy_factory.clone());
@@ -398,23 +680,13 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
 
                         supported_streams_2.wait_for(|ss| ss.contains(&StreamKind::Sub)).await?;
 
-                        stream_main(camera.clone(), StreamKind::Sub, rtsp, &permitted_users, &paths).await
+                        stream_main(camera.clone(), StreamKind::Sub, rtsp, &users, &talk_users(&users, &acls, &name), paths).await
                     }, if active_streams.contains(&StreamKind::Sub) => v,
                     v = async {
-                        let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/extern"),
-                            format!("/{name}/Extern"),
-                            format!("/{name}/externStream"),
-                            format!("/{name}/ExternStream"),
-                            format!("/{name}/Externstream"),
-                            format!("/{name}/externstream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) && ! active_streams.contains(&StreamKind::Sub) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
+                        let paths = &extern_paths;
+                        let users = stream_users(&permitted_users, &acls, &name, StreamKind::Extern);
+                        let dummy_factory = make_dummy_factory(use_splash, splash.clone()).await?;
+                        dummy_factory.add_permitted_roles(&users);
 
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
@@ -431,8 +703,9 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_3.wait_for(|ss| ss.contains(&StreamKind::Extern)).await?;
-                        stream_main(camera.clone(), StreamKind::Extern, rtsp, &permitted_users, &paths).await
+                        stream_main(camera.clone(), StreamKind::Extern, rtsp, &users, &talk_users(&users, &acls, &name), paths).await
                     }, if active_streams.contains(&StreamKind::Extern) => v,
+                    v = playback_main(camera.clone(), rtsp, &permitted_users, &acls) => v,
                     else => {
                         // all disabled just wait here until config is changed
                         futures::future::pending().await
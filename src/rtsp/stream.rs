@@ -1,11 +1,43 @@
 use anyhow::anyhow;
-use gstreamer_rtsp_server::prelude::*;
+use gstreamer_rtsp_server::{prelude::*, RTSPMountPoints};
 use std::collections::HashSet;
+use tokio::time::{interval, sleep_until, Duration, Instant};
 
-use crate::{common::NeoInstance, AnyResult};
+#[cfg(feature = "pushnoti")]
+use crate::common::PushNoti;
+use crate::{
+    common::{MdState, NeoCamThreadState, NeoInstance},
+    config::{CompositeConfig, UserConfig},
+    utils::parse_replay_time,
+    AnyResult,
+};
 use neolink_core::bc_protocol::StreamKind;
 
-use super::{factory::*, gst::NeoRtspServer};
+use super::{
+    factory::*,
+    gst::{NeoMediaFactory, NeoMountPoints, NeoRtspServer},
+};
+
+/// How long the camera must be lost before the offline splash is shown
+const OFFLINE_AFTER: Duration = Duration::from_secs(5);
+
+/// The permitted users whose `[[users]]` acl lets them watch the stream
+pub(crate) fn stream_users(
+    users: &HashSet<String>,
+    acls: &[UserConfig],
+    camera: &str,
+    stream: StreamKind,
+) -> HashSet<String> {
+    users
+        .iter()
+        .filter(|user| {
+            acls.iter()
+                .find(|acl| &acl.name == *user)
+                .is_none_or(|acl| acl.may_watch(camera, stream))
+        })
+        .cloned()
+        .collect()
+}
 
 /// This handles the stream itself by creating the factory and pushing messages into it
 pub(crate) async fn stream_main(
@@ -13,6 +45,7 @@ pub(crate) async fn stream_main(
     stream: StreamKind,
     rtsp: &NeoRtspServer,
     users: &HashSet<String>,
+    talkers: &HashSet<String>,
     paths: &[String],
 ) -> AnyResult<()> {
     let name = camera.config().await?.borrow().name.clone();
@@ -21,9 +54,23 @@ pub(crate) async fn stream_main(
         .mount_points()
         .ok_or(anyhow!("RTSP server lacks mount point"))?;
     // Create the factory
-    let (factory, thread) = make_factory(camera, stream).await?;
+    let (factory, thread) = make_factory(
+        camera.clone(),
+        stream,
+        talkers.clone(),
+        rtsp.sent_counter(&name, &stream.to_string(), paths),
+    )
+    .await?;
 
     factory.add_permitted_roles(users);
+    if let Some(multicast) = camera.config().await?.borrow().multicast.as_ref() {
+        factory.set_multicast(multicast, stream)?;
+        log::info!(
+            "{name}::{stream}: Multicast to {} from port {}",
+            multicast.address,
+            multicast.port
+        );
+    }
 
     for path in paths.iter() {
         log::debug!("Path: {}", path);
@@ -31,6 +78,350 @@ pub(crate) async fn stream_main(
     }
     log::info!("{}: Available at {}", name, paths.join(", "));
 
+    let config = camera.config().await?.borrow().clone();
+    if config.splash_offline {
+        let offline_factory = make_dummy_factory(true, Splash::offline(&config)).await?;
+        offline_factory.add_permitted_roles(users);
+        tokio::select! {
+            v = thread => v??,
+            v = offline_main(&camera, &mounts, &factory, &offline_factory, paths) => v?,
+        }
+    } else {
+        thread.await??;
+    }
+    Ok(())
+}
+
+/// Mount the offline splash at the paths while the camera is lost
+///
+/// A camera that was disconnected on purpose, such as by `idle_disconnect`,
+/// is not offline. It keeps the real stream so that clients can wake it.
+async fn offline_main(
+    camera: &NeoInstance,
+    mounts: &RTSPMountPoints,
+    factory: &NeoMediaFactory,
+    offline_factory: &NeoMediaFactory,
+    paths: &[String],
+) -> AnyResult<()> {
+    let name = camera.config().await?.borrow().name.clone();
+    let mut check = interval(Duration::from_secs(1));
+    let mut lost_at: Option<Instant> = None;
+    let mut showing = false;
+    loop {
+        check.tick().await;
+        let connected = camera.camera().borrow().upgrade().is_some();
+        let wanted = camera.get_state().await? == NeoCamThreadState::Connected;
+        lost_at = if connected || !wanted {
+            None
+        } else {
+            lost_at.or(Some(Instant::now()))
+        };
+        let offline = lost_at.is_some_and(|at| at.elapsed() >= OFFLINE_AFTER);
+        if offline != showing {
+            showing = offline;
+            let factory = if offline { offline_factory } else { factory };
+            for path in paths.iter() {
+                mounts.add_factory(path, factory.clone());
+            }
+            if offline {
+                log::info!("{name}: Camera lost, serving the offline splash");
+            } else {
+                log::info!("{name}: Camera is back, serving the stream");
+            }
+        }
+    }
+}
+
+/// Stream the camera on motion for `pause.motion_resume` even when there is
+/// no rtsp client
+///
+/// The frames go nowhere, the stream is what keeps the camera awake and
+/// connected so that `[cameras.record]` and new clients have it straight away
+pub(crate) async fn motion_resume_main(camera: NeoInstance) -> AnyResult<()> {
+    let mut config_rx = camera.config().await?;
+    loop {
+        let config = config_rx
+            .wait_for(|config| config.pause.motion_resume)
+            .await?
+            .clone();
+        let Some(stream) = config.stream.as_stream_kinds().first().copied() else {
+            config_rx
+                .wait_for(|new| new.stream != config.stream)
+                .await?;
+            continue;
+        };
+        let duration = Duration::from_secs(config.pause.motion_resume_duration);
+        tokio::select! {
+            v = async { config_rx.wait_for(|new| new.pause != config.pause || new.stream != config.stream).await.map(|_| ()) } => v?,
+            v = resume_on_motion(&camera, &config.name, stream, duration) => v?,
+        }
+    }
+}
+
+/// Turn the status led off while there are rtsp clients with
+/// `led_auto_off`
+///
+/// The light is put back to what it was before when the last client leaves
+/// or `led_auto_off` is turned off
+pub(crate) async fn led_auto_off_main(camera: NeoInstance) -> AnyResult<()> {
+    let mut config_rx = camera.config().await?;
+    let mut clients = camera.rtsp_clients().await?;
+    loop {
+        let name = config_rx
+            .wait_for(|config| config.led_auto_off)
+            .await?
+            .name
+            .clone();
+        tokio::select! {
+            v = async { config_rx.wait_for(|config| !config.led_auto_off).await.map(|_| ()) } => {
+                v?;
+                continue;
+            },
+            v = async { clients.wait_for(|count| *count > 0).await.map(|_| ()) } => v?,
+        }
+
+        let previous = match camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    let previous = cam.get_ledstate().await?.light_state;
+                    cam.led_light_set(false).await?;
+                    Ok(previous)
+                })
+            })
+            .await
+        {
+            Ok(previous) => {
+                log::debug!("{name}: Status led off while streaming");
+                Some(previous)
+            }
+            Err(e) => {
+                log::warn!("{name}: Could not turn the status led off: {e:?}");
+                None
+            }
+        };
+
+        tokio::select! {
+            v = async { config_rx.wait_for(|config| !config.led_auto_off).await.map(|_| ()) } => v?,
+            v = async { clients.wait_for(|count| *count == 0).await.map(|_| ()) } => v?,
+        }
+        if let Some(previous) = previous {
+            if let Err(e) = camera
+                .run_task(|cam| {
+                    let on = previous == "open";
+                    Box::pin(async move { Ok(cam.led_light_set(on).await?) })
+                })
+                .await
+            {
+                log::warn!("{name}: Could not put the status led back: {e:?}");
+            } else {
+                log::debug!("{name}: Status led put back");
+            }
+        }
+    }
+}
+
+async fn resume_on_motion(
+    camera: &NeoInstance,
+    name: &str,
+    stream: StreamKind,
+    duration: Duration,
+) -> AnyResult<()> {
+    let mut md = camera.motion().await?;
+    md.borrow_and_update();
+    #[cfg(feature = "pushnoti")]
+    let mut pn = camera.push_notifications().await?;
+    #[cfg(feature = "pushnoti")]
+    pn.borrow_and_update(); // Ignore any PNs that have already been sent before this
+    loop {
+        tokio::select! {
+            v = md.wait_for(|state| matches!(state, MdState::Start(_))) => {
+                v?;
+            },
+            v = async {
+                #[cfg(feature = "pushnoti")]
+                {
+                    motion_alert(&mut pn).await
+                }
+                #[cfg(not(feature = "pushnoti"))]
+                {
+                    futures::future::pending::<AnyResult<()>>().await
+                }
+            } => v?,
+        }
+        log::info!(
+            "{name}::{stream}: Motion, streaming for {}s",
+            duration.as_secs()
+        );
+        let mut media_rx = camera.stream(stream).await?;
+        let mut until = Instant::now() + duration;
+        loop {
+            tokio::select! {
+                _ = sleep_until(until) => break,
+                media = media_rx.recv() => {
+                    if media.is_none() {
+                        break;
+                    }
+                },
+                v = md.changed() => {
+                    v?;
+                    // Count from the end of the motion
+                    if !matches!(*md.borrow_and_update(), MdState::Unknown) {
+                        until = Instant::now() + duration;
+                    }
+                },
+                v = async {
+                    #[cfg(feature = "pushnoti")]
+                    {
+                        motion_alert(&mut pn).await
+                    }
+                    #[cfg(not(feature = "pushnoti"))]
+                    {
+                        futures::future::pending::<AnyResult<()>>().await
+                    }
+                } => {
+                    v?;
+                    until = Instant::now() + duration;
+                },
+            }
+        }
+        log::info!("{name}::{stream}: Motion resume over");
+    }
+}
+
+/// Wait for a push notification of motion
+#[cfg(feature = "pushnoti")]
+async fn motion_alert(pn: &mut tokio::sync::watch::Receiver<Option<PushNoti>>) -> AnyResult<()> {
+    loop {
+        pn.changed().await?;
+        let is_motion = pn
+            .borrow_and_update()
+            .as_ref()
+            .is_some_and(|noti| noti.message.contains("Motion Alert from"));
+        if is_motion {
+            return Ok(());
+        }
+    }
+}
+
+/// This mounts the recordings on the SD card at `/{name}/playback/{time}`
+///
+/// The sub stream recordings are at `/{name}/subStream/playback/{time}`
+pub(crate) async fn playback_main(
+    camera: NeoInstance,
+    rtsp: &NeoRtspServer,
+    users: &HashSet<String>,
+    acls: &[UserConfig],
+) -> AnyResult<()> {
+    let name = camera.config().await?.borrow().name.clone();
+    let mounts = rtsp
+        .playback_mounts()
+        .ok_or(anyhow!("RTSP server lacks mount point"))?;
+    let runtime = tokio::runtime::Handle::current();
+    let prefixes = [
+        (StreamKind::Main, format!("/{name}/playback")),
+        (StreamKind::Sub, format!("/{name}/subStream/playback")),
+    ];
+    for (stream, prefix) in prefixes.iter() {
+        let camera = camera.clone();
+        let users = stream_users(users, acls, &name, *stream);
+        let runtime = runtime.clone();
+        let stream = *stream;
+        mounts.add_playback(prefix, move |time| {
+            // Clients may send the `:` of the time escaped
+            let time = time.replace("%3A", ":").replace("%3a", ":");
+            let start = match parse_replay_time(&time) {
+                Ok(start) => start,
+                Err(e) => {
+                    log::debug!("Not a playback time: {e:?}");
+                    return None;
+                }
+            };
+            // This is called from the glib main loop which is not an async thread
+            let factory =
+                match runtime.block_on(make_playback_factory(camera.clone(), stream, start)) {
+                    Ok(factory) => factory,
+                    Err(e) => {
+                        log::warn!("Could not make the playback at {time}: {e:?}");
+                        return None;
+                    }
+                };
+            factory.add_permitted_roles(&users);
+            Some(factory)
+        });
+    }
+    let _guard = PlaybackGuard {
+        mounts,
+        prefixes: prefixes.iter().map(|(_, prefix)| prefix.clone()).collect(),
+    };
+    log::info!(
+        "{}: Recordings available at {}",
+        name,
+        prefixes
+            .iter()
+            .map(|(_, prefix)| format!("{prefix}/<time>"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    futures::future::pending().await
+}
+
+/// This mounts the `[[composites]]` at `/{name}`
+pub(crate) async fn composite_main(
+    cameras: Vec<NeoInstance>,
+    config: CompositeConfig,
+    rtsp: &NeoRtspServer,
+    users: &HashSet<String>,
+) -> AnyResult<()> {
+    let mounts = rtsp
+        .mount_points()
+        .ok_or(anyhow!("RTSP server lacks mount point"))?;
+    let stream = config
+        .stream
+        .as_stream_kinds()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("No stream for the composite"))?;
+    let path = format!("/{}", config.name);
+    let (factory, thread) = make_composite_factory(cameras, stream, config.clone()).await?;
+    factory.add_permitted_roles(users);
+    mounts.add_factory(&path, factory);
+    let _guard = CompositeGuard {
+        mounts,
+        path: path.clone(),
+    };
+    log::info!(
+        "{}: Composite of {} available at {}",
+        config.name,
+        config.cameras.join(", "),
+        path
+    );
     thread.await??;
     Ok(())
 }
+
+/// Stops mounting the composite when it is removed from the config
+struct CompositeGuard {
+    mounts: RTSPMountPoints,
+    path: String,
+}
+
+impl Drop for CompositeGuard {
+    fn drop(&mut self) {
+        self.mounts.remove_factory(&self.path);
+    }
+}
+
+/// Stops mounting the playback paths when the camera stops
+struct PlaybackGuard {
+    mounts: NeoMountPoints,
+    prefixes: Vec<String>,
+}
+
+impl Drop for PlaybackGuard {
+    fn drop(&mut self) {
+        for prefix in self.prefixes.iter() {
+            self.mounts.remove_playback(prefix);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The sdcard command will print the SD cards of the camera or format one
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Format the card with this number, this erases all of its recordings
+    #[arg(long, value_name = "NUMBER")]
+    pub format: Option<u8>,
+    /// How long in seconds `--format` waits for the camera to stop recording
+    #[arg(long, default_value_t = 120, requires = "format")]
+    pub wait: u64,
+    /// Format straight away without waiting for the camera to stop recording
+    #[arg(long, requires = "format")]
+    pub force: bool,
+}
@@ -0,0 +1,95 @@
+///
+/// # Neolink SD Card
+///
+/// This module prints the space on the SD cards of the camera and formats them
+///
+/// # Usage
+///
+/// ```bash
+/// # Print the cards and their space
+/// neolink sdcard --config=config.toml CameraName
+/// # Format card 0
+/// neolink sdcard --config=config.toml CameraName --format 0
+/// ```
+///
+/// Formatting while the camera is writing a clip can corrupt the card so
+/// it first waits for the camera to finish recording unless `--force` is
+/// given.
+///
+use anyhow::{anyhow, Context, Result};
+use std::time::Duration;
+
+mod cmdline;
+
+use crate::{
+    common::NeoReactor,
+    utils::{for_each_camera, wait_until_idle},
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the sdcard subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let (format, wait, force) = (opt.format, Duration::from_secs(opt.wait), opt.force);
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            let hdd_info = camera
+                .run_task(|cam| {
+                    Box::pin(async move {
+                        cam.get_hdd_info()
+                            .await
+                            .context("Unable to get the camera SD cards")
+                    })
+                })
+                .await?;
+
+            if let Some(number) = format {
+                if !hdd_info.hdd_info.iter().any(|hdd| hdd.number == number) {
+                    return Err(anyhow!("The camera has no SD card {number}"));
+                }
+                wait_until_idle(&camera, wait, force).await?;
+                let name = camera.config().await?.borrow().name.clone();
+                log::info!("{name}: Formatting SD card {number}");
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.format_hdd(number)
+                                .await
+                                .context("Unable to format the SD card")
+                        })
+                    })
+                    .await?;
+                Ok(format!("Formatted SD card {number}"))
+            } else if hdd_info.hdd_info.is_empty() {
+                Ok("The camera has no SD card".to_string())
+            } else {
+                let cards = hdd_info
+                    .hdd_info
+                    .iter()
+                    .map(|hdd| {
+                        let used = hdd.capacity.saturating_sub(hdd.remain_size);
+                        format!(
+                            "{}: {:.1} GB, {:.1} GB used, {}",
+                            hdd.number,
+                            hdd.capacity as f64 / 1024.0,
+                            used as f64 / 1024.0,
+                            if hdd.mount == 1 {
+                                "ok"
+                            } else if hdd.format == Some(0) {
+                                "not formatted"
+                            } else {
+                                "not mounted"
+                            }
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Ok(cards.join("\n"))
+            }
+        },
+    )
+    .await
+}
@@ -1,3 +1,4 @@
+use crate::cmdline::CameraSelection;
 use anyhow::{anyhow, Result};
 use clap::{Parser, ValueEnum};
 
@@ -14,9 +15,13 @@ fn onoff_parse(src: &str) -> Result<bool> {
 
 /// The services command will control the ports for http/https/rtmp/rtsp/onvif
 #[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
 pub struct Opt {
     /// The name of the camera. Must be a name in the config
-    pub camera: String,
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
     /// service to change
     pub service: Services,
     /// The action to perform
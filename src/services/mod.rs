@@ -29,16 +29,24 @@ use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    utils::for_each_camera,
+};
 pub(crate) use cmdline::*;
 
 /// Entry point for the pir subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
+    for_each_camera(&reactor, opt.camera.as_deref(), &opt.select, |camera| {
+        camera_main(&opt, camera)
+    })
+    .await
+}
 
-    match opt.cmd {
+async fn camera_main(opt: &Opt, camera: NeoInstance) -> Result<String> {
+    let output = match &opt.cmd {
         PortAction::Get => match opt.service {
             Services::Http => {
                 let state = camera
@@ -50,15 +58,14 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
             Services::Https => {
                 let state = camera
@@ -70,15 +77,14 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
             Services::Rtsp => {
                 let state = camera
@@ -90,15 +96,14 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
             Services::Rtmp => {
                 let state = camera
@@ -110,15 +115,14 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
             Services::Onvif => {
                 let state = camera
@@ -130,15 +134,14 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
             Services::Baichuan => {
                 let state = camera
@@ -150,25 +153,24 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         })
                     })
                     .await?;
-                let ser = String::from_utf8(
+                String::from_utf8(
                     {
                         let mut buf = bytes::BytesMut::new();
                         quick_xml::se::to_writer(&mut buf, &state).map(|_| buf.to_vec())
                     }
                     .expect("Should Ser the struct"),
                 )
-                .expect("Should be UTF8");
-                println!("{}", ser);
+                .expect("Should be UTF8")
             }
         },
         action => {
-            let on = match &action {
+            let on = match action {
                 PortAction::On => Some(true),
                 PortAction::Off => Some(false),
                 PortAction::Set { enabled, .. } => Some(*enabled),
                 _ => None,
             };
-            let port = match &action {
+            let port = match action {
                 PortAction::Port { port } => Some(*port),
                 PortAction::Set { port, .. } => Some(*port),
                 _ => None,
@@ -253,8 +255,9 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         .await?;
                 }
             }
+            String::new()
         }
-    }
+    };
 
-    Ok(())
+    Ok(output)
 }
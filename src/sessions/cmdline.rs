@@ -0,0 +1,13 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The sessions command will list the neolink processes on this machine
+/// that are currently logged into the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+}
@@ -0,0 +1,87 @@
+///
+/// # Neolink Sessions
+///
+/// This module handles the sessions subcommand
+///
+/// The subcommand lists the neolink processes on this machine that
+/// currently hold a login with the camera. It reads the on disk
+/// session registry and never connects to the camera itself.
+///
+/// # Usage
+///
+/// ```bash
+/// neolink sessions --config=config.toml CameraName
+/// ```
+///
+use anyhow::{anyhow, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoReactor, SessionRegistry},
+    utils::select_cameras,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the sessions subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    // This only reads the registry so the cameras are not connected to
+    let names = select_cameras(opt.camera.as_deref(), &opt.select, &config)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut json = vec![];
+    for name in names.iter() {
+        let camera_config = config
+            .cameras
+            .iter()
+            .find(|cam| &cam.name == name)
+            .ok_or_else(|| anyhow!("Camera `{}` not found in config", name))?;
+
+        let sessions = SessionRegistry::new(&config.session_dir, &camera_config.name)
+            .list()
+            .await?;
+
+        if opt.select.json {
+            json.push(serde_json::json!({
+                "camera": camera_config.name,
+                "max": camera_config.max_own_sessions,
+                "sessions": sessions,
+            }));
+            continue;
+        }
+
+        match camera_config.max_own_sessions {
+            Some(max) => println!(
+                "{}: {} of {} sessions in use",
+                camera_config.name,
+                sessions.len(),
+                max
+            ),
+            None => println!("{}: {} sessions in use", camera_config.name, sessions.len()),
+        }
+
+        if !sessions.is_empty() {
+            println!("{:<8} {:<10} COMMAND", "PID", "HELD FOR");
+            for session in sessions.iter() {
+                println!(
+                    "{:<8} {:<10} {}",
+                    session.pid,
+                    format!("{}s", now.saturating_sub(session.started)),
+                    session.command
+                );
+            }
+        }
+    }
+    if opt.select.json {
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    }
+
+    Ok(())
+}
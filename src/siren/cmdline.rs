@@ -0,0 +1,17 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The siren command will sound the siren of the camera
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Keep the siren on for this many seconds instead of playing it once, at
+    /// most 120
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=super::MAX_SIREN_SECS))]
+    pub duration: Option<u64>,
+}
@@ -0,0 +1,176 @@
+///
+/// # Neolink Siren
+///
+/// This module handles sounding the siren
+///
+/// # Usage
+///
+/// ```bash
+/// # Play the siren once
+/// neolink siren --config=config.toml CameraName
+/// # Or keep it on for 30s
+/// neolink siren --config=config.toml CameraName --duration 30
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
+
+mod cmdline;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    utils::for_each_camera_control,
+};
+pub(crate) use cmdline::Opt;
+
+/// The longest that the siren may be kept on for
+pub(crate) const MAX_SIREN_SECS: u64 = 120;
+
+/// The timed siren of each camera by name, a newer one replaces it
+static TIMED_SIRENS: Lazy<Mutex<HashMap<String, (u64, CancellationToken)>>> =
+    Lazy::new(Default::default);
+static NEXT_SIREN: AtomicU64 = AtomicU64::new(0);
+
+/// Entry point for the siren subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let duration = opt.duration.map(Duration::from_secs);
+    let message = opt
+        .duration
+        .map(|secs| secs.to_string())
+        .unwrap_or_else(|| "on".to_string());
+    for_each_camera_control(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        Some(("siren", &message)),
+        |camera| async move {
+            match duration {
+                Some(duration) => siren_for(&camera, duration).await?,
+                None => {
+                    camera
+                        .run_task(|cam| {
+                            Box::pin(async move {
+                                cam.siren().await.context("Unable to sound the siren")
+                            })
+                        })
+                        .await?
+                }
+            }
+            Ok(String::new())
+        },
+    )
+    .await
+}
+
+/// Turn the siren on then off again after the duration
+///
+/// A newer timed siren on the same camera replaces this one and leaves the
+/// siren on. If this is dropped while the siren is on it is still turned off
+pub(crate) async fn siren_for(camera: &NeoInstance, duration: Duration) -> Result<()> {
+    if duration > Duration::from_secs(MAX_SIREN_SECS) {
+        return Err(anyhow!(
+            "The siren can only be kept on for up to {MAX_SIREN_SECS}s"
+        ));
+    }
+    let name = camera.config().await?.borrow().name.clone();
+    let id = NEXT_SIREN.fetch_add(1, Ordering::Relaxed);
+    let replaced = CancellationToken::new();
+    if let Some((_, older)) = TIMED_SIRENS
+        .lock()
+        .unwrap()
+        .insert(name.clone(), (id, replaced.clone()))
+    {
+        older.cancel();
+    }
+    let mut off = SirenOff {
+        camera: Some(camera.clone()),
+    };
+    let result = async {
+        camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.siren_manual(true)
+                        .await
+                        .context("Unable to turn the siren on")
+                })
+            })
+            .await?;
+        tokio::select! {
+            _ = sleep(duration) => siren_off(camera).await,
+            _ = replaced.cancelled() => {
+                // The newer siren turns it off
+                off.camera = None;
+                Ok(())
+            },
+        }
+    }
+    .await;
+    off.camera = None;
+    let mut sirens = TIMED_SIRENS.lock().unwrap();
+    if sirens.get(&name).is_some_and(|(current, _)| *current == id) {
+        sirens.remove(&name);
+    }
+    result
+}
+
+/// Stop the timed siren of a camera so that it does not turn off a siren
+/// that was started after it
+pub(crate) fn cancel_timed_siren(name: &str) {
+    if let Some((_, timed)) = TIMED_SIRENS.lock().unwrap().remove(name) {
+        timed.cancel();
+    }
+}
+
+/// Turn the siren off, trying again if the camera does not answer
+async fn siren_off(camera: &NeoInstance) -> Result<()> {
+    let mut tries = 0;
+    loop {
+        let result = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.siren_manual(false)
+                        .await
+                        .context("Unable to turn the siren off")
+                })
+            })
+            .await;
+        tries += 1;
+        match result {
+            Err(e) if tries < 3 => {
+                log::warn!("{e:?}, trying again");
+                sleep(Duration::from_secs(1)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Turns the siren off when a timed siren is dropped before it has
+struct SirenOff {
+    camera: Option<NeoInstance>,
+}
+
+impl Drop for SirenOff {
+    fn drop(&mut self) {
+        if let Some(camera) = self.camera.take() {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn(async move {
+                    if let Err(e) = siren_off(&camera).await {
+                        log::error!("{e:?}");
+                    }
+                });
+            }
+        }
+    }
+}
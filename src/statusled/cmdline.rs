@@ -1,3 +1,4 @@
+use crate::cmdline::CameraSelection;
 use anyhow::{anyhow, Result};
 use clap::Parser;
 
@@ -14,9 +15,13 @@ fn onoff_parse(src: &str) -> Result<bool> {
 
 /// The status-light command will control the blue status light on the camera
 #[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
-    pub camera: String,
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
     /// Whether to turn the light on or off
     #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
     pub on: bool,
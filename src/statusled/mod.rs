@@ -19,26 +19,32 @@ use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{common::NeoReactor, utils::for_each_camera_control};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the ledstatus subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
-
     let on = opt.on;
-    camera
-        .run_task(|camera| {
-            Box::pin(async move {
-                camera
-                    .led_light_set(on)
-                    .await
-                    .context("Unable to set camera light state")
-            })
-        })
-        .await?;
-
-    Ok(())
+    for_each_camera_control(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        Some(("led", if on { "on" } else { "off" })),
+        |camera| async move {
+            camera
+                .run_task(|camera| {
+                    Box::pin(async move {
+                        camera
+                            .led_light_set(on)
+                            .await
+                            .context("Unable to set camera light state")
+                    })
+                })
+                .await?;
+            Ok(String::new())
+        },
+    )
+    .await
 }
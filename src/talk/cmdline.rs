@@ -13,13 +13,26 @@ pub struct Opt {
     /// The name of the camera to talk through. Must be a name in the config
     pub camera: String,
     /// The path to the audio file.
-    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with = "microphone")]
+    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with_all = ["microphone", "tts"])]
     pub file_path: Option<PathBuf>,
-    /// Use the microphone as the source. Defaults to autoaudiosrc - Which microphone depends
+    /// Use the microphone as the source. Without a device it is `--input-src` which defaults
+    /// to autoaudiosrc - Which microphone depends
     /// on [gstreamer](https://gstreamer.freedesktop.org/documentation/autodetect/autoaudiosrc.html?gi-language=c#autoaudiosrc-page)
-    #[arg(short, long, conflicts_with = "file_path")]
-    pub microphone: bool,
-    /// Use a specific microphone like "alsasrc device=hw:1"
+    ///
+    /// A device can be given as `alsa:hw:1`, `pulse:NAME` or `wasapi:ID`. A device without
+    /// a prefix uses pulse on linux and wasapi on windows
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["file_path", "tts"],
+        num_args = 0..=1,
+        // So that `--microphone CameraName` is not read as a device
+        require_equals = true,
+        default_missing_value = "",
+        value_name = "DEVICE"
+    )]
+    pub microphone: Option<String>,
+    /// Use a specific gstreamer source like "alsasrc device=hw:1"
     #[arg(
         short,
         long,
@@ -27,6 +40,12 @@ pub struct Opt {
         conflicts_with = "file_path"
     )]
     pub input_src: String,
+    /// Say this text, this needs the espeak plugin of gstreamer
+    #[arg(short, long, conflicts_with_all = ["file_path", "microphone"])]
+    pub tts: Option<String>,
+    /// The espeak voice of `--tts` such as `en-us`
+    #[arg(long, requires = "tts")]
+    pub voice: Option<String>,
     /// Use to change the volume of the input
     #[arg(short, long, default_value = "1.0")]
     pub volume: f32,
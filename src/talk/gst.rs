@@ -6,6 +6,7 @@ use gstreamer::{
     MessageView, ParseFlags, Pipeline, ResourceError, State,
 };
 use gstreamer_app::{AppSink, AppSinkCallbacks};
+use std::path::Path;
 use tokio::task::JoinSet;
 
 use byte_slice_cast::*;
@@ -21,6 +22,59 @@ pub(super) fn from_input(
     input(pipeline)
 }
 
+/// The gstreamer source of a `--microphone` device
+///
+/// No device is the `--input-src`
+pub(super) fn microphone_src(device: &str, input_src: &str) -> String {
+    let (element, name) = if let Some(name) = device.strip_prefix("alsa:") {
+        ("alsasrc", name)
+    } else if let Some(name) = device.strip_prefix("pulse:") {
+        ("pulsesrc", name)
+    } else if let Some(name) = device.strip_prefix("wasapi:") {
+        ("wasapisrc", name)
+    } else if device.is_empty() {
+        return input_src.to_string();
+    } else if cfg!(target_os = "windows") {
+        ("wasapisrc", device)
+    } else {
+        ("pulsesrc", device)
+    };
+    format!("{element} device={}", quote(name))
+}
+
+pub(super) fn file_src(path: &Path) -> Result<String> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow!("{} is not valid UTF-8", path.display()))?;
+    Ok(format!("filesrc location={}", quote(path)))
+}
+
+pub(super) fn url_src(url: &str) -> String {
+    format!("souphttpsrc location={}", quote(url))
+}
+
+/// The gstreamer source that speaks the text with the espeak plugin
+pub(super) fn tts_src(text: &str, voice: Option<&str>) -> String {
+    match voice {
+        Some(voice) => format!("espeak text={} voice={}", quote(text), quote(voice)),
+        None => format!("espeak text={}", quote(text)),
+    }
+}
+
+/// The value in quotes for a gstreamer launch line
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The adpcm that arrives on an appsink of another pipeline
+///
+/// The channel closes when the appsink is dropped
+pub(crate) fn from_appsink(appsink: &AppSink) -> Receiver<Vec<u8>> {
+    let (tx, rx) = bounded(30);
+    set_data_channel(appsink, tx);
+    rx
+}
+
 #[allow(clippy::type_complexity)]
 fn input(pipeline: Pipeline) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
     let appsink = get_sink(&pipeline)?;
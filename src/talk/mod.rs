@@ -9,22 +9,124 @@
 ///
 /// ```bash
 /// neolink talk --config=config.toml --adpcm-file=data.adpcm --sample-rate=16000 --block-size=512 CameraName
+/// # Or live from a microphone
+/// neolink talk --config=config.toml --microphone=alsa:hw:1 CameraName
+/// # Or say some text
+/// neolink talk --config=config.toml --tts="Dinner is ready" CameraName
 /// ```
 ///
 use anyhow::{anyhow, Context, Result};
-use neolink_core::bc::xml::TalkConfig;
+use neolink_core::bc::xml::{TalkAbility, TalkConfig};
+use reqwest::Url;
+use std::path::Path;
 
 mod cmdline;
 mod gst;
 
-use crate::common::NeoReactor;
+use crate::common::{NeoInstance, NeoReactor};
 pub(crate) use cmdline::Opt;
+pub(crate) use gst::from_appsink;
 
 /// Entry point for the talk subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
+
+    let source = match (&opt.file_path, &opt.microphone, &opt.tts) {
+        (Some(path), None, None) => gst::file_src(path)?,
+        (None, Some(device), None) => gst::microphone_src(device, &opt.input_src),
+        (None, None, Some(text)) => gst::tts_src(text, opt.voice.as_deref()),
+        (None, None, None) => {
+            return Err(anyhow!("Give one of --file-path, --microphone or --tts"))
+        }
+        _ => unreachable!("Clap stops these together"),
+    };
+
+    talk_from(&camera, &source, opt.volume).await
+}
+
+/// Say the text on the camera speaker with espeak
+pub(crate) async fn say(camera: &NeoInstance, text: &str) -> Result<()> {
+    talk_from(camera, &gst::tts_src(text, None), 1.0).await
+}
+
+/// Play the audio file or url on the camera speaker
+///
+/// It must be inside one of the `play_allowed` directories or under the path
+/// of one of its urls. A bare file name is looked for in the directories
+pub(crate) async fn play(camera: &NeoInstance, target: &str) -> Result<()> {
+    let allowed = camera.config().await?.borrow().play_allowed.clone();
+    let source = play_src(target, &allowed)?;
+    talk_from(camera, &source, 1.0).await
+}
+
+fn play_src(target: &str, allowed: &[String]) -> Result<String> {
+    if target.contains("://") {
+        let url = Url::parse(target).with_context(|| format!("{target} is not a url"))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow!("Only http and https urls can be played"));
+        }
+        // Dot segments and encoded slashes would let the url leave the
+        // allowed path once the server decodes them
+        let lower = target.to_lowercase();
+        if target.contains("..") || ["%2e", "%2f", "%5c"].iter().any(|e| lower.contains(e)) {
+            return Err(anyhow!("{target} is not in play_allowed"));
+        }
+        if !allowed
+            .iter()
+            .filter(|prefix| prefix.contains("://"))
+            .filter_map(|prefix| Url::parse(prefix).ok())
+            .any(|prefix| url_inside(&url, &prefix))
+        {
+            return Err(anyhow!("{target} is not in play_allowed"));
+        }
+        return Ok(gst::url_src(url.as_str()));
+    }
+
+    let dirs = allowed
+        .iter()
+        .filter(|dir| !dir.contains("://"))
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .collect::<Vec<_>>();
+    let target = Path::new(target);
+    let candidates = if target.is_absolute() {
+        vec![target.to_path_buf()]
+    } else {
+        dirs.iter().map(|dir| dir.join(target)).collect()
+    };
+    // Canonical so that `..` and links cannot leave the directory
+    let path = candidates
+        .iter()
+        .filter_map(|path| std::fs::canonicalize(path).ok())
+        .find(|path| dirs.iter().any(|dir| path.starts_with(dir)))
+        .ok_or_else(|| anyhow!("{} is not a file in play_allowed", target.display()))?;
+    gst::file_src(&path)
+}
+
+/// Whether the url is the allowed one or below its path on the same server
+fn url_inside(url: &Url, allowed: &Url) -> bool {
+    if url.scheme() != allowed.scheme()
+        || url.host_str() != allowed.host_str()
+        || url.port_or_known_default() != allowed.port_or_known_default()
+    {
+        return false;
+    }
+    let segments = |url: &Url| {
+        url.path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+    segments(url).starts_with(&segments(allowed))
+}
+
+/// Play the gstreamer source on the camera until it ends
+async fn talk_from(camera: &NeoInstance, source: &str, volume: f32) -> Result<()> {
     let config = camera.config().await?.borrow().clone();
     let name = config.name.clone();
 
@@ -38,11 +140,41 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         .await
         .with_context(|| format!("Camera {} does not support talk", name))?;
 
+    let (talk_config, block_size, sample_rate) = talk_config(config.channel_id, &talk_ability)
+        .with_context(|| format!("Camera {} does not support talk", name))?;
+
+    let (mut set, rx) = gst::from_input(source, volume, block_size, sample_rate)
+        .with_context(|| format!("Failed to setup gst with {source}"))?;
+
+    camera
+        .run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        })
+        .await
+        .context("Talk stream ended early")?;
+
+    drop(rx);
+    while set.join_next().await.is_some() {}
+
+    Ok(())
+}
+
+/// The talk config to send to the camera along with the adpcm block size and
+/// sample rate that it expects
+pub(crate) fn talk_config(
+    channel_id: u8,
+    talk_ability: &TalkAbility,
+) -> Result<(TalkConfig, u16, u16)> {
     if talk_ability.duplex_list.is_empty()
         || talk_ability.audio_stream_mode_list.is_empty()
         || talk_ability.audio_config_list.is_empty()
     {
-        return Err(anyhow!("Camera {} does not support talk", name));
+        return Err(anyhow!("No talk ability"));
     }
 
     // Just copy that data from the first talk ability in the config have never seen more
@@ -50,7 +182,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let config_id = 0;
 
     let talk_config = TalkConfig {
-        channel_id: config.channel_id,
+        channel_id,
         duplex: talk_ability.duplex_list[config_id].duplex.clone(),
         audio_stream_mode: talk_ability.audio_stream_mode_list[config_id]
             .audio_stream_mode
@@ -64,42 +196,57 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let block_size = (talk_config.audio_config.length_per_encoder / 2) + 4;
     let sample_rate = talk_config.audio_config.sample_rate;
     if block_size == 0 || sample_rate == 0 {
-        return Err(anyhow!(
-            "The camera {} does not support talk with adpcm",
-            name
-        ));
+        return Err(anyhow!("Talk is not possible with adpcm"));
     }
+    Ok((talk_config, block_size, sample_rate))
+}
 
-    let (mut set, rx) = match (&opt.file_path, &opt.microphone) {
-        (Some(path), false) => gst::from_input(
-            &format!(
-                "filesrc location={}",
-                path.to_str().expect("File path not UTF8 complient")
-            ),
-            opt.volume,
-            block_size,
-            sample_rate,
-        )
-        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
-        (None, true) => gst::from_input(&opt.input_src, opt.volume, block_size, sample_rate)
-            .context("Failed to setup gst using the microphone")?,
-        _ => unreachable!(),
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    camera
-        .run_task(|cam| {
-            let rx = rx.clone();
-            let talk_config = talk_config.clone();
-            Box::pin(async move {
-                cam.talk_stream(rx, talk_config).await?;
-                Ok(())
-            })
-        })
-        .await
-        .context("Talk stream ended early")?;
+    #[test]
+    fn test_play_src_url() {
+        let allowed = vec!["https://example.com/chimes/".to_string()];
+        assert!(play_src("https://example.com/chimes/ding.mp3", &allowed).is_ok());
+        assert!(play_src("https://example.com/chimes/a/ding.mp3", &allowed).is_ok());
+        // No boundary after the prefix
+        let unbounded = vec!["https://example.com/chimes".to_string()];
+        assert!(play_src("https://example.com/chimesevil/ding.mp3", &unbounded).is_err());
+        assert!(play_src("https://example.com.evil.net/chimes/ding.mp3", &unbounded).is_err());
+        assert!(play_src("https://example.com@evil.net/chimes/ding.mp3", &allowed).is_err());
+        // Another scheme or port
+        assert!(play_src("http://example.com/chimes/ding.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com:8443/chimes/ding.mp3", &allowed).is_err());
+        assert!(play_src("file:///etc/passwd", &allowed).is_err());
+        // Leaving the path
+        assert!(play_src("https://example.com/chimes/../secret.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com/chimes/%2e%2e/secret.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com/chimes/%2E%2E/secret.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com/chimes/.%2e/secret.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com/chimes%2f..%2fsecret.mp3", &allowed).is_err());
+        assert!(play_src("https://example.com/other/ding.mp3", &allowed).is_err());
+        // Directories are not urls
+        let dirs = vec!["/etc/neolink/sounds".to_string()];
+        assert!(play_src("https://example.com/chimes/ding.mp3", &dirs).is_err());
+    }
 
-    drop(rx);
-    while set.join_next().await.is_some() {}
+    #[test]
+    fn test_play_src_file() {
+        let root = std::env::temp_dir().join(format!("neolink-play-{}", std::process::id()));
+        let sounds = root.join("sounds");
+        std::fs::create_dir_all(&sounds).unwrap();
+        std::fs::write(sounds.join("ding.wav"), b"").unwrap();
+        std::fs::write(root.join("secret.wav"), b"").unwrap();
+        let allowed = vec![sounds.to_str().unwrap().to_string()];
 
-    Ok(())
+        assert!(play_src("ding.wav", &allowed).is_ok());
+        assert!(play_src(sounds.join("ding.wav").to_str().unwrap(), &allowed).is_ok());
+        assert!(play_src("../secret.wav", &allowed).is_err());
+        assert!(play_src(sounds.join("../secret.wav").to_str().unwrap(), &allowed).is_err());
+        assert!(play_src(root.join("secret.wav").to_str().unwrap(), &allowed).is_err());
+        assert!(play_src("missing.wav", &allowed).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,29 @@
+use crate::cmdline::CameraSelection;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The tracking command will control the AI auto tracking of the camera
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// Whether to turn the auto tracking ON or OFF.
+    /// Without this the current state is printed
+    #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+    pub on: Option<bool>,
+}
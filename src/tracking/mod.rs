@@ -0,0 +1,63 @@
+///
+/// # Neolink Tracking
+///
+/// This module handles the AI auto tracking of Trackmix and PTZ cameras
+///
+/// When it is on the camera turns to follow the people and vehicles that it
+/// detects
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the auto tracking on
+/// neolink tracking --config=config.toml CameraName on
+/// # Or off
+/// neolink tracking --config=config.toml CameraName off
+/// # Print the current state
+/// neolink tracking --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the tracking subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let on = opt.on;
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            if let Some(on) = on {
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.set_auto_tracking(on)
+                                .await
+                                .context("Unable to set the camera auto tracking")
+                        })
+                    })
+                    .await?;
+                Ok(String::new())
+            } else {
+                let on = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.get_auto_tracking()
+                                .await
+                                .context("Unable to get the camera auto tracking")
+                        })
+                    })
+                    .await?;
+                Ok(if on { "on" } else { "off" }.to_string())
+            }
+        },
+    )
+    .await
+}
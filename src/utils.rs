@@ -2,17 +2,48 @@
 //!
 use log::*;
 
-use super::config::CameraConfig;
+use super::{
+    cmdline::CameraSelection,
+    common::{ControlSocket, NeoInstance, NeoReactor},
+    config::{CameraConfig, Config},
+};
 use anyhow::{anyhow, Context, Error, Result};
+use futures::{stream, Future, StreamExt};
+use neolink_core::bc::xml::ReplayTime;
 use neolink_core::bc_protocol::{
-    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, MaxEncryption,
+    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, EncryptionLevel,
+    LoginMethod, MaxEncryption, UdpSettings,
 };
+use once_cell::sync::OnceCell;
 use std::{
     fmt::{Display, Error as FmtError, Formatter},
-    net::{IpAddr, ToSocketAddrs},
+    net::{IpAddr, SocketAddr},
     str::FromStr,
 };
 
+/// The offset of the local time, see [`read_local_offset`]
+static LOCAL_OFFSET: OnceCell<time::UtcOffset> = OnceCell::new();
+
+/// Find the offset of the local time for [`now_local`]
+///
+/// The offset can only be read before other threads start so this must be
+/// called before the runtime is made. False when it could not be read and
+/// UTC is used instead
+pub(crate) fn read_local_offset() -> bool {
+    let offset = time::UtcOffset::current_local_offset();
+    let _ = LOCAL_OFFSET.set(offset.unwrap_or(time::UtcOffset::UTC));
+    offset.is_ok()
+}
+
+/// The local time or UTC if the local offset could not be found
+///
+/// The offset is the one found at startup so a change of daylight saving
+/// needs a restart
+pub(crate) fn now_local() -> time::OffsetDateTime {
+    time::OffsetDateTime::now_utc()
+        .to_offset(LOCAL_OFFSET.get().copied().unwrap_or(time::UtcOffset::UTC))
+}
+
 pub(crate) fn timeout<F>(future: F) -> tokio::time::Timeout<F>
 where
     F: std::future::Future,
@@ -20,6 +51,295 @@ where
     tokio::time::timeout(tokio::time::Duration::from_secs(15), future)
 }
 
+/// The names of the cameras chosen by a one shot command's arguments
+pub(crate) fn select_cameras(
+    camera: Option<&str>,
+    selection: &CameraSelection,
+    config: &Config,
+) -> Result<Vec<String>> {
+    let enabled = config.cameras.iter().filter(|cam| cam.enabled);
+    let names: Vec<String> = if let Some(camera) = camera {
+        vec![camera.to_string()]
+    } else if selection.all {
+        enabled.map(|cam| cam.name.clone()).collect()
+    } else if let Some(pattern) = selection.pattern.as_ref() {
+        let re = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(re) => regex::Regex::new(re),
+            None => {
+                // Translate the glob into an anchored regex
+                let mut re = String::from("^");
+                for c in pattern.chars() {
+                    match c {
+                        '*' => re.push_str(".*"),
+                        '?' => re.push('.'),
+                        c => re.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                re.push('$');
+                regex::Regex::new(&re)
+            }
+        }
+        .with_context(|| format!("Invalid camera match {:?}", pattern))?;
+        enabled
+            .filter(|cam| re.is_match(&cam.name))
+            .map(|cam| cam.name.clone())
+            .collect()
+    } else {
+        return Err(anyhow!("A camera name, --all or --match is required"));
+    };
+    if names.is_empty() {
+        return Err(anyhow!("No cameras in the config match"));
+    }
+    Ok(names)
+}
+
+/// Runs a one shot command against the cameras chosen on the command line
+///
+/// The task returns the text to print for that camera.
+///
+/// With a single named camera this is the same as running the task directly.
+/// Otherwise up to `--parallel` cameras are run at once, every camera is
+/// attempted and a result table (or json with `--json`) is printed at the end.
+/// An error is returned if any of the cameras failed.
+pub(crate) async fn for_each_camera<F, Fut>(
+    reactor: &NeoReactor,
+    camera: Option<&str>,
+    selection: &CameraSelection,
+    task: F,
+) -> Result<()>
+where
+    F: Fn(NeoInstance) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let task = &task;
+    for_each_name(reactor, camera, selection, |name| async move {
+        task(reactor.get(&name).await?).await
+    })
+    .await
+}
+
+/// Like [`for_each_camera`] but the control is sent to a running neolink
+///
+/// When `control_addr` reaches a running `neolink rtsp` the `control/...`
+/// message is run by it, so this process does not log in to the cameras as
+/// well with a session of its own. Otherwise, or without a control, the task
+/// is run here
+pub(crate) async fn for_each_camera_control<F, Fut>(
+    reactor: &NeoReactor,
+    camera: Option<&str>,
+    selection: &CameraSelection,
+    control: Option<(&str, &str)>,
+    task: F,
+) -> Result<()>
+where
+    F: Fn(NeoInstance) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let socket = ControlSocket::from_config(&reactor.config().await?.borrow());
+    let (Some(socket), Some((control, message))) = (socket, control) else {
+        return for_each_camera(reactor, camera, selection, task).await;
+    };
+    if !socket.running().await {
+        return for_each_camera(reactor, camera, selection, task).await;
+    }
+    info!("Sending {control} to the running neolink");
+    let socket = &socket;
+    for_each_name(reactor, camera, selection, |name| async move {
+        socket.control(&name, control, message).await?;
+        Ok(String::new())
+    })
+    .await
+}
+
+async fn for_each_name<F, Fut>(
+    reactor: &NeoReactor,
+    camera: Option<&str>,
+    selection: &CameraSelection,
+    task: F,
+) -> Result<()>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let config = reactor.config().await?.borrow().clone();
+    let names = select_cameras(camera, selection, &config)?;
+
+    if camera.is_some() && !selection.json {
+        let output = task(names[0].clone()).await?;
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        return Ok(());
+    }
+
+    let task = &task;
+    let results: Vec<(String, Result<String>)> = stream::iter(names)
+        .map(|name| async move {
+            let result = task(name.clone()).await;
+            if let Err(e) = &result {
+                warn!("{}: {:?}", name, e);
+            }
+            (name, result)
+        })
+        .buffered(selection.parallel)
+        .collect()
+        .await;
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+
+    if selection.json {
+        let json = results
+            .iter()
+            .map(|(name, result)| match result {
+                Ok(output) => serde_json::json!({
+                    "camera": name,
+                    "ok": true,
+                    "output": output,
+                }),
+                Err(e) => serde_json::json!({
+                    "camera": name,
+                    "ok": false,
+                    "error": format!("{:#}", e),
+                }),
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else {
+        for (name, result) in results.iter() {
+            if let Ok(output) = result {
+                if !output.is_empty() {
+                    println!("{}:\n{}\n", name, output);
+                }
+            }
+        }
+        let width = results
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(std::iter::once(6))
+            .max()
+            .unwrap_or(6);
+        println!("{:<width$} RESULT", "CAMERA");
+        for (name, result) in results.iter() {
+            match result {
+                Ok(_) => println!("{:<width$} OK", name),
+                Err(e) => println!("{:<width$} FAILED: {:#}", name, e),
+            }
+        }
+    }
+
+    if failed > 0 {
+        Err(anyhow!("{} of {} cameras failed", failed, results.len()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Wait for the camera to finish writing any clip to its storage
+///
+/// This is used before destructive commands such as reboot since cutting
+/// the camera off mid write can corrupt the card. No extra requests are
+/// made to the camera, the status arrives with its motion events.
+///
+/// If the camera is still recording after `wait` (or never reports its
+/// status) this fails. With `force` it does not wait at all
+pub(crate) async fn wait_until_idle(
+    camera: &NeoInstance,
+    wait: std::time::Duration,
+    force: bool,
+) -> Result<()> {
+    let name = camera.config().await?.borrow().name.clone();
+    if force {
+        info!("{name}: Not waiting for the camera to finish recording as it was forced");
+        return Ok(());
+    }
+    // Keep the camera connected so that it keeps reporting
+    let _permit = camera.permit().await?;
+    let mut recording = camera.recording().await?;
+    let deadline = tokio::time::Instant::now() + wait;
+    let mut progress = tokio::time::interval(std::time::Duration::from_secs(10));
+    let state = loop {
+        let state = *recording.borrow_and_update();
+        if state == Some(false) {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => break state,
+            v = recording.changed() => v?,
+            _ = progress.tick() => {
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                info!(
+                    "{name}: Waiting up to {}s for the camera to {}",
+                    remaining.as_secs(),
+                    if state.is_some() { "finish recording" } else { "report its recording status" }
+                );
+            }
+        }
+    };
+    let why = if state.is_some() {
+        "still recording"
+    } else {
+        "not reporting its recording status"
+    };
+    Err(anyhow!(
+        "{name}: Camera is {why} after {}s, force the command to continue anyway",
+        wait.as_secs()
+    ))
+}
+
+/// Camera names are free text so keep only the file name safe parts
+pub(crate) fn file_safe_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Parses a local time of the camera such as `2023-10-14 08:30:00`, `2023-10-14T08:30` or `2023-10-14`
+pub(crate) fn parse_replay_time(src: &str) -> Result<ReplayTime> {
+    let parts = src
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let part = |i: usize| parts.get(i).copied().unwrap_or_default();
+    if !(3..=6).contains(&parts.len())
+        || !(1..=12).contains(&part(1))
+        || !(1..=31).contains(&part(2))
+        || !(0..=23).contains(&part(3))
+        || !(0..=59).contains(&part(4))
+        || !(0..=59).contains(&part(5))
+    {
+        return Err(anyhow!(
+            "Could not understand {}, should be a local time like 2023-10-14 08:30:00",
+            src
+        ));
+    }
+    Ok(ReplayTime {
+        year: part(0),
+        month: part(1) as u8,
+        day: part(2) as u8,
+        hour: part(3) as u8,
+        minute: part(4) as u8,
+        second: part(5) as u8,
+    })
+}
+
+/// The local time of a date time as used to search the recordings
+pub(crate) fn replay_time(time: &time::OffsetDateTime) -> ReplayTime {
+    ReplayTime {
+        year: time.year(),
+        month: time.month() as u8,
+        day: time.day(),
+        hour: time.hour(),
+        minute: time.minute(),
+        second: time.second(),
+    }
+}
+
 pub(crate) enum AddressOrUid {
     Address(String),
     #[allow(dead_code)]
@@ -43,18 +363,15 @@ impl Display for AddressOrUid {
 impl AddressOrUid {
     // Created by translating the config fields directly
     pub(crate) fn new(
-        address: &Option<String>,
+        addresses: &[String],
         uid: &Option<String>,
         method: &DiscoveryMethods,
     ) -> Result<Self, Error> {
+        let address = (!addresses.is_empty()).then(|| addresses.join(", "));
         match (address, uid) {
             (None, None) => Err(anyhow!("Neither address or uid given")),
-            (Some(host), Some(uid)) => Ok(AddressOrUid::AddressWithUid(
-                host.clone(),
-                uid.clone(),
-                *method,
-            )),
-            (Some(host), None) => Ok(AddressOrUid::Address(host.clone())),
+            (Some(host), Some(uid)) => Ok(AddressOrUid::AddressWithUid(host, uid.clone(), *method)),
+            (Some(host), None) => Ok(AddressOrUid::Address(host)),
             (None, Some(host)) => Ok(AddressOrUid::Uid(host.clone(), *method)),
         }
     }
@@ -65,27 +382,28 @@ impl AddressOrUid {
         &self,
         camera_config: &CameraConfig,
     ) -> Result<BcCamera, Error> {
-        let (port, addrs) = {
-            if let Some(addr_str) = camera_config.camera_addr.as_ref() {
-                match addr_str.to_socket_addrs() {
-                    Ok(addr_iter) => {
-                        let mut port = None;
-                        let mut ipaddrs = vec![];
-                        for addr in addr_iter {
-                            port = Some(addr.port());
-                            ipaddrs.push(addr.ip());
+        // The addresses are looked up on every connect so that a DDNS name
+        // that has moved is followed. The IPs are tried in the order of the
+        // addresses with the first port that was given
+        let mut port = None;
+        let mut addrs = vec![];
+        for addr_str in camera_config.camera_addrs.iter() {
+            match resolve_camera_addr(addr_str).await {
+                Ok((addr_port, ips)) => {
+                    debug!("{}: {} is at {:?}", camera_config.name, addr_str, ips);
+                    port = port.or(addr_port);
+                    for ip in ips {
+                        if !addrs.contains(&ip) {
+                            addrs.push(ip);
                         }
-                        Ok((port, ipaddrs))
                     }
-                    Err(_) => match IpAddr::from_str(addr_str) {
-                        Ok(ip) => Ok((None, vec![ip])),
-                        Err(_) => Err(anyhow!("Could not parse address in config")),
-                    },
                 }
-            } else {
-                Ok((None, vec![]))
+                Err(e) => warn!("{}: {:?}", camera_config.name, e),
             }
-        }?;
+        }
+        if addrs.is_empty() && camera_config.camera_uid.is_none() {
+            return Err(anyhow!("Could not resolve any address in config"));
+        }
 
         let options = BcCameraOpt {
             name: camera_config.name.clone(),
@@ -101,6 +419,15 @@ impl AddressOrUid {
             },
             debug: camera_config.debug,
             max_discovery_retries: camera_config.max_discovery_retries,
+            udp: UdpSettings {
+                mtu: camera_config.udp.mtu,
+                ack_interval: tokio::time::Duration::from_millis(camera_config.udp.ack_interval),
+                resend_interval: tokio::time::Duration::from_millis(
+                    camera_config.udp.resend_interval,
+                ),
+                resend_window: camera_config.udp.resend_window,
+            },
+            proxy: camera_config.proxy.as_deref().map(str::parse).transpose()?,
         };
 
         trace!("Camera Info: {:?}", options);
@@ -109,9 +436,34 @@ impl AddressOrUid {
     }
 }
 
+/// The IPs of a camera address and its port if one was given
+async fn resolve_camera_addr(addr: &str) -> Result<(Option<u16>, Vec<IpAddr>)> {
+    if let Ok(ip) = IpAddr::from_str(addr) {
+        return Ok((None, vec![ip]));
+    }
+    let has_port = addr
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+    let resolved: Vec<SocketAddr> = if has_port {
+        tokio::net::lookup_host(addr)
+            .await
+            .map(|addrs| addrs.collect())
+    } else {
+        tokio::net::lookup_host((addr, 0))
+            .await
+            .map(|addrs| addrs.collect())
+    }
+    .with_context(|| format!("Could not resolve the address {addr}"))?;
+    let port = resolved
+        .first()
+        .filter(|_| has_port)
+        .map(|addr| addr.port());
+    Ok((port, resolved.iter().map(|addr| addr.ip()).collect()))
+}
+
 pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<BcCamera> {
     let camera_addr = AddressOrUid::new(
-        &camera_config.camera_addr,
+        &camera_config.camera_addrs,
         &camera_config.camera_uid,
         &camera_config.discovery,
     )
@@ -137,10 +489,26 @@ pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<Bc
         "aes" => MaxEncryption::Aes,
         _ => MaxEncryption::Aes,
     };
+    let login_method = match camera_config.login.to_lowercase().as_str() {
+        "modern" => LoginMethod::Modern,
+        "legacy" => LoginMethod::Legacy,
+        _ => LoginMethod::Auto,
+    };
+    let min_level = match camera_config.min_protocol_security.to_lowercase().as_str() {
+        "bc" | "bcencrypt" => EncryptionLevel::BcEncrypt,
+        "aes" => EncryptionLevel::Aes,
+        _ => EncryptionLevel::None,
+    };
     info!("{}: Logging in", camera_config.name);
-    timeout(camera.login_with_maxenc(max_encryption))
+    timeout(camera.login_with_min_security(max_encryption, login_method, min_level))
         .await
-        .with_context(|| format!("Failed to login to {}", camera_config.name))??;
+        .with_context(|| format!("Failed to login to {}", camera_config.name))?
+        .with_context(|| {
+            format!(
+                "Failed to login to {} with min_protocol_security = {:?}",
+                camera_config.name, camera_config.min_protocol_security
+            )
+        })?;
 
     info!("{}: Connected and logged in", camera_config.name);
 
@@ -0,0 +1,16 @@
+use crate::cmdline::CameraSelection;
+use clap::Parser;
+
+/// The zoom command will zoom a camera with a motorized lens
+#[derive(Parser, Debug)]
+#[command(allow_missing_positional = true)]
+pub struct Opt {
+    /// The name of the camera to zoom. Must be a name in the config
+    #[arg(required_unless_present_any = ["all", "pattern"])]
+    pub camera: Option<String>,
+    #[command(flatten)]
+    pub select: CameraSelection,
+    /// The zoom factor e.g. 1.0 for no zoom and 3.5 for 3.5x.
+    /// Without this the current zoom is printed
+    pub level: Option<f32>,
+}
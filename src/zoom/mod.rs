@@ -0,0 +1,70 @@
+///
+/// # Neolink Zoom
+///
+/// This module handles the zoom of cameras with a motorized lens such as the
+/// RLC-811A and RLC-823A
+///
+/// # Usage
+///
+/// ```bash
+/// # Zoom to 3.5x
+/// neolink zoom --config=config.toml CameraName 3.5
+/// # Zoom all the way out
+/// neolink zoom --config=config.toml CameraName 1.0
+/// # Print the current zoom
+/// neolink zoom --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use tokio::time::{sleep, Duration};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, utils::for_each_camera};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the zoom subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let level = opt.level;
+    for_each_camera(
+        &reactor,
+        opt.camera.as_deref(),
+        &opt.select,
+        |camera| async move {
+            if let Some(level) = level {
+                camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.zoom_to((level * 1000.0) as u32)
+                                .await
+                                .context("Unable to zoom the camera")
+                        })
+                    })
+                    .await?;
+                // Give the lens time to move before logging out
+                sleep(Duration::from_secs(1)).await;
+                Ok(String::new())
+            } else {
+                let zoom = camera
+                    .run_task(|cam| {
+                        Box::pin(async move {
+                            cam.get_zoom()
+                                .await
+                                .context("Unable to get the zoom of the camera")
+                        })
+                    })
+                    .await?
+                    .zoom;
+                Ok(format!(
+                    "Zoom: {:.1}x ({:.1}x to {:.1}x)",
+                    zoom.cur_pos as f32 / 1000.0,
+                    zoom.min_pos as f32 / 1000.0,
+                    zoom.max_pos as f32 / 1000.0
+                ))
+            }
+        },
+    )
+    .await
+}